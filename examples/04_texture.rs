@@ -3,13 +3,13 @@ use futures::future::Future;
 use simplelog::Config;
 use simplelog::LevelFilter;
 use simplelog::TermLogger;
+use starstruck::asset::Handle;
 use starstruck::camera::DebugCamera;
 use starstruck::graphics::Bundle;
 use starstruck::graphics::Pipeline;
 use starstruck::graphics::Texture;
 use starstruck::primitive::Vertex3DUV;
 use starstruck::Context;
-use starstruck::CreateBundleFromObj;
 use starstruck::CreateTexturedPipeline;
 use starstruck::SetupContext;
 use std::sync::Arc;
@@ -21,18 +21,19 @@ use starstruck::StarstruckBuilder;
 struct State {
     camera: DebugCamera,
     triangle_pipeline: Pipeline<Vertex3DUV>,
-    triangle_bundle: Bundle<u16, Vertex3DUV>,
-    texture: Texture,
+    triangle_bundle: Handle<Bundle<u16, Vertex3DUV>>,
+    texture: Handle<Texture>,
 }
 
 impl State {
     pub fn new(setup: Arc<SetupContext>) -> impl Future<Item = Self, Error = Error> {
         let pipeline_promise = setup.create_textured_pipeline();
-        let bundle_promise = setup.create_bundle_from_obj(include_bytes!("assets/cube.obj"));
-        let texture_promise = setup.create_texture_from_bytes(include_bytes!("assets/bricks.jpg"));
+        // Loaded in the background from the asset root, see `with_asset_root` below
+        let bundle = setup.assets().load_mesh("cube.obj");
+        let texture = setup.assets().load_texture("bricks.jpg");
 
-        pipeline_promise.join3(bundle_promise, texture_promise).map(
-            |(pipeline, bundle, texture)| {
+        pipeline_promise.join3(bundle.clone(), texture.clone()).map(
+            move |(pipeline, _, _)| {
                 let mut camera = DebugCamera::new();
                 camera.set_position(Vec3 {
                     x: 0.0,
@@ -40,7 +41,8 @@ impl State {
                     z: -3.0,
                 });
 
-                pipeline.bind_texture(&texture);
+                // Binds the texture again if bricks.jpg changes while running
+                pipeline.bind_texture_handle(&texture);
 
                 State {
                     texture,
@@ -54,7 +56,10 @@ impl State {
 
     pub fn render(&mut self, context: &mut Context) -> Result<(), Error> {
         self.camera.update_from_context(context);
-        context.draw_with_camera(&self.triangle_pipeline, &self.triangle_bundle, &self.camera);
+        // Getting the bundle every frame picks up the new one when cube.obj is reloaded
+        if let Some(bundle) = self.triangle_bundle.get() {
+            context.draw_with_camera(&self.triangle_pipeline, &*bundle, &self.camera);
+        }
         Ok(())
     }
 }
@@ -67,6 +72,7 @@ fn main() -> Result<(), Error> {
 
     let starstruck = StarstruckBuilder::new_with_setup(setup_callback)
         .with_render_callback(|(state, context)| state.render(context))
+        .with_asset_root("examples/assets")
         .init()?;
 
     starstruck.run()?;
@@ -0,0 +1,115 @@
+use failure::Error;
+use futures::future::Future;
+use simplelog::Config;
+use simplelog::LevelFilter;
+use simplelog::TermLogger;
+use starstruck::camera::DebugCamera;
+use starstruck::graphics::Bundle;
+use starstruck::graphics::DirectionalLight;
+use starstruck::graphics::Pipeline;
+use starstruck::graphics::ShadowCasterPipeline;
+use starstruck::graphics::ShadowConfig;
+use starstruck::primitive::Vertex3D;
+use starstruck::Context;
+use starstruck::CreateBundleFromObj;
+use starstruck::CreateLitPipeline;
+use starstruck::PrepareContext;
+use starstruck::SetupContext;
+use starstruck::StarstruckBuilder;
+use std::sync::Arc;
+use vek::vec::Rgb;
+use vek::vec::Vec3;
+
+// The y axis points down, so the floor is placed just below the cube
+const FLOOR_VERTICES: [Vertex3D; 4] = [
+    Vertex3D { x: -5.0, y: 1.0, z: -5.0 },
+    Vertex3D { x: 5.0, y: 1.0, z: -5.0 },
+    Vertex3D { x: 5.0, y: 1.0, z: 5.0 },
+    Vertex3D { x: -5.0, y: 1.0, z: 5.0 },
+];
+const FLOOR_INDEXES: [u16; 6] = [0, 1, 2, 2, 3, 0];
+
+// THIS IS OUR STATE WHERE WE STORE ALL OUR DATA
+struct State {
+    camera: DebugCamera,
+    light: DirectionalLight,
+    pipeline: Pipeline<Vertex3D>,
+    shadow_pipeline: ShadowCasterPipeline<Vertex3D>,
+    cube: Bundle<u16, Vertex3D>,
+    floor: Bundle<u16, Vertex3D>,
+}
+
+impl State {
+    pub fn new(setup: Arc<SetupContext>) -> impl Future<Item = Self, Error = Error> {
+        let light_promise = setup.create_directional_light(
+            Vec3::new(0.5, 1.0, 0.3),
+            Rgb::new(1.0, 1.0, 1.0),
+            ShadowConfig::default(),
+        );
+        let pipeline_promise = setup.create_lit_pipeline();
+        let shadow_pipeline_promise = setup.create_shadow_caster_pipeline();
+        let cube_promise = setup.create_bundle_from_obj(include_bytes!("assets/cube.obj"));
+        let floor_promise = setup.create_bundle(&FLOOR_INDEXES, &FLOOR_VERTICES);
+
+        light_promise
+            .join5(
+                pipeline_promise,
+                shadow_pipeline_promise,
+                cube_promise,
+                floor_promise,
+            )
+            .map(|(light, pipeline, shadow_pipeline, cube, floor)| {
+                pipeline.bind_directional_light(&light);
+
+                let mut camera = DebugCamera::new();
+                camera.set_position(Vec3 {
+                    x: 0.0,
+                    y: -2.0,
+                    z: -6.0,
+                });
+
+                State {
+                    camera,
+                    light,
+                    pipeline,
+                    shadow_pipeline,
+                    cube,
+                    floor,
+                }
+            })
+    }
+
+    pub fn prepare(&mut self, context: &mut PrepareContext) -> Result<(), Error> {
+        let shadow_pipeline = &self.shadow_pipeline;
+        let cube = &self.cube;
+        let floor = &self.floor;
+        context.render_shadow_map(&mut self.light, |encoder| {
+            encoder.draw(shadow_pipeline, cube);
+            encoder.draw(shadow_pipeline, floor);
+            Ok(())
+        })
+    }
+
+    pub fn render(&mut self, context: &mut Context) -> Result<(), Error> {
+        self.camera.update_from_context(context);
+        context.draw_with_camera(&self.pipeline, &self.cube, &self.camera);
+        context.draw_with_camera(&self.pipeline, &self.floor, &self.camera);
+        Ok(())
+    }
+}
+
+// MAIN
+fn main() -> Result<(), Error> {
+    TermLogger::init(LevelFilter::Info, Config::default()).unwrap();
+
+    let setup_callback = |setup| State::new(setup);
+
+    let starstruck = StarstruckBuilder::new_with_setup(setup_callback)
+        .with_prepare_callback(|(state, context)| state.prepare(context))
+        .with_render_callback(|(state, context)| state.render(context))
+        .init()?;
+
+    starstruck.run()?;
+
+    Ok(())
+}
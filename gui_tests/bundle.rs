@@ -1,6 +1,9 @@
+use failure::Error;
 use futures::future::join_all;
+use futures::Future;
 use starstruck::primitive::Vertex2D;
 use starstruck::StarstruckBuilder;
+use std::env::temp_dir;
 
 // OUR VERTICES
 const VERTICES: [Vertex2D; 3] = [
@@ -30,3 +33,29 @@ pub fn it_should_create_a_lot_of_bundles() {
 
     starstruck.run().unwrap();
 }
+
+pub fn it_should_load_packed_bundles_from_mesh_files() {
+    let path = temp_dir().join("starstruck_gui_test_triangle.ssmesh");
+
+    let starstruck = StarstruckBuilder::new_with_setup(move |setup| {
+        let path = path.clone();
+        setup
+            .create_bundle(&INDEXES, &VERTICES)
+            .and_then(move |bundle| bundle.pack().wait())
+            .and_then(move |mesh| -> Result<_, Error> {
+                mesh.save(&path)?;
+                Ok(setup.create_bundle_from_mesh_file::<u16, Vertex2D, _>(path))
+            })
+            .flatten()
+            .and_then(|bundle| bundle.read_back().wait())
+    })
+        .with_render_callback(|((indexes, vertexes), context)| {
+            assert_eq!(INDEXES.to_vec(), *indexes);
+            assert_eq!(VERTICES.iter().map(|vertex| vertex.x).collect::<Vec<_>>(), vertexes.iter().map(|vertex| vertex.x).collect::<Vec<_>>());
+            context.stop_starstruck();
+            Ok(())
+        })
+        .init().unwrap();
+
+    starstruck.run().unwrap();
+}
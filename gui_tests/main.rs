@@ -1,13 +1,18 @@
 extern crate starstruck;
 
 mod bundle;
+mod pipeline;
 
 use crate::bundle::it_should_create_a_lot_of_bundles;
+use crate::bundle::it_should_load_packed_bundles_from_mesh_files;
+use crate::pipeline::it_should_create_a_pipeline_from_a_generated_shader_module;
 use colored::*;
 use failure::Error;
 use log::LevelFilter;
 use simplelog::Config;
 use simplelog::TermLogger;
+// The generated shader module refers to `crate::graphics::ShaderDescription`
+use starstruck::graphics;
 use std::panic;
 use std::time::Instant;
 
@@ -16,10 +21,20 @@ fn main() -> Result<(), Error> {
     TermLogger::init(LevelFilter::Warn, Config::default()).unwrap();
     println!();
 
-    let tests = vec![(
-        "It should create a lot of bundles",
-        it_should_create_a_lot_of_bundles,
-    )];
+    let tests: Vec<(&str, fn())> = vec![
+        (
+            "It should create a lot of bundles",
+            it_should_create_a_lot_of_bundles,
+        ),
+        (
+            "It should load packed bundles from mesh files",
+            it_should_load_packed_bundles_from_mesh_files,
+        ),
+        (
+            "It should create a pipeline from a generated shader module",
+            it_should_create_a_pipeline_from_a_generated_shader_module,
+        ),
+    ];
 
     println!("running {} tests", tests.len());
 
@@ -0,0 +1,34 @@
+use gfx_hal::pso::DescriptorType;
+use pretty_assertions::assert_eq;
+use starstruck::graphics::ShaderSet;
+use starstruck::primitive::Vertex3D;
+use starstruck::StarstruckBuilder;
+
+// Only a few of the generated shaders are used here
+#[allow(dead_code)]
+mod shaders {
+    include!(concat!(env!("OUT_DIR"), "/shaders.rs"));
+}
+
+pub fn it_should_create_a_pipeline_from_a_generated_shader_module() {
+    let starstruck = StarstruckBuilder::new_with_setup(|setup| {
+        let vertex = shaders::vertex_xyz_lit_vert();
+        assert_eq!(16, vertex.push_constant_floats);
+        assert_eq!(vec![(2, DescriptorType::UniformBuffer, 1)], vertex.bindings);
+
+        setup.create_pipeline::<Vertex3D>(ShaderSet {
+            vertex,
+            hull: None,
+            domain: None,
+            geometry: None,
+            fragment: Some(shaders::vertex_xyz_default_frag()),
+        })
+    })
+        .with_render_callback(|(_pipeline, context)| {
+            context.stop_starstruck();
+            Ok(())
+        })
+        .init().unwrap();
+
+    starstruck.run().unwrap();
+}
@@ -0,0 +1,223 @@
+use crate::allocator::Memory;
+use failure::Backtrace;
+use gfx_hal::Backend;
+use std::collections::HashMap;
+use std::ops::Range;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::Mutex;
+
+/// Decides what happens when a tracked `Memory` is dropped without being freed
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LeakPolicy {
+    /// Logs the leak and panics, like untracked memory does
+    Panic,
+
+    /// Logs the leak and forgets about the memory, the region stays in use until the chunk is
+    /// released
+    Log,
+
+    /// Logs a warning and frees the memory the next time the allocator is maintained
+    Reclaim,
+}
+
+/// A `Memory` that is still in use
+#[derive(Clone, Debug)]
+pub struct LiveAllocation {
+    pub label: Option<String>,
+    pub chunk_id: u64,
+    pub range: Range<u32>,
+
+    /// Where the memory was allocated, only captured in debug builds
+    pub backtrace: Option<String>,
+}
+
+#[derive(Debug)]
+struct Record {
+    label: Option<String>,
+    chunk_id: u64,
+    range: Range<u32>,
+    backtrace: Option<Backtrace>,
+}
+
+/// Keeps a record of every `Memory` handed out by a `TrackingAllocator`
+#[derive(Debug)]
+pub struct AllocationTracker<B: Backend> {
+    policy: LeakPolicy,
+    records: Mutex<HashMap<u64, Record>>,
+    reclaimed: Mutex<Vec<Memory<B>>>,
+    next_id: AtomicUsize,
+}
+
+impl<B: Backend> AllocationTracker<B> {
+    pub fn new(policy: LeakPolicy) -> Self {
+        Self {
+            policy,
+            records: Mutex::new(HashMap::new()),
+            reclaimed: Mutex::new(Vec::new()),
+            next_id: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn policy(&self) -> LeakPolicy {
+        self.policy
+    }
+
+    /// Starts tracking `memory`, returns the id it's tracked by
+    pub(crate) fn track(&self, memory: &Memory<B>) -> u64 {
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst) as u64;
+        let backtrace = if cfg!(debug_assertions) {
+            Some(Backtrace::new())
+        } else {
+            None
+        };
+        self.records.lock().unwrap().insert(
+            id,
+            Record {
+                label: memory.label().map(str::to_string),
+                chunk_id: memory.chunk_id(),
+                range: memory.memory_range(),
+                backtrace,
+            },
+        );
+        id
+    }
+
+    pub(crate) fn untrack(&self, id: u64) {
+        self.records.lock().unwrap().remove(&id);
+    }
+
+    pub(crate) fn set_label(&self, id: u64, label: &str) {
+        if let Some(record) = self.records.lock().unwrap().get_mut(&id) {
+            record.label = Some(label.to_string());
+        }
+    }
+
+    /// Called when a tracked `Memory` is dropped without being freed
+    pub(crate) fn leaked(&self, id: u64, memory: &Memory<B>) {
+        let record = self.records.lock().unwrap().remove(&id);
+        let description = record.as_ref().map(describe).unwrap_or_else(|| "untracked memory".to_string());
+
+        match self.policy {
+            LeakPolicy::Panic => {
+                error!("Memory dropped while still not freed! This is a memory leak! {}", description);
+                // Panicking while already panicking would abort
+                if !std::thread::panicking() {
+                    panic!("Memory dropped while still not freed! This is a memory leak! {}", description)
+                }
+            }
+            LeakPolicy::Log => {
+                error!("Memory dropped while still not freed! This is a memory leak! {}", description);
+            }
+            LeakPolicy::Reclaim => {
+                warn!("Memory dropped while still not freed, it will be reclaimed. {}", description);
+                self.reclaimed.lock().unwrap().push(Memory::new(memory.memory_range(), memory.chunk_id(), memory.device_memory()));
+            }
+        }
+    }
+
+    /// Takes the memory that was dropped without being freed under `LeakPolicy::Reclaim`
+    pub(crate) fn take_reclaimed(&self) -> Vec<Memory<B>> {
+        self.reclaimed.lock().unwrap().drain(..).collect()
+    }
+
+    /// Every tracked `Memory` that hasn't been freed yet
+    pub fn live_allocations(&self) -> Vec<LiveAllocation> {
+        self.records
+            .lock()
+            .unwrap()
+            .values()
+            .map(|record| LiveAllocation {
+                label: record.label.clone(),
+                chunk_id: record.chunk_id,
+                range: record.range.clone(),
+                backtrace: record.backtrace.as_ref().map(|backtrace| format!("{}", backtrace)),
+            })
+            .collect()
+    }
+
+    /// Logs every live allocation
+    pub fn report(&self) {
+        let records = self.records.lock().unwrap();
+        if records.is_empty() {
+            info!("No live allocations");
+            return;
+        }
+        warn!("{} live allocations", records.len());
+        for record in records.values() {
+            warn!("{}", describe(record));
+        }
+    }
+}
+
+fn describe(record: &Record) -> String {
+    let mut description = format!(
+        "{} ({} bytes in chunk {})",
+        record.label.as_ref().map(String::as_str).unwrap_or("unlabeled memory"),
+        record.range.len(),
+        record.chunk_id
+    );
+    if let Some(backtrace) = &record.backtrace {
+        description.push_str(&format!(", allocated at:\n{}", backtrace));
+    }
+    description
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use std::sync::Arc;
+
+    type Tracker = AllocationTracker<gfx_backend_empty::Backend>;
+
+    fn tracked(tracker: &Arc<Tracker>, label: &str) -> Memory<gfx_backend_empty::Backend> {
+        let mut memory = Memory::new(0..100, 7, Arc::new(()));
+        memory.set_label(label);
+        memory.track(Arc::clone(tracker));
+        memory
+    }
+
+    #[test]
+    fn it_should_list_live_allocations_with_their_labels() {
+        let tracker = Arc::new(Tracker::new(LeakPolicy::Panic));
+        let mut memory = tracked(&tracker, "vertex buffer");
+
+        let live = tracker.live_allocations();
+        assert_eq!(1, live.len());
+        assert_eq!(Some("vertex buffer".to_string()), live[0].label);
+        assert_eq!(7, live[0].chunk_id);
+
+        memory.set_freed();
+        assert_eq!(0, tracker.live_allocations().len());
+    }
+
+    #[test]
+    fn it_should_queue_leaked_memory_for_reclaiming() {
+        let tracker = Arc::new(Tracker::new(LeakPolicy::Reclaim));
+        drop(tracked(&tracker, "texture"));
+
+        let mut reclaimed = tracker.take_reclaimed();
+        assert_eq!(1, reclaimed.len());
+        assert_eq!(0..100, reclaimed[0].memory_range());
+        assert_eq!(0, tracker.live_allocations().len());
+
+        for memory in &mut reclaimed {
+            memory.set_freed();
+        }
+    }
+
+    #[test]
+    fn it_should_only_log_leaks_when_asked_to() {
+        let tracker = Arc::new(Tracker::new(LeakPolicy::Log));
+        drop(tracked(&tracker, "texture"));
+        assert_eq!(0, tracker.live_allocations().len());
+    }
+
+    #[test]
+    #[should_panic(expected = "Memory dropped while still not freed! This is a memory leak! texture")]
+    fn it_should_panic_with_the_label() {
+        let tracker = Arc::new(Tracker::new(LeakPolicy::Panic));
+        drop(tracked(&tracker, "texture"));
+    }
+}
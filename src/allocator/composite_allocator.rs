@@ -0,0 +1,178 @@
+use crate::allocator::DedicatedAllocator;
+use crate::allocator::DefaultChunk;
+use crate::allocator::DefaultGpuAllocator;
+use crate::allocator::GpuAllocator;
+use crate::allocator::HeapStats;
+use crate::allocator::LinearAllocator;
+use crate::allocator::Memory;
+use crate::allocator::MemoryUsage;
+use crate::allocator::PoolAllocator;
+use failure::Error;
+use gfx_hal::adapter::MemoryProperties;
+use gfx_hal::Backend;
+use gfx_hal::Device;
+use gfx_hal::MemoryTypeId;
+use std::sync::Arc;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Route {
+    General,
+    Linear,
+    Pool,
+    Dedicated,
+}
+
+/// Routes each allocation to the allocator that suits it best. Transient memory goes to a
+/// `LinearAllocator`, uniform blocks that fit in a block go to a `PoolAllocator`, images larger
+/// than the dedicated threshold go to a `DedicatedAllocator` and everything else goes to a
+/// `DefaultGpuAllocator`
+#[derive(Debug)]
+pub struct CompositeAllocator<B: Backend = backend::Backend, D: Device<B> = backend::Device> {
+    general: DefaultGpuAllocator<DefaultChunk<B, D>, B, D>,
+    linear: LinearAllocator<B, D>,
+    pool: PoolAllocator<B, D>,
+    dedicated: DedicatedAllocator<DefaultChunk<B, D>, B, D>,
+    dedicated_threshold: u64,
+}
+
+impl<B: Backend, D: Device<B>> CompositeAllocator<B, D> {
+    const DEDICATED_DEFAULT_THRESHOLD: u64 = 16_777_216; // 16mb
+
+    pub fn new() -> Self {
+        Self {
+            general: DefaultGpuAllocator::new(),
+            linear: LinearAllocator::new(),
+            pool: PoolAllocator::new(),
+            dedicated: DedicatedAllocator::new(),
+            dedicated_threshold: Self::DEDICATED_DEFAULT_THRESHOLD,
+        }
+    }
+
+    pub fn with_general(mut self, general: DefaultGpuAllocator<DefaultChunk<B, D>, B, D>) -> Self {
+        self.general = general;
+        self
+    }
+
+    pub fn with_linear(mut self, linear: LinearAllocator<B, D>) -> Self {
+        self.linear = linear;
+        self
+    }
+
+    pub fn with_pool(mut self, pool: PoolAllocator<B, D>) -> Self {
+        self.pool = pool;
+        self
+    }
+
+    /// Images of at least this many bytes get a device allocation of their own, defaults to 16 MiB
+    pub fn with_dedicated_threshold(mut self, dedicated_threshold: u64) -> Self {
+        self.dedicated_threshold = dedicated_threshold;
+        self
+    }
+
+    fn route(&self, usage: MemoryUsage, size: u64, alignment: u64) -> Route {
+        let block_size = u64::from(self.pool.block_size());
+        match usage {
+            MemoryUsage::Transient => Route::Linear,
+            MemoryUsage::Uniform if size <= block_size && block_size % alignment.max(1) == 0 => Route::Pool,
+            MemoryUsage::Image if size >= self.dedicated_threshold => Route::Dedicated,
+            _ => Route::General,
+        }
+    }
+
+    fn allocator(&self, route: Route) -> &GpuAllocator<B, D> {
+        match route {
+            Route::General => &self.general,
+            Route::Linear => &self.linear,
+            Route::Pool => &self.pool,
+            Route::Dedicated => &self.dedicated,
+        }
+    }
+}
+
+impl<B: Backend, D: Device<B>> Default for CompositeAllocator<B, D> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<B: Backend, D: Device<B>> GpuAllocator<B, D> for CompositeAllocator<B, D> {
+    fn init(&mut self, device: Arc<D>, memory_properties: &MemoryProperties) {
+        self.general.init(Arc::clone(&device), memory_properties);
+        self.linear.init(Arc::clone(&device), memory_properties);
+        self.pool.init(Arc::clone(&device), memory_properties);
+        self.dedicated.init(device, memory_properties);
+    }
+
+    fn allocate_memory(&self, memory_id: MemoryTypeId, size: u64, alignment: u64) -> Result<Memory<B>, Error> {
+        self.allocate_memory_for(MemoryUsage::General, memory_id, size, alignment)
+    }
+
+    fn allocate_memory_for(&self, usage: MemoryUsage, memory_id: MemoryTypeId, size: u64, alignment: u64) -> Result<Memory<B>, Error> {
+        self.allocator(self.route(usage, size, alignment))
+            .allocate_memory(memory_id, size, alignment)
+    }
+
+    fn free_memory(&self, memory: &mut Memory<B>) {
+        // Chunk ids are unique across allocators, so only the one that allocated it owns the chunk
+        let chunk_id = memory.chunk_id();
+        let route = if self.linear.owns(chunk_id) {
+            Route::Linear
+        } else if self.pool.owns(chunk_id) {
+            Route::Pool
+        } else if self.dedicated.owns(chunk_id) {
+            Route::Dedicated
+        } else {
+            Route::General
+        };
+        self.allocator(route).free_memory(memory)
+    }
+
+    fn maintain(&self) {
+        self.general.maintain();
+        self.linear.maintain();
+        self.pool.maintain();
+        self.dedicated.maintain();
+    }
+
+    fn stats(&self) -> Vec<HeapStats> {
+        let mut stats = self.general.stats();
+        for other in &[self.linear.stats(), self.pool.stats(), self.dedicated.stats()] {
+            for (heap, other) in stats.iter_mut().zip(other.iter()) {
+                heap.allocated += other.allocated;
+                heap.used += other.used;
+                heap.largest_free_region = heap.largest_free_region.max(other.largest_free_region);
+                heap.chunk_count += other.chunk_count;
+            }
+        }
+        stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    type Allocator = CompositeAllocator<gfx_backend_empty::Backend, gfx_backend_empty::Device>;
+
+    #[test]
+    fn it_should_route_by_usage_and_size() {
+        let allocator = Allocator::new().with_dedicated_threshold(1000);
+
+        assert_eq!(Route::Linear, allocator.route(MemoryUsage::Transient, 100_000, 4));
+        assert_eq!(Route::Pool, allocator.route(MemoryUsage::Uniform, 64, 64));
+        assert_eq!(Route::General, allocator.route(MemoryUsage::Uniform, 1024, 64));
+        assert_eq!(Route::Dedicated, allocator.route(MemoryUsage::Image, 1000, 256));
+        assert_eq!(Route::General, allocator.route(MemoryUsage::Image, 999, 256));
+        assert_eq!(Route::General, allocator.route(MemoryUsage::General, 100_000, 4));
+    }
+
+    #[test]
+    fn it_should_fail_if_not_initialized() {
+        let allocator = Allocator::new();
+        let error = allocator
+            .allocate_memory_for(MemoryUsage::Transient, MemoryTypeId(0), 100, 1)
+            .unwrap_err();
+        assert_eq!("This allocator has not been initialized", format!("{}", error));
+    }
+}
@@ -0,0 +1,194 @@
+use crate::allocator::next_chunk_id;
+use crate::allocator::Chunk;
+use crate::allocator::DefaultChunk;
+use crate::allocator::GpuAllocator;
+use crate::allocator::HeapLayout;
+use crate::allocator::HeapStats;
+use crate::allocator::Memory;
+use crate::errors::AllocationError;
+use failure::Error;
+use gfx_hal::adapter::MemoryProperties;
+use gfx_hal::Backend;
+use gfx_hal::Device;
+use gfx_hal::MemoryTypeId;
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+/// Gives every allocation a device allocation of its own, which suits large images that would
+/// take up most of a shared chunk anyway. The memory is returned to the device at the start of the
+/// frame after it was freed
+#[derive(Debug)]
+pub struct DedicatedAllocator<C: Chunk<B, D> = DefaultChunk<backend::Backend, backend::Device>, B: Backend = backend::Backend, D: Device<B> = backend::Device> {
+    device: Option<Arc<D>>,
+    chunks: Mutex<HashMap<u64, C>>,
+    layout: HeapLayout,
+    phantom: PhantomData<B>,
+}
+
+impl<C: Chunk<B, D>, B: Backend, D: Device<B>> DedicatedAllocator<C, B, D> {
+    pub fn new() -> Self {
+        Self {
+            device: None,
+            chunks: Mutex::new(HashMap::new()),
+            layout: HeapLayout::default(),
+            phantom: PhantomData,
+        }
+    }
+
+    /// Whether the chunk with the given id belongs to this allocator
+    pub fn owns(&self, chunk_id: u64) -> bool {
+        self.chunks.lock().unwrap().contains_key(&chunk_id)
+    }
+}
+
+impl<C: Chunk<B, D>, B: Backend, D: Device<B>> Default for DedicatedAllocator<C, B, D> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<C: Chunk<B, D>, B: Backend, D: Device<B>> GpuAllocator<B, D> for DedicatedAllocator<C, B, D> {
+    fn init(&mut self, device: Arc<D>, memory_properties: &MemoryProperties) {
+        self.device = Some(device);
+        self.layout = HeapLayout::new(memory_properties);
+    }
+
+    fn allocate_memory(&self, memory_id: MemoryTypeId, size: u64, alignment: u64) -> Result<Memory<B>, Error> {
+        let device = Arc::clone(self.device.as_ref().ok_or(AllocationError::NotInitialized)?);
+        // Chunks address their memory with u32 offsets
+        if size > u64::from(u32::max_value()) {
+            Err(AllocationError::TooLarge { requested: size, max: u64::from(u32::max_value()) })?;
+        }
+        if size == 0 {
+            Err(AllocationError::EmptyAllocation)?;
+        }
+        let id = next_chunk_id();
+        let mut chunk = C::new(device, memory_id, self.layout.properties(memory_id), size as u32, id)?;
+        let memory = match chunk.allocate(size as u32, alignment.max(1) as u32) {
+            Ok(memory) => memory,
+            Err(error) => {
+                chunk.release();
+                return Err(error);
+            }
+        };
+        self.chunks.lock().unwrap().insert(id, chunk);
+        Ok(memory)
+    }
+
+    fn free_memory(&self, memory: &mut Memory<B>) {
+        let mut lock = self.chunks.lock().unwrap();
+        match lock.get_mut(&memory.chunk_id()) {
+            Some(chunk) => chunk.deallocate(memory),
+            None => error!("Chunk has already been freed! This should not happen! Seems like we are over freeing memory"),
+        }
+        memory.set_freed();
+    }
+
+    fn maintain(&self) {
+        let mut chunks = self.chunks.lock().unwrap();
+        let unused: Vec<_> = chunks.iter().filter(|(_, chunk)| chunk.is_unused()).map(|(id, _)| *id).collect();
+        for id in unused {
+            if let Some(chunk) = chunks.remove(&id) {
+                chunk.release();
+            }
+        }
+    }
+
+    fn stats(&self) -> Vec<HeapStats> {
+        let mut stats = self.layout.stats(|heap| self.layout.heap_size(heap).unwrap_or(0));
+        for chunk in self.chunks.lock().unwrap().values() {
+            self.layout.record::<B, D, C>(&mut stats, chunk);
+        }
+        stats
+    }
+}
+
+impl<C: Chunk<B, D>, B: Backend, D: Device<B>> Drop for DedicatedAllocator<C, B, D> {
+    fn drop(&mut self) {
+        for (_, chunk) in self.chunks.lock().unwrap().drain() {
+            chunk.release();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::allocator::MockChunk;
+    use gfx_hal::adapter::MemoryType;
+    use gfx_hal::memory::Properties;
+    use pretty_assertions::assert_eq;
+    use pretty_assertions::assert_ne;
+
+    type Allocator = DedicatedAllocator<MockChunk, gfx_backend_empty::Backend, gfx_backend_empty::Device>;
+
+    fn allocator() -> Allocator {
+        let mut allocator = Allocator::new();
+        allocator.init(Arc::new(gfx_backend_empty::Device), &MemoryProperties {
+            memory_types: vec![MemoryType { properties: Properties::DEVICE_LOCAL, heap_index: 0 }],
+            memory_heaps: vec![10_000],
+        });
+        allocator
+    }
+
+    #[test]
+    fn it_should_fail_if_not_initialized() {
+        let allocator = Allocator::new();
+        let error = allocator.allocate_memory(MemoryTypeId(0), 100, 1).unwrap_err();
+        assert_eq!("This allocator has not been initialized", format!("{}", error));
+    }
+
+    #[test]
+    fn it_should_give_every_allocation_a_chunk_of_its_own() {
+        let allocator = allocator();
+        let mut memory1 = allocator.allocate_memory(MemoryTypeId(0), 1000, 256).unwrap();
+        let mut memory2 = allocator.allocate_memory(MemoryTypeId(0), 3000, 256).unwrap();
+
+        assert_ne!(memory1.chunk_id(), memory2.chunk_id());
+        assert_eq!(0..1000, memory1.memory_range());
+        assert_eq!(0..3000, memory2.memory_range());
+        let stats = allocator.stats();
+        assert_eq!(2, stats[0].chunk_count);
+        assert_eq!(4000, stats[0].allocated);
+
+        allocator.free_memory(&mut memory1);
+        allocator.free_memory(&mut memory2);
+    }
+
+    #[test]
+    fn it_should_release_freed_memory_on_maintain() {
+        let allocator = allocator();
+        let mut memory1 = allocator.allocate_memory(MemoryTypeId(0), 1000, 1).unwrap();
+        let mut memory2 = allocator.allocate_memory(MemoryTypeId(0), 1000, 1).unwrap();
+
+        allocator.free_memory(&mut memory1);
+        assert!(allocator.owns(memory1.chunk_id()));
+        allocator.maintain();
+        assert!(!allocator.owns(memory1.chunk_id()));
+        assert!(allocator.owns(memory2.chunk_id()));
+
+        allocator.free_memory(&mut memory2);
+        allocator.maintain();
+        assert_eq!(0, allocator.stats()[0].chunk_count);
+    }
+
+    #[test]
+    fn it_should_reject_allocations_that_dont_fit_in_a_chunk() {
+        let allocator = allocator();
+        let error = allocator.allocate_memory(MemoryTypeId(0), 1 << 32, 1).unwrap_err();
+        assert_eq!(
+            Some(&AllocationError::TooLarge { requested: 1 << 32, max: u64::from(u32::max_value()) }),
+            error.downcast_ref::<AllocationError>()
+        );
+    }
+
+    #[test]
+    fn it_should_reject_empty_allocations() {
+        let allocator = allocator();
+        let error = allocator.allocate_memory(MemoryTypeId(0), 0, 1).unwrap_err();
+        assert_eq!(Some(&AllocationError::EmptyAllocation), error.downcast_ref::<AllocationError>());
+        assert_eq!(0, allocator.stats()[0].chunk_count);
+    }
+}
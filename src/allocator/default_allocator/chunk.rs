@@ -1,13 +1,26 @@
 use gfx_hal::Backend;
 use gfx_hal::Device;
 use gfx_hal::MemoryTypeId;
+use gfx_hal::memory::Properties;
 use std::sync::Arc;
 use failure::Error;
 use crate::allocator::Memory;
 
+/// A block of device memory that an allocator hands out parts of
 pub trait Chunk<B: Backend, D: Device<B>>: Send + Sync + 'static where Self: std::marker::Sized {
-    fn new(device: Arc<D>, memory_id: MemoryTypeId, size: u32, id: u64) -> Result<Self, Error>;
-    fn allocate(&mut self, size: u32) -> Result<Memory<B>, Error>;
+    /// Allocates the memory of the chunk, host visible chunks stay mapped until they're released
+    fn new(device: Arc<D>, memory_id: MemoryTypeId, properties: Properties, size: u32, id: u64) -> Result<Self, Error>;
+    /// Allocates `size` bytes starting at a multiple of `alignment`, which is a power of two
+    fn allocate(&mut self, size: u32, alignment: u32) -> Result<Memory<B>, Error>;
     fn deallocate(&mut self, memory: &mut Memory<B>);
     fn memory_id(&self) -> MemoryTypeId;
+    fn size(&self) -> u32;
+    fn free(&self) -> u32;
+    fn largest_free_region(&self) -> u32;
+
+    /// True when everything has been deallocated and no `Memory` from this chunk is alive
+    fn is_unused(&self) -> bool;
+
+    /// Returns the memory of the chunk to the device
+    fn release(self);
 }
\ No newline at end of file
@@ -5,7 +5,10 @@ use std::ops::Range;
 use std::sync::Arc;
 use failure::Error;
 use crate::allocator::Memory;
+use crate::allocator::Mapping;
+use gfx_hal::memory::Properties;
 use crate::allocator::default_allocator::chunk::Chunk;
+use crate::errors::AllocationError;
 
 #[derive(Debug)]
 pub struct DefaultChunk<B: Backend, D: Device<B>> {
@@ -15,14 +18,23 @@ pub struct DefaultChunk<B: Backend, D: Device<B>> {
     pub regions: Vec<Range<u32>>,
     pub memory: Arc<B::Memory>,
     pub device: Arc<D>,
-    pub id: u64
+    pub id: u64,
+    pub(crate) mapping: Mapping
 }
 
 impl<B: Backend, D: Device<B>> Chunk<B, D> for DefaultChunk<B, D> {
-    fn new(device: Arc<D>, memory_id: MemoryTypeId, size: u32, id: u64) -> Result<Self, Error> {
+    fn new(device: Arc<D>, memory_id: MemoryTypeId, properties: Properties, size: u32, id: u64) -> Result<Self, Error> {
 
         info!("Allocating new memory chunk that is {} bytes long", size);
-        let memory = Arc::new(unsafe { device.allocate_memory(memory_id, u64::from(size)) }?);
+        let memory = unsafe { device.allocate_memory(memory_id, u64::from(size)) }.map_err(AllocationError::from)?;
+        let mapping = match unsafe { Mapping::map(&*device, &memory, properties, size) } {
+            Ok(mapping) => mapping,
+            Err(error) => {
+                unsafe { device.free_memory(memory) };
+                return Err(error);
+            }
+        };
+        let memory = Arc::new(memory);
 
         Ok(Self {
             size,
@@ -31,39 +43,98 @@ impl<B: Backend, D: Device<B>> Chunk<B, D> for DefaultChunk<B, D> {
             regions: vec![0..size],
             memory,
             device,
-            id
+            id,
+            mapping
         })
     }
 
-    fn allocate(&mut self, size: u32) -> Result<Memory<B>, Error> {
-        if size > self.free {
-            bail!("No available region found")
+    fn allocate(&mut self, size: u32, alignment: u32) -> Result<Memory<B>, Error> {
+        if size == 0 || size > self.free {
+            return Err(AllocationError::NoAvailableRegion.into());
         }
-        match self.regions.iter_mut().find(|range| range.len() as u32 >= size).map(|range| {
-            let start = range.start;
-            range.start += size;
-
-            start..range.start
-        }) {
-            Some(range) => {
-                self.free -= range.len() as u32;
-                Ok(Memory::new(range, self.id, Arc::clone(&self.memory)))
-            },
-            None => {
-                bail!("No available region found")
-            }
+
+        // Best fit, the smallest free region that can hold the aligned allocation
+        let (index, offset) = self
+            .regions
+            .iter()
+            .enumerate()
+            .filter_map(|(index, region)| {
+                let offset = align_up(region.start, alignment)?;
+                if offset.checked_add(size)? <= region.end {
+                    Some((index, offset, region.len()))
+                } else {
+                    None
+                }
+            })
+            .min_by_key(|&(_, _, len)| len)
+            .map(|(index, offset, _)| (index, offset))
+            .ok_or(AllocationError::NoAvailableRegion)?;
+
+        // The padding before the allocation and whatever is left after it stay free
+        let region = self.regions.remove(index);
+        let range = offset..offset + size;
+        if range.end < region.end {
+            self.regions.insert(index, range.end..region.end);
         }
+        if region.start < range.start {
+            self.regions.insert(index, region.start..range.start);
+        }
+
+        self.free -= size;
+        Ok(Memory::new(range, self.id, Arc::clone(&self.memory)).with_mapping(self.mapping))
     }
 
     fn deallocate(&mut self, memory: &mut Memory<B>) {
         let range = memory.memory_range();
         self.free += range.len() as u32;
-        self.regions.push(range);
+
+        // Regions are kept sorted and merged with their neighbours so that they don't fragment
+        let index = self.regions.binary_search_by_key(&range.start, |region| region.start).unwrap_or_else(|index| index);
+        debug_assert!(index == self.regions.len() || range.end <= self.regions[index].start, "Freed memory overlaps a free region");
+        self.regions.insert(index, range);
+        if index + 1 < self.regions.len() && self.regions[index].end == self.regions[index + 1].start {
+            self.regions[index].end = self.regions.remove(index + 1).end;
+        }
+        if index > 0 && self.regions[index - 1].end == self.regions[index].start {
+            self.regions[index - 1].end = self.regions.remove(index).end;
+        }
     }
 
     fn memory_id(&self) -> MemoryTypeId {
         self.memory_id
     }
+
+    fn size(&self) -> u32 {
+        self.size
+    }
+
+    fn free(&self) -> u32 {
+        self.free
+    }
+
+    fn largest_free_region(&self) -> u32 {
+        self.regions.iter().map(|region| region.len() as u32).max().unwrap_or(0)
+    }
+
+    fn is_unused(&self) -> bool {
+        self.free == self.size && Arc::strong_count(&self.memory) == 1
+    }
+
+    fn release(self) {
+        info!("Releasing memory chunk that is {} bytes long", self.size);
+        unsafe { self.mapping.unmap(&*self.device, &self.memory) };
+        match Arc::try_unwrap(self.memory) {
+            Ok(memory) => unsafe { self.device.free_memory(memory) },
+            Err(_) => error!("Released a memory chunk that is still in use! This is a memory leak!"),
+        }
+    }
+}
+
+/// Rounds `value` up to a multiple of `alignment`, which has to be a power of two
+pub(crate) fn align_up(value: u32, alignment: u32) -> Option<u32> {
+    debug_assert!(alignment.is_power_of_two(), "Alignment has to be a power of two");
+    let mask = alignment - 1;
+    value.checked_add(mask).map(|value| value & !mask)
 }
 
 #[cfg(test)]
@@ -74,12 +145,16 @@ mod test {
     use std::sync::Arc;
     use crate::allocator::default_allocator::default_chunk::DefaultChunk;
     use crate::allocator::default_allocator::chunk::Chunk;
+    use crate::allocator::Memory;
+    use crate::allocator::Mapping;
+    use gfx_hal::memory::Properties;
+    use proptest::prelude::*;
 
     #[test]
     #[should_panic(expected = "not yet implemented")]
     #[allow(unused_must_use)]
     fn new_chunk_should_fail_by_calling_allocate() {
-        DefaultChunk::new(Arc::new(gfx_backend_empty::Device), MemoryTypeId(0), 1000, 0);
+        DefaultChunk::new(Arc::new(gfx_backend_empty::Device), MemoryTypeId(0), Properties::DEVICE_LOCAL, 1000, 0);
     }
 
     #[test]
@@ -91,10 +166,11 @@ mod test {
             regions: vec![0..1000],
             memory: Arc::new(()),
             device: Arc::new(gfx_backend_empty::Device),
-            id: 0
+            id: 0,
+            mapping: Mapping::unmapped(1000)
         };
 
-        let mut memory = chunk.allocate(100).unwrap();
+        let mut memory = chunk.allocate(100, 1).unwrap();
 
         // To avoid panic when dropping
         memory.set_freed();
@@ -112,9 +188,10 @@ mod test {
             regions: vec![0..1000],
             memory: Arc::new(()),
             device: Arc::new(gfx_backend_empty::Device),
-            id: 0
+            id: 0,
+            mapping: Mapping::unmapped(1000)
         };
-        let error = chunk.allocate(10000).unwrap_err();
+        let error = chunk.allocate(10000, 1).unwrap_err();
 
         assert_eq!("No available region found", format!("{}", error));
     }
@@ -128,17 +205,18 @@ mod test {
             regions: vec![0..1000],
             memory: Arc::new(()),
             device: Arc::new(gfx_backend_empty::Device),
-            id: 0
+            id: 0,
+            mapping: Mapping::unmapped(1000)
         };
 
         for _ in 0..10 {
-            let mut memory = chunk.allocate(100).unwrap();
+            let mut memory = chunk.allocate(100, 1).unwrap();
             // To avoid panic when dropping
             memory.set_freed();
         }
 
         // this should error
-        let error = chunk.allocate(100).unwrap_err();
+        let error = chunk.allocate(100, 1).unwrap_err();
         assert_eq!("No available region found", format!("{}", error));
     }
 
@@ -151,10 +229,11 @@ mod test {
             regions: vec![0..1000],
             memory: Arc::new(()),
             device: Arc::new(gfx_backend_empty::Device),
-            id: 0
+            id: 0,
+            mapping: Mapping::unmapped(1000)
         };
-        let mut memory1 = chunk.allocate(100).unwrap();
-        let mut memory2 = chunk.allocate(100).unwrap();
+        let mut memory1 = chunk.allocate(100, 1).unwrap();
+        let mut memory2 = chunk.allocate(100, 1).unwrap();
 
         // To avoid panic when dropping
         memory1.set_freed();
@@ -162,6 +241,109 @@ mod test {
 
         assert_ne!(memory1.memory_range(), memory2.memory_range())
     }
+
+    fn chunk(size: u32) -> DefaultChunk<gfx_backend_empty::Backend, gfx_backend_empty::Device> {
+        DefaultChunk {
+            size,
+            free: size,
+            memory_id: MemoryTypeId(0),
+            regions: vec![0..size],
+            memory: Arc::new(()),
+            device: Arc::new(gfx_backend_empty::Device),
+            id: 0,
+            mapping: Mapping::unmapped(size)
+        }
+    }
+
+    #[test]
+    fn allocate_should_respect_alignment() {
+        let mut chunk = chunk(1000);
+        let mut memory1 = chunk.allocate(10, 1).unwrap();
+        let mut memory2 = chunk.allocate(10, 256).unwrap();
+
+        memory1.set_freed();
+        memory2.set_freed();
+
+        assert_eq!(256..266, memory2.memory_range());
+        assert_eq!(1000 - 20, chunk.free);
+    }
+
+    #[test]
+    fn deallocate_should_merge_neighbouring_regions() {
+        let mut chunk = chunk(300);
+        let mut memories: Vec<_> = (0..3).map(|_| chunk.allocate(100, 1).unwrap()).collect();
+        for memory in &mut memories {
+            memory.set_freed();
+        }
+
+        chunk.deallocate(&mut memories[1]);
+        chunk.deallocate(&mut memories[0]);
+        assert_eq!(vec![0..200], chunk.regions);
+
+        chunk.deallocate(&mut memories[2]);
+        assert_eq!(vec![0..300], chunk.regions);
+
+        let mut memory = chunk.allocate(300, 1).unwrap();
+        memory.set_freed();
+    }
+
+    #[test]
+    fn allocate_should_pick_the_best_fitting_region() {
+        let mut chunk = chunk(1000);
+        chunk.regions = vec![0..500, 600..650, 700..1000];
+        chunk.free = 850;
+
+        let mut memory = chunk.allocate(40, 1).unwrap();
+        memory.set_freed();
+
+        assert_eq!(600..640, memory.memory_range());
+    }
+
+    proptest! {
+        #[test]
+        fn random_allocations_should_never_overlap(
+            operations in prop::collection::vec((any::<bool>(), 1u32..300, 0u32..8, any::<usize>()), 1..200)
+        ) {
+            let size = 4096;
+            let mut chunk = chunk(size);
+            let mut live: Vec<Memory<gfx_backend_empty::Backend>> = Vec::new();
+
+            for (allocate, len, alignment_shift, pick) in operations {
+                if allocate || live.is_empty() {
+                    let alignment = 1 << alignment_shift;
+                    if let Ok(mut memory) = chunk.allocate(len, alignment) {
+                        memory.set_freed();
+                        let range = memory.memory_range();
+                        prop_assert_eq!(len, range.len() as u32);
+                        prop_assert_eq!(0, range.start % alignment);
+                        prop_assert!(range.end <= size);
+                        live.push(memory);
+                    }
+                } else {
+                    let mut memory = live.swap_remove(pick % live.len());
+                    chunk.deallocate(&mut memory);
+                }
+
+                let mut ranges: Vec<_> = live.iter().map(Memory::memory_range).collect();
+                let used: u32 = ranges.iter().map(|range| range.len() as u32).sum();
+                prop_assert_eq!(size - used, chunk.free);
+
+                ranges.extend(chunk.regions.iter().cloned());
+                ranges.sort_by_key(|range| range.start);
+                for pair in ranges.windows(2) {
+                    prop_assert!(pair[0].end <= pair[1].start, "{:?} overlaps {:?}", pair[0], pair[1]);
+                }
+                for pair in chunk.regions.windows(2) {
+                    prop_assert!(pair[0].end < pair[1].start, "{:?} wasn't merged with {:?}", pair[0], pair[1]);
+                }
+            }
+
+            for mut memory in live.drain(..) {
+                chunk.deallocate(&mut memory);
+            }
+            prop_assert_eq!(vec![0..size], chunk.regions.clone());
+        }
+    }
 }
 
 
@@ -174,6 +356,7 @@ mod bench {
     use std::sync::Arc;
     use bencher::black_box;
     use crate::allocator::default_allocator::chunk::Chunk;
+    use crate::allocator::Mapping;
 
     #[bench]
     fn allocate_bench(b: &mut Bencher) {
@@ -184,11 +367,12 @@ mod bench {
             regions: vec![0..10000],
             memory: Arc::new(()),
             device: Arc::new(gfx_backend_empty::Device),
-            id: 0
+            id: 0,
+            mapping: Mapping::unmapped(10000)
         };
 
         b.iter(|| {
-            let mut memory = chunk.allocate(100).unwrap();
+            let mut memory = chunk.allocate(100, 1).unwrap();
             // To avoid panic when dropping
             memory.set_freed();
             chunk.deallocate(&mut memory);
@@ -205,11 +389,12 @@ mod bench {
             regions: vec![0..10],
             memory: Arc::new(()),
             device: Arc::new(gfx_backend_empty::Device),
-            id: 0
+            id: 0,
+            mapping: Mapping::unmapped(10)
         };
 
         b.iter(|| {
-            let result = chunk.allocate(1000);
+            let result = chunk.allocate(1000, 1);
             black_box(result);
         })
     }
@@ -1,5 +1,10 @@
 use crate::allocator::GpuAllocator;
+use crate::allocator::HeapStats;
+use crate::allocator::HeapLayout;
+use crate::allocator::next_chunk_id;
+use crate::allocator::UnusedChunks;
 use gfx_hal::MemoryTypeId;
+use gfx_hal::adapter::MemoryProperties;
 use failure::Error;
 use gfx_hal::Backend;
 use std::sync::Arc;
@@ -7,66 +12,150 @@ use gfx_hal::Device;
 use crate::allocator::Memory;
 use std::sync::Mutex;
 use std::collections::HashMap;
-use std::sync::atomic::AtomicUsize;
-use std::sync::atomic::Ordering;
+use std::time::Duration;
 use crate::allocator::default_allocator::chunk::Chunk;
 use crate::allocator::default_allocator::default_chunk::DefaultChunk;
 use std::marker::PhantomData;
+use crate::errors::AllocationError;
 
 
-#[derive(Debug, Default)]
+/// Sub-allocates resources from large chunks of device memory. Chunks that have been unused for
+/// longer than the release delay are returned to the device
+#[derive(Debug)]
 pub struct DefaultGpuAllocator<C: Chunk<B, D> = DefaultChunk<backend::Backend, backend::Device>, B: Backend = backend::Backend, D: Device<B> = backend::Device> {
     device: Option<Arc<D>>,
     chunks: Mutex<HashMap<u64, C>>,
-    next_id: AtomicUsize,
+    unused: Mutex<UnusedChunks>,
+    chunk_size: u32,
+    release_delay: Duration,
+    budget: Option<f64>,
+    layout: HeapLayout,
     phantom: PhantomData<B>
 }
 
 impl<C: Chunk<B, D>, B: Backend, D: Device<B>> DefaultGpuAllocator<C, B, D> {
     const CHUNK_DEFAULT_SIZE: u32 = 67_108_864; // 64mb
+    const RELEASE_DEFAULT_DELAY: Duration = Duration::from_secs(2);
 
     pub fn new() -> Self {
         Self {
             device: None,
             chunks: Mutex::new(HashMap::new()),
-            next_id: AtomicUsize::new(0),
+            unused: Mutex::new(UnusedChunks::new()),
+            chunk_size: Self::CHUNK_DEFAULT_SIZE,
+            release_delay: Self::RELEASE_DEFAULT_DELAY,
+            budget: None,
+            layout: HeapLayout::default(),
             phantom: PhantomData
         }
     }
 
-    fn create_chunk_and_get_memory(&self, memory_id: MemoryTypeId, chunk_size: u32, memory_size: u32) -> Result<Memory<B>, Error> {
-        let id = self.next_id.fetch_add(1, Ordering::SeqCst) as u64;
-        let mut chunk = C::new(Arc::clone(self.device.as_ref().ok_or_else(|| format_err!("This allocator has not been initialized"))?), memory_id, chunk_size, id)?;
-        let mem = chunk.allocate(memory_size).expect("This should always work since we just allocated it");
-        {
-            let mut lock = self.chunks.lock().unwrap();
-            lock.insert(id, chunk);
+    /// The size of the chunks resources are sub-allocated from, defaults to 64 MiB. Resources
+    /// larger than this get a chunk of their own
+    pub fn with_chunk_size(mut self, chunk_size: u32) -> Self {
+        self.chunk_size = chunk_size;
+        self
+    }
+
+    /// How long a chunk has to be unused before it's returned to the device, defaults to two
+    /// seconds. This keeps chunks around when resources are recreated every now and then
+    pub fn with_release_delay(mut self, release_delay: Duration) -> Self {
+        self.release_delay = release_delay;
+        self
+    }
+
+    /// Limits each heap to the given fraction of its size. Allocations that would go over the
+    /// budget fail with `AllocationError::BudgetExceeded`
+    pub fn with_budget(mut self, fraction: f64) -> Self {
+        self.budget = Some(fraction.max(0.0).min(1.0));
+        self
+    }
+
+    /// Whether the chunk with the given id belongs to this allocator
+    pub fn owns(&self, chunk_id: u64) -> bool {
+        self.chunks.lock().unwrap().contains_key(&chunk_id)
+    }
+
+    fn budget(&self, heap: usize) -> u64 {
+        let size = self.layout.heap_size(heap).unwrap_or(u64::max_value());
+        match self.budget {
+            Some(fraction) => (size as f64 * fraction) as u64,
+            None => size,
+        }
+    }
+
+    fn allocated(&self, chunks: &HashMap<u64, C>, heap: usize) -> u64 {
+        chunks
+            .values()
+            .filter(|chunk| self.layout.heap(chunk.memory_id()) == heap)
+            .map(|chunk| u64::from(chunk.size()))
+            .sum()
+    }
+
+    fn create_chunk_and_get_memory(&self, memory_id: MemoryTypeId, chunk_size: u32, memory_size: u32, alignment: u32) -> Result<Memory<B>, Error> {
+        let device = Arc::clone(self.device.as_ref().ok_or(AllocationError::NotInitialized)?);
+        let mut lock = self.chunks.lock().unwrap();
+
+        // Shrink the chunk to what's left of the budget rather than failing right away
+        let heap = self.layout.heap(memory_id);
+        let budget = self.budget(heap);
+        let remaining = budget.saturating_sub(self.allocated(&lock, heap));
+        if remaining < u64::from(memory_size) {
+            Err(AllocationError::BudgetExceeded { heap, requested: u64::from(memory_size), budget })?;
         }
+        let chunk_size = u64::from(chunk_size).min(remaining) as u32;
+
+        let id = next_chunk_id();
+        let mut chunk = C::new(device, memory_id, self.layout.properties(memory_id), chunk_size, id)?;
+        let mem = match chunk.allocate(memory_size, alignment) {
+            Ok(mem) => mem,
+            Err(error) => {
+                chunk.release();
+                return Err(error);
+            }
+        };
+        lock.insert(id, chunk);
         Ok(mem)
     }
 }
 
+impl<C: Chunk<B, D>, B: Backend, D: Device<B>> Default for DefaultGpuAllocator<C, B, D> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<C: Chunk<B, D>, B: Backend, D: Device<B>> GpuAllocator<B, D> for DefaultGpuAllocator<C, B, D> {
 
-    fn init(&mut self, device: Arc<D>) {
+    fn init(&mut self, device: Arc<D>, memory_properties: &MemoryProperties) {
         self.device = Some(device);
+        self.layout = HeapLayout::new(memory_properties);
     }
 
-    fn allocate_memory(&self, memory_id: MemoryTypeId, size: u64) -> Result<Memory<B>, Error> {
-        if size as u32 <= Self::CHUNK_DEFAULT_SIZE {
+    fn allocate_memory(&self, memory_id: MemoryTypeId, size: u64, alignment: u64) -> Result<Memory<B>, Error> {
+        if size == 0 {
+            Err(AllocationError::EmptyAllocation)?;
+        }
+        // Chunks address their memory with u32 offsets
+        if size > u64::from(u32::max_value()) {
+            Err(AllocationError::TooLarge { requested: size, max: u64::from(u32::max_value()) })?;
+        }
+        let size = size as u32;
+        let alignment = alignment.max(1) as u32;
+        if size <= self.chunk_size {
             {
                 let mut lock = self.chunks.lock().unwrap();
                 for chunk in lock.values_mut() {
                     if chunk.memory_id() == memory_id {
-                        if let Ok(res) = chunk.allocate(size as u32) {
+                        if let Ok(res) = chunk.allocate(size, alignment) {
                             return Ok(res)
                         }
                     }
                 }
             }
-            self.create_chunk_and_get_memory(memory_id, Self::CHUNK_DEFAULT_SIZE, size as u32)
+            self.create_chunk_and_get_memory(memory_id, self.chunk_size, size, alignment)
         } else {
-            self.create_chunk_and_get_memory(memory_id, size as u32, size as u32)
+            self.create_chunk_and_get_memory(memory_id, size, size, alignment)
         }
     }
 
@@ -82,49 +171,192 @@ impl<C: Chunk<B, D>, B: Backend, D: Device<B>> GpuAllocator<B, D> for DefaultGpu
         }
         memory.set_freed();
     }
+
+    fn maintain(&self) {
+        let mut chunks = self.chunks.lock().unwrap();
+        for chunk in self.unused.lock().unwrap().expired::<B, D, C>(&mut chunks, self.release_delay) {
+            chunk.release();
+        }
+    }
+
+    fn stats(&self) -> Vec<HeapStats> {
+        let mut stats = self.layout.stats(|heap| self.budget(heap));
+        for chunk in self.chunks.lock().unwrap().values() {
+            self.layout.record::<B, D, C>(&mut stats, chunk);
+        }
+        stats
+    }
+}
+
+impl<C: Chunk<B, D>, B: Backend, D: Device<B>> Drop for DefaultGpuAllocator<C, B, D> {
+    fn drop(&mut self) {
+        let mut chunks = self.chunks.lock().unwrap();
+        for (_, chunk) in chunks.drain() {
+            chunk.release();
+        }
+    }
 }
 
 #[cfg(test)]
 mod test {
     use pretty_assertions::assert_eq;
     use crate::allocator::DefaultGpuAllocator;
-    use crate::allocator::default_allocator::chunk::Chunk;
+    use crate::allocator::MockChunk;
     use gfx_hal::MemoryTypeId;
     use std::sync::Arc;
-    use failure::Error;
-    use crate::allocator::Memory;
     use crate::allocator::gpu_allocator::GpuAllocator;
+    use crate::errors::AllocationError;
+    use gfx_hal::adapter::MemoryProperties;
+    use gfx_hal::adapter::MemoryType;
+    use gfx_hal::memory::Properties;
+    use std::thread::sleep;
+    use std::time::Duration;
 
-    struct MockChunk;
-
-    impl Chunk<gfx_backend_empty::Backend, gfx_backend_empty::Device> for MockChunk {
-        fn new(_device: Arc<gfx_backend_empty::Device>, _memory_id: MemoryTypeId, _size: u32, _id: u64) -> Result<Self, Error> {
-            Ok(MockChunk)
-        }
-
-        fn allocate(&mut self, _size: u32) -> Result<Memory<gfx_backend_empty::Backend>, Error> {
-            unimplemented!()
-        }
-
-        fn deallocate(&mut self, _memory: &mut Memory<gfx_backend_empty::Backend>) {
-            unimplemented!()
-        }
+    type Allocator = DefaultGpuAllocator<MockChunk, gfx_backend_empty::Backend, gfx_backend_empty::Device>;
 
-        fn memory_id(&self) -> MemoryTypeId {
-            unimplemented!()
-        }
+    fn init(mut allocator: Allocator) -> Allocator {
+        allocator.init(Arc::new(gfx_backend_empty::Device), &MemoryProperties {
+            memory_types: vec![MemoryType { properties: Properties::DEVICE_LOCAL, heap_index: 0 }],
+            memory_heaps: vec![1000],
+        });
+        allocator
     }
 
     #[test]
     fn default_chunk_size_should_be_correct() {
-        assert_eq!(67_108_864, DefaultGpuAllocator::<MockChunk, gfx_backend_empty::Backend, gfx_backend_empty::Device>::CHUNK_DEFAULT_SIZE);
+        assert_eq!(67_108_864, Allocator::CHUNK_DEFAULT_SIZE);
     }
 
     #[test]
     fn allocator_should_throw_if_not_initialized() {
-        let allocator = DefaultGpuAllocator::<MockChunk, gfx_backend_empty::Backend, gfx_backend_empty::Device>::new();
-        let error = allocator.allocate_memory(MemoryTypeId(1), 1000).unwrap_err();
+        let allocator = Allocator::new();
+        let error = allocator.allocate_memory(MemoryTypeId(1), 1000, 1).unwrap_err();
         assert_eq!("This allocator has not been initialized", format!("{}", error));
     }
 
-}
\ No newline at end of file
+    #[test]
+    fn allocator_should_fail_when_over_budget() {
+        let allocator = init(Allocator::new().with_chunk_size(100).with_budget(0.5));
+
+        let error = allocator.allocate_memory(MemoryTypeId(0), 600, 1).unwrap_err();
+        assert_eq!(
+            Some(&AllocationError::BudgetExceeded { heap: 0, requested: 600, budget: 500 }),
+            error.downcast_ref::<AllocationError>()
+        );
+    }
+
+    #[test]
+    fn allocator_should_reject_allocations_that_dont_fit_in_a_chunk() {
+        let allocator = init(Allocator::new());
+
+        let error = allocator.allocate_memory(MemoryTypeId(0), 1 << 32, 1).unwrap_err();
+        assert_eq!(
+            Some(&AllocationError::TooLarge { requested: 1 << 32, max: u64::from(u32::max_value()) }),
+            error.downcast_ref::<AllocationError>()
+        );
+    }
+
+    #[test]
+    fn allocator_should_reject_empty_allocations_without_creating_a_chunk() {
+        let allocator = init(Allocator::new());
+
+        let error = allocator.allocate_memory(MemoryTypeId(0), 0, 1).unwrap_err();
+        assert_eq!(Some(&AllocationError::EmptyAllocation), error.downcast_ref::<AllocationError>());
+        assert_eq!(0, allocator.stats()[0].chunk_count);
+    }
+
+    #[test]
+    fn allocator_should_shrink_the_last_chunk_to_the_budget() {
+        let allocator = init(Allocator::new().with_chunk_size(400).with_budget(0.5).with_release_delay(Duration::from_secs(0)));
+
+        let mut first = allocator.allocate_memory(MemoryTypeId(0), 300, 1).unwrap();
+        let mut second = allocator.allocate_memory(MemoryTypeId(0), 100, 1).unwrap();
+        assert_eq!(first.chunk_id(), second.chunk_id());
+        let mut third = allocator.allocate_memory(MemoryTypeId(0), 100, 1).unwrap();
+        assert_eq!(500, allocator.stats()[0].allocated);
+
+        let error = allocator.allocate_memory(MemoryTypeId(0), 1, 1).unwrap_err();
+        assert_eq!(
+            Some(&AllocationError::BudgetExceeded { heap: 0, requested: 1, budget: 500 }),
+            error.downcast_ref::<AllocationError>()
+        );
+
+        // Released chunks give their memory back to the budget
+        allocator.free_memory(&mut first);
+        allocator.free_memory(&mut second);
+        allocator.maintain();
+        assert_eq!(100, allocator.stats()[0].allocated);
+        let mut fourth = allocator.allocate_memory(MemoryTypeId(0), 400, 1).unwrap();
+
+        allocator.free_memory(&mut third);
+        allocator.free_memory(&mut fourth);
+    }
+
+    #[test]
+    fn maintain_should_release_chunks_once_the_delay_has_passed() {
+        let allocator = init(Allocator::new().with_release_delay(Duration::from_millis(50)));
+
+        let mut memory = allocator.allocate_memory(MemoryTypeId(0), 100, 1).unwrap();
+        let chunk_id = memory.chunk_id();
+        allocator.free_memory(&mut memory);
+
+        allocator.maintain();
+        assert!(allocator.owns(chunk_id));
+
+        sleep(Duration::from_millis(100));
+        allocator.maintain();
+        assert!(!allocator.owns(chunk_id));
+        assert_eq!(0, allocator.stats()[0].chunk_count);
+    }
+
+    #[test]
+    fn maintain_should_keep_chunks_that_are_in_use() {
+        let allocator = init(Allocator::new().with_release_delay(Duration::from_secs(0)));
+
+        let mut memory = allocator.allocate_memory(MemoryTypeId(0), 100, 1).unwrap();
+        allocator.maintain();
+        assert!(allocator.owns(memory.chunk_id()));
+
+        allocator.free_memory(&mut memory);
+        allocator.maintain();
+        assert!(!allocator.owns(memory.chunk_id()));
+    }
+
+    #[test]
+    fn maintain_should_restart_the_delay_when_a_chunk_is_used_again() {
+        let allocator = init(Allocator::new().with_release_delay(Duration::from_millis(100)));
+
+        let mut memory = allocator.allocate_memory(MemoryTypeId(0), 100, 1).unwrap();
+        let chunk_id = memory.chunk_id();
+        allocator.free_memory(&mut memory);
+        allocator.maintain();
+
+        sleep(Duration::from_millis(150));
+        let mut memory = allocator.allocate_memory(MemoryTypeId(0), 100, 1).unwrap();
+        assert_eq!(chunk_id, memory.chunk_id());
+        allocator.maintain();
+        allocator.free_memory(&mut memory);
+
+        // The chunk has been unused for long enough in total, but not since it was last used
+        allocator.maintain();
+        assert!(allocator.owns(chunk_id));
+
+        sleep(Duration::from_millis(150));
+        allocator.maintain();
+        assert!(!allocator.owns(chunk_id));
+    }
+
+    #[test]
+    fn stats_should_list_every_heap() {
+        let mut allocator = Allocator::new();
+        allocator.init(Arc::new(gfx_backend_empty::Device), &MemoryProperties {
+            memory_types: Vec::new(),
+            memory_heaps: vec![1000, 2000],
+        });
+
+        let stats = allocator.stats();
+        assert_eq!(2, stats.len());
+        assert_eq!(2000, stats[1].budget);
+        assert_eq!(0, stats[1].chunk_count);
+    }
+}
@@ -3,4 +3,6 @@ mod default_chunk;
 mod chunk;
 
 pub use self::default_gpu_allocator::DefaultGpuAllocator;
-pub use self::default_chunk::DefaultChunk;
\ No newline at end of file
+pub use self::default_chunk::DefaultChunk;
+pub use self::chunk::Chunk;
+pub(crate) use self::default_chunk::align_up;
@@ -4,9 +4,49 @@ use failure::Error;
 use gfx_hal::Backend;
 use std::sync::Arc;
 use gfx_hal::Device;
+use gfx_hal::adapter::MemoryProperties;
+use crate::allocator::HeapStats;
+use crate::allocator::LiveAllocation;
+
+/// What a piece of memory is going to be used for, allocators may use it to pick a strategy
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum MemoryUsage {
+    General,
+    Image,
+
+    /// Small uniform blocks, which usually all have the same size
+    Uniform,
+
+    /// Memory that is only used for the current frame, such as the staging buffers that buffers
+    /// and textures are read back through
+    Transient,
+}
 
 pub trait GpuAllocator<B: Backend = backend::Backend, D: Device<B> = backend::Device>: Send + Sync + 'static {
-    fn init(&mut self, device: Arc<D>);
-    fn allocate_memory(&self, memory_id: MemoryTypeId, size: u64) -> Result<Memory<B>, Error>;
+    fn init(&mut self, device: Arc<D>, memory_properties: &MemoryProperties);
+    /// Allocates `size` bytes of the given memory type. The offset of the memory is a multiple of
+    /// `alignment`, pass `requirements.alignment` here
+    fn allocate_memory(&self, memory_id: MemoryTypeId, size: u64, alignment: u64) -> Result<Memory<B>, Error>;
     fn free_memory(&self, memory: &mut Memory<B>);
+
+    /// Like `allocate_memory`, but tells the allocator what the memory will be used for
+    fn allocate_memory_for(&self, _usage: MemoryUsage, memory_id: MemoryTypeId, size: u64, alignment: u64) -> Result<Memory<B>, Error> {
+        self.allocate_memory(memory_id, size, alignment)
+    }
+
+    /// Called once per frame, after the memory of the resources that completed frames dropped has
+    /// been freed. Allocators can use it to reset per frame memory and return unused memory to the
+    /// device
+    fn maintain(&self) {}
+
+    /// The usage of each memory heap
+    fn stats(&self) -> Vec<HeapStats> {
+        Vec::new()
+    }
+
+    /// The memory that hasn't been freed yet, only allocators that track their memory such as
+    /// `TrackingAllocator` report anything
+    fn live_allocations(&self) -> Vec<LiveAllocation> {
+        Vec::new()
+    }
 }
\ No newline at end of file
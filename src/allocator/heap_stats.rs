@@ -0,0 +1,136 @@
+use crate::allocator::Chunk;
+use gfx_hal::adapter::MemoryProperties;
+use gfx_hal::memory::Properties;
+use gfx_hal::Backend;
+use gfx_hal::Device;
+use gfx_hal::MemoryTypeId;
+
+/// Memory usage of one device memory heap
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct HeapStats {
+    /// The index of the heap
+    pub heap: usize,
+
+    /// The size of the heap as reported by the device
+    pub size: u64,
+
+    /// How much of the heap the allocator is allowed to take
+    pub budget: u64,
+
+    /// Bytes allocated from the device, including the free parts of chunks
+    pub allocated: u64,
+
+    /// Bytes handed out to resources
+    pub used: u64,
+
+    /// The largest free region in any chunk of this heap
+    pub largest_free_region: u64,
+    pub chunk_count: usize,
+}
+
+impl HeapStats {
+    /// Bytes allocated from the device that aren't used by any resource
+    pub fn free(&self) -> u64 {
+        self.allocated - self.used
+    }
+
+    /// How scattered the free memory is. 0 means it's all in one region, values closer to 1 mean
+    /// that it's split into many small regions
+    pub fn fragmentation(&self) -> f32 {
+        if self.free() == 0 {
+            0.0
+        } else {
+            1.0 - self.largest_free_region as f32 / self.free() as f32
+        }
+    }
+}
+
+/// Which heap each memory type belongs to, shared by the allocators to build their stats
+#[derive(Clone, Debug, Default)]
+pub(crate) struct HeapLayout {
+    heap_sizes: Vec<u64>,
+    memory_heaps: Vec<usize>,
+    properties: Vec<Properties>,
+}
+
+impl HeapLayout {
+    pub fn new(memory_properties: &MemoryProperties) -> Self {
+        Self {
+            heap_sizes: memory_properties.memory_heaps.clone(),
+            memory_heaps: memory_properties
+                .memory_types
+                .iter()
+                .map(|memory_type| memory_type.heap_index)
+                .collect(),
+            properties: memory_properties
+                .memory_types
+                .iter()
+                .map(|memory_type| memory_type.properties)
+                .collect(),
+        }
+    }
+
+    pub fn heap(&self, memory_id: MemoryTypeId) -> usize {
+        self.memory_heaps.get(memory_id.0).cloned().unwrap_or(0)
+    }
+
+    pub fn properties(&self, memory_id: MemoryTypeId) -> Properties {
+        self.properties.get(memory_id.0).cloned().unwrap_or_else(Properties::empty)
+    }
+
+    pub fn heap_size(&self, heap: usize) -> Option<u64> {
+        self.heap_sizes.get(heap).cloned()
+    }
+
+    /// Empty stats for every heap
+    pub fn stats(&self, budget: impl Fn(usize) -> u64) -> Vec<HeapStats> {
+        self.heap_sizes
+            .iter()
+            .enumerate()
+            .map(|(heap, &size)| HeapStats {
+                heap,
+                size,
+                budget: budget(heap),
+                ..HeapStats::default()
+            })
+            .collect()
+    }
+
+    /// Adds the usage of `chunk` to the stats of its heap
+    pub fn record<B: Backend, D: Device<B>, C: Chunk<B, D>>(&self, stats: &mut [HeapStats], chunk: &C) {
+        if let Some(heap) = stats.get_mut(self.heap(chunk.memory_id())) {
+            heap.allocated += u64::from(chunk.size());
+            heap.used += u64::from(chunk.size() - chunk.free());
+            heap.largest_free_region = heap.largest_free_region.max(u64::from(chunk.largest_free_region()));
+            heap.chunk_count += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn it_should_not_be_fragmented_without_free_memory() {
+        let stats = HeapStats {
+            allocated: 100,
+            used: 100,
+            ..HeapStats::default()
+        };
+        assert_eq!(0.0, stats.fragmentation());
+    }
+
+    #[test]
+    fn it_should_compare_the_largest_region_to_the_free_memory() {
+        let stats = HeapStats {
+            allocated: 1000,
+            used: 600,
+            largest_free_region: 100,
+            ..HeapStats::default()
+        };
+        assert_eq!(400, stats.free());
+        assert_eq!(0.75, stats.fragmentation());
+    }
+}
@@ -0,0 +1,381 @@
+use crate::allocator::align_up;
+use crate::allocator::next_chunk_id;
+use crate::allocator::Chunk;
+use crate::allocator::GpuAllocator;
+use crate::allocator::HeapLayout;
+use crate::allocator::HeapStats;
+use crate::allocator::Mapping;
+use crate::allocator::Memory;
+use crate::allocator::UnusedChunks;
+use crate::errors::AllocationError;
+use failure::Error;
+use gfx_hal::adapter::MemoryProperties;
+use gfx_hal::memory::Properties;
+use gfx_hal::Backend;
+use gfx_hal::Device;
+use gfx_hal::MemoryTypeId;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// A chunk that hands out memory by bumping an offset. Freeing memory doesn't make it available
+/// again, the chunk has to be reset once everything allocated from it has been freed. Once an
+/// allocation didn't fit the chunk is full, and nothing is allocated from it until it's reset
+#[derive(Debug)]
+pub struct LinearChunk<B: Backend, D: Device<B>> {
+    pub size: u32,
+    pub offset: u32,
+    pub live: u32,
+    pub full: bool,
+    pub memory_id: MemoryTypeId,
+    pub memory: Arc<B::Memory>,
+    pub device: Arc<D>,
+    pub id: u64,
+    pub(crate) mapping: Mapping,
+}
+
+impl<B: Backend, D: Device<B>> LinearChunk<B, D> {
+    /// Starts over from the beginning of the chunk, if nothing allocated from it is in use
+    pub fn reset(&mut self) -> bool {
+        if self.live == 0 {
+            self.offset = 0;
+            self.full = false;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+impl<B: Backend, D: Device<B>> Chunk<B, D> for LinearChunk<B, D> {
+    fn new(device: Arc<D>, memory_id: MemoryTypeId, properties: Properties, size: u32, id: u64) -> Result<Self, Error> {
+        info!("Allocating new linear memory chunk that is {} bytes long", size);
+        let memory = unsafe { device.allocate_memory(memory_id, u64::from(size)) }.map_err(AllocationError::from)?;
+        let mapping = match unsafe { Mapping::map(&*device, &memory, properties, size) } {
+            Ok(mapping) => mapping,
+            Err(error) => {
+                unsafe { device.free_memory(memory) };
+                return Err(error);
+            }
+        };
+        let memory = Arc::new(memory);
+
+        Ok(Self {
+            size,
+            offset: 0,
+            live: 0,
+            full: false,
+            memory_id,
+            memory,
+            device,
+            id,
+            mapping,
+        })
+    }
+
+    fn allocate(&mut self, size: u32, alignment: u32) -> Result<Memory<B>, Error> {
+        if size == 0 {
+            Err(AllocationError::EmptyAllocation)?;
+        }
+        let start = align_up(self.offset, alignment);
+        let (start, end) = match (start, start.and_then(|start| start.checked_add(size))) {
+            (Some(start), Some(end)) if !self.full && end <= self.size => (start, end),
+            _ => {
+                self.full = true;
+                return Err(AllocationError::NoAvailableRegion.into());
+            }
+        };
+
+        self.offset = end;
+        self.live += 1;
+        Ok(Memory::new(start..end, self.id, Arc::clone(&self.memory)).with_mapping(self.mapping))
+    }
+
+    fn deallocate(&mut self, _memory: &mut Memory<B>) {
+        self.live -= 1;
+    }
+
+    fn memory_id(&self) -> MemoryTypeId {
+        self.memory_id
+    }
+
+    fn size(&self) -> u32 {
+        self.size
+    }
+
+    fn free(&self) -> u32 {
+        self.size - self.offset
+    }
+
+    fn largest_free_region(&self) -> u32 {
+        if self.full {
+            0
+        } else {
+            self.size - self.offset
+        }
+    }
+
+    /// Nothing has been allocated since the chunk was last reset
+    fn is_unused(&self) -> bool {
+        self.offset == 0 && self.live == 0 && Arc::strong_count(&self.memory) == 1
+    }
+
+    fn release(self) {
+        info!("Releasing linear memory chunk that is {} bytes long", self.size);
+        unsafe { self.mapping.unmap(&*self.device, &self.memory) };
+        match Arc::try_unwrap(self.memory) {
+            Ok(memory) => unsafe { self.device.free_memory(memory) },
+            Err(_) => error!("Released a memory chunk that is still in use! This is a memory leak!"),
+        }
+    }
+}
+
+/// Bump allocates memory for data that only lives for a frame, such as per frame vertex data.
+/// Chunks are reset at the start of each frame, after the resources of completed frames have been
+/// freed, once everything allocated from them was freed. A full chunk isn't allocated from until
+/// it has been reset, so it drains even under steady traffic. Chunks that nothing was allocated
+/// from for longer than the release delay are returned to the device
+#[derive(Debug)]
+pub struct LinearAllocator<B: Backend = backend::Backend, D: Device<B> = backend::Device> {
+    device: Option<Arc<D>>,
+    chunks: Mutex<HashMap<u64, LinearChunk<B, D>>>,
+    unused: Mutex<UnusedChunks>,
+    chunk_size: u32,
+    release_delay: Duration,
+    layout: HeapLayout,
+}
+
+impl<B: Backend, D: Device<B>> LinearAllocator<B, D> {
+    const CHUNK_DEFAULT_SIZE: u32 = 4_194_304; // 4mb
+    const RELEASE_DEFAULT_DELAY: Duration = Duration::from_secs(2);
+
+    pub fn new() -> Self {
+        Self {
+            device: None,
+            chunks: Mutex::new(HashMap::new()),
+            unused: Mutex::new(UnusedChunks::new()),
+            chunk_size: Self::CHUNK_DEFAULT_SIZE,
+            release_delay: Self::RELEASE_DEFAULT_DELAY,
+            layout: HeapLayout::default(),
+        }
+    }
+
+    /// The size of each chunk, defaults to 4 MiB
+    pub fn with_chunk_size(mut self, chunk_size: u32) -> Self {
+        self.chunk_size = chunk_size;
+        self
+    }
+
+    /// How long nothing has to be allocated from a chunk before it's returned to the device,
+    /// defaults to two seconds
+    pub fn with_release_delay(mut self, release_delay: Duration) -> Self {
+        self.release_delay = release_delay;
+        self
+    }
+
+    /// Whether the chunk with the given id belongs to this allocator
+    pub fn owns(&self, chunk_id: u64) -> bool {
+        self.chunks.lock().unwrap().contains_key(&chunk_id)
+    }
+
+    /// Takes out the chunks that have been unused for longer than the release delay, and resets
+    /// the rest. Chunks that were allocated from since the last reset count as used
+    fn reset(&self) -> Vec<LinearChunk<B, D>> {
+        let mut chunks = self.chunks.lock().unwrap();
+        let expired = self.unused.lock().unwrap().expired::<B, D, _>(&mut chunks, self.release_delay);
+        for chunk in chunks.values_mut() {
+            chunk.reset();
+        }
+        expired
+    }
+}
+
+impl<B: Backend, D: Device<B>> Default for LinearAllocator<B, D> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<B: Backend, D: Device<B>> GpuAllocator<B, D> for LinearAllocator<B, D> {
+    fn init(&mut self, device: Arc<D>, memory_properties: &MemoryProperties) {
+        self.device = Some(device);
+        self.layout = HeapLayout::new(memory_properties);
+    }
+
+    fn allocate_memory(&self, memory_id: MemoryTypeId, size: u64, alignment: u64) -> Result<Memory<B>, Error> {
+        // Chunks address their memory with u32 offsets
+        if size > u64::from(u32::max_value()) {
+            Err(AllocationError::TooLarge { requested: size, max: u64::from(u32::max_value()) })?;
+        }
+        if size == 0 {
+            Err(AllocationError::EmptyAllocation)?;
+        }
+        let alignment = alignment.max(1) as u32;
+        let mut lock = self.chunks.lock().unwrap();
+        for chunk in lock.values_mut() {
+            if chunk.memory_id == memory_id {
+                if let Ok(memory) = chunk.allocate(size as u32, alignment) {
+                    return Ok(memory);
+                }
+            }
+        }
+
+        let device = Arc::clone(self.device.as_ref().ok_or(AllocationError::NotInitialized)?);
+        let id = next_chunk_id();
+        let mut chunk = LinearChunk::new(device, memory_id, self.layout.properties(memory_id), self.chunk_size.max(size as u32), id)?;
+        let memory = match chunk.allocate(size as u32, alignment) {
+            Ok(memory) => memory,
+            Err(error) => {
+                chunk.release();
+                return Err(error);
+            }
+        };
+        lock.insert(id, chunk);
+        Ok(memory)
+    }
+
+    fn free_memory(&self, memory: &mut Memory<B>) {
+        let mut lock = self.chunks.lock().unwrap();
+        match lock.get_mut(&memory.chunk_id()) {
+            Some(chunk) => chunk.deallocate(memory),
+            None => error!("Chunk has already been freed! This should not happen! Seems like we are over freeing memory"),
+        }
+        memory.set_freed();
+    }
+
+    fn maintain(&self) {
+        for chunk in self.reset() {
+            chunk.release();
+        }
+    }
+
+    fn stats(&self) -> Vec<HeapStats> {
+        let mut stats = self.layout.stats(|heap| self.layout.heap_size(heap).unwrap_or(0));
+        for chunk in self.chunks.lock().unwrap().values() {
+            self.layout.record::<B, D, _>(&mut stats, chunk);
+        }
+        stats
+    }
+}
+
+impl<B: Backend, D: Device<B>> Drop for LinearAllocator<B, D> {
+    fn drop(&mut self) {
+        for (_, chunk) in self.chunks.lock().unwrap().drain() {
+            chunk.release();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use std::thread::sleep;
+
+    type Allocator = LinearAllocator<gfx_backend_empty::Backend, gfx_backend_empty::Device>;
+
+    fn chunk(size: u32) -> LinearChunk<gfx_backend_empty::Backend, gfx_backend_empty::Device> {
+        LinearChunk {
+            size,
+            offset: 0,
+            live: 0,
+            full: false,
+            memory_id: MemoryTypeId(0),
+            memory: Arc::new(()),
+            device: Arc::new(gfx_backend_empty::Device),
+            id: 0,
+            mapping: Mapping::unmapped(size),
+        }
+    }
+
+    #[test]
+    fn allocate_should_bump_the_offset_respecting_alignment() {
+        let mut chunk = chunk(1000);
+        let mut memory1 = chunk.allocate(10, 1).unwrap();
+        let mut memory2 = chunk.allocate(10, 64).unwrap();
+        memory1.set_freed();
+        memory2.set_freed();
+
+        assert_eq!(0..10, memory1.memory_range());
+        assert_eq!(64..74, memory2.memory_range());
+        assert_eq!(1000 - 74, chunk.free());
+    }
+
+    #[test]
+    fn allocate_should_fail_when_the_chunk_is_full() {
+        let mut chunk = chunk(100);
+        let mut memory = chunk.allocate(80, 1).unwrap();
+        memory.set_freed();
+
+        let error = chunk.allocate(30, 1).unwrap_err();
+        assert_eq!("No available region found", format!("{}", error));
+    }
+
+    #[test]
+    fn reset_should_wait_until_everything_is_freed() {
+        let mut chunk = chunk(100);
+        let mut memory1 = chunk.allocate(50, 1).unwrap();
+        let mut memory2 = chunk.allocate(50, 1).unwrap();
+        memory1.set_freed();
+        memory2.set_freed();
+
+        chunk.deallocate(&mut memory1);
+        assert!(!chunk.reset());
+
+        chunk.deallocate(&mut memory2);
+        assert!(chunk.reset());
+        assert_eq!(100, chunk.free());
+    }
+
+    #[test]
+    fn a_full_chunk_should_not_be_allocated_from_until_it_is_reset() {
+        let mut chunk = chunk(100);
+        let mut memory1 = chunk.allocate(80, 1).unwrap();
+        assert!(chunk.allocate(30, 1).is_err());
+
+        // Small allocations would still fit, but would keep the chunk from draining
+        assert!(chunk.allocate(10, 1).is_err());
+        assert_eq!(0, chunk.largest_free_region());
+
+        chunk.deallocate(&mut memory1);
+        memory1.set_freed();
+        assert!(chunk.reset());
+        let mut memory2 = chunk.allocate(10, 1).unwrap();
+        memory2.set_freed();
+        assert_eq!(0..10, memory2.memory_range());
+    }
+
+    #[test]
+    fn allocate_should_reject_empty_allocations() {
+        let mut chunk = chunk(100);
+        let error = chunk.allocate(0, 1).unwrap_err();
+        assert_eq!(Some(&AllocationError::EmptyAllocation), error.downcast_ref::<AllocationError>());
+    }
+
+    #[test]
+    fn reset_should_only_take_out_chunks_nothing_was_allocated_from_for_long_enough() {
+        let allocator = Allocator::new().with_release_delay(Duration::from_millis(50));
+        // Chunks are put in directly, since the empty backend can't allocate device memory
+        allocator.chunks.lock().unwrap().insert(0, chunk(100));
+
+        let mut memory = allocator.allocate_memory(MemoryTypeId(0), 10, 1).unwrap();
+        allocator.free_memory(&mut memory);
+        drop(memory);
+        assert!(allocator.reset().is_empty());
+        assert_eq!(100, allocator.chunks.lock().unwrap()[&0].free());
+
+        // Allocating again after the delay keeps the chunk around
+        sleep(Duration::from_millis(100));
+        let mut memory = allocator.allocate_memory(MemoryTypeId(0), 10, 1).unwrap();
+        allocator.free_memory(&mut memory);
+        drop(memory);
+        assert!(allocator.reset().is_empty());
+        assert!(allocator.reset().is_empty());
+
+        sleep(Duration::from_millis(100));
+        let expired = allocator.reset();
+        assert_eq!(vec![0], expired.iter().map(|chunk| chunk.id).collect::<Vec<_>>());
+        assert!(!allocator.owns(0));
+    }
+}
@@ -3,8 +3,58 @@ use failure::Error;
 use std::ops::Range;
 use gfx_hal::Device;
 use std::sync::Arc;
-use gfx_hal::mapping::Writer;
+use gfx_hal::memory::Properties;
+use std::mem::size_of;
+use std::ptr;
 use colored::*;
+use crate::errors::AllocationError;
+use crate::allocator::AllocationTracker;
+
+/// A pointer to the start of a persistently mapped chunk
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct MappedPointer(*mut u8);
+
+// The pointer is only written through by the owner of each range
+unsafe impl Send for MappedPointer {}
+unsafe impl Sync for MappedPointer {}
+
+/// How the chunk a `Memory` comes from is mapped
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct Mapping {
+    chunk_size: u32,
+    coherent: bool,
+    pointer: Option<MappedPointer>,
+}
+
+impl Mapping {
+    pub fn unmapped(chunk_size: u32) -> Self {
+        Self {
+            chunk_size,
+            coherent: true,
+            pointer: None,
+        }
+    }
+
+    /// Maps the whole chunk if it's host visible. The mapping lives until `unmap` is called
+    pub unsafe fn map<B: Backend, D: Device<B>>(device: &D, memory: &B::Memory, properties: Properties, chunk_size: u32) -> Result<Self, Error> {
+        let pointer = if properties.contains(Properties::CPU_VISIBLE) {
+            Some(MappedPointer(device.map_memory(memory, 0..u64::from(chunk_size))?))
+        } else {
+            None
+        };
+        Ok(Self {
+            chunk_size,
+            coherent: properties.contains(Properties::COHERENT),
+            pointer,
+        })
+    }
+
+    pub unsafe fn unmap<B: Backend, D: Device<B>>(&self, device: &D, memory: &B::Memory) {
+        if self.pointer.is_some() {
+            device.unmap_memory(memory);
+        }
+    }
+}
 
 #[derive(Debug)]
 pub struct  Memory<B: Backend> {
@@ -12,42 +62,128 @@ pub struct  Memory<B: Backend> {
     chunk_id: u64,
     memory: Arc<B::Memory>,
     is_freed: bool,
-    is_allocated: bool
+    is_allocated: bool,
+    label: Option<String>,
+    tracking: Option<(u64, Arc<AllocationTracker<B>>)>,
+    mapping: Mapping
 }
 
 impl<B: Backend> Memory<B> {
     pub fn new(range: Range<u32>, chunk_id: u64, memory: Arc<B::Memory>) -> Self {
+        let range_end = range.end;
         Self {
             range,
             chunk_id,
             memory,
             is_freed: false,
-            is_allocated: false
+            is_allocated: false,
+            label: None,
+            tracking: None,
+            mapping: Mapping::unmapped(range_end)
+        }
+    }
+
+    /// Names the resource this memory belongs to, it's shown when the memory leaks
+    pub fn set_label<L: Into<String>>(&mut self, label: L) {
+        let label = label.into();
+        if let Some((id, tracker)) = &self.tracking {
+            tracker.set_label(*id, &label);
         }
+        self.label = Some(label);
+    }
+
+    pub fn label(&self) -> Option<&str> {
+        self.label.as_ref().map(String::as_str)
+    }
+
+    pub(crate) fn track(&mut self, tracker: Arc<AllocationTracker<B>>) {
+        let id = tracker.track(self);
+        self.tracking = Some((id, tracker));
+    }
+
+    pub(crate) fn device_memory(&self) -> Arc<B::Memory> {
+        Arc::clone(&self.memory)
     }
 
     pub unsafe fn bind_buffer_memory<D: Device<B>>(&mut self, device: &Arc<D>, buffer: &mut B::Buffer) -> Result<(), Error> {
         if !self.is_freed && !self.is_allocated {
             device.bind_buffer_memory(&self.memory, u64::from(self.range.start), buffer)?;
+            self.is_allocated = true;
             Ok(())
         } else if self.is_freed {
-            bail!("Can't bind to already freed memory!")
+            Err(AllocationError::AlreadyFreed.into())
         } else {
-            bail!("This memory is already allocated")
+            Err(AllocationError::AlreadyBound.into())
         }
     }
 
-    pub unsafe fn acquire_mapping_writer<D: Device<B>, T: Copy>(&mut self, device: &Arc<D>, range: Range<u64>) -> Result<Writer<B, T>, Error> {
-        let start = u64::from(self.range.start) + range.start;
-        let end = u64::from(self.range.end) + range.end;
-        Ok(device.acquire_mapping_writer(&self.memory, start..end)?)
+    /// Copies `data` into the memory at `offset` bytes from its start and flushes it if the memory
+    /// isn't coherent. `atom_size` is `non_coherent_atom_size` from the device limits
+    pub unsafe fn write<D: Device<B>, T: Copy>(&self, device: &Arc<D>, atom_size: u64, offset: u64, data: &[T]) -> Result<(), Error> {
+        let range = offset..offset + (data.len() * size_of::<T>()) as u64;
+        let pointer = self.mapped_pointer(&range)?;
+        ptr::copy_nonoverlapping(data.as_ptr() as *const u8, pointer, (range.end - range.start) as usize);
+        self.flush(device, atom_size, range)
     }
 
-    pub unsafe fn bind_image_memory<D: Device<B>>(&mut self, device: &Arc<D>, offset: u64, image: &mut B::Image) -> Result<(), Error> {
-        device.bind_image_memory(&self.memory, offset, image)?;
+    /// Reads `count` values starting at `offset` bytes from the start of the memory, invalidating
+    /// it first if the memory isn't coherent
+    pub unsafe fn read<D: Device<B>, T: Copy>(&self, device: &Arc<D>, atom_size: u64, offset: u64, count: usize) -> Result<Vec<T>, Error> {
+        let range = offset..offset + (count * size_of::<T>()) as u64;
+        self.invalidate(device, atom_size, range.clone())?;
+        let pointer = self.mapped_pointer(&range)?;
+        let mut data = Vec::with_capacity(count);
+        ptr::copy_nonoverlapping(pointer, data.as_mut_ptr() as *mut u8, (range.end - range.start) as usize);
+        data.set_len(count);
+        Ok(data)
+    }
+
+    /// Makes host writes to `range` visible to the device, does nothing for coherent memory
+    pub unsafe fn flush<D: Device<B>>(&self, device: &Arc<D>, atom_size: u64, range: Range<u64>) -> Result<(), Error> {
+        if !self.mapping.coherent {
+            let range = self.atom_range(&range, atom_size)?;
+            device.flush_mapped_memory_ranges(Some((&*self.memory, range)))?;
+        }
+        Ok(())
+    }
+
+    /// Makes device writes to `range` visible to the host, does nothing for coherent memory
+    pub unsafe fn invalidate<D: Device<B>>(&self, device: &Arc<D>, atom_size: u64, range: Range<u64>) -> Result<(), Error> {
+        if !self.mapping.coherent {
+            let range = self.atom_range(&range, atom_size)?;
+            device.invalidate_mapped_memory_ranges(Some((&*self.memory, range)))?;
+        }
         Ok(())
     }
 
+    fn mapped_pointer(&self, range: &Range<u64>) -> Result<*mut u8, AllocationError> {
+        if self.is_freed {
+            return Err(AllocationError::AlreadyFreed);
+        }
+        let range = sub_range(&self.range, range)?;
+        match self.mapping.pointer {
+            Some(MappedPointer(pointer)) => Ok(unsafe { pointer.add(range.start as usize) }),
+            None => Err(AllocationError::NotHostVisible),
+        }
+    }
+
+    fn atom_range(&self, range: &Range<u64>, atom_size: u64) -> Result<Range<u64>, AllocationError> {
+        let range = sub_range(&self.range, range)?;
+        Ok(align_to_atom(&range, atom_size, u64::from(self.mapping.chunk_size)))
+    }
+
+    pub unsafe fn bind_image_memory<D: Device<B>>(&mut self, device: &Arc<D>, image: &mut B::Image) -> Result<(), Error> {
+        if !self.is_freed && !self.is_allocated {
+            device.bind_image_memory(&self.memory, u64::from(self.range.start), image)?;
+            self.is_allocated = true;
+            Ok(())
+        } else if self.is_freed {
+            Err(AllocationError::AlreadyFreed.into())
+        } else {
+            Err(AllocationError::AlreadyBound.into())
+        }
+    }
+
     pub fn is_freed(&self) -> bool {
         self.is_freed
     }
@@ -64,18 +200,87 @@ impl<B: Backend> Memory<B> {
         self.range.clone()
     }
 
+    pub(crate) fn with_mapping(mut self, mapping: Mapping) -> Self {
+        self.mapping = mapping;
+        self
+    }
+
     pub(crate) fn set_freed(&mut self) {
         self.is_freed = true;
+        if let Some((id, tracker)) = &self.tracking {
+            tracker.untrack(*id);
+        }
     }
 }
 
+/// Turns `range`, relative to the start of `region`, into a range within the chunk
+fn sub_range(region: &Range<u32>, range: &Range<u64>) -> Result<Range<u64>, AllocationError> {
+    let len = u64::from(region.end - region.start);
+    if range.start > range.end || range.end > len {
+        return Err(AllocationError::OutOfBounds {
+            start: range.start,
+            end: range.end,
+            size: len,
+        });
+    }
+    let start = u64::from(region.start);
+    Ok(start + range.start..start + range.end)
+}
+
+/// Widens `range` to multiples of `atom_size`, without going past the end of the chunk
+fn align_to_atom(range: &Range<u64>, atom_size: u64, chunk_size: u64) -> Range<u64> {
+    let atom_size = atom_size.max(1);
+    let start = range.start / atom_size * atom_size;
+    let end = (range.end + atom_size - 1) / atom_size * atom_size;
+    start..end.min(chunk_size)
+}
+
 impl<B: Backend> Drop for Memory<B> {
     fn drop(&mut self) {
         trace!(
             "{}", "Dropping memory".red());
         if !self.is_freed {
-            error!("Memory dropped while still not freed! This is a memory leak!");
-            panic!("Memory dropped while still not freed! This is a memory leak!")
+            match self.tracking.take() {
+                Some((id, tracker)) => tracker.leaked(id, self),
+                None => {
+                    let label = self.label().unwrap_or("unlabeled memory");
+                    error!("Memory dropped while still not freed! This is a memory leak! {}", label);
+                    panic!("Memory dropped while still not freed! This is a memory leak! {}", label)
+                }
+            }
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn sub_range_should_offset_by_the_region_start() {
+        assert_eq!(Ok(1010..1020), sub_range(&(1000..1100), &(10..20)));
+        assert_eq!(Ok(1000..1100), sub_range(&(1000..1100), &(0..100)));
+    }
+
+    #[test]
+    fn sub_range_should_reject_ranges_outside_the_region() {
+        assert_eq!(
+            Err(AllocationError::OutOfBounds { start: 50, end: 101, size: 100 }),
+            sub_range(&(1000..1100), &(50..101))
+        );
+        assert!(sub_range(&(1000..1100), &(20..10)).is_err());
+    }
+
+    #[test]
+    fn align_to_atom_should_widen_to_whole_atoms() {
+        assert_eq!(1024..1280, align_to_atom(&(1030..1200), 256, 4096));
+        assert_eq!(1024..1280, align_to_atom(&(1024..1280), 256, 4096));
+        assert_eq!(10..20, align_to_atom(&(10..20), 0, 4096));
+    }
+
+    #[test]
+    fn align_to_atom_should_stop_at_the_end_of_the_chunk() {
+        assert_eq!(3840..4000, align_to_atom(&(3900..3990), 256, 4000));
+    }
+}
@@ -0,0 +1,59 @@
+use crate::allocator::Chunk;
+use crate::allocator::Memory;
+use crate::errors::AllocationError;
+use failure::Error;
+use gfx_hal::memory::Properties;
+use gfx_hal::MemoryTypeId;
+use std::sync::Arc;
+
+/// A chunk without device memory for testing allocators. It hands out memory from the front and
+/// never reuses it
+pub(crate) struct MockChunk {
+    id: u64,
+    memory_id: MemoryTypeId,
+    size: u32,
+    used: u32,
+    live: usize,
+}
+
+impl Chunk<gfx_backend_empty::Backend, gfx_backend_empty::Device> for MockChunk {
+    fn new(_device: Arc<gfx_backend_empty::Device>, memory_id: MemoryTypeId, _properties: Properties, size: u32, id: u64) -> Result<Self, Error> {
+        Ok(MockChunk { id, memory_id, size, used: 0, live: 0 })
+    }
+
+    fn allocate(&mut self, size: u32, _alignment: u32) -> Result<Memory<gfx_backend_empty::Backend>, Error> {
+        if self.size - self.used < size {
+            Err(AllocationError::NoAvailableRegion)?;
+        }
+        let memory = Memory::new(self.used..self.used + size, self.id, Arc::new(()));
+        self.used += size;
+        self.live += 1;
+        Ok(memory)
+    }
+
+    fn deallocate(&mut self, _memory: &mut Memory<gfx_backend_empty::Backend>) {
+        self.live -= 1;
+    }
+
+    fn memory_id(&self) -> MemoryTypeId {
+        self.memory_id
+    }
+
+    fn size(&self) -> u32 {
+        self.size
+    }
+
+    fn free(&self) -> u32 {
+        self.size - self.used
+    }
+
+    fn largest_free_region(&self) -> u32 {
+        self.size - self.used
+    }
+
+    fn is_unused(&self) -> bool {
+        self.live == 0
+    }
+
+    fn release(self) {}
+}
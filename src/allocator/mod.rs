@@ -1,10 +1,49 @@
 mod gpu_allocator;
+mod allocation_tracker;
+mod composite_allocator;
+mod dedicated_allocator;
 mod default_allocator;
+mod heap_stats;
+mod linear_allocator;
 mod memory;
+mod pool_allocator;
+mod tracking_allocator;
+mod unused_chunks;
+
+#[cfg(test)]
+mod mock_chunk;
+
+pub(crate) use self::default_allocator::align_up;
+pub(crate) use self::heap_stats::HeapLayout;
+pub(crate) use self::memory::Mapping;
+pub(crate) use self::unused_chunks::UnusedChunks;
+
+#[cfg(test)]
+pub(crate) use self::mock_chunk::MockChunk;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
 
 #[doc(inline)]
 pub use self::gpu_allocator::GpuAllocator;
 
+#[doc(inline)]
+pub use self::gpu_allocator::MemoryUsage;
+
+#[doc(inline)]
+pub use self::allocation_tracker::AllocationTracker;
+
+#[doc(inline)]
+pub use self::allocation_tracker::LeakPolicy;
+
+#[doc(inline)]
+pub use self::allocation_tracker::LiveAllocation;
+
+#[doc(inline)]
+pub use self::composite_allocator::CompositeAllocator;
+
+#[doc(inline)]
+pub use self::dedicated_allocator::DedicatedAllocator;
+
 #[doc(inline)]
 pub use self::default_allocator::DefaultGpuAllocator;
 
@@ -12,4 +51,33 @@ pub use self::default_allocator::DefaultGpuAllocator;
 pub use self::default_allocator::DefaultChunk;
 
 #[doc(inline)]
-pub use self::memory::Memory;
\ No newline at end of file
+pub use self::default_allocator::Chunk;
+
+#[doc(inline)]
+pub use self::heap_stats::HeapStats;
+
+#[doc(inline)]
+pub use self::linear_allocator::LinearAllocator;
+
+#[doc(inline)]
+pub use self::linear_allocator::LinearChunk;
+
+#[doc(inline)]
+pub use self::memory::Memory;
+
+#[doc(inline)]
+pub use self::pool_allocator::PoolAllocator;
+
+#[doc(inline)]
+pub use self::pool_allocator::PoolChunk;
+
+#[doc(inline)]
+pub use self::tracking_allocator::TrackingAllocator;
+
+static NEXT_CHUNK_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// Chunk ids are unique across all allocators, so that a `CompositeAllocator` can tell which
+/// allocator a `Memory` came from
+pub(crate) fn next_chunk_id() -> u64 {
+    NEXT_CHUNK_ID.fetch_add(1, Ordering::SeqCst) as u64
+}
@@ -0,0 +1,367 @@
+use crate::allocator::next_chunk_id;
+use crate::allocator::Chunk;
+use crate::allocator::GpuAllocator;
+use crate::allocator::HeapLayout;
+use crate::allocator::HeapStats;
+use crate::allocator::Mapping;
+use crate::allocator::Memory;
+use crate::allocator::UnusedChunks;
+use crate::errors::AllocationError;
+use failure::Error;
+use gfx_hal::adapter::MemoryProperties;
+use gfx_hal::memory::Properties;
+use gfx_hal::Backend;
+use gfx_hal::Device;
+use gfx_hal::MemoryTypeId;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// A chunk split into blocks of the same size. Every allocation takes up a whole block, so the
+/// chunk never fragments
+#[derive(Debug)]
+pub struct PoolChunk<B: Backend, D: Device<B>> {
+    pub block_size: u32,
+    pub blocks: u32,
+    pub free_blocks: Vec<u32>,
+    pub memory_id: MemoryTypeId,
+    pub memory: Arc<B::Memory>,
+    pub device: Arc<D>,
+    pub id: u64,
+    pub(crate) mapping: Mapping,
+}
+
+impl<B: Backend, D: Device<B>> PoolChunk<B, D> {
+    const BLOCK_DEFAULT_SIZE: u32 = 256;
+
+    /// Creates a chunk of `size` bytes split into blocks of `block_size` bytes
+    pub fn with_block_size(device: Arc<D>, memory_id: MemoryTypeId, properties: Properties, size: u32, block_size: u32, id: u64) -> Result<Self, Error> {
+        info!("Allocating new pool memory chunk with {} blocks of {} bytes", size / block_size, block_size);
+        let memory = unsafe { device.allocate_memory(memory_id, u64::from(size)) }.map_err(AllocationError::from)?;
+        let mapping = match unsafe { Mapping::map(&*device, &memory, properties, size) } {
+            Ok(mapping) => mapping,
+            Err(error) => {
+                unsafe { device.free_memory(memory) };
+                return Err(error);
+            }
+        };
+        let memory = Arc::new(memory);
+        let blocks = size / block_size;
+
+        Ok(Self {
+            block_size,
+            blocks,
+            free_blocks: (0..blocks).rev().collect(),
+            memory_id,
+            memory,
+            device,
+            id,
+            mapping,
+        })
+    }
+}
+
+impl<B: Backend, D: Device<B>> Chunk<B, D> for PoolChunk<B, D> {
+    fn new(device: Arc<D>, memory_id: MemoryTypeId, properties: Properties, size: u32, id: u64) -> Result<Self, Error> {
+        Self::with_block_size(device, memory_id, properties, size, Self::BLOCK_DEFAULT_SIZE, id)
+    }
+
+    fn allocate(&mut self, size: u32, alignment: u32) -> Result<Memory<B>, Error> {
+        // Blocks start at multiples of the block size, so they're aligned as long as the alignment
+        // divides the block size
+        if size == 0 || size > self.block_size || self.block_size % alignment != 0 {
+            return Err(AllocationError::NoAvailableRegion.into());
+        }
+        let block = self.free_blocks.pop().ok_or(AllocationError::NoAvailableRegion)?;
+        let start = block * self.block_size;
+        Ok(Memory::new(start..start + size, self.id, Arc::clone(&self.memory)).with_mapping(self.mapping))
+    }
+
+    fn deallocate(&mut self, memory: &mut Memory<B>) {
+        self.free_blocks.push(memory.memory_range().start / self.block_size);
+    }
+
+    fn memory_id(&self) -> MemoryTypeId {
+        self.memory_id
+    }
+
+    fn size(&self) -> u32 {
+        self.blocks * self.block_size
+    }
+
+    fn free(&self) -> u32 {
+        self.free_blocks.len() as u32 * self.block_size
+    }
+
+    fn largest_free_region(&self) -> u32 {
+        if self.free_blocks.is_empty() {
+            0
+        } else {
+            self.block_size
+        }
+    }
+
+    fn is_unused(&self) -> bool {
+        self.free_blocks.len() as u32 == self.blocks && Arc::strong_count(&self.memory) == 1
+    }
+
+    fn release(self) {
+        info!("Releasing pool memory chunk with {} blocks", self.blocks);
+        unsafe { self.mapping.unmap(&*self.device, &self.memory) };
+        match Arc::try_unwrap(self.memory) {
+            Ok(memory) => unsafe { self.device.free_memory(memory) },
+            Err(_) => error!("Released a memory chunk that is still in use! This is a memory leak!"),
+        }
+    }
+}
+
+/// Hands out fixed size blocks, which suits uniform blocks that all have the same size. Requests
+/// larger than a block fail, route them to another allocator with a `CompositeAllocator`. Chunks
+/// that have been unused for longer than the release delay are returned to the device
+#[derive(Debug)]
+pub struct PoolAllocator<B: Backend = backend::Backend, D: Device<B> = backend::Device> {
+    device: Option<Arc<D>>,
+    chunks: Mutex<HashMap<u64, PoolChunk<B, D>>>,
+    unused: Mutex<UnusedChunks>,
+    block_size: u32,
+    blocks_per_chunk: u32,
+    release_delay: Duration,
+    layout: HeapLayout,
+}
+
+impl<B: Backend, D: Device<B>> PoolAllocator<B, D> {
+    const BLOCKS_DEFAULT_PER_CHUNK: u32 = 1024;
+    const RELEASE_DEFAULT_DELAY: Duration = Duration::from_secs(2);
+
+    pub fn new() -> Self {
+        Self {
+            device: None,
+            chunks: Mutex::new(HashMap::new()),
+            unused: Mutex::new(UnusedChunks::new()),
+            block_size: PoolChunk::<B, D>::BLOCK_DEFAULT_SIZE,
+            blocks_per_chunk: Self::BLOCKS_DEFAULT_PER_CHUNK,
+            release_delay: Self::RELEASE_DEFAULT_DELAY,
+            layout: HeapLayout::default(),
+        }
+    }
+
+    /// The size of each block, defaults to 256 bytes which satisfies the uniform buffer alignment
+    /// of every device
+    pub fn with_block_size(mut self, block_size: u32) -> Self {
+        self.block_size = block_size;
+        self
+    }
+
+    /// How many blocks each chunk holds, defaults to 1024
+    pub fn with_blocks_per_chunk(mut self, blocks_per_chunk: u32) -> Self {
+        self.blocks_per_chunk = blocks_per_chunk;
+        self
+    }
+
+    /// How long a chunk has to be unused before it's returned to the device, defaults to two
+    /// seconds
+    pub fn with_release_delay(mut self, release_delay: Duration) -> Self {
+        self.release_delay = release_delay;
+        self
+    }
+
+    pub fn block_size(&self) -> u32 {
+        self.block_size
+    }
+
+    /// Whether the chunk with the given id belongs to this allocator
+    pub fn owns(&self, chunk_id: u64) -> bool {
+        self.chunks.lock().unwrap().contains_key(&chunk_id)
+    }
+
+    /// Takes out the chunks that have been unused for longer than the release delay
+    fn expired(&self) -> Vec<PoolChunk<B, D>> {
+        let mut chunks = self.chunks.lock().unwrap();
+        self.unused.lock().unwrap().expired::<B, D, _>(&mut chunks, self.release_delay)
+    }
+}
+
+impl<B: Backend, D: Device<B>> Default for PoolAllocator<B, D> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<B: Backend, D: Device<B>> GpuAllocator<B, D> for PoolAllocator<B, D> {
+    fn init(&mut self, device: Arc<D>, memory_properties: &MemoryProperties) {
+        self.device = Some(device);
+        self.layout = HeapLayout::new(memory_properties);
+    }
+
+    fn allocate_memory(&self, memory_id: MemoryTypeId, size: u64, alignment: u64) -> Result<Memory<B>, Error> {
+        let alignment = alignment.max(1) as u32;
+        if size == 0 {
+            Err(AllocationError::EmptyAllocation)?;
+        }
+        if size > u64::from(self.block_size) || self.block_size % alignment != 0 {
+            Err(AllocationError::NoAvailableRegion)?;
+        }
+
+        let mut lock = self.chunks.lock().unwrap();
+        for chunk in lock.values_mut() {
+            if chunk.memory_id == memory_id {
+                if let Ok(memory) = chunk.allocate(size as u32, alignment) {
+                    return Ok(memory);
+                }
+            }
+        }
+
+        let device = Arc::clone(self.device.as_ref().ok_or(AllocationError::NotInitialized)?);
+        let id = next_chunk_id();
+        let mut chunk = PoolChunk::with_block_size(
+            device,
+            memory_id,
+            self.layout.properties(memory_id),
+            self.block_size * self.blocks_per_chunk,
+            self.block_size,
+            id,
+        )?;
+        let memory = match chunk.allocate(size as u32, alignment) {
+            Ok(memory) => memory,
+            Err(error) => {
+                chunk.release();
+                return Err(error);
+            }
+        };
+        lock.insert(id, chunk);
+        Ok(memory)
+    }
+
+    fn free_memory(&self, memory: &mut Memory<B>) {
+        let mut lock = self.chunks.lock().unwrap();
+        match lock.get_mut(&memory.chunk_id()) {
+            Some(chunk) => chunk.deallocate(memory),
+            None => error!("Chunk has already been freed! This should not happen! Seems like we are over freeing memory"),
+        }
+        memory.set_freed();
+    }
+
+    fn maintain(&self) {
+        for chunk in self.expired() {
+            chunk.release();
+        }
+    }
+
+    fn stats(&self) -> Vec<HeapStats> {
+        let mut stats = self.layout.stats(|heap| self.layout.heap_size(heap).unwrap_or(0));
+        for chunk in self.chunks.lock().unwrap().values() {
+            self.layout.record::<B, D, _>(&mut stats, chunk);
+        }
+        stats
+    }
+}
+
+impl<B: Backend, D: Device<B>> Drop for PoolAllocator<B, D> {
+    fn drop(&mut self) {
+        for (_, chunk) in self.chunks.lock().unwrap().drain() {
+            chunk.release();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use pretty_assertions::assert_ne;
+    use std::thread::sleep;
+
+    type Allocator = PoolAllocator<gfx_backend_empty::Backend, gfx_backend_empty::Device>;
+
+    fn chunk(blocks: u32) -> PoolChunk<gfx_backend_empty::Backend, gfx_backend_empty::Device> {
+        PoolChunk {
+            block_size: 256,
+            blocks,
+            free_blocks: (0..blocks).rev().collect(),
+            memory_id: MemoryTypeId(0),
+            memory: Arc::new(()),
+            device: Arc::new(gfx_backend_empty::Device),
+            id: 0,
+            mapping: Mapping::unmapped(blocks * 256),
+        }
+    }
+
+    #[test]
+    fn allocate_should_hand_out_separate_blocks() {
+        let mut chunk = chunk(4);
+        let mut memory1 = chunk.allocate(100, 64).unwrap();
+        let mut memory2 = chunk.allocate(256, 256).unwrap();
+        memory1.set_freed();
+        memory2.set_freed();
+
+        assert_eq!(0..100, memory1.memory_range());
+        assert_eq!(256..512, memory2.memory_range());
+        assert_ne!(memory1.memory_range().start, memory2.memory_range().start);
+        assert_eq!(512, chunk.free());
+    }
+
+    #[test]
+    fn deallocate_should_make_the_block_available_again() {
+        let mut chunk = chunk(1);
+        let mut memory = chunk.allocate(10, 1).unwrap();
+        memory.set_freed();
+        assert!(chunk.allocate(10, 1).is_err());
+
+        chunk.deallocate(&mut memory);
+        let mut memory = chunk.allocate(10, 1).unwrap();
+        memory.set_freed();
+        assert_eq!(0..10, memory.memory_range());
+    }
+
+    #[test]
+    fn allocate_should_reject_what_doesnt_fit_in_a_block() {
+        let mut chunk = chunk(4);
+        assert!(chunk.allocate(300, 1).is_err());
+        assert!(chunk.allocate(10, 512).is_err());
+        assert_eq!(1024, chunk.free());
+    }
+
+    #[test]
+    fn expired_should_only_take_out_chunks_that_have_been_unused_for_long_enough() {
+        let allocator = Allocator::new().with_release_delay(Duration::from_millis(50));
+        // Chunks are put in directly, since the empty backend can't allocate device memory
+        allocator.chunks.lock().unwrap().insert(0, chunk(4));
+
+        let mut memory = allocator.allocate_memory(MemoryTypeId(0), 100, 64).unwrap();
+        assert_eq!(0, memory.chunk_id());
+        assert!(allocator.expired().is_empty());
+
+        allocator.free_memory(&mut memory);
+        drop(memory);
+        assert!(allocator.expired().is_empty());
+        assert!(allocator.owns(0));
+
+        sleep(Duration::from_millis(100));
+        let expired = allocator.expired();
+        assert_eq!(vec![0], expired.iter().map(|chunk| chunk.id).collect::<Vec<_>>());
+        assert!(!allocator.owns(0));
+    }
+
+    #[test]
+    fn expired_should_keep_chunks_that_are_used_again() {
+        let allocator = Allocator::new().with_release_delay(Duration::from_millis(50));
+        allocator.chunks.lock().unwrap().insert(0, chunk(4));
+
+        let mut memory = allocator.allocate_memory(MemoryTypeId(0), 100, 64).unwrap();
+        allocator.free_memory(&mut memory);
+        drop(memory);
+        assert!(allocator.expired().is_empty());
+
+        sleep(Duration::from_millis(100));
+        let mut memory = allocator.allocate_memory(MemoryTypeId(0), 100, 64).unwrap();
+        assert!(allocator.expired().is_empty());
+        allocator.free_memory(&mut memory);
+        drop(memory);
+        assert!(allocator.expired().is_empty());
+        assert!(allocator.owns(0));
+
+        // Releasing the chunk would free its memory on the empty backend
+        allocator.chunks.lock().unwrap().clear();
+    }
+}
@@ -0,0 +1,86 @@
+use crate::allocator::AllocationTracker;
+use crate::allocator::GpuAllocator;
+use crate::allocator::HeapStats;
+use crate::allocator::LeakPolicy;
+use crate::allocator::LiveAllocation;
+use crate::allocator::Memory;
+use crate::allocator::MemoryUsage;
+use failure::Error;
+use gfx_hal::adapter::MemoryProperties;
+use gfx_hal::Backend;
+use gfx_hal::Device;
+use gfx_hal::MemoryTypeId;
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+/// Wraps another allocator and tracks every `Memory` it hands out, so that leaks can be traced
+/// back to the resource and, in debug builds, the place they were allocated. Live allocations are
+/// reported when the allocator is dropped
+#[derive(Debug)]
+pub struct TrackingAllocator<A: GpuAllocator<B, D>, B: Backend = backend::Backend, D: Device<B> = backend::Device> {
+    inner: A,
+    tracker: Arc<AllocationTracker<B>>,
+    phantom: PhantomData<D>,
+}
+
+impl<A: GpuAllocator<B, D>, B: Backend, D: Device<B>> TrackingAllocator<A, B, D> {
+    pub fn new(inner: A, policy: LeakPolicy) -> Self {
+        Self {
+            inner,
+            tracker: Arc::new(AllocationTracker::new(policy)),
+            phantom: PhantomData,
+        }
+    }
+
+    pub fn tracker(&self) -> &AllocationTracker<B> {
+        &self.tracker
+    }
+
+    fn reclaim(&self) {
+        for mut memory in self.tracker.take_reclaimed() {
+            self.inner.free_memory(&mut memory);
+        }
+    }
+}
+
+impl<A: GpuAllocator<B, D>, B: Backend, D: Device<B>> GpuAllocator<B, D> for TrackingAllocator<A, B, D> {
+    fn init(&mut self, device: Arc<D>, memory_properties: &MemoryProperties) {
+        self.inner.init(device, memory_properties);
+    }
+
+    fn allocate_memory(&self, memory_id: MemoryTypeId, size: u64, alignment: u64) -> Result<Memory<B>, Error> {
+        let mut memory = self.inner.allocate_memory(memory_id, size, alignment)?;
+        memory.track(Arc::clone(&self.tracker));
+        Ok(memory)
+    }
+
+    fn allocate_memory_for(&self, usage: MemoryUsage, memory_id: MemoryTypeId, size: u64, alignment: u64) -> Result<Memory<B>, Error> {
+        let mut memory = self.inner.allocate_memory_for(usage, memory_id, size, alignment)?;
+        memory.track(Arc::clone(&self.tracker));
+        Ok(memory)
+    }
+
+    fn free_memory(&self, memory: &mut Memory<B>) {
+        self.inner.free_memory(memory);
+    }
+
+    fn maintain(&self) {
+        self.reclaim();
+        self.inner.maintain();
+    }
+
+    fn stats(&self) -> Vec<HeapStats> {
+        self.inner.stats()
+    }
+
+    fn live_allocations(&self) -> Vec<LiveAllocation> {
+        self.tracker.live_allocations()
+    }
+}
+
+impl<A: GpuAllocator<B, D>, B: Backend, D: Device<B>> Drop for TrackingAllocator<A, B, D> {
+    fn drop(&mut self) {
+        self.reclaim();
+        self.tracker.report();
+    }
+}
@@ -0,0 +1,44 @@
+use crate::allocator::Chunk;
+use gfx_hal::Backend;
+use gfx_hal::Device;
+use std::collections::HashMap;
+use std::time::Duration;
+use std::time::Instant;
+
+/// Remembers since when each chunk has been unused, so that allocators can hold on to chunks for a
+/// while before returning them to the device
+#[derive(Debug, Default)]
+pub(crate) struct UnusedChunks {
+    since: HashMap<u64, Instant>,
+}
+
+impl UnusedChunks {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Removes the chunks that have been unused for at least `release_delay` from `chunks` and
+    /// returns them. Chunks that are used again start over
+    pub fn expired<B: Backend, D: Device<B>, C: Chunk<B, D>>(&mut self, chunks: &mut HashMap<u64, C>, release_delay: Duration) -> Vec<C> {
+        let now = Instant::now();
+        let mut expired = Vec::new();
+        for (id, chunk) in chunks.iter() {
+            if chunk.is_unused() {
+                let since = *self.since.entry(*id).or_insert(now);
+                if now.duration_since(since) >= release_delay {
+                    expired.push(*id);
+                }
+            } else {
+                self.since.remove(id);
+            }
+        }
+
+        expired
+            .into_iter()
+            .filter_map(|id| {
+                self.since.remove(&id);
+                chunks.remove(&id)
+            })
+            .collect()
+    }
+}
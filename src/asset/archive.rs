@@ -0,0 +1,393 @@
+use crate::asset::bytes::ByteReader;
+use crate::asset::bytes::WriteBytes;
+use crate::errors::AssetError;
+use failure::Error;
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::io::Write;
+use std::path::Component;
+use std::path::Path;
+use std::sync::Mutex;
+
+const MAGIC: &[u8; 4] = b"SSPK";
+const VERSION: u32 = 2;
+
+/// The magic, the version and the offset of the index
+const HEADER_SIZE: usize = 4 + 4 + 8;
+
+/// The most deflate can compress data by, each stored byte expands to at most this many bytes
+const MAX_DEFLATE_RATIO: u64 = 1032;
+
+/// What an archive entry holds, which decides how the `AssetServer` reads it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EntryKind {
+    /// The file as it was on disk
+    Raw,
+
+    /// A decoded image, see `PackedTexture`
+    Texture,
+
+    /// A mesh converted to the binary mesh format, see `PackedMesh`
+    Mesh,
+}
+
+impl EntryKind {
+    fn from_u8(value: u8) -> Result<Self, Error> {
+        match value {
+            0 => Ok(EntryKind::Raw),
+            1 => Ok(EntryKind::Texture),
+            2 => Ok(EntryKind::Mesh),
+            _ => Err(AssetError::InvalidArchive("unknown entry kind").into()),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Deflate,
+}
+
+impl Compression {
+    fn from_u8(value: u8) -> Result<Self, Error> {
+        match value {
+            0 => Ok(Compression::None),
+            1 => Ok(Compression::Deflate),
+            _ => Err(AssetError::InvalidArchive("unknown compression").into()),
+        }
+    }
+}
+
+/// Where an entry is stored in the archive
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ArchiveEntry {
+    kind: EntryKind,
+    compression: Compression,
+    offset: u64,
+    stored_size: u64,
+    size: u64,
+}
+
+impl ArchiveEntry {
+    pub fn kind(&self) -> EntryKind {
+        self.kind
+    }
+
+    pub fn compression(&self) -> Compression {
+        self.compression
+    }
+
+    /// The size of the entry once read
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// The size of the entry in the archive, after compression
+    pub fn stored_size(&self) -> u64 {
+        self.stored_size
+    }
+}
+
+/// Writes an indexed asset archive, see `Archive`. Entries are written as they are added, the
+/// index is written by `finish`
+#[derive(Debug)]
+pub struct ArchiveWriter<W: Write + Seek> {
+    writer: W,
+    entries: Vec<(String, ArchiveEntry)>,
+    offset: u64,
+}
+
+impl<W: Write + Seek> ArchiveWriter<W> {
+    pub fn new(mut writer: W) -> Result<Self, Error> {
+        writer.write_all(&[0; HEADER_SIZE])?;
+        Ok(Self {
+            writer,
+            entries: vec![],
+            offset: HEADER_SIZE as u64,
+        })
+    }
+
+    /// Adds an entry at the given path, relative to the asset root. Entries that don't get any
+    /// smaller from compression are stored as they are
+    pub fn add<P: AsRef<Path>>(&mut self, path: P, kind: EntryKind, data: &[u8], compression: Compression) -> Result<&ArchiveEntry, Error> {
+        let name = entry_name(path.as_ref())?;
+        if self.entries.iter().any(|(existing, _)| *existing == name) {
+            Err(AssetError::InvalidArchive("the same path was added twice"))?;
+        }
+
+        let compressed = match compression {
+            Compression::None => None,
+            Compression::Deflate => {
+                let mut encoder = DeflateEncoder::new(Vec::new(), flate2::Compression::best());
+                encoder.write_all(data)?;
+                Some(encoder.finish()?).filter(|compressed| compressed.len() < data.len())
+            }
+        };
+        let (compression, stored) = match &compressed {
+            Some(compressed) => (Compression::Deflate, compressed.as_slice()),
+            None => (Compression::None, data),
+        };
+
+        self.writer.write_all(stored)?;
+        self.entries.push((
+            name,
+            ArchiveEntry {
+                kind,
+                compression,
+                offset: self.offset,
+                stored_size: stored.len() as u64,
+                size: data.len() as u64,
+            },
+        ));
+        self.offset += stored.len() as u64;
+        Ok(&self.entries[self.entries.len() - 1].1)
+    }
+
+    /// Writes the index and the header, and returns the writer
+    pub fn finish(mut self) -> Result<W, Error> {
+        let mut index = vec![];
+        index.put_u32(self.entries.len() as u32);
+        for (name, entry) in &self.entries {
+            index.put_bytes(name.as_bytes());
+            index.put_u8(entry.kind as u8);
+            index.put_u8(entry.compression as u8);
+            index.put_u64(entry.offset);
+            index.put_u64(entry.stored_size);
+            index.put_u64(entry.size);
+        }
+        self.writer.write_all(&index)?;
+
+        let mut header = Vec::with_capacity(HEADER_SIZE);
+        header.extend_from_slice(MAGIC);
+        header.put_u32(VERSION);
+        header.put_u64(self.offset);
+        self.writer.seek(SeekFrom::Start(0))?;
+        self.writer.write_all(&header)?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// An asset archive, built by the `starstruck-pack` tool for shipping builds. Point
+/// `StarstruckBuilder::with_asset_root` at an archive and it's mounted in place of the asset
+/// directory. Paths are looked up relative to the asset root, just like files on disk
+#[derive(Debug)]
+pub struct Archive<R: Read + Seek = BufReader<File>> {
+    reader: Mutex<R>,
+    entries: HashMap<String, ArchiveEntry>,
+}
+
+impl Archive {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        Self::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: Read + Seek> Archive<R> {
+    /// Reads the index of the archive, the entries are read when asked for
+    pub fn new(mut reader: R) -> Result<Self, Error> {
+        let mut header = [0; HEADER_SIZE];
+        reader.read_exact(&mut header)?;
+        let mut header = ByteReader::new(&header);
+        if header.take(4)? != MAGIC {
+            Err(AssetError::InvalidArchive("not an asset archive"))?;
+        }
+        if header.u32()? != VERSION {
+            Err(AssetError::InvalidArchive("unsupported version"))?;
+        }
+        let index_offset = header.u64()?;
+        let archive_size = reader.seek(SeekFrom::End(0))?;
+        if index_offset < HEADER_SIZE as u64 || index_offset > archive_size {
+            Err(AssetError::InvalidArchive("index is outside of the archive"))?;
+        }
+
+        let mut index = vec![];
+        reader.seek(SeekFrom::Start(index_offset))?;
+        reader.read_to_end(&mut index)?;
+        let mut index = ByteReader::new(&index);
+        let count = index.u32()?;
+        let mut entries = HashMap::new();
+        for _ in 0..count {
+            let name = String::from_utf8(index.bytes()?.to_vec())
+                .map_err(|_| AssetError::InvalidArchive("entry path isn't utf-8"))?;
+            let entry = ArchiveEntry {
+                kind: EntryKind::from_u8(index.u8()?)?,
+                compression: Compression::from_u8(index.u8()?)?,
+                offset: index.u64()?,
+                stored_size: index.u64()?,
+                size: index.u64()?,
+            };
+            let end = entry
+                .offset
+                .checked_add(entry.stored_size)
+                .ok_or(AssetError::InvalidArchive("entry size overflows"))?;
+            if entry.offset < HEADER_SIZE as u64 || end > index_offset {
+                Err(AssetError::InvalidArchive("entry overlaps the index"))?;
+            }
+            // Deflate can't shrink data more than about a thousand times, so a larger claimed size
+            // is a damaged index rather than something worth allocating for
+            let max_size = match entry.compression {
+                Compression::None => entry.stored_size,
+                Compression::Deflate => entry.stored_size.saturating_mul(MAX_DEFLATE_RATIO),
+            };
+            if entry.size > max_size {
+                Err(AssetError::InvalidArchive("entry size doesn't match the index"))?;
+            }
+            entries.insert(name, entry);
+        }
+
+        Ok(Self {
+            reader: Mutex::new(reader),
+            entries,
+        })
+    }
+
+    /// The entry at the path, paths that leave the asset root are never in the archive
+    pub fn entry<P: AsRef<Path>>(&self, path: P) -> Option<&ArchiveEntry> {
+        entry_name(path.as_ref()).ok().and_then(|name| self.entries.get(&name))
+    }
+
+    /// The paths of every entry, in no particular order
+    pub fn paths(&self) -> impl Iterator<Item = &str> {
+        self.entries.keys().map(String::as_str)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Reads and decompresses an entry
+    pub fn read<P: AsRef<Path>>(&self, path: P) -> Result<Vec<u8>, Error> {
+        let path = path.as_ref();
+        let entry = self
+            .entries
+            .get(&entry_name(path)?)
+            .ok_or_else(|| AssetError::NotInArchive(path.to_path_buf()))?;
+
+        let mut stored = vec![0; entry.stored_size as usize];
+        {
+            let mut reader = self.reader.lock().unwrap();
+            reader.seek(SeekFrom::Start(entry.offset))?;
+            reader.read_exact(&mut stored)?;
+        }
+
+        let data = match entry.compression {
+            Compression::None => stored,
+            Compression::Deflate => {
+                let mut data = Vec::with_capacity(entry.size as usize);
+                // Never inflate more than the index claims, even when the stored data is damaged
+                DeflateDecoder::new(stored.as_slice())
+                    .take(entry.size + 1)
+                    .read_to_end(&mut data)?;
+                data
+            }
+        };
+        if data.len() as u64 != entry.size {
+            Err(AssetError::InvalidArchive("entry size doesn't match the index"))?;
+        }
+        Ok(data)
+    }
+}
+
+/// Archive paths are always separated by `/`, no matter what platform packed them. Paths that
+/// go up with `..` are refused rather than looked up somewhere else
+fn entry_name(path: &Path) -> Result<String, Error> {
+    let mut names = vec![];
+    for component in path.components() {
+        match component {
+            Component::Normal(name) => names.push(name.to_string_lossy()),
+            Component::ParentDir => Err(AssetError::InvalidPath(path.to_path_buf()))?,
+            _ => {}
+        }
+    }
+    Ok(names.join("/"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use std::io::Cursor;
+
+    fn archive(entries: &[(&str, &[u8], Compression)]) -> Archive<Cursor<Vec<u8>>> {
+        let mut writer = ArchiveWriter::new(Cursor::new(vec![])).unwrap();
+        for (path, data, compression) in entries {
+            writer.add(path, EntryKind::Raw, data, *compression).unwrap();
+        }
+        let mut cursor = writer.finish().unwrap();
+        cursor.set_position(0);
+        Archive::new(cursor).unwrap()
+    }
+
+    #[test]
+    fn it_should_read_back_every_entry() {
+        let repeated = vec![b'a'; 4096];
+        let archive = archive(&[
+            ("shaders/basic.vert", b"void main() {}", Compression::None),
+            ("textures/sand.png", &repeated, Compression::Deflate),
+        ]);
+
+        assert_eq!(2, archive.len());
+        assert_eq!(b"void main() {}".to_vec(), archive.read("shaders/basic.vert").unwrap());
+        assert_eq!(repeated, archive.read(Path::new("textures").join("sand.png")).unwrap());
+
+        let entry = archive.entry("textures/sand.png").unwrap();
+        assert_eq!(Compression::Deflate, entry.compression());
+        assert!(entry.stored_size() < entry.size());
+    }
+
+    #[test]
+    fn it_should_store_entries_that_dont_compress_as_they_are() {
+        let archive = archive(&[("tiny.txt", b"ab", Compression::Deflate)]);
+        assert_eq!(Compression::None, archive.entry("tiny.txt").unwrap().compression());
+        assert_eq!(b"ab".to_vec(), archive.read("./tiny.txt").unwrap());
+    }
+
+    #[test]
+    fn it_should_report_missing_entries_and_foreign_files() {
+        let archive = archive(&[]);
+        assert_eq!(
+            "missing.png is not in the asset archive",
+            format!("{}", archive.read("missing.png").unwrap_err())
+        );
+
+        let error = Archive::new(Cursor::new(b"PK\x03\x04 definitely a zip".to_vec())).unwrap_err();
+        assert_eq!("The asset archive is invalid: not an asset archive", format!("{}", error));
+    }
+
+    #[test]
+    fn it_should_refuse_paths_that_leave_the_asset_root() {
+        let archive = archive(&[("textures/sand.png", b"sand", Compression::None)]);
+        assert_eq!(None, archive.entry("shaders/../textures/sand.png"));
+        assert_eq!(
+            "../sand.png leaves the asset root",
+            format!("{}", archive.read("../sand.png").unwrap_err())
+        );
+
+        let mut writer = ArchiveWriter::new(Cursor::new(vec![])).unwrap();
+        assert!(writer.add("../outside.txt", EntryKind::Raw, b"", Compression::None).is_err());
+    }
+
+    #[test]
+    fn it_should_refuse_entries_with_sizes_the_file_cant_hold() {
+        let mut writer = ArchiveWriter::new(Cursor::new(vec![])).unwrap();
+        writer.add("tiny.txt", EntryKind::Raw, b"ab", Compression::None).unwrap();
+        let mut bytes = writer.finish().unwrap().into_inner();
+
+        // The stored size is the second to last u64 of the index
+        let stored_size = bytes.len() - 16;
+        bytes[stored_size..stored_size + 8].copy_from_slice(&[0xff; 8]);
+        let error = Archive::new(Cursor::new(bytes)).unwrap_err();
+        assert_eq!("The asset archive is invalid: entry size overflows", format!("{}", error));
+    }
+}
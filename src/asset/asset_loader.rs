@@ -0,0 +1,287 @@
+use crate::asset::AssetReloader;
+use crate::asset::AssetSource;
+use crate::asset::Handle;
+use crate::errors::AssetError;
+use failure::Error;
+use futures::lazy;
+use futures::Future;
+use futures::IntoFuture;
+use std::any::Any;
+use std::any::TypeId;
+use std::collections::HashMap;
+use std::fmt;
+use std::fmt::Debug;
+use std::fmt::Formatter;
+use std::panic::AssertUnwindSafe;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::executor::thread_pool::Builder;
+use tokio::executor::thread_pool::ThreadPool;
+
+/// The path, the type and the variant of an asset. The variant tells apart assets of the same type
+/// that are loaded from one path in different ways, such as the stages of a shader
+type AssetKey = (PathBuf, TypeId, usize);
+
+/// Hands a freshly loaded asset to the listeners of its handle
+type Notify = Box<Fn() + Send>;
+
+/// The part of the `AssetServer` that doesn't touch the GPU. It hands out a handle per asset, runs
+/// the loaders on a pool of background threads and keeps track of the assets that failed
+pub(crate) struct AssetLoader {
+    source: AssetSource,
+    handles: Mutex<HashMap<AssetKey, Box<Any + Send + Sync>>>,
+    failures: Arc<Mutex<HashMap<PathBuf, String>>>,
+    loaded: Arc<Mutex<Vec<Notify>>>,
+    reloader: AssetReloader,
+    // The threads are only started once the first asset is loaded
+    pool: Mutex<Option<ThreadPool>>,
+}
+
+impl AssetLoader {
+    pub fn new(source: AssetSource) -> Self {
+        let failures = Arc::new(Mutex::new(HashMap::new()));
+        Self {
+            source,
+            handles: Mutex::new(HashMap::new()),
+            reloader: AssetReloader::new(Duration::from_millis(500), Arc::clone(&failures)),
+            failures,
+            loaded: Arc::new(Mutex::new(vec![])),
+            pool: Mutex::new(None),
+        }
+    }
+
+    pub fn source(&self) -> &AssetSource {
+        &self.source
+    }
+
+    /// Returns the handle of the asset, and starts loading it with `loader` on the pool if this is
+    /// the first time it's asked for
+    pub fn load<T, R, L>(&self, path: &Path, variant: usize, loader: L) -> Handle<T>
+    where
+        T: Send + Sync + 'static,
+        R: IntoFuture<Item = T, Error = Error>,
+        R::Future: Send + 'static,
+        L: Fn(PathBuf) -> R + Send + Sync + 'static,
+    {
+        let key = (path.to_path_buf(), TypeId::of::<T>(), variant);
+        let mut handles = self.handles.lock().unwrap();
+        if let Some(handle) = handles.get(&key).and_then(|handle| handle.downcast_ref::<Handle<T>>()) {
+            return handle.clone();
+        }
+
+        info!("Loading asset {}", path.display());
+        let handle = Handle::new(path.to_path_buf());
+        handles.insert(key, Box::new(handle.clone()));
+
+        let loader = Arc::new(loader);
+        if cfg!(debug_assertions) {
+            if let Some(file) = self.source.file(path) {
+                self.reloader.watch(file, handle.clone(), Arc::clone(&loader));
+            }
+        }
+
+        let resolver = handle.clone();
+        let failures = Arc::clone(&self.failures);
+        let loaded = Arc::clone(&self.loaded);
+        let path = path.to_path_buf();
+        self.spawn(catch_panics(lazy(move || loader(path))).then(move |result| {
+            match result {
+                Ok(asset) => {
+                    resolver.resolve(Ok(Arc::new(asset)));
+                    let notify = resolver.clone();
+                    loaded.lock().unwrap().push(Box::new(move || notify.notify()));
+                }
+                Err(error) => {
+                    let reason = format!("{}", error);
+                    error!("Failed to load asset {}: {}", resolver.path().display(), reason);
+                    failures.lock().unwrap().insert(resolver.path().to_path_buf(), reason.clone());
+                    resolver.resolve(Err(reason));
+                }
+            }
+            Ok(())
+        }));
+        handle
+    }
+
+    /// The assets that failed to load, along with the reason
+    pub fn failures(&self) -> Vec<(PathBuf, String)> {
+        self.failures
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(path, reason)| (path.clone(), reason.clone()))
+            .collect()
+    }
+
+    /// Starts reloading the assets whose files have changed, returns whether any loaded or
+    /// reloaded assets are waiting to be handed to their listeners
+    pub fn poll(&self) -> bool {
+        for reload in self.reloader.poll() {
+            self.spawn(reload);
+        }
+        !self.loaded.lock().unwrap().is_empty() || self.reloader.has_swaps()
+    }
+
+    /// Hands the loaded assets to their listeners and swaps the reloaded assets into their
+    /// handles, returns the paths of the reloaded ones
+    pub fn swap(&self) -> Vec<PathBuf> {
+        let loaded: Vec<_> = self.loaded.lock().unwrap().drain(..).collect();
+        for notify in loaded {
+            notify();
+        }
+        self.reloader.swap()
+    }
+
+    fn spawn<F: Future<Item = (), Error = ()> + Send + 'static>(&self, future: F) {
+        let mut pool = self.pool.lock().unwrap();
+        pool.get_or_insert_with(|| Builder::new().name_prefix("starstruck-assets-").build())
+            .spawn(future);
+    }
+}
+
+/// Turns a panic while loading into an error, so the handle still resolves and the failure is
+/// reported rather than lost on a pool thread
+pub(crate) fn catch_panics<F>(future: F) -> impl Future<Item = F::Item, Error = Error>
+where
+    F: Future<Error = Error>,
+{
+    AssertUnwindSafe(future).catch_unwind().then(|result| match result {
+        Ok(result) => result,
+        Err(panic) => {
+            let message = match panic.downcast::<String>() {
+                Ok(message) => *message,
+                Err(panic) => panic.downcast_ref::<&str>().map_or("unknown reason", |message| *message).to_string(),
+            };
+            Err(AssetError::LoaderPanicked(message).into())
+        }
+    })
+}
+
+impl Debug for AssetLoader {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(f, "Asset loader at {:?}", self.source.root())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asset::LoadState;
+    use crate::internal::TestDirectory;
+    use pretty_assertions::assert_eq;
+    use std::fs::write;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::atomic::Ordering;
+    use std::thread;
+
+    fn read_number(source: AssetSource) -> impl Fn(PathBuf) -> Result<u32, Error> + Send + Sync + 'static {
+        move |path| Ok(String::from_utf8(source.read(&path)?)?.trim().parse()?)
+    }
+
+    #[test]
+    fn it_should_resolve_handles_once_loaded() {
+        let directory = TestDirectory::new("asset_loader_resolve");
+        write(directory.join("one.txt"), "1").unwrap();
+        let loader = AssetLoader::new(AssetSource::Directory(directory.path().to_path_buf()));
+
+        let handle = loader.load(Path::new("one.txt"), 0, read_number(loader.source().clone()));
+
+        assert_eq!(1, *handle.clone().wait().unwrap());
+        assert_eq!(LoadState::Loaded, handle.state());
+        assert_eq!(Path::new("one.txt"), handle.path());
+        assert!(loader.failures().is_empty());
+    }
+
+    #[test]
+    fn it_should_report_each_asset_that_fails_to_load() {
+        let directory = TestDirectory::new("asset_loader_failures");
+        write(directory.join("two.txt"), "2").unwrap();
+        write(directory.join("three.txt"), "three").unwrap();
+        let loader = AssetLoader::new(AssetSource::Directory(directory.path().to_path_buf()));
+
+        let two = loader.load(Path::new("two.txt"), 0, read_number(loader.source().clone()));
+        let three = loader.load(Path::new("three.txt"), 0, read_number(loader.source().clone()));
+        let four = loader.load(Path::new("four.txt"), 0, read_number(loader.source().clone()));
+
+        assert_eq!(2, *two.wait().unwrap());
+        assert!(three.clone().wait().is_err());
+        assert!(four.clone().wait().is_err());
+        assert_eq!(LoadState::Failed("invalid digit found in string".to_string()), three.state());
+
+        let mut failures = loader.failures();
+        failures.sort();
+        assert_eq!(
+            vec![PathBuf::from("four.txt"), PathBuf::from("three.txt")],
+            failures.iter().map(|(path, _)| path.clone()).collect::<Vec<_>>()
+        );
+        assert_eq!("invalid digit found in string", failures[1].1);
+    }
+
+    #[test]
+    fn it_should_report_loaders_that_panic() {
+        let directory = TestDirectory::new("asset_loader_panics");
+        let loader = AssetLoader::new(AssetSource::Directory(directory.path().to_path_buf()));
+
+        let handle = loader.load(Path::new("broken.obj"), 0, |_| -> Result<u32, Error> { panic!("no groups") });
+
+        assert!(handle.clone().wait().is_err());
+        assert_eq!(LoadState::Failed("The loader panicked: no groups".to_string()), handle.state());
+        assert_eq!(vec![(PathBuf::from("broken.obj"), "The loader panicked: no groups".to_string())], loader.failures());
+    }
+
+    #[test]
+    fn it_should_load_each_path_once_per_type_and_variant() {
+        let directory = TestDirectory::new("asset_loader_dedup");
+        write(directory.join("five.txt"), "5").unwrap();
+        let loader = AssetLoader::new(AssetSource::Directory(directory.path().to_path_buf()));
+        let loads = Arc::new(AtomicUsize::new(0));
+        let counted = |loads: &Arc<AtomicUsize>| {
+            let loads = Arc::clone(loads);
+            let read = read_number(loader.source().clone());
+            move |path: PathBuf| {
+                loads.fetch_add(1, Ordering::SeqCst);
+                read(path)
+            }
+        };
+
+        let first = loader.load(Path::new("five.txt"), 0, counted(&loads));
+        let second = loader.load(Path::new("five.txt"), 0, counted(&loads));
+        let other_variant = loader.load(Path::new("five.txt"), 1, counted(&loads));
+        let other_type = loader.load(Path::new("five.txt"), 0, |_| -> Result<String, Error> { Ok("five".to_string()) });
+
+        let asset = first.wait().unwrap();
+        assert_eq!(5, *asset);
+        assert!(Arc::ptr_eq(&asset, &second.get().unwrap()));
+        assert_eq!(5, *other_variant.wait().unwrap());
+        assert_eq!("five", *other_type.wait().unwrap());
+        assert_eq!(2, loads.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn it_should_only_call_listeners_of_loaded_assets_when_swapping() {
+        let directory = TestDirectory::new("asset_loader_listeners");
+        write(directory.join("six.txt"), "6").unwrap();
+        let loader = AssetLoader::new(AssetSource::Directory(directory.path().to_path_buf()));
+        let seen = Arc::new(Mutex::new(vec![]));
+        let listener_seen = Arc::clone(&seen);
+
+        let handle = loader.load(Path::new("six.txt"), 0, read_number(loader.source().clone()));
+        handle.on_change(move |asset| {
+            listener_seen.lock().unwrap().push(**asset);
+            true
+        });
+        assert_eq!(6, *handle.clone().wait().unwrap());
+        assert_eq!(Vec::<u32>::new(), *seen.lock().unwrap());
+
+        // The handle resolves just before the asset is queued for its listeners
+        while !loader.poll() {
+            thread::yield_now();
+        }
+        assert!(loader.swap().is_empty());
+        assert_eq!(vec![6], *seen.lock().unwrap());
+        assert!(!loader.poll());
+    }
+}
@@ -0,0 +1,184 @@
+use crate::asset::catch_panics;
+use crate::asset::Handle;
+use crate::internal::FileWatcher;
+use failure::Error;
+use futures::lazy;
+use futures::Future;
+use futures::IntoFuture;
+use std::collections::HashMap;
+use std::fmt;
+use std::fmt::Debug;
+use std::fmt::Formatter;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Loads the asset again, the future resolves once the new asset is ready to be swapped in
+type Reload = Box<Fn() -> Box<Future<Item = (), Error = ()> + Send> + Send + Sync>;
+
+/// Swaps a reloaded asset into its handle
+type Swap = Box<Fn() + Send>;
+
+/// Watches the files of loaded assets and loads them again when they change. Reloaded assets are
+/// only swapped into their handles when `swap` is called, which happens between frames
+pub(crate) struct AssetReloader {
+    watcher: Mutex<FileWatcher>,
+    reloads: Mutex<HashMap<PathBuf, Vec<Reload>>>,
+    swaps: Arc<Mutex<Vec<(PathBuf, Swap)>>>,
+    failures: Arc<Mutex<HashMap<PathBuf, String>>>,
+}
+
+impl AssetReloader {
+    pub fn new(interval: Duration, failures: Arc<Mutex<HashMap<PathBuf, String>>>) -> Self {
+        Self {
+            watcher: Mutex::new(FileWatcher::new(interval)),
+            reloads: Mutex::new(HashMap::new()),
+            swaps: Arc::new(Mutex::new(vec![])),
+            failures,
+        }
+    }
+
+    /// Loads the asset again with `loader` whenever the file at `full_path` changes. The loader is
+    /// given the path of the handle, relative to the asset root. If the new version fails to load
+    /// the error is logged and the handle keeps the old one
+    pub fn watch<T, R, L>(&self, full_path: PathBuf, handle: Handle<T>, loader: Arc<L>)
+    where
+        T: Send + Sync + 'static,
+        R: IntoFuture<Item = T, Error = Error>,
+        R::Future: Send + 'static,
+        L: Fn(PathBuf) -> R + Send + Sync + 'static,
+    {
+        let swaps = Arc::clone(&self.swaps);
+        let failures = Arc::clone(&self.failures);
+        let reload: Reload = Box::new(move || {
+            let path = handle.path().to_path_buf();
+            let loader = Arc::clone(&loader);
+            let handle = handle.clone();
+            let swaps = Arc::clone(&swaps);
+            let failures = Arc::clone(&failures);
+            Box::new(catch_panics(lazy(move || loader(path))).then(move |result| {
+                match result {
+                    Ok(asset) => {
+                        failures.lock().unwrap().remove(handle.path());
+                        let asset = Arc::new(asset);
+                        let swap: Swap = Box::new(move || handle.replace(Arc::clone(&asset)));
+                        swaps.lock().unwrap().push((handle.path().to_path_buf(), swap));
+                    }
+                    Err(error) => {
+                        let reason = format!("{}", error);
+                        error!("Failed to reload asset {}, keeping the old one: {}", handle.path().display(), reason);
+                        failures.lock().unwrap().insert(handle.path().to_path_buf(), reason);
+                    }
+                }
+                Ok(())
+            }))
+        });
+
+        self.watcher.lock().unwrap().watch(&full_path);
+        self.reloads.lock().unwrap().entry(full_path).or_insert_with(Vec::new).push(reload);
+    }
+
+    /// Starts reloading the assets whose files have changed, the returned futures do the loading
+    pub fn poll(&self) -> Vec<Box<Future<Item = (), Error = ()> + Send>> {
+        let changed = self.watcher.lock().unwrap().poll();
+        let reloads = self.reloads.lock().unwrap();
+        changed
+            .iter()
+            .filter_map(|path| reloads.get(path))
+            .flat_map(|reloads| reloads.iter().map(|reload| reload()))
+            .collect()
+    }
+
+    pub fn has_swaps(&self) -> bool {
+        !self.swaps.lock().unwrap().is_empty()
+    }
+
+    /// Swaps the reloaded assets into their handles, returns their paths relative to the asset
+    /// root
+    pub fn swap(&self) -> Vec<PathBuf> {
+        let swaps: Vec<_> = self.swaps.lock().unwrap().drain(..).collect();
+        let mut reloaded = vec![];
+        for (path, swap) in swaps {
+            info!("Reloaded asset {}", path.display());
+            swap();
+            if !reloaded.contains(&path) {
+                reloaded.push(path);
+            }
+        }
+        reloaded
+    }
+}
+
+impl Debug for AssetReloader {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(f, "{:?}", self.watcher)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asset::LoadState;
+    use crate::internal::TestDirectory;
+    use pretty_assertions::assert_eq;
+    use std::fs::read_to_string;
+    use std::fs::remove_file;
+    use std::fs::write;
+
+    #[test]
+    fn it_should_swap_in_assets_once_their_file_is_fixed() {
+        let directory = TestDirectory::new("asset_reloader_fixed");
+        let full_path = directory.join("hello.txt");
+        let failures = Arc::new(Mutex::new(HashMap::new()));
+        let reloader = AssetReloader::new(Duration::from_millis(0), Arc::clone(&failures));
+
+        let handle = Handle::new(PathBuf::from("hello.txt"));
+        handle.resolve(Err("No such file or directory".to_string()));
+        let root = directory.path().to_path_buf();
+        let loader = Arc::new(move |path: PathBuf| -> Result<String, Error> { Ok(read_to_string(root.join(path))?) });
+        reloader.watch(full_path.clone(), handle.clone(), loader);
+        assert_eq!(0, reloader.poll().len());
+
+        write(&full_path, "hello").unwrap();
+        for reload in reloader.poll() {
+            reload.wait().unwrap();
+        }
+        assert_eq!(LoadState::Failed("No such file or directory".to_string()), handle.state());
+        assert!(reloader.has_swaps());
+
+        assert_eq!(vec![PathBuf::from("hello.txt")], reloader.swap());
+        assert_eq!(Some(Arc::new("hello".to_string())), handle.get());
+        assert!(!reloader.has_swaps());
+    }
+
+    #[test]
+    fn it_should_keep_the_old_asset_if_reloading_fails() {
+        let directory = TestDirectory::new("asset_reloader_broken");
+        let full_path = directory.join("number.txt");
+        write(&full_path, "1").unwrap();
+        let failures = Arc::new(Mutex::new(HashMap::new()));
+        let reloader = AssetReloader::new(Duration::from_millis(0), Arc::clone(&failures));
+
+        let handle = Handle::new(PathBuf::from("number.txt"));
+        handle.resolve(Ok(Arc::new(1)));
+        let root = directory.path().to_path_buf();
+        let loader =
+            Arc::new(move |path: PathBuf| -> Result<u32, Error> { Ok(read_to_string(root.join(path))?.trim().parse()?) });
+        reloader.watch(full_path.clone(), handle.clone(), loader);
+
+        // Written within the same second on file systems with coarse modification times, the new
+        // length is what gives the change away
+        remove_file(&full_path).unwrap();
+        write(&full_path, "not a number").unwrap();
+        let reloads = reloader.poll();
+        assert_eq!(1, reloads.len());
+        for reload in reloads {
+            reload.wait().unwrap();
+        }
+
+        assert_eq!(Vec::<PathBuf>::new(), reloader.swap());
+        assert_eq!(Some(Arc::new(1)), handle.get());
+        assert!(failures.lock().unwrap().contains_key(&PathBuf::from("number.txt")));
+    }
+}
@@ -0,0 +1,204 @@
+use crate::allocator::DefaultChunk;
+use crate::allocator::DefaultGpuAllocator;
+use crate::allocator::GpuAllocator;
+use crate::asset::AssetLoader;
+use crate::asset::AssetSource;
+use crate::asset::EntryKind;
+use crate::asset::Font;
+use crate::asset::Handle;
+use crate::asset::MeshCache;
+use crate::asset::PackedMesh;
+use crate::asset::PackedTexture;
+use crate::asset::MESH_EXTENSION;
+use crate::errors::AssetError;
+use crate::graphics::Bundle;
+use crate::graphics::Rgba8Srgb;
+use crate::graphics::ShaderDescription;
+use crate::graphics::ShaderStage;
+use crate::graphics::Single;
+use crate::graphics::Texture;
+use crate::internal::graphics::GraphicsState;
+use crate::primitive::Index;
+use crate::primitive::Vertex;
+use crate::setup_context::CreateBundleFromObj;
+use crate::setup_context::SetupContext;
+use failure::Error;
+use futures::Future;
+use futures::IntoFuture;
+use gfx_hal::Backend;
+use gfx_hal::Device;
+use gfx_hal::Instance;
+use image::load_from_memory;
+use std::fmt;
+use std::fmt::Debug;
+use std::fmt::Formatter;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// Loads assets by path, relative to the asset root, on a pool of background threads. Each path
+/// is only loaded once per asset type and shader stage, loading it again returns a handle to the
+/// same asset. In debug builds the files are watched, and changed assets are loaded again and
+/// swapped into their handles between frames. The asset root can also be an `Archive`, which is
+/// read like the directory it was packed from
+#[allow(clippy::type_complexity)]
+pub struct AssetServer<
+    A: GpuAllocator<B, D> = DefaultGpuAllocator<DefaultChunk<backend::Backend, backend::Device>, backend::Backend, backend::Device>,
+    B: Backend = backend::Backend,
+    D: Device<B> = backend::Device,
+    I: Instance<Backend = B> = backend::Instance,
+> {
+    loader: AssetLoader,
+    state: Arc<GraphicsState<A, B, D, I>>,
+    meshes: MeshCache,
+}
+
+impl<A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Instance<Backend = B>> AssetServer<A, B, D, I> {
+    pub(crate) fn new(state: Arc<GraphicsState<A, B, D, I>>, source: AssetSource) -> Self {
+        Self {
+            loader: AssetLoader::new(source),
+            state,
+            meshes: MeshCache::user(),
+        }
+    }
+
+    /// The directory or archive asset paths are relative to, see
+    /// `StarstruckBuilder::with_asset_root`
+    pub fn root(&self) -> &Path {
+        self.loader.source().root()
+    }
+
+    /// Loads an image and uploads it as a texture. Textures packed by `starstruck-pack` are
+    /// already decoded
+    pub fn load_texture<P: AsRef<Path>>(&self, path: P) -> Handle<Texture<Rgba8Srgb, Single, A, B, D, I>> {
+        let state = Arc::clone(&self.state);
+        let source = self.loader.source().clone();
+        self.loader.load(path.as_ref(), 0, move |path| {
+            let state = Arc::clone(&state);
+            let data = source.read(&path);
+            let image = match source.kind(&path) {
+                EntryKind::Texture => data.and_then(|data| Ok(PackedTexture::from_bytes(&data)?.into_image())),
+                _ => data.and_then(|data| Ok(load_from_memory(&data).map_err(AssetError::from)?.to_rgba())),
+            };
+            image
+                .into_future()
+                .and_then(move |image| Texture::<Rgba8Srgb, Single, A, B, D, I>::from_rgba(state, Arc::new(image), false))
+        })
+    }
+
+    /// Loads an obj file into a bundle, see `CreateBundleFromObj`. Files in the binary mesh
+    /// format, see `PackedMesh`, are uploaded as they are, as long as they were written with the
+    /// same index and vertex types. Meshes packed by `starstruck-pack` are converted to the
+    /// requested types when they were packed with others, see `PackedMesh::converted`. In debug
+    /// builds parsed obj files are cached in the binary mesh format until they change
+    #[allow(clippy::type_complexity)]
+    pub fn load_mesh<In: Index + 'static, V: Vertex + 'static, P: AsRef<Path>>(&self, path: P) -> Handle<Bundle<In, V, A, B, D, I>>
+    where
+        SetupContext<A, B, D, I>: CreateBundleFromObj<In, V, A, B, D, I>,
+    {
+        let state = Arc::clone(&self.state);
+        let source = self.loader.source().clone();
+        let meshes = self.meshes.clone();
+        self.loader.load(path.as_ref(), 0, move |path| -> Box<Future<Item = Bundle<In, V, A, B, D, I>, Error = Error> + Send> {
+            let setup = SetupContext::new(Arc::clone(&state), source.clone());
+            let packed = source.kind(&path) == EntryKind::Mesh
+                || path.extension().map_or(false, |extension| extension == MESH_EXTENSION);
+            match (packed, source.file(&path)) {
+                (true, Some(file)) => Box::new(setup.create_bundle_from_mesh_file(file)),
+                (true, None) => Box::new(
+                    source
+                        .read(&path)
+                        .and_then(PackedMesh::from_bytes)
+                        .and_then(|mesh| match mesh.check_layout::<In, V>() {
+                            Ok(()) => Ok(mesh),
+                            // The pack tool can't know which types the mesh will be loaded as
+                            Err(_) => mesh.converted::<In, V>(),
+                        })
+                        .into_future()
+                        .and_then(move |mesh| setup.create_bundle_from_packed(mesh)),
+                ),
+                (false, Some(file)) if meshes.is_enabled() => match meshes.get::<In, V>(&file) {
+                    Some(mesh) => Box::new(setup.create_bundle_from_packed(mesh)),
+                    None => {
+                        let meshes = meshes.clone();
+                        Box::new(
+                            source
+                                .read(&path)
+                                .into_future()
+                                .and_then(move |data| setup.create_bundle_from_obj(&data))
+                                .and_then(move |bundle| {
+                                    // Only happens when the obj file has changed, so reading the
+                                    // bundle back from the GPU is worth it
+                                    let cached_file = file.clone();
+                                    bundle
+                                        .submit_pack()
+                                        .into_future()
+                                        .flatten()
+                                        .and_then(move |mesh| meshes.put::<In, V>(&cached_file, &mesh))
+                                        .then(move |cached| {
+                                            if let Err(error) = cached {
+                                                warn!("Failed to cache mesh {}: {}", file.display(), error);
+                                            }
+                                            Ok(bundle)
+                                        })
+                                }),
+                        )
+                    }
+                },
+                (false, _) => Box::new(
+                    source
+                        .read(&path)
+                        .into_future()
+                        .and_then(move |data| setup.create_bundle_from_obj(&data)),
+                ),
+            }
+        })
+    }
+
+    /// Loads a shader. Files ending with `.spv` are read as SPIR-V, anything else is compiled as
+    /// glsl. Includes are only resolved for shaders loaded from a directory. A path loaded as two
+    /// stages gives two separate shaders
+    pub fn load_shader<P: AsRef<Path>>(&self, path: P, stage: ShaderStage) -> Handle<ShaderDescription> {
+        let source = self.loader.source().clone();
+        self.loader.load(path.as_ref(), stage as usize, move |path| {
+            if path.extension().map_or(false, |extension| extension == "spv") {
+                return source.read(&path).and_then(ShaderDescription::reflect);
+            }
+            match source.file(&path) {
+                Some(file) => ShaderDescription::from_glsl_file(file, stage),
+                None => ShaderDescription::from_glsl(&String::from_utf8(source.read(&path)?)?, stage),
+            }
+        })
+    }
+
+    /// Loads a TrueType or OpenType font
+    pub fn load_font<P: AsRef<Path>>(&self, path: P) -> Handle<Font> {
+        let source = self.loader.source().clone();
+        self.loader.load(path.as_ref(), 0, move |path| -> Result<Font, Error> {
+            Ok(Font::from_bytes(source.read(&path)?)?)
+        })
+    }
+
+    /// The assets that failed to load, along with the reason
+    pub fn failures(&self) -> Vec<(PathBuf, String)> {
+        self.loader.failures()
+    }
+
+    /// Starts reloading the assets whose files have changed, and swaps in the ones that have
+    /// finished reloading. Must be called between frames, since bound assets are rebound. Returns
+    /// the paths of the swapped in assets
+    pub(crate) fn reload(&self) -> Result<Vec<PathBuf>, Error> {
+        if !self.loader.poll() {
+            return Ok(vec![]);
+        }
+        // Rebinding a descriptor set that a frame in flight uses isn't allowed
+        self.state.wait_for_frames_in_flight()?;
+        Ok(self.loader.swap())
+    }
+}
+
+impl<A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Instance<Backend = B>> Debug for AssetServer<A, B, D, I> {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(f, "Asset server at {:?}", self.loader.source().root())
+    }
+}
@@ -0,0 +1,58 @@
+use crate::asset::Archive;
+use crate::asset::EntryKind;
+use failure::Error;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// Where the `AssetServer` reads assets from, a directory during development or an archive built
+/// by `starstruck-pack` in shipping builds
+#[derive(Clone, Debug)]
+pub(crate) enum AssetSource {
+    Directory(PathBuf),
+    Archive(PathBuf, Arc<Archive>),
+}
+
+impl AssetSource {
+    /// Mounts the archive if the root is a file, otherwise assets are read from the directory
+    pub fn mount(root: PathBuf) -> Result<Self, Error> {
+        if root.is_file() {
+            info!("Mounting asset archive {}", root.display());
+            let archive = Archive::open(&root)?;
+            Ok(AssetSource::Archive(root, Arc::new(archive)))
+        } else {
+            Ok(AssetSource::Directory(root))
+        }
+    }
+
+    pub fn root(&self) -> &Path {
+        match self {
+            AssetSource::Directory(root) => root,
+            AssetSource::Archive(root, _) => root,
+        }
+    }
+
+    /// The file to watch for changes to the asset, archives are never reloaded
+    pub fn file(&self, path: &Path) -> Option<PathBuf> {
+        match self {
+            AssetSource::Directory(root) => Some(root.join(path)),
+            AssetSource::Archive(..) => None,
+        }
+    }
+
+    /// Whether the asset was converted when it was packed, files on disk are always raw
+    pub fn kind(&self, path: &Path) -> EntryKind {
+        match self {
+            AssetSource::Directory(_) => EntryKind::Raw,
+            AssetSource::Archive(_, archive) => archive.entry(path).map_or(EntryKind::Raw, |entry| entry.kind()),
+        }
+    }
+
+    pub fn read(&self, path: &Path) -> Result<Vec<u8>, Error> {
+        match self {
+            AssetSource::Directory(root) => Ok(fs::read(root.join(path))?),
+            AssetSource::Archive(_, archive) => archive.read(path),
+        }
+    }
+}
@@ -0,0 +1,131 @@
+use crate::errors::AssetError;
+use failure::Error;
+use std::mem::size_of;
+use std::ptr;
+use std::slice;
+
+/// Appends little endian values to a buffer
+pub(crate) trait WriteBytes {
+    fn put_u8(&mut self, value: u8);
+    fn put_u32(&mut self, value: u32);
+    fn put_u64(&mut self, value: u64);
+    fn put_bytes(&mut self, bytes: &[u8]);
+}
+
+impl WriteBytes for Vec<u8> {
+    fn put_u8(&mut self, value: u8) {
+        self.push(value);
+    }
+
+    fn put_u32(&mut self, value: u32) {
+        self.extend((0..4).map(|i| (value >> (i * 8)) as u8));
+    }
+
+    fn put_u64(&mut self, value: u64) {
+        self.extend((0..8).map(|i| (value >> (i * 8)) as u8));
+    }
+
+    /// Writes the length followed by the bytes
+    fn put_bytes(&mut self, bytes: &[u8]) {
+        self.put_u64(bytes.len() as u64);
+        self.extend_from_slice(bytes);
+    }
+}
+
+/// Reads the values written by `WriteBytes`, running out of data is an `AssetError::InvalidArchive`
+#[derive(Debug)]
+pub(crate) struct ByteReader<'a> {
+    data: &'a [u8],
+    len: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data, len: data.len() }
+    }
+
+    /// How many bytes have been read so far
+    pub fn position(&self) -> usize {
+        self.len - self.data.len()
+    }
+
+    pub fn u8(&mut self) -> Result<u8, Error> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn u32(&mut self) -> Result<u32, Error> {
+        Ok(self.take(4)?.iter().rev().fold(0, |value, byte| (value << 8) | u32::from(*byte)))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, Error> {
+        Ok(self.take(8)?.iter().rev().fold(0, |value, byte| (value << 8) | u64::from(*byte)))
+    }
+
+    pub fn bytes(&mut self) -> Result<&'a [u8], Error> {
+        let len = self.u64()?;
+        self.take(len as usize)
+    }
+
+    pub fn take(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        if self.data.len() < len {
+            Err(AssetError::InvalidArchive("unexpected end of data"))?;
+        }
+        let (taken, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(taken)
+    }
+}
+
+/// The raw bytes of a slice of plain data, in the byte order of the target
+pub(crate) fn as_bytes<T: Copy>(values: &[T]) -> &[u8] {
+    unsafe { slice::from_raw_parts(values.as_ptr() as *const u8, values.len() * size_of::<T>()) }
+}
+
+/// Copies raw bytes into a vec of plain data, the inverse of `as_bytes`. The bytes don't have to
+/// be aligned
+pub(crate) fn from_bytes<T: Copy>(bytes: &[u8]) -> Result<Vec<T>, Error> {
+    if size_of::<T>() == 0 || bytes.len() % size_of::<T>() != 0 {
+        Err(AssetError::InvalidArchive("data isn't a whole number of elements"))?;
+    }
+    let count = bytes.len() / size_of::<T>();
+    let mut values = Vec::with_capacity(count);
+    unsafe {
+        ptr::copy_nonoverlapping(bytes.as_ptr(), values.as_mut_ptr() as *mut u8, bytes.len());
+        values.set_len(count);
+    }
+    Ok(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn it_should_read_back_what_was_written() {
+        let mut data = vec![];
+        data.put_u8(7);
+        data.put_u32(0xDEAD_BEEF);
+        data.put_u64(1 << 40);
+        data.put_bytes(b"hello");
+
+        assert_eq!(&[0xEF, 0xBE, 0xAD, 0xDE], &data[1..5]);
+        let mut reader = ByteReader::new(&data);
+        assert_eq!(7, reader.u8().unwrap());
+        assert_eq!(0xDEAD_BEEF, reader.u32().unwrap());
+        assert_eq!(1 << 40, reader.u64().unwrap());
+        assert_eq!(b"hello", reader.bytes().unwrap());
+        assert_eq!(data.len(), reader.position());
+        assert!(reader.u8().is_err());
+    }
+
+    #[test]
+    fn it_should_copy_plain_data_from_unaligned_bytes() {
+        let values = [1.5f32, -2.0, 3.25];
+        let mut data = vec![0];
+        data.extend_from_slice(as_bytes(&values));
+
+        assert_eq!(values.to_vec(), from_bytes::<f32>(&data[1..]).unwrap());
+        assert!(from_bytes::<f32>(&data[2..]).is_err());
+    }
+}
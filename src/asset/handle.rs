@@ -0,0 +1,296 @@
+use crate::errors::AssetError;
+use failure::Error;
+use futures::task;
+use futures::task::Task;
+use futures::Async;
+use futures::Future;
+use futures::Poll;
+use std::fmt;
+use std::fmt::Debug;
+use std::fmt::Formatter;
+use std::mem;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+/// How far along the loading of an asset is
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LoadState {
+    Loading,
+    Loaded,
+
+    /// The asset couldn't be loaded, holds the reason
+    Failed(String),
+}
+
+type Listener<T> = Box<Fn(&Arc<T>) -> bool + Send>;
+
+struct Slot<T> {
+    result: Option<Result<Arc<T>, String>>,
+    /// The asset the listeners were last called with, it only changes between frames
+    current: Option<Arc<T>>,
+    waiting: Vec<Task>,
+    listeners: Vec<Listener<T>>,
+}
+
+/// A reference to an asset that is loaded in the background by the `AssetServer`. Handles are
+/// cheap to clone, every clone refers to the same asset. A handle is also a future that resolves
+/// once the asset is ready. When the file of an asset changes it's reloaded and swapped into the
+/// handle, see `on_change`
+pub struct Handle<T> {
+    path: Arc<PathBuf>,
+    slot: Arc<Mutex<Slot<T>>>,
+}
+
+impl<T> Handle<T> {
+    pub(crate) fn new(path: PathBuf) -> Self {
+        Self {
+            path: Arc::new(path),
+            slot: Arc::new(Mutex::new(Slot {
+                result: None,
+                current: None,
+                waiting: Vec::new(),
+                listeners: Vec::new(),
+            })),
+        }
+    }
+
+    /// Called by the loader once the asset has been loaded or has failed to load. This wakes up
+    /// whatever is waiting on the handle, the listeners are only called by `notify`
+    pub(crate) fn resolve(&self, result: Result<Arc<T>, String>) {
+        let waiting = {
+            let mut slot = self.slot.lock().unwrap();
+            slot.result = Some(result);
+            slot.waiting.drain(..).collect::<Vec<_>>()
+        };
+        for task in waiting {
+            task.notify();
+        }
+    }
+
+    /// Swaps in a reloaded asset, the old one is dropped once nothing else holds on to it. Must
+    /// be called between frames, since the listeners are called
+    pub(crate) fn replace(&self, asset: Arc<T>) {
+        self.resolve(Ok(asset));
+        self.notify();
+    }
+
+    /// Calls the listeners with the loaded asset, if they haven't seen it yet. Must be called
+    /// between frames, so that the listeners are free to rebind the asset
+    pub(crate) fn notify(&self) {
+        // The listeners are called without holding the lock, so that they can use the handle
+        let (asset, listeners) = {
+            let mut slot = self.slot.lock().unwrap();
+            let asset = match &slot.result {
+                Some(Ok(asset)) => Arc::clone(asset),
+                _ => return,
+            };
+            if slot.current.as_ref().map_or(false, |current| Arc::ptr_eq(current, &asset)) {
+                return;
+            }
+            slot.current = Some(Arc::clone(&asset));
+            (asset, mem::replace(&mut slot.listeners, Vec::new()))
+        };
+        let kept: Vec<_> = listeners.into_iter().filter(|listener| listener(&asset)).collect();
+        self.slot.lock().unwrap().listeners.extend(kept);
+    }
+
+    /// Calls the listener with the asset once it has loaded, and again every time it's reloaded.
+    /// The listener returns false to stop listening. Loaded and reloaded assets are handed to the
+    /// listeners between frames, so the listener is free to rebind them. If the asset was handed
+    /// out already, the listener is called with it right away
+    pub fn on_change<F: Fn(&Arc<T>) -> bool + Send + 'static>(&self, listener: F) {
+        let mut seen: Option<Arc<T>> = None;
+        loop {
+            // The listener is only added under the same lock that checks for a newer asset, so
+            // it can't miss one that is handed out in the meantime
+            let asset = {
+                let mut slot = self.slot.lock().unwrap();
+                match &slot.current {
+                    Some(current) if seen.as_ref().map_or(true, |seen| !Arc::ptr_eq(seen, current)) => {
+                        Arc::clone(current)
+                    }
+                    _ => {
+                        slot.listeners.push(Box::new(listener));
+                        return;
+                    }
+                }
+            };
+            if !listener(&asset) {
+                return;
+            }
+            seen = Some(asset);
+        }
+    }
+
+    /// The path this asset was loaded from, relative to the asset root
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn state(&self) -> LoadState {
+        match &self.slot.lock().unwrap().result {
+            None => LoadState::Loading,
+            Some(Ok(_)) => LoadState::Loaded,
+            Some(Err(reason)) => LoadState::Failed(reason.clone()),
+        }
+    }
+
+    /// The asset, if it has been loaded
+    pub fn get(&self) -> Option<Arc<T>> {
+        match &self.slot.lock().unwrap().result {
+            Some(Ok(asset)) => Some(Arc::clone(asset)),
+            _ => None,
+        }
+    }
+}
+
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        Self {
+            path: Arc::clone(&self.path),
+            slot: Arc::clone(&self.slot),
+        }
+    }
+}
+
+impl<T> Future for Handle<T> {
+    type Item = Arc<T>;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let mut slot = self.slot.lock().unwrap();
+        match &slot.result {
+            Some(Ok(asset)) => Ok(Async::Ready(Arc::clone(asset))),
+            Some(Err(reason)) => Err(AssetError::LoadFailed {
+                path: self.path.to_path_buf(),
+                reason: reason.clone(),
+            }
+            .into()),
+            None => {
+                slot.waiting.push(task::current());
+                Ok(Async::NotReady)
+            }
+        }
+    }
+}
+
+impl<T> Debug for Handle<T> {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(f, "Handle to {:?} ({:?})", self.path, self.state())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use std::thread;
+
+    #[test]
+    fn it_should_resolve_every_clone_once_loaded() {
+        let handle = Handle::<u32>::new(PathBuf::from("numbers/one.txt"));
+        let clone = handle.clone();
+        assert_eq!(LoadState::Loading, handle.state());
+        assert_eq!(None, handle.get());
+
+        let loader = handle.clone();
+        thread::spawn(move || loader.resolve(Ok(Arc::new(1))));
+
+        assert_eq!(1, *clone.wait().unwrap());
+        assert_eq!(LoadState::Loaded, handle.state());
+        assert_eq!(Some(Arc::new(1)), handle.get());
+    }
+
+    #[test]
+    fn it_should_report_why_loading_failed() {
+        let handle = Handle::<u32>::new(PathBuf::from("numbers/two.txt"));
+        handle.resolve(Err("No such file or directory".to_string()));
+
+        assert_eq!(LoadState::Failed("No such file or directory".to_string()), handle.state());
+        let error = handle.wait().unwrap_err();
+        assert_eq!(
+            "Failed to load numbers/two.txt: No such file or directory",
+            format!("{}", error)
+        );
+    }
+
+    #[test]
+    fn it_should_tell_listeners_about_reloads_until_they_stop_listening() {
+        let handle = Handle::<u32>::new(PathBuf::from("numbers/three.txt"));
+        let seen = Arc::new(Mutex::new(vec![]));
+        let listener_seen = Arc::clone(&seen);
+        handle.on_change(move |asset| {
+            let mut seen = listener_seen.lock().unwrap();
+            seen.push(**asset);
+            seen.len() < 2
+        });
+
+        handle.resolve(Ok(Arc::new(3)));
+        handle.notify();
+        handle.replace(Arc::new(33));
+        handle.replace(Arc::new(333));
+
+        assert_eq!(vec![3, 33], *seen.lock().unwrap());
+        assert_eq!(Some(Arc::new(333)), handle.get());
+    }
+
+    #[test]
+    fn it_should_only_tell_listeners_about_the_first_load_once_notified() {
+        let handle = Handle::<u32>::new(PathBuf::from("numbers/four.txt"));
+        let seen = Arc::new(Mutex::new(vec![]));
+        let listener_seen = Arc::clone(&seen);
+        handle.on_change(move |asset| {
+            listener_seen.lock().unwrap().push(**asset);
+            true
+        });
+
+        handle.resolve(Ok(Arc::new(4)));
+        assert_eq!(Some(Arc::new(4)), handle.get());
+        assert_eq!(Vec::<u32>::new(), *seen.lock().unwrap());
+
+        handle.notify();
+        handle.notify();
+        assert_eq!(vec![4], *seen.lock().unwrap());
+    }
+
+    #[test]
+    fn it_should_call_late_listeners_with_the_asset_that_was_handed_out() {
+        let handle = Handle::<u32>::new(PathBuf::from("numbers/five.txt"));
+        handle.resolve(Ok(Arc::new(5)));
+        handle.notify();
+
+        let seen = Arc::new(Mutex::new(vec![]));
+        let listener_seen = Arc::clone(&seen);
+        handle.on_change(move |asset| {
+            listener_seen.lock().unwrap().push(**asset);
+            true
+        });
+        handle.replace(Arc::new(55));
+
+        assert_eq!(vec![5, 55], *seen.lock().unwrap());
+    }
+
+    #[test]
+    fn it_should_not_lose_listeners_added_while_notifying() {
+        let handle = Handle::<u32>::new(PathBuf::from("numbers/six.txt"));
+        let seen = Arc::new(Mutex::new(vec![]));
+        let listener_handle = handle.clone();
+        let listener_seen = Arc::clone(&seen);
+        handle.on_change(move |_| {
+            let seen = Arc::clone(&listener_seen);
+            listener_handle.on_change(move |asset| {
+                seen.lock().unwrap().push(**asset);
+                true
+            });
+            false
+        });
+
+        handle.resolve(Ok(Arc::new(6)));
+        handle.notify();
+        handle.replace(Arc::new(66));
+
+        assert_eq!(vec![6, 66], *seen.lock().unwrap());
+    }
+}
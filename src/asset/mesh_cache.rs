@@ -0,0 +1,115 @@
+use crate::asset::packed_mesh::layout;
+use crate::asset::PackedMesh;
+use crate::asset::MESH_EXTENSION;
+use crate::primitive::Index;
+use crate::primitive::Vertex;
+use failure::Error;
+use memmap::Mmap;
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::Hash;
+use std::hash::Hasher;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// Keeps meshes parsed from obj files in the binary mesh format, so they are only parsed again
+/// once the obj file changes. A mesh is cached once for every index and vertex type it's loaded as
+#[derive(Clone, Debug)]
+pub(crate) struct MeshCache {
+    directory: Option<PathBuf>,
+}
+
+impl MeshCache {
+    /// Caches in the given directory, or not at all
+    pub fn new(directory: Option<PathBuf>) -> Self {
+        Self { directory }
+    }
+
+    /// Caches in the cache directory of the user in debug builds. Shipping builds load packed
+    /// meshes from an archive, so they don't write anything outside of the asset root
+    pub fn user() -> Self {
+        if !cfg!(debug_assertions) {
+            return Self::new(None);
+        }
+        Self::new(dirs::cache_dir().map(|directory| directory.join("starstruck").join("meshes")))
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.directory.is_some()
+    }
+
+    /// The cached mesh, memory mapped, unless the file has changed since it was cached
+    pub fn get<In: Index, V: Vertex>(&self, file: &Path) -> Option<PackedMesh<Mmap>> {
+        let cached = self.cache_file::<In, V>(file)?;
+        let modified = fs::metadata(file).and_then(|metadata| metadata.modified()).ok()?;
+        let cached_modified = fs::metadata(&cached).and_then(|metadata| metadata.modified()).ok()?;
+        if cached_modified < modified {
+            return None;
+        }
+
+        match PackedMesh::open(&cached).and_then(|mesh| mesh.check_layout::<In, V>().map(|_| mesh)) {
+            Ok(mesh) => Some(mesh),
+            Err(error) => {
+                warn!("Ignoring cached mesh {}: {}", cached.display(), error);
+                None
+            }
+        }
+    }
+
+    /// Caches the mesh of the file. The mesh is written next to the cached one and then renamed,
+    /// so meshes that are mapped at the time aren't changed under their feet
+    pub fn put<In: Index, V: Vertex>(&self, file: &Path, mesh: &PackedMesh) -> Result<(), Error> {
+        let cached = match self.cache_file::<In, V>(file) {
+            Some(cached) => cached,
+            None => return Ok(()),
+        };
+        if let Some(directory) = cached.parent() {
+            fs::create_dir_all(directory)?;
+        }
+        let partial = cached.with_extension("partial");
+        mesh.save(&partial)?;
+        fs::rename(&partial, &cached)?;
+        trace!("Cached mesh {} in {}", file.display(), cached.display());
+        Ok(())
+    }
+
+    fn cache_file<In: Index, V: Vertex>(&self, file: &Path) -> Option<PathBuf> {
+        let directory = self.directory.as_ref()?;
+        let mut hasher = DefaultHasher::new();
+        file.hash(&mut hasher);
+        layout::<In, V>().hash(&mut hasher);
+        let name = file.file_stem().map_or("mesh".into(), |stem| stem.to_string_lossy());
+        Some(directory.join(format!("{}-{:016x}.{}", name, hasher.finish(), MESH_EXTENSION)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::internal::TestDirectory;
+    use crate::primitive::VertexXYZ;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn it_should_return_cached_meshes_for_the_same_layout_only() {
+        let directory = TestDirectory::new("mesh_cache");
+        let file = directory.join("triangle.obj");
+        fs::write(&file, "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n").unwrap();
+
+        let cache = MeshCache::new(Some(directory.join("cache")));
+        assert!(cache.get::<u16, VertexXYZ>(&file).is_none());
+
+        let mesh = PackedMesh::new(&[0u16, 1, 2], &[VertexXYZ::default(); 3]);
+        cache.put::<u16, VertexXYZ>(&file, &mesh).unwrap();
+        assert_eq!(mesh.bytes(), cache.get::<u16, VertexXYZ>(&file).unwrap().bytes());
+        assert!(cache.get::<u32, VertexXYZ>(&file).is_none());
+    }
+
+    #[test]
+    fn it_should_not_cache_without_a_directory() {
+        let cache = MeshCache::new(None);
+        let mesh = PackedMesh::new(&[0u16], &[VertexXYZ::default()]);
+        cache.put::<u16, VertexXYZ>(Path::new("cube.obj"), &mesh).unwrap();
+        assert!(cache.get::<u16, VertexXYZ>(Path::new("cube.obj")).is_none());
+    }
+}
@@ -0,0 +1,55 @@
+//! Loading of textures, meshes, shaders and fonts from disk or from an asset archive, see
+//! [`AssetServer`] and [`Archive`]
+
+mod archive;
+mod asset_loader;
+mod asset_reloader;
+mod asset_server;
+mod asset_source;
+mod bytes;
+mod handle;
+mod mesh_cache;
+mod packed_mesh;
+mod packed_texture;
+
+pub(crate) use self::asset_loader::catch_panics;
+pub(crate) use self::asset_loader::AssetLoader;
+pub(crate) use self::asset_reloader::AssetReloader;
+pub(crate) use self::asset_source::AssetSource;
+pub(crate) use self::mesh_cache::MeshCache;
+
+#[doc(inline)]
+pub use self::archive::Archive;
+
+#[doc(inline)]
+pub use self::archive::ArchiveEntry;
+
+#[doc(inline)]
+pub use self::archive::ArchiveWriter;
+
+#[doc(inline)]
+pub use self::archive::Compression;
+
+#[doc(inline)]
+pub use self::archive::EntryKind;
+
+#[doc(inline)]
+pub use self::asset_server::AssetServer;
+
+#[doc(inline)]
+pub use self::handle::Handle;
+
+#[doc(inline)]
+pub use self::handle::LoadState;
+
+#[doc(inline)]
+pub use self::packed_mesh::PackedMesh;
+
+#[doc(inline)]
+pub use self::packed_mesh::MESH_EXTENSION;
+
+#[doc(inline)]
+pub use self::packed_texture::PackedTexture;
+
+/// A TrueType or OpenType font loaded by the `AssetServer`
+pub type Font = glyph_brush::rusttype::Font<'static>;
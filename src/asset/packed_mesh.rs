@@ -0,0 +1,374 @@
+use crate::asset::bytes::as_bytes;
+use crate::asset::bytes::from_bytes;
+use crate::asset::bytes::ByteReader;
+use crate::asset::bytes::WriteBytes;
+use crate::errors::AssetError;
+use crate::primitive::Index;
+use crate::primitive::Vertex;
+use failure::Error;
+use gfx_hal::format::Format;
+use memmap::Mmap;
+use std::fs;
+use std::fs::File;
+use std::mem::size_of;
+use std::ops::Range;
+use std::path::Path;
+use std::ptr;
+use vek::Aabb;
+use vek::Vec3;
+
+const MAGIC: &[u8; 4] = b"SSMS";
+const VERSION: u32 = 1;
+
+/// The extension of files in the binary mesh format
+pub const MESH_EXTENSION: &str = "ssmesh";
+
+/// A mesh in the binary mesh format, the index and vertex buffers of a `Bundle` along with the
+/// layout they were written with and the bounds of the positions. Loading it as another index or
+/// vertex type fails instead of uploading garbage.
+///
+/// The mesh is read straight from the bytes it was created from, so a memory mapped mesh, see
+/// `open`, is uploaded without being copied into a vec first
+#[derive(Clone, Debug)]
+pub struct PackedMesh<M: AsRef<[u8]> = Vec<u8>> {
+    data: M,
+    layout: Range<usize>,
+    bounds: Aabb<f32>,
+    indices: Range<usize>,
+    vertices: Range<usize>,
+}
+
+impl PackedMesh {
+    pub fn new<In: Index, V: Vertex>(indices: &[In], vertices: &[V]) -> Self {
+        Self::from_parts(&layout::<In, V>(), bounds(vertices), as_bytes(indices), as_bytes(vertices))
+    }
+
+    fn from_parts(layout: &[u8], bounds: Aabb<f32>, indices: &[u8], vertices: &[u8]) -> Self {
+        let mut data = Vec::with_capacity(64 + layout.len() + indices.len() + vertices.len());
+        data.extend_from_slice(MAGIC);
+        data.put_u32(VERSION);
+        data.put_bytes(layout);
+        for value in &[bounds.min.x, bounds.min.y, bounds.min.z, bounds.max.x, bounds.max.y, bounds.max.z] {
+            data.put_u32(value.to_bits());
+        }
+        data.put_bytes(indices);
+        data.put_bytes(vertices);
+        Self::from_bytes(data).expect("A mesh that was just written can always be read")
+    }
+}
+
+impl PackedMesh<Mmap> {
+    /// Memory maps a mesh file, nothing is read until the mesh is uploaded
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let file = File::open(path)?;
+        let map = unsafe { Mmap::map(&file)? };
+        Self::from_bytes(map)
+    }
+}
+
+impl<M: AsRef<[u8]>> PackedMesh<M> {
+    /// Reads the header, the indices and vertices stay where they are
+    pub fn from_bytes(data: M) -> Result<Self, Error> {
+        // The reader reports running out of data as a broken archive, but meshes are also cached
+        let (layout, bounds, indices, vertices) = header(data.as_ref()).map_err(|error| match error.downcast::<AssetError>() {
+            Ok(AssetError::InvalidArchive(reason)) => AssetError::InvalidMesh(reason).into(),
+            Ok(error) => error.into(),
+            Err(error) => error,
+        })?;
+
+        let mesh = Self {
+            data,
+            layout,
+            bounds,
+            indices,
+            vertices,
+        };
+        let (index_size, vertex_size) = (mesh.index_size(), mesh.vertex_size());
+        if index_size == 0 || vertex_size == 0 || mesh.index_bytes().len() % index_size != 0 || mesh.vertex_bytes().len() % vertex_size != 0 {
+            Err(AssetError::InvalidMesh("mesh data doesn't match its layout"))?;
+        }
+        Ok(mesh)
+    }
+
+    /// The size of an index in bytes, 2 or 4
+    pub fn index_size(&self) -> usize {
+        ByteReader::new(self.layout_bytes()).u32().unwrap_or(0) as usize
+    }
+
+    pub fn vertex_size(&self) -> usize {
+        let mut reader = ByteReader::new(self.layout_bytes());
+        reader.u32().and_then(|_| reader.u32()).unwrap_or(0) as usize
+    }
+
+    pub fn index_count(&self) -> usize {
+        self.index_bytes().len() / self.index_size()
+    }
+
+    pub fn vertex_count(&self) -> usize {
+        self.vertex_bytes().len() / self.vertex_size()
+    }
+
+    /// The bounds of the positions, which are taken from the attribute at location 0
+    pub fn bounds(&self) -> Aabb<f32> {
+        self.bounds
+    }
+
+    /// The whole mesh as it's stored
+    pub fn bytes(&self) -> &[u8] {
+        self.data.as_ref()
+    }
+
+    pub fn index_bytes(&self) -> &[u8] {
+        &self.bytes()[self.indices.clone()]
+    }
+
+    pub fn vertex_bytes(&self) -> &[u8] {
+        &self.bytes()[self.vertices.clone()]
+    }
+
+    /// Fails unless the mesh was packed with the same index and vertex types
+    pub fn check_layout<In: Index, V: Vertex>(&self) -> Result<(), Error> {
+        if self.layout_bytes() != layout::<In, V>().as_slice() {
+            Err(AssetError::LayoutMismatch)?;
+        }
+        Ok(())
+    }
+
+    /// Copies out the indices and vertices, as long as the mesh was packed with the same index and
+    /// vertex types
+    pub fn to_data<In: Index, V: Vertex>(&self) -> Result<(Vec<In>, Vec<V>), Error> {
+        self.check_layout::<In, V>()?;
+        Ok((from_bytes(self.index_bytes())?, from_bytes(self.vertex_bytes())?))
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        Ok(fs::write(path, self.bytes())?)
+    }
+
+    pub fn into_inner(self) -> M {
+        self.data
+    }
+
+    /// Copies the mesh into the given index and vertex types. Every attribute of `V` has to be in
+    /// the mesh at the same location and with the same format, attributes `V` doesn't have are left
+    /// out. Indices are widened or narrowed, as long as they fit
+    pub fn converted<In: Index, V: Vertex>(&self) -> Result<PackedMesh, Error> {
+        let mut reader = ByteReader::new(self.layout_bytes());
+        reader.take(8)?;
+        let mut attributes = vec![];
+        while let Ok(location) = reader.u32() {
+            let _binding = reader.u32()?;
+            attributes.push((location, reader.u32()?, reader.u32()? as usize));
+        }
+
+        let mut copies = vec![];
+        for attribute in V::attributes() {
+            let element = attribute.element;
+            let source = attributes
+                .iter()
+                .find(|&&(location, format, _)| location == attribute.location && format == element.format as u32)
+                .ok_or(AssetError::LayoutMismatch)?;
+            let size = usize::from(element.format.surface_desc().bits) / 8;
+            if source.2 + size > self.vertex_size() || element.offset as usize + size > size_of::<V>() {
+                Err(AssetError::LayoutMismatch)?;
+            }
+            copies.push((source.2, element.offset as usize, size));
+        }
+
+        let mut vertices = vec![0; self.vertex_count() * size_of::<V>()];
+        for (source, target) in self.vertex_bytes().chunks(self.vertex_size()).zip(vertices.chunks_mut(size_of::<V>())) {
+            for &(from, to, size) in &copies {
+                target[to..to + size].copy_from_slice(&source[from..from + size]);
+            }
+        }
+
+        let indices: Vec<u32> = match self.index_size() {
+            2 => from_bytes::<u16>(self.index_bytes())?.into_iter().map(u32::from).collect(),
+            4 => from_bytes::<u32>(self.index_bytes())?,
+            _ => Err(AssetError::LayoutMismatch)?,
+        };
+        let indices = match size_of::<In>() {
+            2 if indices.iter().all(|&index| index <= u32::from(u16::max_value())) => {
+                as_bytes(&indices.iter().map(|&index| index as u16).collect::<Vec<_>>()).to_vec()
+            }
+            4 => as_bytes(&indices).to_vec(),
+            _ => Err(AssetError::LayoutMismatch)?,
+        };
+
+        Ok(PackedMesh::from_parts(&layout::<In, V>(), self.bounds, &indices, &vertices))
+    }
+
+    fn layout_bytes(&self) -> &[u8] {
+        &self.bytes()[self.layout.clone()]
+    }
+}
+
+/// Where the next length prefixed section is, relative to the start of the mesh
+/// The layout, the bounds, and where the indices and vertices are
+fn header(data: &[u8]) -> Result<(Range<usize>, Aabb<f32>, Range<usize>, Range<usize>), Error> {
+    let mut reader = ByteReader::new(data);
+    if reader.take(4)? != MAGIC {
+        Err(AssetError::InvalidMesh("not a mesh"))?;
+    }
+    if reader.u32()? != VERSION {
+        Err(AssetError::InvalidMesh("unsupported mesh version"))?;
+    }
+    let layout = section(&mut reader)?;
+    let mut corners = [0.0; 6];
+    for corner in corners.iter_mut() {
+        *corner = f32::from_bits(reader.u32()?);
+    }
+    let bounds = Aabb {
+        min: Vec3::new(corners[0], corners[1], corners[2]),
+        max: Vec3::new(corners[3], corners[4], corners[5]),
+    };
+    Ok((layout, bounds, section(&mut reader)?, section(&mut reader)?))
+}
+
+fn section(reader: &mut ByteReader) -> Result<Range<usize>, Error> {
+    let len = reader.u64()? as usize;
+    let start = reader.position();
+    reader.take(len)?;
+    Ok(start..start + len)
+}
+
+/// The index size, the vertex size, and the location, binding, format and offset of every vertex
+/// attribute
+pub(crate) fn layout<In: Index, V: Vertex>() -> Vec<u8> {
+    let mut layout = vec![];
+    layout.put_u32(size_of::<In>() as u32);
+    layout.put_u32(size_of::<V>() as u32);
+    for attribute in V::attributes() {
+        layout.put_u32(attribute.location);
+        layout.put_u32(attribute.binding);
+        layout.put_u32(attribute.element.format as u32);
+        layout.put_u32(attribute.element.offset);
+    }
+    layout
+}
+
+/// Vertices without a float position at location 0 get empty bounds
+fn bounds<V: Vertex>(vertices: &[V]) -> Aabb<f32> {
+    let position = V::attributes().into_iter().find(|attribute| attribute.location == 0);
+    let (offset, components) = match position.map(|attribute| attribute.element) {
+        Some(element) if element.format == Format::Rgb32Float => (element.offset as usize, 3),
+        Some(element) if element.format == Format::Rg32Float => (element.offset as usize, 2),
+        _ => return empty_bounds(),
+    };
+    if vertices.is_empty() || offset + components * size_of::<f32>() > size_of::<V>() {
+        return empty_bounds();
+    }
+
+    let mut min = [std::f32::INFINITY; 3];
+    let mut max = [std::f32::NEG_INFINITY; 3];
+    for vertex in vertices {
+        let mut position = [0.0; 3];
+        for (component, value) in position.iter_mut().enumerate().take(components) {
+            *value = unsafe {
+                let bytes = (vertex as *const V as *const u8).add(offset + component * size_of::<f32>());
+                ptr::read_unaligned(bytes as *const f32)
+            };
+        }
+        for component in 0..3 {
+            min[component] = min[component].min(position[component]);
+            max[component] = max[component].max(position[component]);
+        }
+    }
+    Aabb {
+        min: Vec3::from(min),
+        max: Vec3::from(max),
+    }
+}
+
+fn empty_bounds() -> Aabb<f32> {
+    Aabb {
+        min: Vec3::zero(),
+        max: Vec3::zero(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::internal::TestDirectory;
+    use crate::primitive::VertexXYZ;
+    use crate::primitive::VertexXYZRG;
+    use pretty_assertions::assert_eq;
+
+    fn triangle() -> Vec<VertexXYZ> {
+        vec![
+            VertexXYZ { x: -1.0, y: 0.0, z: 2.0 },
+            VertexXYZ { x: 1.0, y: 0.5, z: 0.0 },
+            VertexXYZ { x: 0.0, y: 1.0, z: -3.0 },
+        ]
+    }
+
+    #[test]
+    fn it_should_round_trip_meshes() {
+        let mesh = PackedMesh::new(&[0u16, 1, 2], &triangle());
+        assert_eq!((2, 12), (mesh.index_size(), mesh.vertex_size()));
+        assert_eq!((3, 3), (mesh.index_count(), mesh.vertex_count()));
+
+        let read = PackedMesh::from_bytes(mesh.bytes().to_vec()).unwrap();
+        let (indices, vertices) = read.to_data::<u16, VertexXYZ>().unwrap();
+        assert_eq!(vec![0, 1, 2], indices);
+        assert_eq!(vec![-1.0, 1.0, 0.0], vertices.iter().map(|vertex| vertex.x).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn it_should_store_the_bounds_of_the_positions() {
+        let mesh = PackedMesh::new(&[0u32, 1, 2], &triangle());
+        let read = PackedMesh::from_bytes(mesh.into_inner()).unwrap();
+        assert_eq!(Vec3::new(-1.0, 0.0, -3.0), read.bounds().min);
+        assert_eq!(Vec3::new(1.0, 1.0, 2.0), read.bounds().max);
+    }
+
+    #[test]
+    fn it_should_refuse_meshes_loaded_with_another_layout() {
+        let mesh = PackedMesh::new(&[0u16], &[VertexXYZ::default()]);
+        assert!(mesh.check_layout::<u32, VertexXYZ>().is_err());
+        assert_eq!(
+            "The mesh was packed with a different vertex or index type than it's loaded as",
+            format!("{}", mesh.to_data::<u16, VertexXYZRG>().unwrap_err())
+        );
+    }
+
+    #[test]
+    fn it_should_map_saved_meshes() {
+        let directory = TestDirectory::new("packed_mesh");
+        let path = directory.join("triangle.ssmesh");
+        PackedMesh::new(&[2u16, 1, 0], &triangle()).save(&path).unwrap();
+
+        let mapped = PackedMesh::open(&path).unwrap();
+        assert_eq!(vec![2, 1, 0], mapped.to_data::<u16, VertexXYZ>().unwrap().0);
+    }
+
+    #[test]
+    fn it_should_convert_meshes_to_types_with_a_subset_of_the_attributes() {
+        let textured = vec![
+            VertexXYZRG { x: -1.0, y: 0.0, z: 2.0, r: 0.0, g: 1.0 },
+            VertexXYZRG { x: 1.0, y: 0.5, z: 0.0, r: 1.0, g: 1.0 },
+        ];
+        let mesh = PackedMesh::new(&[0u16, 1, 1], &textured);
+
+        let converted = mesh.converted::<u32, VertexXYZ>().unwrap();
+        let (indices, vertices) = converted.to_data::<u32, VertexXYZ>().unwrap();
+        assert_eq!(vec![0, 1, 1], indices);
+        assert_eq!(vec![(-1.0, 2.0), (1.0, 0.0)], vertices.iter().map(|vertex| (vertex.x, vertex.z)).collect::<Vec<_>>());
+        assert_eq!(mesh.bounds(), converted.bounds());
+
+        let untextured = PackedMesh::new(&[0u16, 1, 2], &triangle());
+        assert!(untextured.converted::<u16, VertexXYZRG>().is_err());
+    }
+
+    #[test]
+    fn it_should_reject_truncated_meshes() {
+        let mesh = PackedMesh::new(&[0u16, 1, 2], &triangle());
+        let truncated = mesh.bytes()[..mesh.bytes().len() - 1].to_vec();
+        let error = PackedMesh::from_bytes(truncated).unwrap_err();
+        assert_eq!("The packed mesh is invalid: unexpected end of data", format!("{}", error));
+
+        let error = PackedMesh::from_bytes(b"RIFF\0\0\0\0".to_vec()).unwrap_err();
+        assert_eq!("The packed mesh is invalid: not a mesh", format!("{}", error));
+    }
+}
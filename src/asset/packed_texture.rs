@@ -0,0 +1,74 @@
+use crate::asset::bytes::ByteReader;
+use crate::asset::bytes::WriteBytes;
+use crate::errors::AssetError;
+use failure::Error;
+use image::DynamicImage;
+use image::RgbaImage;
+
+/// A texture decoded ahead of time into the RGBA8 pixels the GPU is given, so loading it skips the
+/// image decoding
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PackedTexture {
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+}
+
+impl PackedTexture {
+    pub fn new(image: &DynamicImage) -> Self {
+        let image = image.to_rgba();
+        Self {
+            width: image.width(),
+            height: image.height(),
+            pixels: image.into_raw(),
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(self.pixels.len() + 16);
+        data.put_u32(self.width);
+        data.put_u32(self.height);
+        data.put_bytes(&self.pixels);
+        data
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self, Error> {
+        let mut reader = ByteReader::new(data);
+        let width = reader.u32()?;
+        let height = reader.u32()?;
+        let pixels = reader.bytes()?.to_vec();
+        if pixels.len() as u64 != u64::from(width) * u64::from(height) * 4 {
+            Err(AssetError::InvalidArchive("texture size doesn't match its pixels"))?;
+        }
+        Ok(Self { width, height, pixels })
+    }
+
+    pub fn into_image(self) -> RgbaImage {
+        RgbaImage::from_raw(self.width, self.height, self.pixels).expect("The size was checked when the texture was created")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn it_should_round_trip_textures() {
+        let image = RgbaImage::from_pixel(3, 2, Rgba([10, 20, 30, 255]));
+        let texture = PackedTexture::new(&DynamicImage::ImageRgba8(image.clone()));
+
+        let read = PackedTexture::from_bytes(&texture.to_bytes()).unwrap();
+        assert_eq!((3, 2), (read.width(), read.height()));
+        assert_eq!(image.into_raw(), read.into_image().into_raw());
+    }
+}
@@ -0,0 +1,105 @@
+//! Packs an asset directory into an archive for shipping builds. Textures are decoded into the
+//! pixels uploaded to the GPU, and obj meshes are converted into the binary mesh format, see
+//! `PackedMesh`. Everything else is stored as it is. Point `StarstruckBuilder::with_asset_root` at
+//! the archive to load from it.
+//!
+//! ```text
+//! starstruck-pack [--no-compression] <asset directory> <archive>
+//! ```
+
+use failure::Error;
+use obj::Obj;
+use obj::SimplePolygon;
+use starstruck::asset::ArchiveWriter;
+use starstruck::asset::Compression;
+use starstruck::asset::EntryKind;
+use starstruck::asset::PackedMesh;
+use starstruck::asset::PackedTexture;
+use starstruck::primitive::ObjVertex;
+use starstruck::primitive::VertexXYZ;
+use starstruck::primitive::VertexXYZRG;
+use std::env;
+use std::fs;
+use std::fs::File;
+use std::io::BufReader;
+use std::io::BufWriter;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process;
+
+const TEXTURE_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "bmp", "gif", "tga"];
+
+fn main() {
+    let mut compression = Compression::Deflate;
+    let mut paths = vec![];
+    for arg in env::args().skip(1) {
+        match arg.as_str() {
+            "--no-compression" => compression = Compression::None,
+            _ => paths.push(PathBuf::from(arg)),
+        }
+    }
+    if paths.len() != 2 {
+        eprintln!("Usage: starstruck-pack [--no-compression] <asset directory> <archive>");
+        process::exit(2);
+    }
+
+    if let Err(error) = pack(&paths[0], &paths[1], compression) {
+        eprintln!("Failed to pack {}: {}", paths[0].display(), error);
+        process::exit(1);
+    }
+}
+
+fn pack(root: &Path, archive: &Path, compression: Compression) -> Result<(), Error> {
+    let mut files = vec![];
+    find_files(root, &mut files)?;
+    files.sort();
+
+    let mut writer = ArchiveWriter::new(BufWriter::new(File::create(archive)?))?;
+    for file in files {
+        let path = file.strip_prefix(root)?;
+        let (kind, data) = convert(&file).map_err(|error| failure::format_err!("{}: {}", path.display(), error))?;
+        let entry = writer.add(path, kind, &data, compression)?;
+        println!("{:>10} {:>10} {:?} {}", entry.size(), entry.stored_size(), entry.kind(), path.display());
+    }
+    writer.finish()?;
+    Ok(())
+}
+
+fn find_files(directory: &Path, files: &mut Vec<PathBuf>) -> Result<(), Error> {
+    for entry in fs::read_dir(directory)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            find_files(&path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Meshes with texture coordinates are packed as `VertexXYZRG`, other meshes as `VertexXYZ`, both
+/// with `u16` indices. The `AssetServer` converts them when they're loaded as other types
+fn convert(file: &Path) -> Result<(EntryKind, Vec<u8>), Error> {
+    let data = fs::read(file)?;
+    let extension = file
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+
+    if TEXTURE_EXTENSIONS.contains(&extension.as_str()) {
+        let image = image::load_from_memory(&data)?;
+        return Ok((EntryKind::Texture, PackedTexture::new(&image).to_bytes()));
+    }
+    if extension == "obj" {
+        let obj: Obj<SimplePolygon> = Obj::load_buf(&mut BufReader::new(data.as_slice()))?;
+        let mesh = if obj.texture.is_empty() {
+            let (indices, vertices) = VertexXYZ::from_obj(&data)?;
+            PackedMesh::new(&indices, &vertices)
+        } else {
+            let (indices, vertices) = VertexXYZRG::from_obj(&data)?;
+            PackedMesh::new(&indices, &vertices)
+        };
+        return Ok((EntryKind::Mesh, mesh.into_inner()));
+    }
+    Ok((EntryKind::Raw, data))
+}
@@ -19,6 +19,7 @@ use vek::Mat4;
 use crate::allocator::GpuAllocator;
 use crate::allocator::DefaultGpuAllocator;
 use crate::allocator::DefaultChunk;
+use std::path::PathBuf;
 use std::sync::Arc;
 use futures::lazy;
 use failure::Error;
@@ -37,6 +38,7 @@ pub struct Context<
     encoder: RenderPassInlineEncoder<'a, B>,
     base_projection: Mat4<f32>,
     render_area: Extent2D,
+    reloaded_assets: Vec<PathBuf>,
     stop: bool,
 }
 
@@ -46,6 +48,7 @@ impl<'a, A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Instance<Backend =
         setup_context: Arc<SetupContext<A, B, D, I>>,
         encoder: RenderPassInlineEncoder<'a, B>,
         render_area: Extent2D,
+        reloaded_assets: Vec<PathBuf>,
     ) -> Self {
         let ratio = (((render_area.width as f32 / render_area.height as f32) - 1.0) / 2.0) + 1.0;
         Context {
@@ -61,6 +64,7 @@ impl<'a, A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Instance<Backend =
                 near: 0.,
                 far: 100.,
             }),
+            reloaded_assets,
             stop: false,
         }
     }
@@ -94,6 +98,12 @@ impl<'a, A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Instance<Backend =
         &self.input
     }
 
+    /// The assets that were reloaded since the last frame that was drawn because their files
+    /// changed, relative to the asset root
+    pub fn reloaded_assets(&self) -> &[PathBuf] {
+        &self.reloaded_assets
+    }
+
     pub fn setup_context(&self) -> &SetupContext<A, B, D, I> {
         &*self.setup_context
     }
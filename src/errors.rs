@@ -1,8 +1,13 @@
 use failure::Backtrace;
 use failure::Context;
 use failure::Fail;
+use gfx_hal::device::OutOfMemory;
+use gfx_hal::format::Format;
+use image::ImageError;
+use std::error::Error;
 use std::fmt;
 use std::fmt::Display;
+use std::path::PathBuf;
 
 #[derive(Debug)]
 pub struct CreateEncoderError {
@@ -56,6 +61,336 @@ pub enum CreateEncoderErrorKind {
     #[fail(display = "Device has been lost")]
     DeviceLost,
 
-    #[fail(display = "Device has been lost")]
+    #[fail(display = "The device ran out of memory")]
     OutOfMemory,
 }
+
+/// Errors from allocating device memory and binding it to resources
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum AllocationError {
+    NotInitialized,
+    NoAvailableRegion,
+    AlreadyFreed,
+    AlreadyBound,
+
+    /// No memory type supports the resource, which is named by the value
+    NoSuitableMemoryType(&'static str),
+    OutOfDeviceMemory,
+    OutOfHostMemory,
+    TooManyObjects,
+
+    /// Allocating would take the heap over the budget given to the allocator
+    BudgetExceeded { heap: usize, requested: u64, budget: u64 },
+
+    /// The allocation is larger than an allocator can hand out in one piece
+    TooLarge { requested: u64, max: u64 },
+
+    /// Zero bytes were requested, which no chunk hands out
+    EmptyAllocation,
+
+    /// A range given to a mapped read or write doesn't fit in the memory
+    OutOfBounds { start: u64, end: u64, size: u64 },
+    NotHostVisible,
+}
+
+impl Display for AllocationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AllocationError::NotInitialized => write!(f, "This allocator has not been initialized"),
+            AllocationError::NoAvailableRegion => write!(f, "No available region found"),
+            AllocationError::AlreadyFreed => write!(f, "Can't bind to already freed memory!"),
+            AllocationError::AlreadyBound => write!(f, "This memory is already allocated"),
+            AllocationError::NoSuitableMemoryType(resource) => {
+                write!(f, "Couldn't find a memory type to support the {}!", resource)
+            }
+            AllocationError::OutOfDeviceMemory => write!(f, "The device ran out of memory"),
+            AllocationError::OutOfHostMemory => write!(f, "The host ran out of memory"),
+            AllocationError::TooManyObjects => write!(f, "Too many memory objects have been allocated"),
+            AllocationError::BudgetExceeded { heap, requested, budget } => write!(
+                f,
+                "Allocating {} bytes would exceed the budget of {} bytes for heap {}",
+                requested, budget, heap
+            ),
+            AllocationError::TooLarge { requested, max } => write!(
+                f,
+                "Allocating {} bytes is more than the {} bytes that fit in a single allocation",
+                requested, max
+            ),
+            AllocationError::EmptyAllocation => write!(f, "Can't allocate zero bytes of memory"),
+            AllocationError::OutOfBounds { start, end, size } => write!(
+                f,
+                "The range {}..{} is out of bounds for memory that is {} bytes long",
+                start, end, size
+            ),
+            AllocationError::NotHostVisible => write!(f, "The memory isn't host visible, so it can't be mapped"),
+        }
+    }
+}
+
+impl Error for AllocationError {}
+
+impl From<OutOfMemory> for AllocationError {
+    fn from(error: OutOfMemory) -> Self {
+        match error {
+            OutOfMemory::OutOfDeviceMemory => AllocationError::OutOfDeviceMemory,
+            OutOfMemory::OutOfHostMemory => AllocationError::OutOfHostMemory,
+        }
+    }
+}
+
+impl From<gfx_hal::device::AllocationError> for AllocationError {
+    fn from(error: gfx_hal::device::AllocationError) -> Self {
+        match error {
+            gfx_hal::device::AllocationError::OutOfMemory(error) => error.into(),
+            gfx_hal::device::AllocationError::TooManyObjects => AllocationError::TooManyObjects,
+        }
+    }
+}
+
+/// Errors from validating shaders and creating pipelines
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum PipelineError {
+    /// The spirv of the given stage couldn't be reflected
+    InvalidShader { stage: String, reason: String },
+
+    /// The spirv doesn't match its `ShaderDescription`
+    InvalidShaderDescription { stage: String, reason: String },
+    MissingFragmentShader,
+
+    /// The vertex shader reads an input that no vertex attribute is bound to
+    MissingVertexAttribute { input: String, location: u32 },
+
+    /// The vertex shader reads an input as a different type than the format of its attribute
+    VertexAttributeMismatch {
+        input: String,
+        location: u32,
+        components: u32,
+        scalar: String,
+        format: Format,
+    },
+}
+
+impl Display for PipelineError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PipelineError::InvalidShader { stage, reason } => write!(f, "Invalid {} shader: {}", stage, reason),
+            PipelineError::InvalidShaderDescription { stage, reason } => {
+                write!(f, "Invalid {} shader description: {}", stage, reason)
+            }
+            PipelineError::MissingFragmentShader => write!(f, "A post process pass needs a fragment shader"),
+            PipelineError::MissingVertexAttribute { input, location } => write!(
+                f,
+                "The vertex shader reads `{}` at location {}, but the vertex has no attribute at that location",
+                input, location
+            ),
+            PipelineError::VertexAttributeMismatch {
+                input,
+                location,
+                components,
+                scalar,
+                format,
+            } => write!(
+                f,
+                "The vertex shader reads `{}` at location {} as {} {} component(s), but the vertex attribute has \
+                 format {:?}",
+                input, location, components, scalar, format
+            ),
+        }
+    }
+}
+
+impl Error for PipelineError {}
+
+/// Errors from creating textures
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum TextureError {
+    /// The device can't sample images of this format
+    UnsupportedFormat(Format),
+}
+
+impl Display for TextureError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TextureError::UnsupportedFormat(format) => {
+                write!(f, "The format {:?} isn't supported for textures on this device", format)
+            }
+        }
+    }
+}
+
+impl Error for TextureError {}
+
+/// Errors from turning user data into gpu resources
+#[derive(Debug)]
+pub enum AssetError {
+    Decode(ImageError),
+
+    /// The named kind of resource was created without any data
+    Empty(&'static str),
+
+    /// The named kind of resource didn't retain its data, see `Recreatable`
+    NotRecreatable(&'static str),
+
+    /// The `AssetServer` couldn't load the file, the path is relative to the asset root
+    LoadFailed { path: PathBuf, reason: String },
+
+    /// The path isn't in the mounted asset archive
+    NotInArchive(PathBuf),
+
+    /// The path goes up out of the asset root with `..`
+    InvalidPath(PathBuf),
+
+    /// The asset archive is damaged or was written by an incompatible version, holds the reason
+    InvalidArchive(&'static str),
+
+    /// A packed mesh, from the archive or the mesh cache, is damaged or was written by an
+    /// incompatible version, holds the reason
+    InvalidMesh(&'static str),
+
+    /// A packed mesh was loaded with different vertex or index types than it was packed with
+    LayoutMismatch,
+
+    /// The upload manager was dropped before the upload reached the GPU
+    UploadCancelled,
+
+    /// Loading the asset panicked, holds the panic message
+    LoaderPanicked(String),
+}
+
+impl Display for AssetError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AssetError::Decode(error) => Display::fmt(error, f),
+            AssetError::Empty(kind) => write!(f, "A {} needs at least one element", kind),
+            AssetError::NotRecreatable(kind) => {
+                write!(f, "The {} can't be recreated since it wasn't created as recreatable", kind)
+            }
+            AssetError::LoadFailed { path, reason } => write!(f, "Failed to load {}: {}", path.display(), reason),
+            AssetError::NotInArchive(path) => write!(f, "{} is not in the asset archive", path.display()),
+            AssetError::InvalidPath(path) => write!(f, "{} leaves the asset root", path.display()),
+            AssetError::InvalidArchive(reason) => write!(f, "The asset archive is invalid: {}", reason),
+            AssetError::InvalidMesh(reason) => write!(f, "The packed mesh is invalid: {}", reason),
+            AssetError::LayoutMismatch => {
+                write!(f, "The mesh was packed with a different vertex or index type than it's loaded as")
+            }
+            AssetError::UploadCancelled => write!(f, "The upload batch was dropped before it completed"),
+            AssetError::LoaderPanicked(message) => write!(f, "The loader panicked: {}", message),
+        }
+    }
+}
+
+impl Error for AssetError {
+    fn source(&self) -> Option<&(Error + 'static)> {
+        match self {
+            AssetError::Decode(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<ImageError> for AssetError {
+    fn from(error: ImageError) -> Self {
+        AssetError::Decode(error)
+    }
+}
+
+/// Errors from building a `Mesh`
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum MeshError {
+    /// The number of indices isn't a multiple of three
+    NotTriangles(usize),
+
+    IndexOutOfRange { index: u32, vertex_count: usize },
+
+    /// An obj file refers to a position, uv or normal that isn't in it
+    MissingObjData(&'static str),
+
+    /// The mesh has more vertices than its index type can point at
+    TooManyVertices { count: usize, max: usize },
+}
+
+impl Display for MeshError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MeshError::NotTriangles(count) => {
+                write!(f, "The mesh has {} indices, which isn't a whole number of triangles", count)
+            }
+            MeshError::IndexOutOfRange { index, vertex_count } => {
+                write!(f, "The mesh has {} vertices, but an index points at vertex {}", vertex_count, index)
+            }
+            MeshError::MissingObjData(kind) => write!(f, "The obj file refers to a {} that isn't in it", kind),
+            MeshError::TooManyVertices { count, max } => {
+                write!(f, "The mesh has {} vertices, but its indices can only point at {}", count, max)
+            }
+        }
+    }
+}
+
+impl Error for MeshError {}
+
+/// Errors from picking an adapter and creating the swapchain for the window
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum SwapchainError {
+    NoAdapter,
+    QueueFamilyNotFound,
+    QueueGroupUnavailable,
+    NoCommandQueues,
+    NoPresentMode,
+    NoCompositeAlpha,
+    EmptyFormatList,
+    WindowMissing,
+    ColorUnsupported,
+}
+
+impl Display for SwapchainError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SwapchainError::NoAdapter => write!(f, "Couldn't find a graphical Adapter!"),
+            SwapchainError::QueueFamilyNotFound => write!(f, "Couldn't find the selected QueueFamily!"),
+            SwapchainError::QueueGroupUnavailable => write!(f, "Couldn't take ownership of the QueueGroup!"),
+            SwapchainError::NoCommandQueues => write!(f, "The QueueGroup did not have any CommandQueues available!"),
+            SwapchainError::NoPresentMode => write!(f, "No PresentMode values specified!"),
+            SwapchainError::NoCompositeAlpha => write!(f, "No CompositeAlpha values specified!"),
+            SwapchainError::EmptyFormatList => write!(f, "Preferred format list was empty!"),
+            SwapchainError::WindowMissing => write!(f, "Window doesn't exist!"),
+            SwapchainError::ColorUnsupported => write!(f, "The Surface isn't capable of supporting color!"),
+        }
+    }
+}
+
+impl Error for SwapchainError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn it_should_keep_the_old_messages() {
+        assert_eq!("No available region found", AllocationError::NoAvailableRegion.to_string());
+        assert_eq!(
+            "Invalid VERTEX shader: bad magic",
+            PipelineError::InvalidShader {
+                stage: "VERTEX".to_string(),
+                reason: "bad magic".to_string()
+            }
+            .to_string()
+        );
+        assert_eq!("Window doesn't exist!", SwapchainError::WindowMissing.to_string());
+    }
+
+    #[test]
+    fn it_should_map_gfx_allocation_errors() {
+        let error = gfx_hal::device::AllocationError::OutOfMemory(OutOfMemory::OutOfDeviceMemory);
+        assert_eq!(AllocationError::OutOfDeviceMemory, AllocationError::from(error));
+    }
+
+    #[test]
+    fn it_should_be_matchable_through_failure_errors() {
+        let error = failure::Error::from(AssetError::NotRecreatable("bundle"));
+        match error.downcast_ref::<AssetError>() {
+            Some(AssetError::NotRecreatable(kind)) => assert_eq!("bundle", *kind),
+            other => panic!("Expected NotRecreatable, got {:?}", other),
+        }
+    }
+}
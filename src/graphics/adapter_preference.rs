@@ -0,0 +1,178 @@
+use gfx_hal::adapter::AdapterInfo;
+use gfx_hal::adapter::DeviceType;
+use gfx_hal::adapter::MemoryType;
+use gfx_hal::Limits;
+use std::cmp::Reverse;
+use std::env;
+use std::fmt;
+use std::fmt::Debug;
+use std::fmt::Formatter;
+
+/// Setting this environment variable overrides the preference given to the builder. Use
+/// `high-performance`, `low-power`, or part of the name of an adapter
+pub const ADAPTER_ENV_VAR: &str = "STARSTRUCK_ADAPTER";
+
+/// Decides which adapter starstruck renders with when there are several to choose from. Adapters
+/// that can't render to the window are never picked
+#[derive(Clone)]
+pub enum AdapterPreference {
+    /// Prefers discrete gpus
+    HighPerformance,
+
+    /// Prefers integrated gpus, which usually saves battery
+    LowPower,
+
+    /// Prefers the adapter whose name contains the given string, ignoring case. Falls back to
+    /// `HighPerformance` if there is no such adapter
+    ByName(String),
+
+    /// Picks the adapter with the highest score
+    Custom(fn(&AdapterInfo) -> i64),
+}
+
+impl AdapterPreference {
+    /// Reads the preference from the `STARSTRUCK_ADAPTER` environment variable, if it's set
+    pub fn from_env() -> Option<Self> {
+        env::var(ADAPTER_ENV_VAR)
+            .ok()
+            .filter(|value| !value.trim().is_empty())
+            .map(|value| Self::parse(&value))
+    }
+
+    /// Parses the value of the environment variable
+    pub fn parse(value: &str) -> Self {
+        match value.trim().to_lowercase().as_str() {
+            "high-performance" | "high_performance" | "discrete" => AdapterPreference::HighPerformance,
+            "low-power" | "low_power" | "integrated" => AdapterPreference::LowPower,
+            _ => AdapterPreference::ByName(value.trim().to_string()),
+        }
+    }
+
+    pub fn score(&self, info: &AdapterInfo) -> i64 {
+        match self {
+            AdapterPreference::HighPerformance => match info.device_type {
+                DeviceType::DiscreteGpu => 4,
+                DeviceType::IntegratedGpu => 3,
+                DeviceType::VirtualGpu => 2,
+                DeviceType::Other => 1,
+                DeviceType::Cpu => 0,
+            },
+            AdapterPreference::LowPower => match info.device_type {
+                DeviceType::IntegratedGpu => 4,
+                DeviceType::DiscreteGpu => 3,
+                DeviceType::VirtualGpu => 2,
+                DeviceType::Other => 1,
+                DeviceType::Cpu => 0,
+            },
+            AdapterPreference::ByName(name) => {
+                let fallback = AdapterPreference::HighPerformance.score(info);
+                if info.name.to_lowercase().contains(&name.to_lowercase()) {
+                    fallback + 100
+                } else {
+                    fallback
+                }
+            }
+            AdapterPreference::Custom(score) => score(info),
+        }
+    }
+
+    /// Returns the indices of the adapters from the most to the least preferred. Adapters with the
+    /// same score keep their order
+    pub fn rank(&self, adapters: &[AdapterInfo]) -> Vec<usize> {
+        let mut ranked: Vec<_> = (0..adapters.len()).collect();
+        ranked.sort_by_key(|&index| Reverse(self.score(&adapters[index])));
+        ranked
+    }
+}
+
+impl Debug for AdapterPreference {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        match self {
+            AdapterPreference::HighPerformance => write!(f, "HighPerformance"),
+            AdapterPreference::LowPower => write!(f, "LowPower"),
+            AdapterPreference::ByName(name) => write!(f, "ByName({:?})", name),
+            AdapterPreference::Custom(_) => write!(f, "Custom"),
+        }
+    }
+}
+
+/// Describes an adapter found on this machine, see `StarstruckBuilder::available_adapters`
+#[derive(Clone, Debug)]
+pub struct AdapterDescription {
+    pub info: AdapterInfo,
+    pub limits: Limits,
+    pub memory_types: Vec<MemoryType>,
+
+    /// The size of each memory heap in bytes
+    pub memory_heaps: Vec<u64>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn adapter(name: &str, device_type: DeviceType) -> AdapterInfo {
+        AdapterInfo {
+            name: name.to_string(),
+            vendor: 0,
+            device: 0,
+            device_type,
+        }
+    }
+
+    fn laptop() -> Vec<AdapterInfo> {
+        vec![
+            adapter("Intel(R) UHD Graphics 630", DeviceType::IntegratedGpu),
+            adapter("NVIDIA GeForce GTX 1050", DeviceType::DiscreteGpu),
+            adapter("llvmpipe", DeviceType::Cpu),
+        ]
+    }
+
+    #[test]
+    fn it_should_prefer_discrete_gpus_for_high_performance() {
+        assert_eq!(AdapterPreference::HighPerformance.rank(&laptop()), vec![1, 0, 2]);
+    }
+
+    #[test]
+    fn it_should_prefer_integrated_gpus_for_low_power() {
+        assert_eq!(AdapterPreference::LowPower.rank(&laptop()), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn it_should_match_names_ignoring_case() {
+        let preference = AdapterPreference::ByName("intel".to_string());
+        assert_eq!(preference.rank(&laptop()), vec![0, 1, 2]);
+
+        let preference = AdapterPreference::ByName("radeon".to_string());
+        assert_eq!(preference.rank(&laptop()), vec![1, 0, 2]);
+    }
+
+    #[test]
+    fn it_should_rank_by_custom_scores() {
+        let preference = AdapterPreference::Custom(|info| -(info.name.len() as i64));
+        assert_eq!(preference.rank(&laptop()), vec![2, 1, 0]);
+    }
+
+    #[test]
+    fn it_should_rank_the_most_extreme_custom_scores() {
+        let preference = AdapterPreference::Custom(|info| match info.device_type {
+            DeviceType::DiscreteGpu => i64::min_value(),
+            DeviceType::Cpu => i64::max_value(),
+            _ => 0,
+        });
+        assert_eq!(preference.rank(&laptop()), vec![2, 0, 1]);
+    }
+
+    #[test]
+    fn it_should_parse_the_environment_variable() {
+        match AdapterPreference::parse(" Low-Power ") {
+            AdapterPreference::LowPower => {}
+            other => panic!("Expected LowPower, got {:?}", other),
+        }
+        match AdapterPreference::parse("GTX 1050") {
+            AdapterPreference::ByName(name) => assert_eq!(name, "GTX 1050"),
+            other => panic!("Expected ByName, got {:?}", other),
+        }
+    }
+}
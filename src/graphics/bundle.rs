@@ -1,3 +1,5 @@
+use crate::asset::PackedMesh;
+use crate::errors::AssetError;
 use crate::internal::graphics::BufferBundle;
 use crate::internal::graphics::GraphicsState;
 use crate::internal::graphics::GPU;
@@ -5,6 +7,11 @@ use crate::primitive::Index;
 use crate::primitive::Vertex;
 use arrayvec::ArrayVec;
 use failure::Error;
+use futures::future::done;
+use futures::future::failed;
+use futures::future::lazy;
+use futures::future::ok;
+use futures::future::Either;
 use futures::Future;
 use gfx_hal::buffer::IndexBufferView;
 use gfx_hal::buffer::Usage as BufferUsage;
@@ -20,6 +27,8 @@ use std::sync::Arc;
 use crate::allocator::GpuAllocator;
 use crate::allocator::DefaultGpuAllocator;
 use crate::allocator::DefaultChunk;
+use crate::graphics::Recreatable;
+use crate::setup_context::SetupContext;
 
 /// A bundle contains both the vertexes and indexes needed to render a entity
 pub struct Bundle<
@@ -33,6 +42,7 @@ pub struct Bundle<
     index_buffer_bundle: BufferBundle<A, B, D, I, GPU, In>,
     vertex_buffer_bundle: BufferBundle<A, B, D, I, GPU, V>,
     index_count: u32,
+    retained: Option<(Arc<Vec<In>>, Arc<Vec<V>>)>,
 }
 
 impl<In: Index, V: Vertex, A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Instance<Backend = B>>
@@ -42,12 +52,24 @@ impl<In: Index, V: Vertex, A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: I
         state: Arc<GraphicsState<A, B, D, I>>,
         indexes: Arc<Vec<In>>,
         vertexes: Arc<Vec<V>>,
+        retain: bool,
     ) -> impl Future<Item = Self, Error = Error> + Send {
         let index_count = indexes.len() as u32;
-        let index_buffer_bundle =
-            BufferBundle::<A, B, D, I, GPU, In>::new(Arc::clone(&state), BufferUsage::VERTEX, indexes);
-        let vertex_buffer_bundle =
-            BufferBundle::<A, B, D, I, GPU, V>::new(state, BufferUsage::INDEX, vertexes);
+        let retained = if retain {
+            Some((Arc::clone(&indexes), Arc::clone(&vertexes)))
+        } else {
+            None
+        };
+        let index_buffer_bundle = BufferBundle::<A, B, D, I, GPU, In>::new(
+            Arc::clone(&state),
+            BufferUsage::INDEX | BufferUsage::TRANSFER_SRC,
+            indexes,
+        );
+        let vertex_buffer_bundle = BufferBundle::<A, B, D, I, GPU, V>::new(
+            state,
+            BufferUsage::VERTEX | BufferUsage::TRANSFER_SRC,
+            vertexes,
+        );
 
         index_buffer_bundle
             .join(vertex_buffer_bundle)
@@ -55,12 +77,89 @@ impl<In: Index, V: Vertex, A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: I
                 index_buffer_bundle: index,
                 vertex_buffer_bundle: vert,
                 index_count,
+                retained,
+            })
+    }
+
+    /// Uploads a mesh in the binary mesh format. The indexes and vertexes are copied from the mesh
+    /// straight into the staging memory, so a memory mapped mesh is never read into a vec
+    pub(crate) fn from_packed<M: AsRef<[u8]> + Send + Sync + 'static>(
+        state: Arc<GraphicsState<A, B, D, I>>,
+        mesh: PackedMesh<M>,
+    ) -> impl Future<Item = Self, Error = Error> + Send {
+        let mesh = Arc::new(mesh);
+        let index_count = mesh.index_count() as u32;
+        let indexes = Arc::clone(&mesh);
+        let index_buffer_bundle = BufferBundle::<A, B, D, I, GPU, In>::with_contents(
+            Arc::clone(&state),
+            BufferUsage::INDEX | BufferUsage::TRANSFER_SRC,
+            mesh.index_bytes().len() as u64,
+            move |staging| staging.copy_from_slice(indexes.index_bytes()),
+        );
+        let vertexes = Arc::clone(&mesh);
+        let vertex_buffer_bundle = BufferBundle::<A, B, D, I, GPU, V>::with_contents(
+            state,
+            BufferUsage::VERTEX | BufferUsage::TRANSFER_SRC,
+            mesh.vertex_bytes().len() as u64,
+            move |staging| staging.copy_from_slice(vertexes.vertex_bytes()),
+        );
+
+        done(mesh.check_layout::<In, V>())
+            .and_then(move |_| index_buffer_bundle.join(vertex_buffer_bundle))
+            .map(move |(index, vert)| Self {
+                index_buffer_bundle: index,
+                vertex_buffer_bundle: vert,
+                index_count,
+                retained: None,
             })
     }
 
     pub fn index_count(&self) -> u32 {
         self.index_count
     }
+
+    /// Reads the indexes and vertexes back from the GPU
+    pub fn read_back<'a>(&'a self) -> impl Future<Item = (Vec<In>, Vec<V>), Error = Error> + Send + 'a {
+        self.index_buffer_bundle
+            .read_back()
+            .join(self.vertex_buffer_bundle.read_back())
+    }
+
+    /// The indexes and vertexes in the binary mesh format, see `PackedMesh`. Bundles that don't
+    /// retain their data are read back from the GPU
+    pub fn pack<'a>(&'a self) -> impl Future<Item = PackedMesh, Error = Error> + Send + 'a {
+        lazy(move || self.submit_pack()).flatten()
+    }
+
+    /// Like `pack`, but the copies back from the GPU are submitted right away, so the future
+    /// doesn't borrow the bundle
+    pub(crate) fn submit_pack(&self) -> Result<impl Future<Item = PackedMesh, Error = Error> + Send, Error> {
+        Ok(match &self.retained {
+            Some((indexes, vertexes)) => Either::A(ok(PackedMesh::new(indexes, vertexes))),
+            None => Either::B(
+                self.index_buffer_bundle
+                    .submit_read_back()?
+                    .join(self.vertex_buffer_bundle.submit_read_back()?)
+                    .map(|(indexes, vertexes)| PackedMesh::new(&indexes, &vertexes)),
+            ),
+        })
+    }
+}
+
+impl<In: Index + 'static, V: Vertex + 'static, A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Instance<Backend = B>>
+    Recreatable<A, B, D, I> for Bundle<In, V, A, B, D, I>
+{
+    fn recreate(&self, context: &SetupContext<A, B, D, I>) -> Box<Future<Item = Self, Error = Error> + Send> {
+        match &self.retained {
+            Some((indexes, vertexes)) => Box::new(Self::new(
+                context.state(),
+                Arc::clone(indexes),
+                Arc::clone(vertexes),
+                true,
+            )),
+            None => Box::new(failed(AssetError::NotRecreatable("bundle").into())),
+        }
+    }
 }
 
 fn bind_vertex_bundle<A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Instance<Backend = B>, V: Vertex>(
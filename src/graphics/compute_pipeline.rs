@@ -0,0 +1,79 @@
+use crate::allocator::DefaultChunk;
+use crate::allocator::DefaultGpuAllocator;
+use crate::allocator::GpuAllocator;
+use crate::graphics::AsFormat;
+use crate::graphics::ShaderDescription;
+use crate::graphics::StorageBuffer;
+use crate::graphics::Texture;
+use crate::graphics::TextureType;
+use crate::internal::graphics::ComputePipelineBundle;
+use crate::internal::graphics::GraphicsState;
+use failure::Error;
+use futures::lazy;
+use futures::Future;
+use gfx_hal::pso::DescriptorBinding;
+use gfx_hal::Backend;
+use gfx_hal::Device;
+use gfx_hal::Instance;
+use std::fmt;
+use std::fmt::Debug;
+use std::fmt::Formatter;
+use std::sync::Arc;
+
+/// A pipeline running a single compute shader. Dispatch it with `SetupContext::dispatch_compute`
+/// for one off work, or with `PrepareContext::dispatch` to run it every frame
+#[allow(clippy::type_complexity)]
+pub struct ComputePipeline<
+    A: GpuAllocator<B, D> = DefaultGpuAllocator<DefaultChunk<backend::Backend, backend::Device>, backend::Backend, backend::Device>,
+    B: Backend = backend::Backend,
+    D: Device<B> = backend::Device,
+    I: Instance<Backend = B> = backend::Instance,
+> {
+    bundle: Arc<ComputePipelineBundle<A, B, D, I>>,
+}
+
+impl<A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Instance<Backend = B>> ComputePipeline<A, B, D, I> {
+    pub(crate) fn new(
+        state: Arc<GraphicsState<A, B, D, I>>,
+        description: ShaderDescription,
+    ) -> impl Future<Item = Self, Error = Error> + Send {
+        lazy(move || {
+            let bundle = ComputePipelineBundle::new(state, &description)?;
+            Ok(Self {
+                bundle: Arc::new(bundle),
+            })
+        })
+    }
+
+    pub(crate) fn bundle(&self) -> &ComputePipelineBundle<A, B, D, I> {
+        &self.bundle
+    }
+
+    /// Binds the buffer to the given binding in descriptor set 0
+    pub fn bind_storage_buffer<T: Copy + Send + Sync>(
+        &self,
+        binding: DescriptorBinding,
+        buffer: &StorageBuffer<T, A, B, D, I>,
+    ) {
+        self.bundle.bind_assets(vec![buffer.get_descriptor(binding)]);
+    }
+
+    pub fn bind_texture<F: AsFormat + Send, TA: TextureType>(&self, texture: &Texture<F, TA, A, B, D, I>) {
+        self.bundle.bind_assets(texture.get_descriptors());
+    }
+}
+
+impl<A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Instance<Backend = B>> Clone for ComputePipeline<A, B, D, I> {
+    fn clone(&self) -> Self {
+        Self {
+            bundle: Arc::clone(&self.bundle),
+        }
+    }
+}
+
+impl<A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Instance<Backend = B>> Debug for ComputePipeline<A, B, D, I> {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(f, "{:?}", self.bundle)?;
+        Ok(())
+    }
+}
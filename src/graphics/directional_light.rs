@@ -0,0 +1,272 @@
+use crate::allocator::DefaultChunk;
+use crate::allocator::DefaultGpuAllocator;
+use crate::allocator::GpuAllocator;
+use crate::internal::graphics::BufferBundle;
+use crate::internal::graphics::GraphicsState;
+use crate::internal::graphics::ShadowMap;
+use crate::internal::graphics::CPU;
+use failure::Error;
+use futures::lazy;
+use futures::Future;
+use gfx_hal::buffer::Usage as BufferUsage;
+use gfx_hal::image::Layout;
+use gfx_hal::pso::Descriptor;
+use gfx_hal::pso::DescriptorArrayIndex;
+use gfx_hal::pso::DescriptorBinding;
+use gfx_hal::Backend;
+use gfx_hal::Device;
+use gfx_hal::Instance;
+use std::fmt;
+use std::fmt::Debug;
+use std::fmt::Formatter;
+use std::mem::size_of;
+use std::sync::Arc;
+use vek::geom::FrustumPlanes;
+use vek::Mat4;
+use vek::Rgb;
+use vek::Vec3;
+
+/// The number of floats in the `DirectionalLight` uniform block of the lit pipelines
+const UNIFORM_FLOATS: usize = 28;
+
+/// Controls how the shadow map of a [`DirectionalLight`] is rendered and sampled
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ShadowConfig {
+    /// The width and height of the shadow map in texels
+    pub resolution: u32,
+    /// Subtracted from the depth before comparing, increase this if you see shadow acne
+    pub bias: f32,
+    /// Half the width and height of the area around the light target that casts shadows
+    pub extent: f32,
+    pub near: f32,
+    pub far: f32,
+    /// The radius in texels of the percentage closer filter. 0 gives hard shadows
+    pub pcf_radius: u32,
+}
+
+impl Default for ShadowConfig {
+    fn default() -> Self {
+        Self {
+            resolution: 2048,
+            bias: 0.005,
+            extent: 10.0,
+            near: 0.0,
+            far: 50.0,
+            pcf_radius: 1,
+        }
+    }
+}
+
+/// A light infinitely far away that shines in one direction, like the sun. The shadows are
+/// rendered with an orthographic projection centered around `target`
+///
+/// Shadow maps are rendered with `PrepareContext::render_shadow_map` and the light is bound to
+/// a lit pipeline with `Pipeline::bind_directional_light`
+#[allow(clippy::type_complexity)]
+pub struct DirectionalLight<
+    A: GpuAllocator<B, D> = DefaultGpuAllocator<DefaultChunk<backend::Backend, backend::Device>, backend::Backend, backend::Device>,
+    B: Backend = backend::Backend,
+    D: Device<B> = backend::Device,
+    I: Instance<Backend = B> = backend::Instance,
+> {
+    direction: Vec3<f32>,
+    target: Vec3<f32>,
+    color: Rgb<f32>,
+    ambient: f32,
+    config: ShadowConfig,
+    shadow_map: ShadowMap<A, B, D, I>,
+    uniform: BufferBundle<A, B, D, I, CPU, f32>,
+    /// The light data last written to the uniform
+    written: Vec<f32>,
+}
+
+impl<A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Instance<Backend = B>> DirectionalLight<A, B, D, I> {
+    pub(crate) fn new(
+        state: Arc<GraphicsState<A, B, D, I>>,
+        direction: Vec3<f32>,
+        color: Rgb<f32>,
+        config: ShadowConfig,
+    ) -> impl Future<Item = Self, Error = Error> + Send {
+        let cloned_state = Arc::clone(&state);
+        lazy(move || ShadowMap::new(state, config.resolution))
+            .join(BufferBundle::<A, B, D, I, CPU, f32>::new(
+                cloned_state,
+                (size_of::<f32>() * UNIFORM_FLOATS) as u64,
+                BufferUsage::UNIFORM,
+            ))
+            .and_then(move |(shadow_map, uniform)| {
+                let mut light = Self {
+                    direction,
+                    target: Vec3::zero(),
+                    color,
+                    ambient: 0.1,
+                    config,
+                    shadow_map,
+                    uniform,
+                    written: vec![],
+                };
+                light.write_uniform()?;
+                Ok(light)
+            })
+    }
+
+    pub fn direction(&self) -> Vec3<f32> {
+        self.direction
+    }
+
+    pub fn set_direction(&mut self, direction: Vec3<f32>) {
+        self.direction = direction;
+    }
+
+    /// The point the shadow map is centered around, usually the position of the camera or player
+    pub fn target(&self) -> Vec3<f32> {
+        self.target
+    }
+
+    pub fn set_target(&mut self, target: Vec3<f32>) {
+        self.target = target;
+    }
+
+    pub fn color(&self) -> Rgb<f32> {
+        self.color
+    }
+
+    pub fn set_color(&mut self, color: Rgb<f32>) {
+        self.color = color;
+    }
+
+    pub fn ambient(&self) -> f32 {
+        self.ambient
+    }
+
+    pub fn set_ambient(&mut self, ambient: f32) {
+        self.ambient = ambient;
+    }
+
+    /// The shadow config, the resolution can't be changed after the light has been created
+    pub fn config(&self) -> &ShadowConfig {
+        &self.config
+    }
+
+    pub fn set_bias(&mut self, bias: f32) {
+        self.config.bias = bias;
+    }
+
+    pub fn set_pcf_radius(&mut self, pcf_radius: u32) {
+        self.config.pcf_radius = pcf_radius;
+    }
+
+    /// The matrix that transforms world space into the clip space of the shadow map
+    pub fn light_space_matrix(&self) -> Mat4<f32> {
+        light_space_matrix(self.direction, self.target, &self.config)
+    }
+
+    pub(crate) fn shadow_map(&self) -> &ShadowMap<A, B, D, I> {
+        &self.shadow_map
+    }
+
+    fn uniform_data(&self) -> Vec<f32> {
+        let mut data = Vec::with_capacity(UNIFORM_FLOATS);
+        data.extend_from_slice(self.light_space_matrix().as_col_slice());
+        let direction = self.direction.normalized();
+        data.extend_from_slice(&[direction.x, direction.y, direction.z, 0.0]);
+        data.extend_from_slice(&[self.color.r, self.color.g, self.color.b, 1.0]);
+        data.extend_from_slice(&[
+            self.config.bias,
+            1.0 / self.config.resolution as f32,
+            self.config.pcf_radius as f32,
+            self.ambient,
+        ]);
+        data
+    }
+
+    /// Whether the light has changed since the uniform was last written
+    pub(crate) fn uniform_changed(&self) -> bool {
+        self.uniform_data() != self.written
+    }
+
+    /// Writes the light data to the uniform, no frame in flight may read it at the same time
+    pub(crate) fn write_uniform(&mut self) -> Result<(), Error> {
+        let data = self.uniform_data();
+        self.uniform.write_slice(&data)?;
+        self.written = data;
+        Ok(())
+    }
+
+    /// The shadow map at `binding = 0` and `binding = 1`, and the light data at `binding = 2`
+    pub(crate) fn get_descriptors(
+        &self,
+    ) -> Vec<(DescriptorBinding, DescriptorArrayIndex, Descriptor<B>)> {
+        vec![
+            (
+                0,
+                0,
+                Descriptor::Image(self.shadow_map.image_view(), Layout::DepthStencilReadOnlyOptimal),
+            ),
+            (1, 0, Descriptor::Sampler(self.shadow_map.sampler())),
+            (2, 0, Descriptor::Buffer(&*self.uniform.buffer, None..None)),
+        ]
+    }
+}
+
+impl<A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Instance<Backend = B>> Debug for DirectionalLight<A, B, D, I> {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(f, "DirectionalLight {:?} {:?} {:?}", self.direction, self.color, self.config)?;
+        Ok(())
+    }
+}
+
+fn light_space_matrix(direction: Vec3<f32>, target: Vec3<f32>, config: &ShadowConfig) -> Mat4<f32> {
+    let direction = direction.normalized();
+
+    // The up vector can't be parallel to the direction, so use another axis for straight down lights
+    let up = if direction.x.abs() < 1e-4 && direction.z.abs() < 1e-4 {
+        Vec3::<f32>::unit_z()
+    } else {
+        Vec3::<f32>::down()
+    };
+
+    // Place the light so that the target ends up in the middle of the depth range
+    let eye = target - direction * ((config.near + config.far) / 2.0);
+    let view = Mat4::<f32>::look_at(eye, target, up);
+    let projection = Mat4::<f32>::orthographic_lh_zo(FrustumPlanes {
+        left: -config.extent,
+        right: config.extent,
+        bottom: -config.extent,
+        top: config.extent,
+        near: config.near,
+        far: config.far,
+    });
+
+    projection * view
+}
+
+#[cfg(test)]
+mod tests {
+    use super::light_space_matrix;
+    use crate::graphics::ShadowConfig;
+    use pretty_assertions::assert_eq;
+    use vek::Vec3;
+    use vek::Vec4;
+
+    #[test]
+    fn target_should_end_up_in_the_center_of_the_shadow_map() {
+        let config = ShadowConfig::default();
+        let target = Vec3::new(3.0, 1.0, -2.0);
+        let matrix = light_space_matrix(Vec3::new(1.0, -1.0, 0.5), target, &config);
+
+        let projected = matrix * Vec4::from_point(target);
+
+        assert_eq!(projected.x.abs() < 1e-4, true);
+        assert_eq!(projected.y.abs() < 1e-4, true);
+        assert_eq!((projected.z - 0.5).abs() < 1e-4, true);
+    }
+
+    #[test]
+    fn straight_down_lights_should_produce_a_valid_matrix() {
+        let config = ShadowConfig::default();
+        let matrix = light_space_matrix(Vec3::new(0.0, -1.0, 0.0), Vec3::zero(), &config);
+
+        assert_eq!(matrix.into_col_array().iter().all(|value| value.is_finite()), true);
+    }
+}
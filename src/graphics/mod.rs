@@ -1,27 +1,76 @@
+mod adapter_preference;
 mod bundle;
+mod compute_pipeline;
+mod directional_light;
 mod pipeline;
+mod post_process;
+mod recreatable;
 mod shader_description;
 mod shader_set;
+mod shadow_caster_pipeline;
+mod storage_buffer;
 mod texture;
 
+#[doc(inline)]
+pub use self::adapter_preference::AdapterDescription;
+
+#[doc(inline)]
+pub use self::adapter_preference::AdapterPreference;
+
+#[doc(inline)]
+pub use self::adapter_preference::ADAPTER_ENV_VAR;
+
+#[doc(inline)]
+pub use gfx_hal::adapter::AdapterInfo;
+
+#[doc(inline)]
+pub use gfx_hal::adapter::DeviceType;
+
 #[doc(inline)]
 pub use self::bundle::Bundle;
 
 #[doc(inline)]
 pub use self::bundle::BundleEncoderExt;
 
+#[doc(inline)]
+pub use self::compute_pipeline::ComputePipeline;
+
+#[doc(inline)]
+pub use self::directional_light::DirectionalLight;
+
+#[doc(inline)]
+pub use self::directional_light::ShadowConfig;
+
 #[doc(inline)]
 pub use self::pipeline::Pipeline;
 
 #[doc(inline)]
 pub use self::pipeline::PipelineEncoderExt;
 
+#[doc(inline)]
+pub use self::post_process::PostProcessChain;
+
+#[doc(inline)]
+pub use self::post_process::PostProcessPass;
+
+#[doc(inline)]
+pub use self::recreatable::Recreatable;
+
 #[doc(inline)]
 pub use self::shader_description::ShaderDescription;
 
 #[doc(inline)]
 pub use self::shader_set::ShaderSet;
 
+#[doc(inline)]
+pub use gfx_hal::pso::Stage as ShaderStage;
+
+#[doc(inline)]
+pub use self::shadow_caster_pipeline::ShadowCasterPipeline;
+
+#[doc(inline)]
+pub use self::storage_buffer::StorageBuffer;
+
 #[doc(inline)]
 pub use self::texture::Texture;
 
@@ -1,4 +1,7 @@
+use crate::asset::Handle;
+use crate::graphics::DirectionalLight;
 use crate::graphics::ShaderSet;
+use crate::graphics::StorageBuffer;
 use crate::graphics::Texture;
 use crate::internal::graphics::GraphicsState;
 use crate::internal::graphics::PipelineBundle;
@@ -7,6 +10,7 @@ use failure::Error;
 use futures::lazy;
 use futures::Future;
 use gfx_hal::command::RenderPassInlineEncoder;
+use gfx_hal::pso::DescriptorBinding;
 use gfx_hal::pso::ShaderStageFlags;
 use gfx_hal::Backend;
 use gfx_hal::Device;
@@ -31,10 +35,13 @@ pub struct Pipeline<
     D: Device<B> = backend::Device,
     I: Instance<Backend = B> = backend::Instance,
 > {
-    bundle: RwLock<Option<PipelineBundle<V, A, B, D, I>>>
+    bundle: Arc<RwLock<Option<PipelineBundle<V, A, B, D, I>>>>
 }
 
-impl<V: Vertex, A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Instance<Backend = B>> Pipeline<V, A, B, D, I> {
+impl<V: 'static + Vertex, A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Instance<Backend = B>> Pipeline<V, A, B, D, I> {
+    /// Creates a new pipeline. In debug builds, shaders created with
+    /// `ShaderDescription::from_glsl_file` are watched and the pipeline is rebuilt in place when
+    /// they change
     pub fn new(
         state: Arc<GraphicsState<A, B, D, I>>,
         shader_set: ShaderSet,
@@ -42,19 +49,54 @@ impl<V: Vertex, A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Instance<Bac
         lazy(move || {
             let coned_state = Arc::clone(&state);
 
-            state.render_pass(move |render_pass| {
+            let bundle = state.render_pass(|render_pass| {
                 PipelineBundle::<V, A, B, D, I>::new(
                     coned_state,
                     render_pass,
                     &shader_set,
                 )
-            })
-        })
-        .map(move |bundle| Self {
-            bundle: RwLock::new(Some(bundle))
+            })?;
+            let bundle = Arc::new(RwLock::new(Some(bundle)));
+
+            if cfg!(debug_assertions) {
+                let weak_bundle = Arc::downgrade(&bundle);
+                state.shader_reloader().watch(
+                    shader_set,
+                    Box::new(move |state: &Arc<GraphicsState<A, B, D, I>>, set: &ShaderSet| {
+                        match weak_bundle.upgrade() {
+                            Some(bundle) => {
+                                let mut lock = bundle.write().unwrap();
+                                if let Some(pipeline) = lock.as_mut() {
+                                    state.render_pass(|render_pass| pipeline.rebuild(render_pass, set))?;
+                                }
+                                Ok(true)
+                            }
+                            None => Ok(false),
+                        }
+                    }),
+                );
+            }
+
+            Ok(Self { bundle })
         })
     }
 
+    /// Binds the texture once it has loaded, and binds it again every time it's reloaded
+    pub fn bind_texture_handle<F: AsFormat + Send, TA: TextureType>(&self, handle: &Handle<Texture<F, TA, A, B, D, I>>) {
+        let weak_bundle = Arc::downgrade(&self.bundle);
+        handle.on_change(move |texture| match weak_bundle.upgrade() {
+            Some(bundle) => {
+                if let Some(pipeline) = bundle.read().unwrap().as_ref() {
+                    pipeline.bind_assets(texture.get_descriptors());
+                }
+                true
+            }
+            None => false,
+        });
+    }
+}
+
+impl<V: Vertex, A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Instance<Backend = B>> Pipeline<V, A, B, D, I> {
     pub(crate) fn layout_and_set<T: FnOnce(&B::PipelineLayout, &B::DescriptorSet) -> ()>(
         &self,
         callback: T,
@@ -76,6 +118,27 @@ impl<V: Vertex, A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Instance<Bac
             pipeline.bind_assets(descriptors);
         }
     }
+
+    /// Binds the shadow map and light data of the light, used together with the lit pipelines
+    pub fn bind_directional_light(&self, light: &DirectionalLight<A, B, D, I>) {
+        let lock = self.bundle.read().unwrap();
+        if let Some(pipeline) = lock.as_ref() {
+            let descriptors = light.get_descriptors();
+            pipeline.bind_assets(descriptors);
+        }
+    }
+
+    /// Binds a buffer written by a compute pipeline, so that the shaders can read from it
+    pub fn bind_storage_buffer<T: Copy + Send + Sync>(
+        &self,
+        binding: DescriptorBinding,
+        buffer: &StorageBuffer<T, A, B, D, I>,
+    ) {
+        let lock = self.bundle.read().unwrap();
+        if let Some(pipeline) = lock.as_ref() {
+            pipeline.bind_assets(vec![buffer.get_descriptor(binding)]);
+        }
+    }
 }
 
 pub trait PipelineEncoderExt<V: Vertex, A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Instance<Backend = B>> {
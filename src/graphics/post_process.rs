@@ -0,0 +1,199 @@
+use crate::graphics::ShaderDescription;
+use crate::graphics::ShaderSet;
+use std::borrow::Cow;
+
+/// A single full screen pass in a [`PostProcessChain`]
+///
+/// Every pass samples the output of the previous pass (or the rendered scene for the first pass)
+/// through `binding = 0` (texture) and `binding = 1` (sampler), and receives the following push
+/// constant block in the fragment stage:
+///
+/// ```glsl
+/// layout (push_constant) uniform PostProcessConstants {
+///   vec2 resolution;
+///   float time;
+///   float padding;
+///   vec4 parameters;
+/// } constants;
+/// ```
+#[derive(Debug, Clone)]
+pub enum PostProcessPass {
+    /// ACES filmic tonemapping followed by gamma correction
+    Tonemap { exposure: f32, gamma: f32 },
+    /// Fast approximate anti aliasing
+    Fxaa,
+    /// Darkens the edges of the screen
+    Vignette {
+        intensity: f32,
+        radius: f32,
+        softness: f32,
+    },
+    /// Colour grading using a lookup table image laid out as a horizontal strip of `size` slices,
+    /// each `size` x `size` pixels. The lut is bound at `binding = 2`
+    ColorGrading { lut: &'static [u8], intensity: f32 },
+    /// A user defined pass. `parameters` are passed to the shader as is
+    Custom {
+        shaders: ShaderSet,
+        parameters: [f32; 4],
+    },
+}
+
+impl PostProcessPass {
+    /// Tonemapping with neutral exposure. The gamma is left at 1.0 since the swapchain is
+    /// usually sRGB and will do the conversion for us
+    pub fn tonemap() -> Self {
+        PostProcessPass::Tonemap {
+            exposure: 1.0,
+            gamma: 1.0,
+        }
+    }
+
+    pub fn fxaa() -> Self {
+        PostProcessPass::Fxaa
+    }
+
+    pub fn vignette() -> Self {
+        PostProcessPass::Vignette {
+            intensity: 0.8,
+            radius: 0.75,
+            softness: 0.45,
+        }
+    }
+
+    pub fn color_grading(lut: &'static [u8]) -> Self {
+        PostProcessPass::ColorGrading {
+            lut,
+            intensity: 1.0,
+        }
+    }
+
+    /// Creates a custom pass from a fragment shader, using the built in full screen vertex shader
+    pub fn custom(fragment: ShaderDescription, parameters: [f32; 4]) -> Self {
+        PostProcessPass::Custom {
+            shaders: ShaderSet {
+                vertex: Self::fullscreen_vertex_shader(),
+                hull: None,
+                domain: None,
+                geometry: None,
+                fragment: Some(fragment),
+            },
+            parameters,
+        }
+    }
+
+    /// The vertex shader used by all built in passes. It draws a single triangle covering the
+    /// screen and outputs the uv coordinates at `location = 0`
+    pub fn fullscreen_vertex_shader() -> ShaderDescription {
+        ShaderDescription {
+            spirv: Cow::Borrowed(include_bytes!(concat!(env!("OUT_DIR"), "/post_fullscreen.vert.spv"))),
+            push_constant_floats: 0,
+            bindings: vec![],
+            source_path: None,
+        }
+    }
+
+    pub(crate) fn shader_set(&self) -> ShaderSet {
+        let fragment: &'static [u8] = match self {
+            PostProcessPass::Tonemap { .. } => {
+                include_bytes!(concat!(env!("OUT_DIR"), "/post_tonemap.frag.spv"))
+            }
+            PostProcessPass::Fxaa => include_bytes!(concat!(env!("OUT_DIR"), "/post_fxaa.frag.spv")),
+            PostProcessPass::Vignette { .. } => {
+                include_bytes!(concat!(env!("OUT_DIR"), "/post_vignette.frag.spv"))
+            }
+            PostProcessPass::ColorGrading { .. } => {
+                include_bytes!(concat!(env!("OUT_DIR"), "/post_color_grading.frag.spv"))
+            }
+            PostProcessPass::Custom { shaders, .. } => return shaders.clone(),
+        };
+
+        ShaderSet {
+            vertex: Self::fullscreen_vertex_shader(),
+            hull: None,
+            domain: None,
+            geometry: None,
+            fragment: Some(ShaderDescription {
+                spirv: Cow::Borrowed(fragment),
+                push_constant_floats: 8,
+                bindings: vec![],
+                source_path: None,
+            }),
+        }
+    }
+
+    /// The values passed in `constants.parameters`. The lut size of colour grading passes is
+    /// only known once the lut has been decoded, so it is passed in separately
+    pub(crate) fn parameters(&self, lut_size: f32) -> [f32; 4] {
+        match self {
+            PostProcessPass::Tonemap { exposure, gamma } => [*exposure, *gamma, 0.0, 0.0],
+            PostProcessPass::Fxaa => [0.0; 4],
+            PostProcessPass::Vignette {
+                intensity,
+                radius,
+                softness,
+            } => [*intensity, *radius, *softness, 0.0],
+            PostProcessPass::ColorGrading { intensity, .. } => [lut_size, *intensity, 0.0, 0.0],
+            PostProcessPass::Custom { parameters, .. } => *parameters,
+        }
+    }
+}
+
+/// An ordered list of full screen passes applied after the scene has been rendered
+///
+/// # Examples
+///
+/// ```
+/// use starstruck::graphics::PostProcessChain;
+/// use starstruck::graphics::PostProcessPass;
+///
+/// let chain = PostProcessChain::new()
+///     .with_pass(PostProcessPass::tonemap())
+///     .with_pass(PostProcessPass::fxaa())
+///     .with_pass(PostProcessPass::vignette());
+///
+/// assert_eq!(chain.passes().len(), 3);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct PostProcessChain {
+    passes: Vec<PostProcessPass>,
+}
+
+impl PostProcessChain {
+    pub fn new() -> Self {
+        Self { passes: vec![] }
+    }
+
+    pub fn with_pass(mut self, pass: PostProcessPass) -> Self {
+        self.passes.push(pass);
+        self
+    }
+
+    pub fn passes(&self) -> &[PostProcessPass] {
+        &self.passes
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.passes.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::graphics::PostProcessPass;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn builtin_passes_should_push_eight_floats_to_the_fragment_stage() {
+        let set = PostProcessPass::fxaa().shader_set();
+
+        assert_eq!(set.vertex.push_constant_floats, 0);
+        assert_eq!(set.fragment.unwrap().push_constant_floats, 8);
+    }
+
+    #[test]
+    fn color_grading_should_pass_lut_size_as_first_parameter() {
+        let pass = PostProcessPass::color_grading(&[]);
+
+        assert_eq!(pass.parameters(16.0), [16.0, 1.0, 0.0, 0.0]);
+    }
+}
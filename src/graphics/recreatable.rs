@@ -0,0 +1,15 @@
+use crate::allocator::GpuAllocator;
+use crate::setup_context::SetupContext;
+use failure::Error;
+use futures::Future;
+use gfx_hal::Backend;
+use gfx_hal::Device;
+use gfx_hal::Instance;
+
+/// A resource that keeps the data it was uploaded from, so that it can be created again after the
+/// device was lost. Resources only keep their data when they are created with one of the
+/// `create_recreatable_*` functions on the setup context, recreating any other resource fails
+pub trait Recreatable<A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Instance<Backend = B>>: Sized {
+    /// Uploads the retained data again using the device of `context`
+    fn recreate(&self, context: &SetupContext<A, B, D, I>) -> Box<Future<Item = Self, Error = Error> + Send>;
+}
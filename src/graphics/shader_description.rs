@@ -1,10 +1,60 @@
+use crate::internal::graphics::compile_glsl;
+use crate::internal::graphics::compile_glsl_file;
+use crate::internal::graphics::reflect;
+use failure::Error;
 use gfx_hal::pso::DescriptorArrayIndex;
 use gfx_hal::pso::DescriptorBinding;
 use gfx_hal::pso::DescriptorType;
+use gfx_hal::pso::Stage as ShaderStage;
+use std::borrow::Cow;
+use std::path::Path;
+use std::path::PathBuf;
 
 #[derive(Debug, Clone)]
 pub struct ShaderDescription {
-    pub spirv: &'static [u8],
+    pub spirv: Cow<'static, [u8]>,
     pub push_constant_floats: u32,
     pub bindings: Vec<(DescriptorBinding, DescriptorType, DescriptorArrayIndex)>,
+    /// The glsl file this shader was compiled from. Pipelines created from shaders with a source
+    /// path are rebuilt when the file changes, in debug builds
+    pub source_path: Option<PathBuf>,
+}
+
+impl ShaderDescription {
+    /// Reads the push constants and descriptor bindings from the SPIR-V module itself
+    ///
+    /// # Errors
+    ///
+    /// Fails if the module isn't valid SPIR-V, or if it uses a descriptor set other than 0
+    pub fn reflect<S: Into<Cow<'static, [u8]>>>(spirv: S) -> Result<Self, Error> {
+        let spirv = spirv.into();
+        let reflection = reflect(&spirv)?;
+        Ok(Self {
+            spirv,
+            push_constant_floats: reflection.push_constant_floats,
+            bindings: reflection.bindings,
+            source_path: None,
+        })
+    }
+
+    /// Compiles glsl source code into SPIR-V at runtime. Push constants and bindings are
+    /// reflected from the compiled shader
+    ///
+    /// # Errors
+    ///
+    /// Returns the compile errors, prefixed with the line they occurred on
+    pub fn from_glsl(source: &str, stage: ShaderStage) -> Result<Self, Error> {
+        Self::reflect(compile_glsl(source, stage, "<source>")?)
+    }
+
+    /// Reads and compiles a glsl file, `#include`s are resolved relative to the file. In debug
+    /// builds any pipeline created from this shader will watch the file and rebuild itself when the
+    /// file changes
+    pub fn from_glsl_file<P: AsRef<Path>>(path: P, stage: ShaderStage) -> Result<Self, Error> {
+        let path = path.as_ref();
+        Ok(Self {
+            source_path: Some(path.to_path_buf()),
+            ..Self::reflect(compile_glsl_file(path, stage)?)?
+        })
+    }
 }
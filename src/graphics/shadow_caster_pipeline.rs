@@ -0,0 +1,69 @@
+use crate::allocator::DefaultChunk;
+use crate::allocator::DefaultGpuAllocator;
+use crate::allocator::GpuAllocator;
+use crate::graphics::ShaderDescription;
+use crate::internal::graphics::GraphicsState;
+use crate::internal::graphics::ShadowCasterBundle;
+use crate::primitive::Vertex;
+use failure::Error;
+use futures::lazy;
+use futures::Future;
+use gfx_hal::Backend;
+use gfx_hal::Device;
+use gfx_hal::Instance;
+use std::fmt;
+use std::fmt::Debug;
+use std::fmt::Formatter;
+use std::sync::Arc;
+use std::borrow::Cow;
+
+/// A depth only pipeline used to draw bundles into the shadow map of a light
+///
+/// The vertex shader receives the light space matrix as a `mat4` push constant and the position
+/// of the vertex at `location = 0`
+#[allow(clippy::type_complexity)]
+pub struct ShadowCasterPipeline<
+    V: Vertex,
+    A: GpuAllocator<B, D> = DefaultGpuAllocator<DefaultChunk<backend::Backend, backend::Device>, backend::Backend, backend::Device>,
+    B: Backend = backend::Backend,
+    D: Device<B> = backend::Device,
+    I: Instance<Backend = B> = backend::Instance,
+> {
+    bundle: ShadowCasterBundle<V, A, B, D, I>,
+}
+
+impl<V: Vertex, A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Instance<Backend = B>> ShadowCasterPipeline<V, A, B, D, I> {
+    pub(crate) fn new(
+        state: Arc<GraphicsState<A, B, D, I>>,
+        vertex: ShaderDescription,
+    ) -> impl Future<Item = Self, Error = Error> + Send {
+        lazy(move || {
+            Ok(Self {
+                bundle: ShadowCasterBundle::new(state, &vertex)?,
+            })
+        })
+    }
+
+    /// The vertex shader used by the default shadow caster pipelines
+    pub fn default_vertex_shader() -> ShaderDescription {
+        ShaderDescription {
+            spirv: Cow::Borrowed(include_bytes!(concat!(env!("OUT_DIR"), "/shadow_caster.vert.spv"))),
+            push_constant_floats: 16,
+            bindings: vec![],
+            source_path: None,
+        }
+    }
+
+    pub(crate) fn bundle(&self) -> &ShadowCasterBundle<V, A, B, D, I> {
+        &self.bundle
+    }
+}
+
+impl<V: Vertex, A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Instance<Backend = B>> Debug
+    for ShadowCasterPipeline<V, A, B, D, I>
+{
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(f, "{:?}", self.bundle)?;
+        Ok(())
+    }
+}
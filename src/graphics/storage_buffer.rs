@@ -0,0 +1,116 @@
+use crate::allocator::DefaultChunk;
+use crate::allocator::DefaultGpuAllocator;
+use crate::allocator::GpuAllocator;
+use crate::errors::AssetError;
+use crate::graphics::Recreatable;
+use crate::internal::graphics::BufferBundle;
+use crate::internal::graphics::GraphicsState;
+use crate::internal::graphics::GPU;
+use crate::setup_context::SetupContext;
+use failure::Error;
+use futures::future::failed;
+use futures::lazy;
+use futures::Future;
+use gfx_hal::buffer::Usage as BufferUsage;
+use gfx_hal::pso::Descriptor;
+use gfx_hal::pso::DescriptorArrayIndex;
+use gfx_hal::pso::DescriptorBinding;
+use gfx_hal::Backend;
+use gfx_hal::Device;
+use gfx_hal::Instance;
+use std::fmt;
+use std::fmt::Debug;
+use std::fmt::Formatter;
+use std::sync::Arc;
+
+/// A device local buffer that compute shaders can read and write. Graphics pipelines can read it as
+/// well, so the results can be drawn without a round trip through the cpu
+#[allow(clippy::type_complexity)]
+pub struct StorageBuffer<
+    T: Copy + Send + Sync,
+    A: GpuAllocator<B, D> = DefaultGpuAllocator<DefaultChunk<backend::Backend, backend::Device>, backend::Backend, backend::Device>,
+    B: Backend = backend::Backend,
+    D: Device<B> = backend::Device,
+    I: Instance<Backend = B> = backend::Instance,
+> {
+    bundle: BufferBundle<A, B, D, I, GPU, T>,
+    len: usize,
+    retained: Option<Arc<Vec<T>>>,
+}
+
+impl<T: Copy + Send + Sync, A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Instance<Backend = B>>
+    StorageBuffer<T, A, B, D, I>
+{
+    pub(crate) fn new(
+        state: Arc<GraphicsState<A, B, D, I>>,
+        data: Arc<Vec<T>>,
+        retain: bool,
+    ) -> impl Future<Item = Self, Error = Error> + Send {
+        lazy(move || {
+            if data.is_empty() {
+                return Err(AssetError::Empty("storage buffer").into());
+            }
+            Ok((state, data))
+        })
+        .and_then(|(state, data)| {
+            let len = data.len();
+            let retained = if retain { Some(Arc::clone(&data)) } else { None };
+            BufferBundle::<A, B, D, I, GPU, T>::new(
+                state,
+                BufferUsage::STORAGE | BufferUsage::TRANSFER_SRC,
+                data,
+            )
+            .map(move |bundle| Self {
+                bundle,
+                len,
+                retained,
+            })
+        })
+    }
+
+    /// The number of elements in the buffer
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Reads the current contents back from the GPU, for example the results of a compute pipeline
+    pub fn read_back<'a>(&'a self) -> impl Future<Item = Vec<T>, Error = Error> + Send + 'a {
+        self.bundle.read_back()
+    }
+
+    pub(crate) fn buffer(&self) -> &B::Buffer {
+        &self.bundle.buffer
+    }
+
+    pub(crate) fn get_descriptor(
+        &self,
+        binding: DescriptorBinding,
+    ) -> (DescriptorBinding, DescriptorArrayIndex, Descriptor<B>) {
+        (binding, 0, Descriptor::Buffer(self.buffer(), None..None))
+    }
+}
+
+impl<T: 'static + Copy + Send + Sync, A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Instance<Backend = B>>
+    Recreatable<A, B, D, I> for StorageBuffer<T, A, B, D, I>
+{
+    /// Uploads the data the buffer was created with, anything written to it since is lost
+    fn recreate(&self, context: &SetupContext<A, B, D, I>) -> Box<Future<Item = Self, Error = Error> + Send> {
+        match &self.retained {
+            Some(data) => Box::new(Self::new(context.state(), Arc::clone(data), true)),
+            None => Box::new(failed(AssetError::NotRecreatable("storage buffer").into())),
+        }
+    }
+}
+
+impl<T: Copy + Send + Sync, A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Instance<Backend = B>> Debug
+    for StorageBuffer<T, A, B, D, I>
+{
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(f, "StorageBuffer {{ len: {} }}", self.len)?;
+        Ok(())
+    }
+}
@@ -1,6 +1,7 @@
 use crate::internal::graphics::GraphicsState;
 use crate::internal::graphics::TextureBundle;
 use failure::Error;
+use futures::future::failed;
 use futures::lazy;
 use futures::Future;
 use gfx_hal::image::Layout;
@@ -14,13 +15,18 @@ use std::fmt;
 use std::fmt::Debug;
 use std::fmt::Formatter;
 use std::sync::Arc;
+use crate::errors::AssetError;
 use crate::allocator::GpuAllocator;
 use crate::allocator::DefaultGpuAllocator;
 use crate::allocator::DefaultChunk;
 use crate::internal::graphics::Single;
 use gfx_hal::format::Rgba8Srgb;
+use gfx_hal::format::Rgba8Unorm;
 use gfx_hal::format::AsFormat;
 use image::DynamicImage;
+use image::RgbaImage;
+use crate::graphics::Recreatable;
+use crate::setup_context::SetupContext;
 use crate::internal::graphics::TextureType;
 
 macro_rules! implement_format {
@@ -29,15 +35,35 @@ macro_rules! implement_format {
             pub fn new(
                 state: Arc<GraphicsState<A, B, D, I>>,
                 image: DynamicImage,
+                retain: bool,
             ) -> impl Future<Item = Self, Error = Error> + Send {
                 lazy(move || {
                     let the_image = $y(&image);
 
                     Ok((state, the_image))
                 })
-                    .map(|(st, the_image)| (TextureBundle::<$x, Single, A, B, D, I>::new(st, 1, the_image.width(), the_image.height()), the_image))
-                    .and_then(|(texture, the_image)| texture.and_then(|tex| tex.write_data_from_image(the_image)))
-                    .map(|texture| Self { texture })
+                    .and_then(move |(st, the_image)| Self::from_rgba(st, Arc::new(the_image), retain))
+            }
+
+            pub(crate) fn from_rgba(
+                state: Arc<GraphicsState<A, B, D, I>>,
+                image: Arc<RgbaImage>,
+                retain: bool,
+            ) -> impl Future<Item = Self, Error = Error> + Send {
+                let retained = if retain { Some(Arc::clone(&image)) } else { None };
+                TextureBundle::<$x, Single, A, B, D, I>::new(state, 1, image.width(), image.height())
+                    // Only copies the image if it's being retained
+                    .and_then(move |tex| tex.write_data_from_image(Arc::try_unwrap(image).unwrap_or_else(|image| (*image).clone())))
+                    .map(move |texture| Self { texture, retained })
+            }
+        }
+
+        impl<A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Instance<Backend = B>> Recreatable<A, B, D, I> for Texture<$x, Single, A, B, D, I> {
+            fn recreate(&self, context: &SetupContext<A, B, D, I>) -> Box<Future<Item = Self, Error = Error> + Send> {
+                match &self.retained {
+                    Some(image) => Box::new(Self::from_rgba(context.state(), Arc::clone(image), true)),
+                    None => Box::new(failed(AssetError::NotRecreatable("texture").into())),
+                }
             }
         }
     };
@@ -52,15 +78,18 @@ pub struct Texture<
     I: Instance<Backend = B> = backend::Instance,
 > {
     texture: TextureBundle<F, TA, A, B, D, I>,
+    retained: Option<Arc<RgbaImage>>,
 }
 
 implement_format!(Rgba8Srgb, DynamicImage::to_rgba);
+implement_format!(Rgba8Unorm, DynamicImage::to_rgba);
 
 impl<F: AsFormat + Send, A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Instance<Backend = B>> Texture<F, Single, A, B, D, I> {
     pub fn sized(state: Arc<GraphicsState<A, B, D, I>>, mip_map_levels: u8, width: u32, height: u32) -> impl Future<Item = Self, Error = Error> + Send {
         TextureBundle::<F, Single, A, B, D, I>::new(state, mip_map_levels, width, height).map(|texture| {
             Self {
-                texture
+                texture,
+                retained: None,
             }
         })
     }
@@ -73,6 +102,23 @@ impl<F: AsFormat + Send, A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Ins
 
 impl<F: AsFormat + Send, TA: TextureType, A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Instance<Backend = B>> Texture<F, TA, A, B, D, I> {
 
+    pub fn width(&self) -> u32 {
+        self.texture.width()
+    }
+
+    pub fn height(&self) -> u32 {
+        self.texture.height()
+    }
+
+    /// Reads the pixels of the first layer back from the GPU, tightly packed row by row
+    pub fn read_back<'a>(&'a self) -> impl Future<Item = Vec<u8>, Error = Error> + Send + 'a {
+        self.texture.read_back()
+    }
+
+    pub(crate) fn image_view(&self) -> &B::ImageView {
+        self.texture.image_view()
+    }
+
     pub(crate) fn get_descriptors(
         &self,
     ) -> Vec<(DescriptorBinding, DescriptorArrayIndex, Descriptor<B>)> {
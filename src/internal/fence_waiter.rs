@@ -0,0 +1,119 @@
+use std::sync::mpsc::channel;
+use std::sync::mpsc::Receiver;
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::thread;
+
+/// How long the waiter blocks on a single fence before it checks the others again
+const WAIT_SLICE_NS: u64 = 1_000_000;
+
+/// A fence that the waiter thread can block on
+pub trait PendingFence: Send + Sync {
+    /// Waits at most `timeout_ns` nanoseconds. Returns true once the fence is signaled, or if it
+    /// never will be because the device was lost
+    fn wait(&self, timeout_ns: u64) -> bool;
+
+    /// Wakes the task that is waiting for the fence
+    fn notify(&self);
+}
+
+/// Blocks on fences on a background thread, so that futures waiting for the GPU don't have to keep
+/// an executor thread busy
+pub struct FenceWaiter {
+    sender: Mutex<Sender<Arc<dyn PendingFence>>>,
+}
+
+impl FenceWaiter {
+    pub fn new() -> Self {
+        let (sender, receiver) = channel();
+        thread::Builder::new()
+            .name("starstruck-fence-waiter".to_string())
+            .spawn(move || Self::run(receiver))
+            .expect("Failed to spawn the fence waiter thread");
+        Self {
+            sender: Mutex::new(sender),
+        }
+    }
+
+    pub(crate) fn sender(&self) -> Sender<Arc<dyn PendingFence>> {
+        self.sender.lock().unwrap().clone()
+    }
+
+    fn run(receiver: Receiver<Arc<dyn PendingFence>>) {
+        let mut pending: Vec<Arc<dyn PendingFence>> = Vec::new();
+        loop {
+            if pending.is_empty() {
+                match receiver.recv() {
+                    Ok(fence) => pending.push(fence),
+                    Err(_) => return,
+                }
+            }
+            pending.extend(receiver.try_iter());
+
+            // Sleep on the oldest fence, it is usually the first one to finish
+            pending[0].wait(WAIT_SLICE_NS);
+            pending.retain(|fence| {
+                if fence.wait(0) {
+                    fence.notify();
+                    false
+                } else {
+                    true
+                }
+            });
+        }
+    }
+}
+
+impl Default for FenceWaiter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::atomic::Ordering;
+    use std::sync::mpsc::SyncSender;
+    use std::sync::mpsc::sync_channel;
+    use std::time::Duration;
+
+    struct FakeFence {
+        waits_left: AtomicUsize,
+        notified: Mutex<SyncSender<()>>,
+    }
+
+    impl PendingFence for FakeFence {
+        fn wait(&self, _timeout_ns: u64) -> bool {
+            let left = self.waits_left.load(Ordering::SeqCst);
+            if left == 0 {
+                true
+            } else {
+                self.waits_left.store(left - 1, Ordering::SeqCst);
+                false
+            }
+        }
+
+        fn notify(&self) {
+            self.notified.lock().unwrap().send(()).unwrap();
+        }
+    }
+
+    #[test]
+    fn it_should_notify_once_the_fence_is_signaled() {
+        let waiter = FenceWaiter::new();
+        let (sender, receiver) = sync_channel(1);
+        let fence = Arc::new(FakeFence {
+            waits_left: AtomicUsize::new(5),
+            notified: Mutex::new(sender),
+        });
+
+        waiter.sender().send(Arc::clone(&fence) as Arc<dyn PendingFence>).unwrap();
+
+        assert_eq!(receiver.recv_timeout(Duration::from_secs(5)), Ok(()));
+        assert_eq!(fence.waits_left.load(Ordering::SeqCst), 0);
+    }
+}
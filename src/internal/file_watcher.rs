@@ -0,0 +1,106 @@
+use std::collections::HashMap;
+use std::fs::metadata;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
+use std::time::Instant;
+use std::time::SystemTime;
+
+/// The modification time and the length of a file. Some file systems only keep the time to the
+/// second, so the length catches most changes made within the same second
+type Stamp = (SystemTime, u64);
+
+/// Watches files by polling their modification time and length. This avoids depending on platform
+/// specific notification apis, and is good enough for the handful of files watched during
+/// development
+#[derive(Debug)]
+pub struct FileWatcher {
+    files: HashMap<PathBuf, Option<Stamp>>,
+    interval: Duration,
+    last_poll: Instant,
+}
+
+impl FileWatcher {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            files: HashMap::new(),
+            interval,
+            last_poll: Instant::now(),
+        }
+    }
+
+    pub fn watch<P: AsRef<Path>>(&mut self, path: P) {
+        let path = path.as_ref().to_path_buf();
+        let stamp = Self::stamp(&path);
+        self.files.entry(path).or_insert(stamp);
+    }
+
+    pub fn unwatch<P: AsRef<Path>>(&mut self, path: P) {
+        self.files.remove(path.as_ref());
+    }
+
+    /// Returns the files that changed since the last poll. Does nothing if called more often than
+    /// the interval
+    pub fn poll(&mut self) -> Vec<PathBuf> {
+        if self.last_poll.elapsed() < self.interval {
+            return vec![];
+        }
+        self.last_poll = Instant::now();
+        self.poll_now()
+    }
+
+    pub fn poll_now(&mut self) -> Vec<PathBuf> {
+        let mut changed = vec![];
+        for (path, last_stamp) in &mut self.files {
+            let stamp = Self::stamp(path);
+            // Editors often remove the file before writing it again, wait until it's back
+            if stamp.is_some() && stamp != *last_stamp {
+                *last_stamp = stamp;
+                changed.push(path.clone());
+            }
+        }
+        changed
+    }
+
+    fn stamp(path: &Path) -> Option<Stamp> {
+        let data = metadata(path).ok()?;
+        Some((data.modified().ok()?, data.len()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::internal::FileWatcher;
+    use crate::internal::TestDirectory;
+    use pretty_assertions::assert_eq;
+    use std::fs::write;
+    use std::time::Duration;
+
+    #[test]
+    fn it_should_report_files_created_after_they_were_watched_once() {
+        let directory = TestDirectory::new("file_watcher");
+        let path = directory.join("shader.glsl");
+
+        let mut watcher = FileWatcher::new(Duration::from_millis(0));
+        watcher.watch(&path);
+        assert_eq!(watcher.poll_now(), vec![]);
+
+        write(&path, "void main() {}").unwrap();
+        assert_eq!(watcher.poll_now(), vec![path.clone()]);
+        assert_eq!(watcher.poll_now(), vec![]);
+    }
+
+    #[test]
+    fn it_should_report_files_whose_length_changed_within_the_same_modification_time() {
+        let directory = TestDirectory::new("file_watcher_length");
+        let path = directory.join("number.txt");
+        write(&path, "1").unwrap();
+
+        let mut watcher = FileWatcher::new(Duration::from_millis(0));
+        watcher.watch(&path);
+        let (modified, _) = watcher.files[&path].unwrap();
+        // Pretend the file was written again within the resolution of the file system
+        watcher.files.insert(path.clone(), Some((modified, 3)));
+        assert_eq!(watcher.poll_now(), vec![path.clone()]);
+    }
+}
@@ -1,40 +1,121 @@
+use crate::errors::CreateEncoderError;
+use crate::errors::CreateEncoderErrorKind;
+use crate::internal::FenceWaiter;
+use crate::internal::PendingFence;
+use failure::Error;
+use futures::task::current;
+use futures::task::AtomicTask;
+use futures::Async;
+use futures::Future;
+use futures::Poll;
 use gfx_hal::device::Device;
 use gfx_hal::Backend;
 use std::mem::ManuallyDrop;
 use std::ops::Deref;
+use std::sync::mpsc::Sender;
 use std::sync::Arc;
 
-pub struct FutureFence<B: Backend, D: Device<B>> {
+struct FenceInner<B: Backend, D: Device<B>> {
     fence: ManuallyDrop<B::Fence>,
     device: Arc<D>,
+    task: AtomicTask,
 }
 
-impl<B: Backend, D: Device<B>> FutureFence<B, D> {
-    pub fn new(fence: B::Fence, device: Arc<D>) -> Self {
-        Self {
-            fence: ManuallyDrop::new(fence),
-            device,
-        }
+impl<B: Backend, D: Device<B>> PendingFence for FenceInner<B, D> {
+    fn wait(&self, timeout_ns: u64) -> bool {
+        unsafe { self.device.wait_for_fence(&self.fence, timeout_ns) }.unwrap_or(true)
+    }
+
+    fn notify(&self) {
+        self.task.notify();
     }
 }
 
-impl<B: Backend, D: Device<B>> Drop for FutureFence<B, D> {
+impl<B: Backend, D: Device<B>> Drop for FenceInner<B, D> {
     fn drop(&mut self) {
         use core::ptr::read;
         unsafe {
+            // The fence has been submitted and must not be destroyed before it signals. This only
+            // blocks if the fence waiter is gone, it otherwise keeps the fence until it's done
+            let _ = self.device.wait_for_fence(&self.fence, core::u64::MAX);
             self.device
                 .destroy_fence(ManuallyDrop::into_inner(read(&self.fence)));
         }
     }
 }
 
+/// A fence that resolves once it is signaled. Waiting is done by the [`FenceWaiter`], so polling it
+/// never blocks. The fence must already have been submitted. It's destroyed once it has signalled,
+/// even if the future is dropped before that
+pub struct FutureFence<B: Backend, D: Device<B>> {
+    inner: Arc<FenceInner<B, D>>,
+    waiter: Option<Sender<Arc<dyn PendingFence>>>,
+    watched: bool,
+}
+
+impl<B: Backend, D: Device<B>> FutureFence<B, D> {
+    pub fn new(fence: B::Fence, device: Arc<D>, waiter: &FenceWaiter) -> Self {
+        Self {
+            inner: Arc::new(FenceInner {
+                fence: ManuallyDrop::new(fence),
+                device,
+                task: AtomicTask::new(),
+            }),
+            waiter: Some(waiter.sender()),
+            watched: false,
+        }
+    }
+}
+
+impl<B: Backend, D: Device<B>> FutureFence<B, D> {
+    /// The fence, for those that need to know when it has signalled without polling the future
+    pub(crate) fn pending(&self) -> Arc<dyn PendingFence> {
+        self.inner.clone()
+    }
+}
+
+impl<B: Backend, D: Device<B>> Drop for FutureFence<B, D> {
+    fn drop(&mut self) {
+        // Let the waiter hold on to the fence until it signals, rather than blocking here
+        if let Some(waiter) = self.waiter.take() {
+            let inner: Arc<dyn PendingFence> = self.inner.clone();
+            let _ = waiter.send(inner);
+        }
+    }
+}
+
+impl<B: Backend, D: Device<B>> Future for FutureFence<B, D> {
+    type Item = ();
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<(), Error> {
+        self.inner.task.register();
+        match unsafe { self.inner.device.get_fence_status(&self.inner.fence) } {
+            Ok(true) => return Ok(Async::Ready(())),
+            Ok(false) => {}
+            Err(_) => Err(CreateEncoderError::from(CreateEncoderErrorKind::DeviceLost))?,
+        }
+
+        // Hand the fence to the waiter the first time around. If the waiter is gone we fall back
+        // to polling
+        if let Some(waiter) = self.waiter.take() {
+            let inner: Arc<dyn PendingFence> = self.inner.clone();
+            self.watched = waiter.send(inner).is_ok();
+        }
+        if !self.watched {
+            current().notify();
+        }
+        Ok(Async::NotReady)
+    }
+}
+
 pub trait FenceExt<B: Backend, D: Device<B>> {
-    fn into_promise(self, device: Arc<D>) -> FutureFence<B, D>;
+    fn into_promise(self, device: Arc<D>, waiter: &FenceWaiter) -> FutureFence<B, D>;
 }
 
 impl<B: Backend, D: Device<B>> FenceExt<B, D> for B::Fence {
-    fn into_promise(self, device: Arc<D>) -> FutureFence<B, D> {
-        FutureFence::new(self, device)
+    fn into_promise(self, device: Arc<D>, waiter: &FenceWaiter) -> FutureFence<B, D> {
+        FutureFence::new(self, device, waiter)
     }
 }
 
@@ -42,6 +123,6 @@ impl<B: Backend, D: Device<B>> Deref for FutureFence<B, D> {
     type Target = B::Fence;
 
     fn deref(&self) -> &Self::Target {
-        &self.fence
+        &self.inner.fence
     }
 }
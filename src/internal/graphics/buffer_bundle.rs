@@ -1,39 +1,34 @@
+use crate::errors::AllocationError;
+use crate::internal::graphics::Corpse;
 use crate::internal::graphics::GraphicsState;
-use crate::internal::FenceExt;
+use crate::internal::graphics::UploadDestination;
 use colored::*;
 use core::mem::{size_of, ManuallyDrop};
 use failure::Error;
-use futures::future::poll_fn;
 use futures::lazy;
 use futures::prelude::*;
-use futures::task::current;
+use gfx_hal::buffer::Access;
 use gfx_hal::buffer::Usage as BufferUsage;
 use gfx_hal::command::BufferCopy;
-use gfx_hal::command::CommandBuffer;
-use gfx_hal::command::OneShot;
-use gfx_hal::command::Primary;
+use gfx_hal::memory::Barrier;
+use gfx_hal::memory::Dependencies;
 use gfx_hal::memory::Properties;
 use gfx_hal::memory::Requirements;
-use gfx_hal::pool::CommandPoolCreateFlags;
-use gfx_hal::queue::family::QueueFamily;
+use gfx_hal::pso::PipelineStage;
 use gfx_hal::Backend;
 use gfx_hal::Device;
-use gfx_hal::Gpu;
 use gfx_hal::Instance;
 use gfx_hal::MemoryTypeId;
 use gfx_hal::PhysicalDevice;
-use gfx_hal::QueueGroup;
-use gfx_hal::Transfer;
 use std::fmt;
 use std::fmt::Debug;
 use std::fmt::Formatter;
 use std::marker::PhantomData;
+use std::slice;
 use std::sync::Arc;
 use crate::allocator::Memory;
 use crate::allocator::GpuAllocator;
-use image::Pixel;
-use image::ImageBuffer;
-use std::ops::Deref;
+use crate::allocator::MemoryUsage;
 
 pub trait BufferBundlePlace {}
 pub struct CPU {}
@@ -52,8 +47,7 @@ pub struct BufferBundle<
     T: Copy + Send + Sync,
 > {
     pub buffer: ManuallyDrop<B::Buffer>,
-    pub memory: Memory<B>,
-    pub queue_group: QueueGroup<B, Transfer>,
+    pub memory: ManuallyDrop<Memory<B>>,
     state: Arc<GraphicsState<A, B, D, I>>,
     pub requirements: Requirements,
     buffer_len: u64,
@@ -73,51 +67,18 @@ impl<A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Instance<Backend = B>,
         Self::create_buffer(state, buffer_len, usage, Properties::CPU_VISIBLE)
     }
 
-    pub fn write_data(mut self, data: Arc<Vec<T>>) -> impl Future<Item = Self, Error = Error> + Send {
-        lazy(move || {
-            trace!("Writing data into buffer");
-            unsafe {
-                let mut writer = self.memory.acquire_mapping_writer(&self
-                    .state
-                    .device(), 0..self.requirements.size)?;
-                writer[..data.len()].copy_from_slice(&data);
-                self.state.device().release_mapping_writer(writer)?;
-            }
-            Ok(self)
-        })
+    /// Reads the whole buffer into a vec, the memory must be host visible
+    pub fn read_data(&self) -> Result<Vec<T>, Error> {
+        trace!("Reading data from buffer");
+        let atom_size = self.state.limits().non_coherent_atom_size as u64;
+        let count = self.buffer_len as usize / size_of::<T>();
+        unsafe { self.memory.read(&self.state.device(), atom_size, 0, count) }
     }
-}
-
-impl<T: Copy + Send + Sync, A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Instance<Backend = B>>
-    BufferBundle<A, B, D, I, CPU, T>
-{
-    pub fn write_image_data<P, C>(
-        mut self,
-        image: ImageBuffer<P, C>,
-        row_size: u32,
-        row_pitch: u32
-    ) -> impl Future<Item = Self, Error = Error> + Send where
-        P: Pixel + 'static + Send,
-        P::Subpixel: 'static,
-        C: Deref<Target = [P::Subpixel]> + Send {
-        lazy(move || {
-            info!("Writing data into buffer");
-            unsafe {
 
-                let mut writer = self.memory.acquire_mapping_writer(&self
-                    .state
-                    .device(), 0..self.requirements.size)?;
-
-                for y in 0..image.height() {
-                    let row = &(*image)[(y * row_size) as usize..((y + 1) * row_size) as usize];
-                    let dest_base = (y * row_pitch) as usize;
-                    writer[dest_base..dest_base + row.len()].copy_from_slice(row);
-                }
-
-                self.state.device().release_mapping_writer(writer)?;
-            }
-            Ok(self)
-        })
+    /// Writes the data straight away, used for small buffers that are updated every frame
+    pub fn write_slice(&mut self, data: &[T]) -> Result<(), Error> {
+        let atom_size = self.state.limits().non_coherent_atom_size as u64;
+        unsafe { self.memory.write(&self.state.device(), atom_size, 0, data) }
     }
 }
 
@@ -129,81 +90,88 @@ impl<A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Instance<Backend = B>,
         usage: BufferUsage,
         data: Arc<Vec<T>>,
     ) -> impl Future<Item = Self, Error = Error> + Send {
-        let buffer_len = (size_of::<T>() * data.len()) as _;
-        let bundle_future = Self::create_buffer(
-            Arc::clone(&state),
-            buffer_len,
-            usage | BufferUsage::TRANSFER_DST,
-            Properties::DEVICE_LOCAL,
-        );
-        let transfer_bundle_future = BufferBundle::<A, B, D, I, CPU, T>::create_buffer(
-            state,
-            buffer_len,
-            BufferUsage::TRANSFER_SRC,
-            Properties::CPU_VISIBLE,
-        );
-
-        bundle_future
-            .join(transfer_bundle_future)
-            .and_then(move |(bundle, transfer_bundle)| {
-                transfer_bundle
-                    .write_data(data)
-                    .and_then(move |t_bundle| bundle.import_data_from(t_bundle))
-            })
+        let buffer_len = (size_of::<T>() * data.len()) as u64;
+        Self::with_contents(state, usage, buffer_len, move |staging| unsafe {
+            let bytes = slice::from_raw_parts(data.as_ptr() as *const u8, buffer_len as usize);
+            staging.copy_from_slice(bytes);
+        })
     }
 
-    fn import_data_from(
-        mut self,
-        source: BufferBundle<A, B, D, I, CPU, T>,
+    /// Creates a buffer of `buffer_len` bytes, `write` fills in the contents straight into the
+    /// staging memory they are uploaded from
+    pub fn with_contents<W: FnOnce(&mut [u8]) + Send + 'static>(
+        state: Arc<GraphicsState<A, B, D, I>>,
+        usage: BufferUsage,
+        buffer_len: u64,
+        write: W,
     ) -> impl Future<Item = Self, Error = Error> + Send {
-        lazy(move || unsafe {
-            let device = self.state.device();
-            let queue_group = &mut self.queue_group;
-            let (buff, mut poo) = {
-                let mut pool = device
-                    .create_command_pool_typed(&queue_group, CommandPoolCreateFlags::TRANSIENT)?;
-                let mut buffer: CommandBuffer<B, Transfer, OneShot, Primary> =
-                    pool.acquire_command_buffer();
+        lazy(move || {
+            let bundle = Self::allocate(
+                Arc::clone(&state),
+                buffer_len,
+                usage | BufferUsage::TRANSFER_DST,
+                Properties::DEVICE_LOCAL,
+            )?;
 
-                trace!("Copying one buffer into another");
-                buffer.begin();
-                buffer.copy_buffer(
-                    &source.buffer,
-                    &self.buffer,
-                    &[BufferCopy {
-                        src: 0,
-                        dst: 0,
-                        size: self.buffer_len,
-                    }],
-                );
-                buffer.finish();
-                (buffer, pool)
-            };
+            trace!("Staging {} bytes for upload", buffer_len);
+            let upload = state.uploads().upload(
+                buffer_len,
+                1,
+                UploadDestination::Buffer(&bundle.buffer),
+                write,
+                |command_buffer, staging, offset| unsafe {
+                    command_buffer.copy_buffer(
+                        staging,
+                        &bundle.buffer,
+                        &[BufferCopy {
+                            src: offset,
+                            dst: 0,
+                            size: buffer_len,
+                        }],
+                    );
+                },
+            )?;
+            Ok(upload.map(move |_| bundle))
+        })
+        .flatten()
+    }
 
-            let raw_fence = device.create_fence(false).unwrap();
-            let fence = Arc::new(raw_fence.into_promise(Arc::clone(&device)));
-            let queue = &mut queue_group.queues[0];
-            queue.submit_nosemaphores(&[buff], Some(&fence));
-            poo.reset();
+    /// Copies the buffer into a host visible staging buffer on the main queue and resolves with its
+    /// contents once the copy is done. The buffer must have been created with `TRANSFER_SRC`
+    pub fn read_back<'a>(&'a self) -> impl Future<Item = Vec<T>, Error = Error> + Send + 'a {
+        lazy(move || self.submit_read_back()).flatten()
+    }
 
-            Ok((device, fence, self))
-        })
-        .and_then(|(dev, fe, se)| {
-            poll_fn(move || unsafe {
-                match dev.get_fence_status(&fe) {
-                    Ok(signaled) => {
-                        if signaled {
-                            Ok(Async::Ready(()))
-                        } else {
-                            current().notify();
-                            Ok(Async::NotReady)
-                        }
-                    }
-                    Err(e) => bail!(e),
-                }
-            })
-            .map(|_| se)
-        })
+    /// Like `read_back`, but the copy is submitted right away, so the future doesn't borrow the
+    /// buffer. Dropping the buffer before the copy is done is fine, the graveyard waits for it
+    pub fn submit_read_back(&self) -> Result<impl Future<Item = Vec<T>, Error = Error> + Send, Error> {
+        let staging = BufferBundle::<A, B, D, I, CPU, T>::allocate_for(
+            Arc::clone(&self.state),
+            self.buffer_len,
+            BufferUsage::TRANSFER_DST,
+            Properties::CPU_VISIBLE,
+            MemoryUsage::Transient,
+        )?;
+
+        trace!("Copying buffer back to the host");
+        let done = self.state.submit_once(|command_buffer| unsafe {
+            command_buffer.copy_buffer(
+                &self.buffer,
+                &staging.buffer,
+                &[BufferCopy {
+                    src: 0,
+                    dst: 0,
+                    size: self.buffer_len,
+                }],
+            );
+            command_buffer.pipeline_barrier(
+                PipelineStage::TRANSFER..PipelineStage::HOST,
+                Dependencies::empty(),
+                &[Barrier::AllBuffers(Access::TRANSFER_WRITE..Access::HOST_READ)],
+            );
+        })?;
+
+        Ok(done.and_then(move |_| staging.read_data()))
     }
 }
 
@@ -222,79 +190,78 @@ impl<
         usage: BufferUsage,
         memory_properties: Properties,
     ) -> impl Future<Item = Self, Error = Error> + Send {
-        lazy(move || {
-            let queue_group = {
-                let queue_family = state
-                    .adapter()
-                    .queue_families
-                    .iter()
-                    .find(|qf| qf.supports_transfer())
-                    .ok_or_else(|| format_err!("Couldn't find a QueueFamily with Transfer!"))?;
-                let Gpu {
-                    mut queues, ..
-                } = unsafe {
-                    state
-                        .adapter()
-                        .physical_device
-                        .open(&[(&queue_family, &[1.0; 1])])?
-                };
-                let queue_group = queues
-                    .take::<Transfer>(queue_family.id())
-                    .ok_or_else(|| format_err!("Couldn't take ownership of the QueueGroup!"))?;
-                if !queue_group.queues.is_empty() {
-                    Ok(())
-                } else {
-                    Err(format_err!(
-                        "The QueueGroup did not have any CommandQueues available!"
-                    ))
-                }?;
-                queue_group
-            };
-
-            trace!(
-                "{} {} {} {} {}",
-                "Allocating new buffer of type".green(),
-                format!("{:?}", usage).yellow(),
-                "that is".green(),
-                buffer_len.to_string().yellow(),
-                "bytes long".green()
-            );
-            unsafe {
-                let mut buffer = state.device().create_buffer(buffer_len, usage)?;
-                let requirements = state.device().get_buffer_requirements(&buffer);
+        lazy(move || Self::allocate(state, buffer_len, usage, memory_properties))
+    }
 
-                let memory_type_id = state
-                    .adapter()
-                    .physical_device
-                    .memory_properties()
-                    .memory_types
-                    .iter()
-                    .enumerate()
-                    .find(|&(id, memory_type)| {
-                        requirements.type_mask & (1 << id) != 0
-                            && memory_type.properties.contains(memory_properties)
-                    })
-                    .map(|(id, _)| MemoryTypeId(id))
-                    .ok_or_else(|| {
-                        format_err!("Couldn't find a memory type to support the buffer!")
-                    })?;
+    pub fn allocate(
+        state: Arc<GraphicsState<A, B, D, I>>,
+        buffer_len: u64,
+        usage: BufferUsage,
+        memory_properties: Properties,
+    ) -> Result<Self, Error> {
+        let memory_usage = if usage.contains(BufferUsage::UNIFORM) {
+            MemoryUsage::Uniform
+        } else {
+            MemoryUsage::General
+        };
+        Self::allocate_for(state, buffer_len, usage, memory_properties, memory_usage)
+    }
 
-                let mut memory = state.allocator().allocate_memory(memory_type_id, requirements.size)?;
-                memory.bind_buffer_memory(&state.device(), &mut buffer)?;
+    /// Like `allocate`, but tells the allocator what the memory will be used for
+    pub fn allocate_for(
+        state: Arc<GraphicsState<A, B, D, I>>,
+        buffer_len: u64,
+        usage: BufferUsage,
+        memory_properties: Properties,
+        memory_usage: MemoryUsage,
+    ) -> Result<Self, Error> {
+        trace!(
+            "{} {} {} {} {}",
+            "Allocating new buffer of type".green(),
+            format!("{:?}", usage).yellow(),
+            "that is".green(),
+            buffer_len.to_string().yellow(),
+            "bytes long".green()
+        );
+        unsafe {
+            // Buffers can't be empty, an empty bundle still gets a byte
+            let mut buffer = state.device().create_buffer(buffer_len.max(1), usage)?;
+            let requirements = state.device().get_buffer_requirements(&buffer);
 
-                Ok(BufferBundle {
-                    buffer: ManuallyDrop::new(buffer),
-                    memory,
-                    requirements,
-                    state,
-                    queue_group,
-                    buffer_len,
-                    usage,
-                    phantom: PhantomData,
-                    phantom_place: PhantomData,
+            let memory_type_id = state
+                .adapter()
+                .physical_device
+                .memory_properties()
+                .memory_types
+                .iter()
+                .enumerate()
+                .find(|&(id, memory_type)| {
+                    requirements.type_mask & (1 << id) != 0
+                        && memory_type.properties.contains(memory_properties)
                 })
-            }
-        })
+                .map(|(id, _)| MemoryTypeId(id))
+                .ok_or(AllocationError::NoSuitableMemoryType("buffer"))?;
+
+            let mut memory = state.allocator().allocate_memory_for(
+                memory_usage,
+                memory_type_id,
+                requirements.size,
+                requirements.alignment,
+            )?;
+            memory.set_label(format!("{:?} buffer of {} bytes", usage, buffer_len));
+            memory.bind_buffer_memory(&state.device(), &mut buffer)?;
+
+            Ok(BufferBundle {
+                buffer: ManuallyDrop::new(buffer),
+                memory: ManuallyDrop::new(memory),
+                requirements,
+                state,
+                buffer_len,
+                usage,
+                phantom: PhantomData,
+                phantom_place: PhantomData,
+            })
+        }
     }
 }
 
@@ -317,15 +284,12 @@ impl<
             "bytes of memory will be freed".red()
         );
 
-        let device = &self.state.device();
-        let buffer = &self.buffer;
-        let memory = &mut self.memory;
-
-        self.state.allocator().free_memory(memory);
-
+        // The buffer might still be used by a frame in flight
         unsafe {
-            device.destroy_buffer(ManuallyDrop::into_inner(read(buffer)));
-            //device.free_memory(ManuallyDrop::into_inner(read(memory)));
+            self.state.bury(vec![
+                Corpse::Buffer(ManuallyDrop::into_inner(read(&self.buffer))),
+                Corpse::Memory(ManuallyDrop::into_inner(read(&self.memory))),
+            ]);
         }
     }
 }
@@ -0,0 +1,134 @@
+use crate::allocator::GpuAllocator;
+use crate::graphics::ShaderDescription;
+use crate::internal::graphics::Corpse;
+use crate::internal::graphics::GraphicsState;
+use crate::internal::graphics::PipelineLayoutBundle;
+use colored::*;
+use failure::Error;
+use gfx_hal::buffer::Access;
+use gfx_hal::command::CommandBuffer;
+use gfx_hal::command::Primary;
+use gfx_hal::command::Shot;
+use gfx_hal::memory::Barrier;
+use gfx_hal::memory::Dependencies;
+use gfx_hal::pso::BasePipeline;
+use gfx_hal::pso::ComputePipelineDesc;
+use gfx_hal::pso::Descriptor;
+use gfx_hal::pso::DescriptorArrayIndex;
+use gfx_hal::pso::DescriptorBinding;
+use gfx_hal::pso::EntryPoint;
+use gfx_hal::pso::PipelineCreationFlags;
+use gfx_hal::pso::PipelineStage;
+use gfx_hal::pso::ShaderStageFlags;
+use gfx_hal::pso::Specialization;
+use gfx_hal::Backend;
+use gfx_hal::Device;
+use gfx_hal::General;
+use gfx_hal::Instance;
+use std::fmt;
+use std::fmt::Debug;
+use std::fmt::Formatter;
+use std::mem::ManuallyDrop;
+use std::sync::Arc;
+
+pub struct ComputePipelineBundle<A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Instance<Backend = B>> {
+    pipeline_layout: PipelineLayoutBundle<A, B, D, I>,
+    pipeline: ManuallyDrop<B::ComputePipeline>,
+    state: Arc<GraphicsState<A, B, D, I>>,
+}
+
+impl<A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Instance<Backend = B>> ComputePipelineBundle<A, B, D, I> {
+    pub fn new(state: Arc<GraphicsState<A, B, D, I>>, description: &ShaderDescription) -> Result<Self, Error> {
+        let pipeline_layout =
+            PipelineLayoutBundle::from_stages(Arc::clone(&state), &[(ShaderStageFlags::COMPUTE, description)])?;
+
+        info!("{}", "Creating new compute pipeline".green());
+
+        let device = state.device();
+        let module = unsafe { device.create_shader_module(&description.spirv)? };
+        let pipeline = unsafe {
+            device.create_compute_pipeline(
+                &ComputePipelineDesc {
+                    shader: EntryPoint {
+                        entry: "main",
+                        module: &module,
+                        specialization: Specialization {
+                            constants: &[],
+                            data: &[],
+                        },
+                    },
+                    layout: pipeline_layout.layout(),
+                    flags: PipelineCreationFlags::empty(),
+                    parent: BasePipeline::None,
+                },
+                None,
+            )
+        };
+        unsafe { device.destroy_shader_module(module) };
+
+        Ok(Self {
+            pipeline_layout,
+            pipeline: ManuallyDrop::new(pipeline?),
+            state,
+        })
+    }
+
+    pub fn bind_assets(&self, descriptors: Vec<(DescriptorBinding, DescriptorArrayIndex, Descriptor<B>)>) {
+        self.pipeline_layout.bind_assets(descriptors);
+    }
+
+    /// Records a dispatch followed by a barrier, so that the buffers written by the shader can be
+    /// read by anything recorded after it
+    pub fn record<S: Shot>(
+        &self,
+        command_buffer: &mut CommandBuffer<B, General, S, Primary>,
+        workgroups: [u32; 3],
+        push_constants: &[u32],
+    ) {
+        let layout = self.pipeline_layout.layout();
+        unsafe {
+            command_buffer.bind_compute_pipeline(&self.pipeline);
+            command_buffer.bind_compute_descriptor_sets(layout, 0, Some(self.pipeline_layout.descriptor_set()), &[]);
+            if !push_constants.is_empty() {
+                command_buffer.push_compute_constants(layout, 0, push_constants);
+            }
+            command_buffer.dispatch(workgroups);
+            command_buffer.pipeline_barrier(
+                PipelineStage::COMPUTE_SHADER
+                    ..PipelineStage::VERTEX_INPUT
+                        | PipelineStage::VERTEX_SHADER
+                        | PipelineStage::FRAGMENT_SHADER
+                        | PipelineStage::COMPUTE_SHADER
+                        | PipelineStage::TRANSFER,
+                Dependencies::empty(),
+                &[Barrier::AllBuffers(
+                    Access::SHADER_WRITE
+                        ..Access::SHADER_READ
+                            | Access::SHADER_WRITE
+                            | Access::VERTEX_BUFFER_READ
+                            | Access::TRANSFER_READ,
+                )],
+            );
+        }
+    }
+}
+
+impl<A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Instance<Backend = B>> Drop for ComputePipelineBundle<A, B, D, I> {
+    fn drop(&mut self) {
+        use core::ptr::read;
+
+        info!("{}", "Dropping compute pipeline".red());
+
+        unsafe {
+            self.state
+                .bury(Some(Corpse::ComputePipeline(ManuallyDrop::into_inner(read(&self.pipeline)))));
+        }
+    }
+}
+
+impl<A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Instance<Backend = B>> Debug for ComputePipelineBundle<A, B, D, I> {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(f, "Compute pipeline {:?}", self.pipeline_layout)?;
+        Ok(())
+    }
+}
@@ -1,7 +1,10 @@
+use crate::errors::AllocationError;
+use crate::internal::graphics::Corpse;
 use failure::Error;
 use gfx_hal::format::Aspects;
 use gfx_hal::format::Format;
 use gfx_hal::image::SubresourceRange;
+use gfx_hal::image::Usage;
 use gfx_hal::memory::Properties;
 use gfx_hal::memory::Requirements;
 use gfx_hal::window::Extent2D;
@@ -23,13 +26,24 @@ pub struct DepthImage<B: Backend, D: Device<B>> {
 
 impl<B: Backend, D: Device<B>> DepthImage<B, D> {
     pub fn new(device: Arc<D>, adapter: &Adapter<B>, extent: Extent2D) -> Result<Self, Error> {
+        Self::with_usage(device, adapter, extent, Usage::DEPTH_STENCIL_ATTACHMENT)
+    }
+
+    /// Creates a depth image with additional usages, `SAMPLED` is needed to read the depth back
+    /// in a later pass
+    pub fn with_usage(
+        device: Arc<D>,
+        adapter: &Adapter<B>,
+        extent: Extent2D,
+        usage: Usage,
+    ) -> Result<Self, Error> {
         unsafe {
             let mut the_image = device.create_image(
                 gfx_hal::image::Kind::D2(extent.width, extent.height, 1, 1),
                 1,
                 Format::D32Float,
                 gfx_hal::image::Tiling::Optimal,
-                gfx_hal::image::Usage::DEPTH_STENCIL_ATTACHMENT,
+                usage,
                 gfx_hal::image::ViewCapabilities::empty(),
             )?;
             let requirements = device.get_image_requirements(&the_image);
@@ -45,7 +59,7 @@ impl<B: Backend, D: Device<B>> DepthImage<B, D> {
                         && memory_type.properties.contains(Properties::DEVICE_LOCAL)
                 })
                 .map(|(id, _)| MemoryTypeId(id))
-                .ok_or_else(|| format_err!("Couldn't find a memory type to support the image!"))?;
+                .ok_or(AllocationError::NoSuitableMemoryType("image"))?;
             let memory = device.allocate_memory(memory_type_id, requirements.size)?;
             device.bind_image_memory(&memory, 0, &mut the_image)?;
             let image_view = device.create_image_view(
@@ -68,6 +82,22 @@ impl<B: Backend, D: Device<B>> DepthImage<B, D> {
             })
         }
     }
+
+    /// Hands the image over to the graveyard rather than destroying it right away, for images
+    /// that frames in flight might still use
+    pub(crate) fn into_corpses(self) -> Vec<Corpse<B>> {
+        use core::ptr::read;
+
+        let image = ManuallyDrop::new(self);
+        unsafe {
+            drop(read(&image.device));
+            vec![
+                Corpse::ImageView(ManuallyDrop::into_inner(read(&image.image_view))),
+                Corpse::Image(ManuallyDrop::into_inner(read(&image.image))),
+                Corpse::DeviceMemory(ManuallyDrop::into_inner(read(&image.memory))),
+            ]
+        }
+    }
 }
 
 impl<B: Backend, D: Device<B>> Drop for DepthImage<B, D> {
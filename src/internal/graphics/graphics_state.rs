@@ -1,8 +1,27 @@
 use crate::errors::CreateEncoderError;
+use crate::errors::SwapchainError;
+use crate::graphics::AdapterDescription;
+use crate::graphics::AdapterPreference;
+use crate::graphics::ADAPTER_ENV_VAR;
+use crate::internal::graphics::select_queue_families;
+use crate::internal::graphics::Corpse;
+use crate::internal::graphics::Graveyard;
+use crate::internal::graphics::QueueFamilyInfo;
+use crate::internal::graphics::ShaderReloader;
 use crate::internal::graphics::SwapchainBundle;
+use crate::internal::graphics::UploadManager;
+use crate::internal::FenceExt;
+use crate::internal::FenceWaiter;
 use core::mem::ManuallyDrop;
 use failure::Error;
+use futures::prelude::*;
+use gfx_hal::command::CommandBuffer;
+use gfx_hal::command::MultiShot;
+use gfx_hal::command::OneShot;
+use gfx_hal::command::Primary;
 use gfx_hal::command::RenderPassInlineEncoder;
+use gfx_hal::format::Format;
+use gfx_hal::pso::DescriptorPool;
 use gfx_hal::window::Extent2D;
 use gfx_hal::Limits;
 use gfx_hal::{
@@ -10,32 +29,47 @@ use gfx_hal::{
     device::Device,
     pool::{CommandPool, CommandPoolCreateFlags},
     queue::family::QueueGroup,
-    Backend, Gpu, Graphics, Instance, QueueFamily, Surface,
+    queue::QueueFamilyId,
+    Backend, Gpu, General, Instance, QueueFamily, Surface, Transfer,
 };
 use std::fmt;
 use std::fmt::Debug;
 use std::fmt::Display;
 use std::fmt::Formatter;
 use std::sync::Arc;
+use std::sync::Mutex;
 use std::sync::RwLock;
 use winit::Window;
 use crate::allocator::GpuAllocator;
 use crate::allocator::DefaultGpuAllocator;
 
+/// The pool the command buffers of `GraphicsState::submit_once` are allocated from. Every buffer is
+/// freed once its fence has signalled, and the pool is reset whenever none are pending. The pool is
+/// taken out when the state is dropped, so late fences don't touch a destroyed pool
+struct TransientPool<B: Backend> {
+    pool: Option<CommandPool<B, General>>,
+    pending: usize,
+}
+
 pub struct GraphicsState<A: GpuAllocator<B, D> = DefaultGpuAllocator, B: Backend = backend::Backend, D: Device<B> = backend::Device, I: Instance<Backend = B> = backend::Instance> {
-    command_pool: RwLock<ManuallyDrop<CommandPool<B, Graphics>>>,
-    queue_group: RwLock<QueueGroup<B, Graphics>>,
+    command_pool: RwLock<ManuallyDrop<CommandPool<B, General>>>,
+    transient_pool: Arc<Mutex<TransientPool<B>>>,
+    queue_group: Arc<RwLock<QueueGroup<B, General>>>,
     device: Arc<D>,
     adapter: Adapter<B>,
     _surface: RwLock<B::Surface>,
     _instance: ManuallyDrop<I>,
     swapchain: RwLock<SwapchainBundle<B, D>>,
     limits: Limits,
-    allocator: A
+    allocator: A,
+    uploads: UploadManager<B, D>,
+    fence_waiter: FenceWaiter,
+    shader_reloader: ShaderReloader<A, B, D, I>,
+    graveyard: Arc<Graveyard<B>>,
 }
 
 impl<A: GpuAllocator<backend::Backend, backend::Device>> GraphicsState<A> {
-    pub fn new(title: &str, window: &Window, mut allocator: A) -> Result<Self, Error> {
+    pub fn new(title: &str, window: &Window, mut allocator: A, preference: &AdapterPreference) -> Result<Self, Error> {
         let instance = backend::Instance::create(title, 1);
         let mut surface = instance.create_surface(window);
         let adapters = instance.enumerate_adapters();
@@ -46,55 +80,75 @@ impl<A: GpuAllocator<backend::Backend, backend::Device>> GraphicsState<A> {
         );
 
         // Select An Adapter
-        let adapter = adapters
+        let preference = match AdapterPreference::from_env() {
+            Some(from_env) => {
+                info!("Using adapter preference {:?} from {}", from_env, ADAPTER_ENV_VAR);
+                from_env
+            }
+            None => preference.clone(),
+        };
+        let mut candidates: Vec<_> = adapters
             .into_iter()
-            .find(|a| {
-                a.queue_families
-                    .iter()
-                    .any(|qf| qf.supports_graphics() && surface.supports_queue_family(qf))
+            .filter_map(|a| {
+                let selection = select_queue_families(&Self::describe_queue_families(&a, &surface))?;
+                Some((a, selection))
             })
-            .ok_or_else(|| format_err!("Couldn't find a graphical Adapter!"))?;
+            .collect();
+        let infos: Vec<_> = candidates.iter().map(|(a, _)| a.info.clone()).collect();
+        let best = *preference
+            .rank(&infos)
+            .first()
+            .ok_or(SwapchainError::NoAdapter)?;
+        let (adapter, selection) = candidates.swap_remove(best);
 
         let limits = adapter.physical_device.limits();
 
         info!("Selected gpu: {:#?}", adapter.info.name);
         info!("Selected gpu: {:#?}", &limits);
+        info!("Selected queue families: {:?}", selection);
 
-        // Open A Device and take out a QueueGroup
-        let (device, queue_group) = {
-            let queue_family = adapter
-                .queue_families
-                .iter()
-                .find(|qf| {
-                    qf.supports_graphics() && surface.supports_queue_family(qf)
-                        || qf.supports_transfer()
-                })
-                .ok_or_else(|| format_err!("Couldn't find a QueueFamily with graphics!"))?;
-            let Gpu { device, mut queues } = unsafe {
+        // Open A Device and take out the QueueGroups
+        let (device, queue_group, transfer_queue) = {
+            let family = |id| {
                 adapter
-                    .physical_device
-                    .open(&[(&queue_family, &[1.0; 1])])?
+                    .queue_families
+                    .iter()
+                    .find(|qf| qf.id() == QueueFamilyId(id))
+                    .ok_or(SwapchainError::QueueFamilyNotFound)
             };
-            let queue_group: QueueGroup<backend::Backend, Graphics> = queues
-                .take::<Graphics>(queue_family.id())
-                .ok_or_else(|| format_err!("Couldn't take ownership of the QueueGroup!"))?;
+            let mut families = vec![(family(selection.main)?, &[1.0][..])];
+            if let Some(transfer) = selection.transfer {
+                families.push((family(transfer)?, &[1.0][..]));
+            }
+            let Gpu { device, mut queues } = unsafe { adapter.physical_device.open(&families)? };
+
+            let queue_group: QueueGroup<backend::Backend, General> = queues
+                .take::<General>(QueueFamilyId(selection.main))
+                .ok_or(SwapchainError::QueueGroupUnavailable)?;
             if !queue_group.queues.is_empty() {
                 Ok(())
             } else {
-                Err(format_err!(
-                    "The QueueGroup did not have any CommandQueues available!"
-                ))
+                Err(SwapchainError::NoCommandQueues)
             }?;
 
-            (Arc::new(device), queue_group)
+            let transfer_queue: Option<QueueGroup<backend::Backend, Transfer>> = selection
+                .transfer
+                .and_then(|transfer| queues.take::<Transfer>(QueueFamilyId(transfer)))
+                .filter(|transfer_queue| !transfer_queue.queues.is_empty());
+
+            (Arc::new(device), queue_group, transfer_queue)
         };
 
         // Create Our CommandPool
-        let mut command_pool: CommandPool<backend::Backend, Graphics> = unsafe {
+        let mut command_pool: CommandPool<backend::Backend, General> = unsafe {
             device
                 .create_command_pool_typed(&queue_group, CommandPoolCreateFlags::RESET_INDIVIDUAL)?
         };
 
+        let transient_pool: CommandPool<backend::Backend, General> = unsafe {
+            device.create_command_pool_typed(&queue_group, CommandPoolCreateFlags::TRANSIENT)?
+        };
+
         let swapchain = SwapchainBundle::<backend::Backend, backend::Device>::new(
             &adapter,
             Arc::clone(&device),
@@ -103,20 +157,75 @@ impl<A: GpuAllocator<backend::Backend, backend::Device>> GraphicsState<A> {
             &mut command_pool,
         )?;
 
-        allocator.init(Arc::clone(&device));
+        allocator.init(Arc::clone(&device), &adapter.physical_device.memory_properties());
+
+        let queue_group = Arc::new(RwLock::new(queue_group));
+        let fence_waiter = FenceWaiter::new();
+        let graveyard = Arc::new(Graveyard::new());
+        let uploads = UploadManager::new(
+            Arc::clone(&device),
+            Arc::clone(&queue_group),
+            transfer_queue,
+            &fence_waiter,
+            Arc::clone(&graveyard),
+            &adapter.physical_device.memory_properties().memory_types,
+            &limits,
+        )?;
 
         Ok(Self {
             _instance: ManuallyDrop::new(instance),
             _surface: RwLock::new(surface),
             adapter,
             device,
-            queue_group: RwLock::new(queue_group),
+            queue_group,
             swapchain: RwLock::new(swapchain),
             command_pool: RwLock::new(ManuallyDrop::new(command_pool)),
+            transient_pool: Arc::new(Mutex::new(TransientPool {
+                pool: Some(transient_pool),
+                pending: 0,
+            })),
             limits,
-            allocator
+            allocator,
+            uploads,
+            fence_waiter,
+            shader_reloader: ShaderReloader::new(),
+            graveyard,
         })
     }
+
+    /// Lists the adapters on this machine, whether or not they can render to a window
+    pub fn available_adapters() -> Vec<AdapterDescription> {
+        let instance = backend::Instance::create("starstruck", 1);
+        instance
+            .enumerate_adapters()
+            .into_iter()
+            .map(|adapter| {
+                let memory_properties = adapter.physical_device.memory_properties();
+                AdapterDescription {
+                    info: adapter.info.clone(),
+                    limits: adapter.physical_device.limits(),
+                    memory_types: memory_properties.memory_types,
+                    memory_heaps: memory_properties.memory_heaps,
+                }
+            })
+            .collect()
+    }
+
+    fn describe_queue_families(
+        adapter: &Adapter<backend::Backend>,
+        surface: &<backend::Backend as Backend>::Surface,
+    ) -> Vec<QueueFamilyInfo> {
+        adapter
+            .queue_families
+            .iter()
+            .map(|qf| QueueFamilyInfo {
+                id: qf.id().0,
+                queue_type: qf.queue_type(),
+                max_queues: qf.max_queues(),
+                supports_present: surface.supports_queue_family(qf),
+            })
+            .collect()
+    }
 }
 
 impl<A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Instance<Backend = B>> GraphicsState<A, B, D, I> {
@@ -134,23 +243,173 @@ impl<A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Instance<Backend = B>>
             let mut lock = self.swapchain.write().unwrap();
             *lock = SwapchainBundle::new(adapter, device, window, surface, command_pool)?;
         }
+        // The old swapchain waits for the device to be idle before it's destroyed
+        self.graveyard.device_idle();
+        self.collect_garbage();
         info!("Swapchain recreated");
         Ok(())
     }
 
+    pub fn begin_frame(&self) -> Result<(), CreateEncoderError> {
+        let frame = self.graveyard.begin_frame();
+        let completed = {
+            let mut lock = self.swapchain.write().unwrap();
+            lock.begin_frame(frame)?
+        };
+        if let Some(completed) = completed {
+            self.graveyard.frame_completed(completed);
+        }
+        self.collect_garbage();
+        // Maintained after the garbage is collected, so the memory of completed frames is freed
+        self.allocator.maintain();
+        Ok(())
+    }
+
+    /// Destroys the resources once no frame in flight can use them anymore. Resources should be
+    /// buried here in `Drop` instead of being destroyed right away
+    pub fn bury<T: IntoIterator<Item = Corpse<B>>>(&self, corpses: T) {
+        self.graveyard.bury(corpses);
+    }
+
+    /// Destroys the buried resources whose frames have completed
+    fn collect_garbage(&self) {
+        for corpse in self.graveyard.exhume() {
+            self.destroy(corpse);
+        }
+    }
+
+    fn destroy(&self, corpse: Corpse<B>) {
+        unsafe {
+            match corpse {
+                Corpse::Buffer(buffer) => self.device.destroy_buffer(buffer),
+                Corpse::Image(image) => self.device.destroy_image(image),
+                Corpse::ImageView(image_view) => self.device.destroy_image_view(image_view),
+                Corpse::Sampler(sampler) => self.device.destroy_sampler(sampler),
+                Corpse::Framebuffer(framebuffer) => self.device.destroy_framebuffer(framebuffer),
+                Corpse::RenderPass(render_pass) => self.device.destroy_render_pass(render_pass),
+                Corpse::GraphicsPipeline(pipeline) => self.device.destroy_graphics_pipeline(pipeline),
+                Corpse::ComputePipeline(pipeline) => self.device.destroy_compute_pipeline(pipeline),
+                Corpse::PipelineLayout(layout) => self.device.destroy_pipeline_layout(layout),
+                Corpse::DescriptorSetLayout(layout) => self.device.destroy_descriptor_set_layout(layout),
+                Corpse::DescriptorPool(mut pool) => {
+                    pool.reset();
+                    self.device.destroy_descriptor_pool(pool)
+                }
+                Corpse::Memory(mut memory) => self.allocator.free_memory(&mut memory),
+                Corpse::DeviceMemory(memory) => self.device.free_memory(memory),
+            }
+        }
+    }
+
+    /// Gives access to the command buffer of the current frame before the main render pass begins.
+    /// The frames in flight keep running, the callback is also given a function that waits for them,
+    /// which has to be called before rewriting buffers they read, like the uniform of a
+    /// `DirectionalLight`
+    pub fn prepare_frame<F>(&self, callback: F) -> Result<(), Error>
+    where
+        F: FnOnce(&mut CommandBuffer<B, General, MultiShot, Primary>, &Fn() -> Result<(), Error>) -> Result<(), Error>,
+    {
+        let mut lock = self.swapchain.write().unwrap();
+        let (command_buffer, fences) = lock.command_buffer_and_fences();
+        let wait_for_frames_in_flight = || -> Result<(), Error> {
+            if let Some(completed) = fences.wait()? {
+                self.graveyard.frame_completed(completed);
+            }
+            Ok(())
+        };
+        callback(command_buffer, &wait_for_frames_in_flight)
+    }
+
+    /// Waits until the frames in flight have completed, after which the resources they use, such
+    /// as descriptor sets, can be changed
+    pub fn wait_for_frames_in_flight(&self) -> Result<(), Error> {
+        if let Some(completed) = self.swapchain.read().unwrap().wait_for_frames_in_flight()? {
+            self.graveyard.frame_completed(completed);
+        }
+        Ok(())
+    }
+
     pub fn next_encoder<F: FnOnce(RenderPassInlineEncoder<B>) -> Result<(), Error>>(
         &self,
+        target: Option<(&B::RenderPass, &[B::Framebuffer])>,
         callback: F,
-    ) -> Result<(), CreateEncoderError> {
+    ) {
         let mut lock = self.swapchain.write().unwrap();
-        let encoder = lock.next_encoder()?;
+        let encoder = lock.next_encoder(target);
         callback(encoder).unwrap();
-        Ok(())
     }
 
-    pub fn present_swapchain(&self) -> Result<(), Error> {
+    pub fn present_swapchain<F: FnOnce(&mut CommandBuffer<B, General, MultiShot, Primary>, usize)>(
+        &self,
+        before_submit: F,
+    ) -> Result<(), CreateEncoderError> {
+        let mut lock = self.swapchain.write().unwrap();
+        lock.present_swapchain(&mut self.queue_group.write().unwrap(), before_submit)
+    }
+
+    /// Gives up the swapchain so the window can be used by a new graphics state after the device
+    /// was lost. Nothing can be rendered with this state afterwards
+    pub fn release_swapchain(&self) {
         let mut lock = self.swapchain.write().unwrap();
-        lock.present_swapchain(&mut self.queue_group.write().unwrap())
+        lock.release();
+        self.graveyard.device_idle();
+        self.collect_garbage();
+    }
+
+    /// Records and submits a one off command buffer on the main queue. The returned future resolves
+    /// once the GPU has finished executing it
+    pub fn submit_once<F: FnOnce(&mut CommandBuffer<B, General, OneShot, Primary>)>(
+        &self,
+        record: F,
+    ) -> Result<impl Future<Item = (), Error = Error> + Send, Error> {
+        let device = self.device();
+        let fence = device.create_fence(false)?;
+        let command_buffer = {
+            let mut lock = self.transient_pool.lock().unwrap();
+            let transient = &mut *lock;
+            let pool = transient.pool.as_mut().unwrap();
+            let mut queue_group = self.queue_group.write().unwrap();
+            unsafe {
+                let mut command_buffer = pool.acquire_command_buffer::<OneShot>();
+                command_buffer.begin();
+                record(&mut command_buffer);
+                command_buffer.finish();
+                queue_group.queues[0].submit_nosemaphores(Some(&command_buffer), Some(&fence));
+                transient.pending += 1;
+                command_buffer
+            }
+        };
+
+        let fence = fence.into_promise(Arc::clone(&device), &self.fence_waiter);
+        self.graveyard.submitted(fence.pending());
+        let transient_pool = Arc::clone(&self.transient_pool);
+        Ok(fence.then(move |result| {
+            let mut lock = transient_pool.lock().unwrap();
+            let transient = &mut *lock;
+            transient.pending -= 1;
+            if let Some(pool) = transient.pool.as_mut() {
+                unsafe {
+                    pool.free(Some(command_buffer));
+                    if transient.pending == 0 {
+                        pool.reset();
+                    }
+                }
+            }
+            result
+        }))
+    }
+
+    pub fn swapchain_format(&self) -> Format {
+        let lock = self.swapchain.read().unwrap();
+        lock.format()
+    }
+
+    pub fn swapchain_image_views<T: FnOnce(&[B::ImageView]) -> Result<R, Error>, R>(
+        &self,
+        callback: T,
+    ) -> Result<R, Error> {
+        let lock = self.swapchain.read().unwrap();
+        callback(lock.image_views())
     }
 
     pub fn adapter(&self) -> &Adapter<B> {
@@ -161,6 +420,18 @@ impl<A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Instance<Backend = B>>
         &self.allocator
     }
 
+    pub fn uploads(&self) -> &UploadManager<B, D> {
+        &self.uploads
+    }
+
+    pub fn fence_waiter(&self) -> &FenceWaiter {
+        &self.fence_waiter
+    }
+
+    pub fn shader_reloader(&self) -> &ShaderReloader<A, B, D, I> {
+        &self.shader_reloader
+    }
+
     pub fn device(&self) -> Arc<D> {
         Arc::clone(&self.device)
     }
@@ -202,3 +473,14 @@ impl<A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Instance<Backend = B>>
         Ok(())
     }
 }
+
+impl<A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Instance<Backend = B>> Drop for GraphicsState<A, B, D, I> {
+    fn drop(&mut self) {
+        let _ = self.device.wait_idle();
+        self.graveyard.device_idle();
+        self.collect_garbage();
+        if let Some(pool) = self.transient_pool.lock().unwrap().pool.take() {
+            unsafe { self.device.destroy_command_pool(pool.into_raw()) }
+        }
+    }
+}
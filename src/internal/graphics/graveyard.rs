@@ -0,0 +1,273 @@
+use crate::allocator::Memory;
+use crate::internal::PendingFence;
+use gfx_hal::Backend;
+use std::collections::VecDeque;
+use std::fmt;
+use std::fmt::Debug;
+use std::fmt::Formatter;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+/// A GPU object that has been dropped but might still be used by a frame in flight
+#[derive(Debug)]
+pub(crate) enum Corpse<B: Backend> {
+    Buffer(B::Buffer),
+    Image(B::Image),
+    ImageView(B::ImageView),
+    Sampler(B::Sampler),
+    Framebuffer(B::Framebuffer),
+    RenderPass(B::RenderPass),
+    GraphicsPipeline(B::GraphicsPipeline),
+    ComputePipeline(B::ComputePipeline),
+    PipelineLayout(B::PipelineLayout),
+    DescriptorSetLayout(B::DescriptorSetLayout),
+    DescriptorPool(B::DescriptorPool),
+    Memory(Memory<B>),
+
+    /// Memory allocated straight from the device rather than through the allocator
+    DeviceMemory(B::Memory),
+}
+
+struct Graves<B: Backend> {
+    recording: u64,
+    completed: u64,
+    submitted: u64,
+    /// The fences of the submissions outside of frames that haven't signalled yet, by number
+    submissions: VecDeque<(u64, Arc<dyn PendingFence>)>,
+    /// The frame and the number of submissions so far when the corpse was buried
+    buried: VecDeque<(u64, u64, Corpse<B>)>,
+}
+
+impl<B: Backend> Graves<B> {
+    /// Whether a corpse buried at `frame`, after `submitted` submissions, can't be used anymore
+    fn at_rest(&self, frame: u64, submitted: u64) -> bool {
+        frame <= self.completed && self.submissions.front().map_or(true, |(oldest, _)| *oldest > submitted)
+    }
+}
+
+impl<B: Backend> Debug for Graves<B> {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(
+            f,
+            "Graves at frame {} ({} completed) with {} pending submissions: {:?}",
+            self.recording,
+            self.completed,
+            self.submissions.len(),
+            self.buried
+        )
+    }
+}
+
+/// Holds on to dropped resources until the fence of the last frame that could have used them has
+/// signaled, along with the fences of the one off and upload submissions made before they were
+/// dropped. Frames are numbered from 1, resources dropped before the first frame only wait for
+/// those submissions
+#[derive(Debug)]
+pub(crate) struct Graveyard<B: Backend> {
+    graves: Mutex<Graves<B>>,
+}
+
+impl<B: Backend> Graveyard<B> {
+    pub fn new() -> Self {
+        Self {
+            graves: Mutex::new(Graves {
+                recording: 0,
+                completed: 0,
+                submitted: 0,
+                submissions: VecDeque::new(),
+                buried: VecDeque::new(),
+            }),
+        }
+    }
+
+    /// Starts a new frame and returns its number, everything dropped from now on is kept alive
+    /// until this frame has completed
+    pub fn begin_frame(&self) -> u64 {
+        let mut graves = self.graves.lock().unwrap();
+        graves.recording += 1;
+        graves.recording
+    }
+
+    /// Called once the fence of `frame` has signaled. Frames complete in the order they were
+    /// submitted, so every frame before it has completed too
+    pub fn frame_completed(&self, frame: u64) {
+        let mut graves = self.graves.lock().unwrap();
+        debug_assert!(
+            frame <= graves.recording,
+            "Frame {} completed before it was recorded, resources would be destroyed while in use",
+            frame
+        );
+        graves.completed = graves.completed.max(frame);
+    }
+
+    /// Called once the device is idle, nothing recorded or submitted so far can be in use anymore
+    pub fn device_idle(&self) {
+        let mut graves = self.graves.lock().unwrap();
+        graves.completed = graves.recording;
+        graves.submissions.clear();
+    }
+
+    /// Called when commands are submitted outside of a frame, such as one off command buffers and
+    /// upload batches. Resources dropped from now on are kept alive until the fence has signalled
+    pub fn submitted(&self, fence: Arc<dyn PendingFence>) {
+        let mut graves = self.graves.lock().unwrap();
+        graves.submitted += 1;
+        let number = graves.submitted;
+        graves.submissions.push_back((number, fence));
+    }
+
+    /// Keeps the resources alive until the frame being recorded, and everything submitted so far,
+    /// has completed
+    pub fn bury<T: IntoIterator<Item = Corpse<B>>>(&self, corpses: T) {
+        let mut graves = self.graves.lock().unwrap();
+        let frame = graves.recording;
+        let submitted = graves.submitted;
+        graves.buried.extend(corpses.into_iter().map(|corpse| (frame, submitted, corpse)));
+    }
+
+    /// Takes out the resources that no frame in flight or pending submission can use anymore
+    pub fn exhume(&self) -> Vec<Corpse<B>> {
+        let mut graves = self.graves.lock().unwrap();
+        // Submissions can complete out of order, since uploads may go to another queue
+        graves.submissions.retain(|(_, fence)| !fence.wait(0));
+        let count = graves
+            .buried
+            .iter()
+            .take_while(|(frame, submitted, _)| graves.at_rest(*frame, *submitted))
+            .count();
+        graves.buried.drain(..count).map(|(_, _, corpse)| corpse).collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.graves.lock().unwrap().buried.len()
+    }
+}
+
+impl<B: Backend> Default for Graveyard<B> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<B: Backend> Drop for Graveyard<B> {
+    fn drop(&mut self) {
+        if !std::thread::panicking() {
+            debug_assert!(
+                self.graves.lock().unwrap().buried.is_empty(),
+                "Graveyard dropped while it still holds resources, they will never be destroyed"
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use std::sync::atomic::AtomicBool;
+    use std::sync::atomic::Ordering;
+
+    type EmptyGraveyard = Graveyard<gfx_backend_empty::Backend>;
+
+    #[derive(Default)]
+    struct FakeFence {
+        signalled: AtomicBool,
+    }
+
+    impl PendingFence for FakeFence {
+        fn wait(&self, _timeout_ns: u64) -> bool {
+            self.signalled.load(Ordering::SeqCst)
+        }
+
+        fn notify(&self) {}
+    }
+
+    fn submit(graveyard: &EmptyGraveyard) -> Arc<FakeFence> {
+        let fence = Arc::new(FakeFence::default());
+        graveyard.submitted(Arc::clone(&fence) as Arc<dyn PendingFence>);
+        fence
+    }
+
+    #[test]
+    fn it_should_release_resources_dropped_before_the_first_frame() {
+        let graveyard = EmptyGraveyard::new();
+        graveyard.bury(vec![Corpse::Buffer(())]);
+        assert_eq!(1, graveyard.exhume().len());
+    }
+
+    #[test]
+    fn it_should_keep_resources_until_their_frame_has_completed() {
+        let graveyard = EmptyGraveyard::new();
+        let first = graveyard.begin_frame();
+        graveyard.bury(vec![Corpse::Buffer(()), Corpse::Image(())]);
+        let second = graveyard.begin_frame();
+        graveyard.bury(vec![Corpse::Sampler(())]);
+        assert_eq!(0, graveyard.exhume().len());
+
+        graveyard.frame_completed(first);
+        assert_eq!(2, graveyard.exhume().len());
+        assert_eq!(1, graveyard.len());
+
+        graveyard.frame_completed(second);
+        assert_eq!(1, graveyard.exhume().len());
+        assert_eq!(0, graveyard.len());
+    }
+
+    #[test]
+    fn it_should_release_everything_once_the_device_is_idle() {
+        let graveyard = EmptyGraveyard::new();
+        graveyard.begin_frame();
+        graveyard.begin_frame();
+        graveyard.bury(vec![Corpse::Buffer(())]);
+        graveyard.device_idle();
+        assert_eq!(1, graveyard.exhume().len());
+    }
+
+    #[test]
+    #[should_panic(expected = "completed before it was recorded")]
+    fn it_should_flag_frames_completed_too_early() {
+        let graveyard = EmptyGraveyard::new();
+        graveyard.frame_completed(1);
+    }
+
+    #[test]
+    fn it_should_keep_resources_dropped_before_the_first_frame_until_earlier_submissions_complete() {
+        let graveyard = EmptyGraveyard::new();
+        let upload = submit(&graveyard);
+        graveyard.bury(vec![Corpse::Buffer(())]);
+        assert_eq!(0, graveyard.exhume().len());
+
+        upload.signalled.store(true, Ordering::SeqCst);
+        assert_eq!(1, graveyard.exhume().len());
+    }
+
+    #[test]
+    fn it_should_only_wait_for_submissions_made_before_the_resources_were_dropped() {
+        let graveyard = EmptyGraveyard::new();
+        let first = submit(&graveyard);
+        graveyard.bury(vec![Corpse::Buffer(())]);
+        let second = submit(&graveyard);
+        graveyard.bury(vec![Corpse::Image(())]);
+
+        // The second submission may finish first when it went to another queue
+        second.signalled.store(true, Ordering::SeqCst);
+        assert_eq!(0, graveyard.exhume().len());
+
+        first.signalled.store(true, Ordering::SeqCst);
+        assert_eq!(2, graveyard.exhume().len());
+    }
+
+    #[test]
+    fn it_should_wait_for_both_the_frame_and_the_submissions() {
+        let graveyard = EmptyGraveyard::new();
+        let frame = graveyard.begin_frame();
+        let upload = submit(&graveyard);
+        graveyard.bury(vec![Corpse::Sampler(())]);
+
+        graveyard.frame_completed(frame);
+        assert_eq!(0, graveyard.exhume().len());
+
+        upload.signalled.store(true, Ordering::SeqCst);
+        assert_eq!(1, graveyard.exhume().len());
+    }
+}
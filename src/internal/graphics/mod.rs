@@ -1,16 +1,41 @@
 mod buffer_bundle;
+mod color_image;
+mod compute_pipeline_bundle;
 mod depth_image;
 mod graphics_state;
+mod graveyard;
 mod pipeline_bundle;
 mod pipeline_layout_bundle;
+mod post_processor;
+mod queue_selection;
+mod shader_compiler;
+mod shader_reflection;
+mod shader_reloader;
+mod shadow_caster_bundle;
+mod shadow_map;
+mod staging_ring;
 mod swapchain_bundle;
 mod texture_bundle;
 mod text_manager;
+mod upload_manager;
 
 pub(crate) use self::buffer_bundle::*;
+pub(crate) use self::compute_pipeline_bundle::ComputePipelineBundle;
 pub(crate) use self::graphics_state::GraphicsState;
+pub(crate) use self::graveyard::Corpse;
+pub(crate) use self::graveyard::Graveyard;
 pub(crate) use self::pipeline_bundle::PipelineBundle;
 pub(crate) use self::pipeline_layout_bundle::PipelineLayoutBundle;
+pub(crate) use self::post_processor::PostProcessor;
+pub(crate) use self::queue_selection::*;
+pub(crate) use self::shader_compiler::compile_glsl;
+pub(crate) use self::shader_compiler::compile_glsl_file;
+pub(crate) use self::shader_compiler::glsl_dependencies;
+pub(crate) use self::shader_reflection::*;
+pub(crate) use self::shader_reloader::ShaderReloader;
+pub(crate) use self::shadow_caster_bundle::ShadowCasterBundle;
+pub(crate) use self::shadow_map::ShadowMap;
+pub(crate) use self::staging_ring::StagingRing;
 pub(crate) use self::swapchain_bundle::SwapchainBundle;
 pub(crate) use self::texture_bundle::TextureBundle;
 pub use self::texture_bundle::{
@@ -20,3 +45,6 @@ pub use self::texture_bundle::{
     Cube
 };
 pub(crate) use self::text_manager::TextManager;
+pub(crate) use self::upload_manager::UploadDestination;
+pub(crate) use self::upload_manager::UploadFuture;
+pub(crate) use self::upload_manager::UploadManager;
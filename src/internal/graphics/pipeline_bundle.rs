@@ -1,9 +1,13 @@
 use crate::graphics::ShaderDescription;
 use crate::graphics::ShaderSet;
+use crate::internal::graphics::reflect;
+use crate::internal::graphics::validate_vertex_attributes;
+use crate::internal::graphics::Corpse;
 use crate::internal::graphics::GraphicsState;
 use crate::internal::graphics::PipelineLayoutBundle;
 use crate::primitive::Vertex;
 use colored::*;
+use crate::errors::PipelineError;
 use failure::Error;
 use gfx_hal::device::Device;
 use gfx_hal::pass::Subpass;
@@ -28,6 +32,7 @@ use gfx_hal::pso::LogicOp;
 use gfx_hal::pso::Multisampling;
 use gfx_hal::pso::PipelineCreationFlags;
 use gfx_hal::pso::Rasterizer;
+use gfx_hal::pso::ShaderStageFlags;
 use gfx_hal::pso::Specialization;
 use gfx_hal::pso::StencilTest;
 use gfx_hal::pso::VertexBufferDesc;
@@ -73,6 +78,20 @@ impl<V: Vertex, A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Instance<Bac
         })
     }
 
+    /// Recreates the pipeline from a new shader set, keeping the layout and bound descriptors
+    pub fn rebuild(&mut self, render_pass: &B::RenderPass, set: &ShaderSet) -> Result<(), Error> {
+        info!("{}", "Rebuilding pipeline".green());
+
+        PipelineLayoutBundle::<A, B, D, I>::validate(set)?;
+        let device = self.state.device();
+        let pipeline = Self::create(&device, render_pass, set, self.pipeline_layout.layout())?;
+        let old_pipeline = std::mem::replace(&mut self.pipeline, ManuallyDrop::new(pipeline));
+        unsafe {
+            device.destroy_graphics_pipeline(ManuallyDrop::into_inner(old_pipeline));
+        }
+        Ok(())
+    }
+
     pub fn layout(&self) -> &B::PipelineLayout {
         &self.pipeline_layout.layout()
     }
@@ -95,6 +114,14 @@ impl<V: Vertex, A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Instance<Bac
         set: &ShaderSet,
         layout: &B::PipelineLayout,
     ) -> Result<B::GraphicsPipeline, Error> {
+        let inputs = reflect(&set.vertex.spirv)
+            .map_err(|error| PipelineError::InvalidShader {
+                stage: format!("{:?}", ShaderStageFlags::VERTEX),
+                reason: error.to_string(),
+            })?
+            .inputs;
+        validate_vertex_attributes(&inputs, &V::attributes())?;
+
         let shader_modules = Self::create_shader_modules(&device, set)?;
         let result = {
             let shaders = Self::create_graphics_shader_set(&shader_modules)?;
@@ -197,7 +224,7 @@ impl<V: Vertex, A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Instance<Bac
         set: &ShaderSet,
     ) -> Result<[Option<B::ShaderModule>; 5], Error> {
         Ok([
-            Some(unsafe { device.create_shader_module(set.vertex.spirv)? }),
+            Some(unsafe { device.create_shader_module(&set.vertex.spirv)? }),
             Self::map_to_shader_module(device, &set.hull)?,
             Self::map_to_shader_module(device, &set.domain)?,
             Self::map_to_shader_module(device, &set.geometry)?,
@@ -233,7 +260,7 @@ impl<V: Vertex, A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Instance<Bac
         desc: &Option<ShaderDescription>,
     ) -> Result<Option<B::ShaderModule>, Error> {
         match desc {
-            Some(d) => Ok(Some(unsafe { device.create_shader_module(d.spirv)? })),
+            Some(d) => Ok(Some(unsafe { device.create_shader_module(&d.spirv)? })),
             None => Ok(None),
         }
     }
@@ -261,11 +288,9 @@ impl<V: Vertex, A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Instance<Bac
 
         info!("{}", "Dropping Pipeline".red());
 
-        let device = &self.state.device();
-        let pipeline = &self.pipeline;
-
         unsafe {
-            device.destroy_graphics_pipeline(ManuallyDrop::into_inner(read(pipeline)));
+            self.state
+                .bury(Some(Corpse::GraphicsPipeline(ManuallyDrop::into_inner(read(&self.pipeline)))));
         }
     }
 }
@@ -1,4 +1,9 @@
+use crate::errors::PipelineError;
+use crate::graphics::ShaderDescription;
 use crate::graphics::ShaderSet;
+use crate::internal::graphics::reflect;
+use crate::internal::graphics::validate_layout;
+use crate::internal::graphics::Corpse;
 use crate::internal::graphics::GraphicsState;
 use colored::*;
 use failure::Error;
@@ -34,77 +39,25 @@ pub struct PipelineLayoutBundle<A: GpuAllocator<B, D>, B: Backend, D: Device<B>,
 
 impl<A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Instance<Backend = B>> PipelineLayoutBundle<A, B, D, I> {
     pub fn new(state: Arc<GraphicsState<A, B, D, I>>, set: &ShaderSet) -> Result<Self, Error> {
+        Self::from_stages(state, &Self::stages(set))
+    }
+
+    /// Creates a layout shared by the given stages, compute pipelines use this with a single stage
+    pub fn from_stages(
+        state: Arc<GraphicsState<A, B, D, I>>,
+        stages: &[(ShaderStageFlags, &ShaderDescription)],
+    ) -> Result<Self, Error> {
+        Self::validate_stages(stages)?;
+
         let mut bindings = Vec::<DescriptorSetLayoutBinding>::new();
         let mut range = HashMap::<DescriptorType, DescriptorRangeDesc>::new();
-        for (binding, ty, count) in &set.vertex.bindings {
-            bindings.push(DescriptorSetLayoutBinding {
-                binding: *binding,
-                ty: *ty,
-                count: *count,
-                stage_flags: ShaderStageFlags::VERTEX,
-                immutable_samplers: false,
-            });
-            if let Some(ran) = range.get_mut(ty) {
-                ran.count += 1;
-            } else {
-                range.insert(*ty, DescriptorRangeDesc { ty: *ty, count: 1 });
-            }
-        }
-        if let Some(hull) = set.hull.as_ref() {
-            for (binding, ty, count) in &hull.bindings {
-                bindings.push(DescriptorSetLayoutBinding {
-                    binding: *binding,
-                    ty: *ty,
-                    count: *count,
-                    stage_flags: ShaderStageFlags::HULL,
-                    immutable_samplers: false,
-                });
-                if let Some(ran) = range.get_mut(ty) {
-                    ran.count += 1;
-                } else {
-                    range.insert(*ty, DescriptorRangeDesc { ty: *ty, count: 1 });
-                }
-            }
-        }
-        if let Some(domain) = set.domain.as_ref() {
-            for (binding, ty, count) in &domain.bindings {
+        for (stage_flags, description) in stages {
+            for (binding, ty, count) in &description.bindings {
                 bindings.push(DescriptorSetLayoutBinding {
                     binding: *binding,
                     ty: *ty,
                     count: *count,
-                    stage_flags: ShaderStageFlags::DOMAIN,
-                    immutable_samplers: false,
-                });
-                if let Some(ran) = range.get_mut(ty) {
-                    ran.count += 1;
-                } else {
-                    range.insert(*ty, DescriptorRangeDesc { ty: *ty, count: 1 });
-                }
-            }
-        }
-        if let Some(fragment) = set.fragment.as_ref() {
-            for (binding, ty, count) in &fragment.bindings {
-                bindings.push(DescriptorSetLayoutBinding {
-                    binding: *binding,
-                    ty: *ty,
-                    count: *count,
-                    stage_flags: ShaderStageFlags::FRAGMENT,
-                    immutable_samplers: false,
-                });
-                if let Some(ran) = range.get_mut(ty) {
-                    ran.count += 1;
-                } else {
-                    range.insert(*ty, DescriptorRangeDesc { ty: *ty, count: 1 });
-                }
-            }
-        }
-        if let Some(geometry) = set.geometry.as_ref() {
-            for (binding, ty, count) in &geometry.bindings {
-                bindings.push(DescriptorSetLayoutBinding {
-                    binding: *binding,
-                    ty: *ty,
-                    count: *count,
-                    stage_flags: ShaderStageFlags::GEOMETRY,
+                    stage_flags: *stage_flags,
                     immutable_samplers: false,
                 });
                 if let Some(ran) = range.get_mut(ty) {
@@ -122,20 +75,10 @@ impl<A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Instance<Backend = B>>
                 .create_descriptor_set_layout(bindings, immutable_samplers)?
         }];
 
-        let mut push_constants = Vec::with_capacity(2);
-        push_constants.push((ShaderStageFlags::VERTEX, 0..set.vertex.push_constant_floats));
-        if let Some(hull) = set.hull.as_ref() {
-            push_constants.push((ShaderStageFlags::HULL, 0..hull.push_constant_floats));
-        }
-        if let Some(domain) = set.domain.as_ref() {
-            push_constants.push((ShaderStageFlags::DOMAIN, 0..domain.push_constant_floats));
-        }
-        if let Some(fragment) = set.fragment.as_ref() {
-            push_constants.push((ShaderStageFlags::FRAGMENT, 0..fragment.push_constant_floats));
-        }
-        if let Some(geometry) = set.geometry.as_ref() {
-            push_constants.push((ShaderStageFlags::GEOMETRY, 0..geometry.push_constant_floats));
-        }
+        let push_constants: Vec<_> = stages
+            .iter()
+            .map(|(stage_flags, description)| (*stage_flags, 0..description.push_constant_floats))
+            .collect();
 
         let layout = unsafe {
             state
@@ -158,6 +101,50 @@ impl<A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Instance<Backend = B>>
         })
     }
 
+    /// Checks that the push constants and bindings in the descriptions cover what the shaders
+    /// actually use, rather than letting the driver fail on a mismatching layout
+    pub fn validate(set: &ShaderSet) -> Result<(), Error> {
+        Self::validate_stages(&Self::stages(set))
+    }
+
+    fn validate_stages(stages: &[(ShaderStageFlags, &ShaderDescription)]) -> Result<(), Error> {
+        let declared: Vec<_> = stages
+            .iter()
+            .flat_map(|(_, description)| description.bindings.iter().cloned())
+            .collect();
+
+        for (stage_flags, description) in stages {
+            let reflection = reflect(&description.spirv)
+                .map_err(|error| PipelineError::InvalidShader {
+                    stage: format!("{:?}", stage_flags),
+                    reason: error.to_string(),
+                })?;
+            validate_layout(&reflection, description.push_constant_floats, &declared)
+                .map_err(|error| PipelineError::InvalidShaderDescription {
+                    stage: format!("{:?}", stage_flags),
+                    reason: error.to_string(),
+                })?;
+        }
+        Ok(())
+    }
+
+    fn stages(set: &ShaderSet) -> Vec<(ShaderStageFlags, &ShaderDescription)> {
+        let mut stages = vec![(ShaderStageFlags::VERTEX, &set.vertex)];
+        if let Some(hull) = set.hull.as_ref() {
+            stages.push((ShaderStageFlags::HULL, hull));
+        }
+        if let Some(domain) = set.domain.as_ref() {
+            stages.push((ShaderStageFlags::DOMAIN, domain));
+        }
+        if let Some(geometry) = set.geometry.as_ref() {
+            stages.push((ShaderStageFlags::GEOMETRY, geometry));
+        }
+        if let Some(fragment) = set.fragment.as_ref() {
+            stages.push((ShaderStageFlags::FRAGMENT, fragment));
+        }
+        stages
+    }
+
     pub fn bind_assets(
         &self,
         descriptors: Vec<(DescriptorBinding, DescriptorArrayIndex, Descriptor<B>)>,
@@ -191,19 +178,15 @@ impl<A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Instance<Backend = B>>
 
         info!("{}", "Dropping Pipeline Layout".red());
 
-        let device = &self.state.device();
-        let layout = &self.layout;
-        let descriptor_layouts = &mut self.descriptor_layouts;
-        let descriptor_pool = &mut self.descriptor_pool;
-
-        unsafe {
-            descriptor_pool.reset();
-            device.destroy_descriptor_pool(ManuallyDrop::into_inner(read(descriptor_pool)));
-            device.destroy_pipeline_layout(ManuallyDrop::into_inner(read(layout)));
-            for item in descriptor_layouts.drain(1..) {
-                device.destroy_descriptor_set_layout(item);
-            }
-        }
+        // The descriptor set might still be bound by a frame in flight
+        let mut corpses = unsafe {
+            vec![
+                Corpse::DescriptorPool(ManuallyDrop::into_inner(read(&self.descriptor_pool))),
+                Corpse::PipelineLayout(ManuallyDrop::into_inner(read(&self.layout))),
+            ]
+        };
+        corpses.extend(self.descriptor_layouts.drain(1..).map(Corpse::DescriptorSetLayout));
+        self.state.bury(corpses);
     }
 }
 
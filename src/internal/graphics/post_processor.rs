@@ -0,0 +1,604 @@
+use crate::allocator::GpuAllocator;
+use crate::errors::AssetError;
+use crate::errors::PipelineError;
+use crate::graphics::PostProcessChain;
+use crate::graphics::PostProcessPass;
+use crate::graphics::ShaderSet;
+use crate::graphics::Texture;
+use crate::internal::graphics::color_image::ColorImage;
+use crate::internal::graphics::depth_image::DepthImage;
+use crate::internal::graphics::Corpse;
+use crate::internal::graphics::GraphicsState;
+use crate::internal::graphics::SwapchainBundle;
+use crate::internal::graphics::Single;
+use arrayvec::ArrayVec;
+use colored::*;
+use failure::Error;
+use futures::future::join_all;
+use futures::Future;
+use gfx_hal::command::ClearColor;
+use gfx_hal::command::ClearValue;
+use gfx_hal::command::CommandBuffer;
+use gfx_hal::command::MultiShot;
+use gfx_hal::command::Primary;
+use gfx_hal::format::Format;
+use gfx_hal::format::Rgba8Unorm;
+use gfx_hal::image::Access as ImageAccess;
+use gfx_hal::image::Anisotropic;
+use gfx_hal::image::Filter;
+use gfx_hal::image::Layout;
+use gfx_hal::image::Lod;
+use gfx_hal::image::PackedColor;
+use gfx_hal::image::SamplerInfo;
+use gfx_hal::image::WrapMode;
+use gfx_hal::pass::Attachment;
+use gfx_hal::pass::AttachmentLoadOp;
+use gfx_hal::pass::AttachmentOps;
+use gfx_hal::pass::AttachmentStoreOp;
+use gfx_hal::pass::Subpass;
+use gfx_hal::pass::SubpassDependency;
+use gfx_hal::pass::SubpassDesc;
+use gfx_hal::pass::SubpassRef;
+use gfx_hal::pso::BakedStates;
+use gfx_hal::pso::BasePipeline;
+use gfx_hal::pso::BlendDesc;
+use gfx_hal::pso::BlendState;
+use gfx_hal::pso::ColorBlendDesc;
+use gfx_hal::pso::ColorMask;
+use gfx_hal::pso::DepthStencilDesc;
+use gfx_hal::pso::DepthTest;
+use gfx_hal::pso::Descriptor;
+use gfx_hal::pso::DescriptorPool;
+use gfx_hal::pso::DescriptorRangeDesc;
+use gfx_hal::pso::DescriptorSetLayoutBinding;
+use gfx_hal::pso::DescriptorSetWrite;
+use gfx_hal::pso::DescriptorType;
+use gfx_hal::pso::EntryPoint;
+use gfx_hal::pso::GraphicsPipelineDesc;
+use gfx_hal::pso::GraphicsShaderSet;
+use gfx_hal::pso::InputAssemblerDesc;
+use gfx_hal::pso::PipelineCreationFlags;
+use gfx_hal::pso::PipelineStage;
+use gfx_hal::pso::Rasterizer;
+use gfx_hal::pso::Rect;
+use gfx_hal::pso::ShaderStageFlags;
+use gfx_hal::pso::Specialization;
+use gfx_hal::pso::StencilTest;
+use gfx_hal::pso::Viewport;
+use gfx_hal::window::Extent2D;
+use gfx_hal::Backend;
+use gfx_hal::Device;
+use gfx_hal::General;
+use gfx_hal::Instance;
+use gfx_hal::Primitive;
+use std::mem::ManuallyDrop;
+use std::sync::Arc;
+use std::time::Instant;
+
+/// The number of floats in the `PostProcessConstants` push constant block
+const PUSH_CONSTANT_FLOATS: u32 = 8;
+
+/// Renders the scene into offscreen targets and then runs the passes of a [`PostProcessChain`],
+/// ping ponging between two intermediate images, with the last pass writing to the swapchain
+pub struct PostProcessor<A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Instance<Backend = B>> {
+    state: Arc<GraphicsState<A, B, D, I>>,
+    format: Format,
+    scene_render_pass: ManuallyDrop<B::RenderPass>,
+    pass_render_pass: ManuallyDrop<B::RenderPass>,
+    present_render_pass: ManuallyDrop<B::RenderPass>,
+    sampler: ManuallyDrop<B::Sampler>,
+    pipelines: Vec<PostProcessPipeline<A, B, D, I>>,
+    luts: Vec<Option<Texture<Rgba8Unorm, Single, A, B, D, I>>>,
+    targets: Vec<FrameTargets<B, D>>,
+    scene_framebuffers: Vec<B::Framebuffer>,
+    present_framebuffers: Vec<B::Framebuffer>,
+    render_area: Extent2D,
+    started: Instant,
+}
+
+struct FrameTargets<B: Backend, D: Device<B>> {
+    scene: ColorImage<B, D>,
+    depth: DepthImage<B, D>,
+    intermediates: Vec<ColorImage<B, D>>,
+    intermediate_framebuffers: Vec<B::Framebuffer>,
+}
+
+impl<A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Instance<Backend = B>> PostProcessor<A, B, D, I> {
+    pub fn new(
+        state: Arc<GraphicsState<A, B, D, I>>,
+        chain: PostProcessChain,
+    ) -> impl Future<Item = Self, Error = Error> {
+        debug_assert!(!chain.is_empty(), "An empty chain should not create a post processor");
+
+        let lut_futures = chain
+            .passes()
+            .iter()
+            .map(|pass| {
+                let lut_state = Arc::clone(&state);
+                let lut = match pass {
+                    PostProcessPass::ColorGrading { lut, .. } => Some(*lut),
+                    _ => None,
+                };
+                futures::lazy(move || -> Box<Future<Item = _, Error = Error> + Send> {
+                    match lut {
+                        Some(bytes) => match image::load_from_memory(bytes) {
+                            Ok(image) => Box::new(
+                                Texture::<Rgba8Unorm, Single, A, B, D, I>::new(lut_state, image, false)
+                                    .map(Some),
+                            ),
+                            Err(error) => Box::new(futures::future::err(Error::from(AssetError::from(error)))),
+                        },
+                        None => Box::new(futures::future::ok(None)),
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+
+        join_all(lut_futures).and_then(move |luts| Self::create(state, chain, luts))
+    }
+
+    fn create(
+        state: Arc<GraphicsState<A, B, D, I>>,
+        chain: PostProcessChain,
+        luts: Vec<Option<Texture<Rgba8Unorm, Single, A, B, D, I>>>,
+    ) -> Result<Self, Error> {
+        info!("{}", "Creating post process chain".green());
+
+        let device = state.device();
+        let format = state.swapchain_format();
+
+        let scene_render_pass =
+            SwapchainBundle::<B, D>::create_render_pass(&device, format, Layout::ShaderReadOnlyOptimal)?;
+        let pass_render_pass =
+            Self::create_pass_render_pass(&device, format, Layout::ShaderReadOnlyOptimal)?;
+        let present_render_pass = Self::create_pass_render_pass(&device, format, Layout::Present)?;
+
+        let sampler = unsafe {
+            device.create_sampler(SamplerInfo {
+                min_filter: Filter::Linear,
+                mag_filter: Filter::Linear,
+                mip_filter: Filter::Nearest,
+                wrap_mode: (WrapMode::Clamp, WrapMode::Clamp, WrapMode::Clamp),
+                lod_bias: Lod::from(0.0),
+                lod_range: Lod::from(0.0)..Lod::from(1.0),
+                comparison: None,
+                border: PackedColor(0),
+                anisotropic: Anisotropic::Off,
+            })?
+        };
+
+        let pipelines = chain
+            .passes()
+            .iter()
+            .zip(luts.iter())
+            .map(|(pass, lut)| {
+                let lut_size = lut
+                    .as_ref()
+                    .map(|texture| texture.height() as f32)
+                    .unwrap_or(0.0);
+                PostProcessPipeline::new(
+                    Arc::clone(&state),
+                    &pass_render_pass,
+                    &pass.shader_set(),
+                    pass.parameters(lut_size),
+                )
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        let mut processor = Self {
+            render_area: state.render_area(),
+            state,
+            format,
+            scene_render_pass: ManuallyDrop::new(scene_render_pass),
+            pass_render_pass: ManuallyDrop::new(pass_render_pass),
+            present_render_pass: ManuallyDrop::new(present_render_pass),
+            sampler: ManuallyDrop::new(sampler),
+            pipelines,
+            luts,
+            targets: vec![],
+            scene_framebuffers: vec![],
+            present_framebuffers: vec![],
+            started: Instant::now(),
+        };
+        processor.resize()?;
+
+        Ok(processor)
+    }
+
+    /// Recreates all size dependent targets. Must be called after the swapchain has been recreated
+    pub fn resize(&mut self) -> Result<(), Error> {
+        debug_assert!(
+            self.format == self.state.swapchain_format(),
+            "Changing the swapchain format is not supported by the post processor"
+        );
+
+        self.destroy_targets();
+
+        let device = self.state.device();
+        let render_area = self.state.render_area();
+        let extent = gfx_hal::image::Extent {
+            width: render_area.width,
+            height: render_area.height,
+            depth: 1,
+        };
+        let intermediate_count = self.pipelines.len().saturating_sub(1).min(2);
+
+        let (targets, present_framebuffers) = self.state.swapchain_image_views(|image_views| {
+            let mut targets = Vec::with_capacity(image_views.len());
+            for _ in image_views {
+                let scene = ColorImage::new(Arc::clone(&device), self.state.adapter(), render_area, self.format)?;
+                let depth = DepthImage::new(Arc::clone(&device), self.state.adapter(), render_area)?;
+                let intermediates = (0..intermediate_count)
+                    .map(|_| ColorImage::new(Arc::clone(&device), self.state.adapter(), render_area, self.format))
+                    .collect::<Result<Vec<_>, Error>>()?;
+                let intermediate_framebuffers = intermediates
+                    .iter()
+                    .map(|image| unsafe {
+                        device.create_framebuffer(&self.pass_render_pass, Some(&*image.image_view), extent)
+                    })
+                    .collect::<Result<Vec<_>, gfx_hal::device::OutOfMemory>>()?;
+                targets.push(FrameTargets {
+                    scene,
+                    depth,
+                    intermediates,
+                    intermediate_framebuffers,
+                });
+            }
+
+            let present_framebuffers = image_views
+                .iter()
+                .map(|view| unsafe { device.create_framebuffer(&self.present_render_pass, Some(view), extent) })
+                .collect::<Result<Vec<_>, gfx_hal::device::OutOfMemory>>()?;
+
+            Ok((targets, present_framebuffers))
+        })?;
+
+        self.scene_framebuffers = targets
+            .iter()
+            .map(|target| unsafe {
+                let attachments: ArrayVec<[_; 2]> =
+                    [&*target.scene.image_view, &*target.depth.image_view].into();
+                device.create_framebuffer(&self.scene_render_pass, attachments, extent)
+            })
+            .collect::<Result<Vec<_>, gfx_hal::device::OutOfMemory>>()?;
+
+        for (index, pipeline) in self.pipelines.iter_mut().enumerate() {
+            let inputs = targets
+                .iter()
+                .map(|target| {
+                    if index == 0 {
+                        &*target.scene.image_view
+                    } else {
+                        &*target.intermediates[(index - 1) % 2].image_view
+                    }
+                })
+                .collect::<Vec<_>>();
+            let lut = self.luts[index].as_ref().map(|texture| texture.image_view());
+            pipeline.bind_inputs(&inputs, &self.sampler, lut)?;
+        }
+
+        self.targets = targets;
+        self.present_framebuffers = present_framebuffers;
+        self.render_area = render_area;
+
+        Ok(())
+    }
+
+    /// The render pass and framebuffers the scene should be rendered into
+    pub fn scene_target(&self) -> (&B::RenderPass, &[B::Framebuffer]) {
+        (&self.scene_render_pass, &self.scene_framebuffers)
+    }
+
+    /// Records all passes into the command buffer. The scene render pass must have ended
+    pub fn record(&self, command_buffer: &mut CommandBuffer<B, General, MultiShot, Primary>, image_index: usize) {
+        let rect = Rect {
+            x: 0,
+            y: 0,
+            w: self.render_area.width as _,
+            h: self.render_area.height as _,
+        };
+        let viewport = Viewport {
+            rect,
+            depth: 0.0..1.0,
+        };
+        let elapsed = self.started.elapsed();
+        let time = elapsed.as_secs() as f32 + elapsed.subsec_nanos() as f32 * 1e-9;
+        let clear_values = [ClearValue::Color(ClearColor::Float([0.0, 0.0, 0.0, 1.0]))];
+
+        let last = self.pipelines.len() - 1;
+        for (index, pipeline) in self.pipelines.iter().enumerate() {
+            let (render_pass, framebuffer) = if index == last {
+                (&*self.present_render_pass, &self.present_framebuffers[image_index])
+            } else {
+                (
+                    &*self.pass_render_pass,
+                    &self.targets[image_index].intermediate_framebuffers[index % 2],
+                )
+            };
+
+            let constants = [
+                self.render_area.width as f32,
+                self.render_area.height as f32,
+                time,
+                0.0,
+                pipeline.parameters[0],
+                pipeline.parameters[1],
+                pipeline.parameters[2],
+                pipeline.parameters[3],
+            ];
+            let constants: Vec<u32> = constants.iter().map(|value| value.to_bits()).collect();
+
+            unsafe {
+                let mut encoder =
+                    command_buffer.begin_render_pass_inline(render_pass, framebuffer, rect, clear_values.iter());
+                encoder.set_viewports(0, &[viewport.clone()]);
+                encoder.set_scissors(0, &[rect]);
+                encoder.bind_graphics_pipeline(&pipeline.pipeline);
+                encoder.bind_graphics_descriptor_sets(
+                    &pipeline.layout,
+                    0,
+                    Some(&pipeline.descriptor_sets[image_index]),
+                    &[],
+                );
+                encoder.push_graphics_constants(&pipeline.layout, ShaderStageFlags::FRAGMENT, 0, &constants);
+                encoder.draw(0..3, 0..1);
+            }
+        }
+    }
+
+    fn create_pass_render_pass(device: &D, format: Format, final_layout: Layout) -> Result<B::RenderPass, Error> {
+        let color_attachment = Attachment {
+            format: Some(format),
+            samples: 1,
+            ops: AttachmentOps {
+                load: AttachmentLoadOp::DontCare,
+                store: AttachmentStoreOp::Store,
+            },
+            stencil_ops: AttachmentOps::DONT_CARE,
+            layouts: Layout::Undefined..final_layout,
+        };
+        let subpass = SubpassDesc {
+            colors: &[(0, Layout::ColorAttachmentOptimal)],
+            depth_stencil: None,
+            inputs: &[],
+            resolves: &[],
+            preserves: &[],
+        };
+        let in_dependency = SubpassDependency {
+            passes: SubpassRef::External..SubpassRef::Pass(0),
+            stages: PipelineStage::COLOR_ATTACHMENT_OUTPUT..PipelineStage::COLOR_ATTACHMENT_OUTPUT,
+            accesses: ImageAccess::empty()..ImageAccess::COLOR_ATTACHMENT_WRITE,
+        };
+        let out_dependency = SubpassDependency {
+            passes: SubpassRef::Pass(0)..SubpassRef::External,
+            stages: PipelineStage::COLOR_ATTACHMENT_OUTPUT..PipelineStage::FRAGMENT_SHADER,
+            accesses: ImageAccess::COLOR_ATTACHMENT_WRITE..ImageAccess::SHADER_READ,
+        };
+        Ok(unsafe { device.create_render_pass(&[color_attachment], &[subpass], &[in_dependency, out_dependency])? })
+    }
+
+    /// Buries the size dependent targets, the frames in flight might still render to them
+    fn destroy_targets(&mut self) {
+        let mut corpses = Vec::new();
+        corpses.extend(self.scene_framebuffers.drain(..).map(Corpse::Framebuffer));
+        corpses.extend(self.present_framebuffers.drain(..).map(Corpse::Framebuffer));
+        for target in self.targets.drain(..) {
+            corpses.extend(target.intermediate_framebuffers.into_iter().map(Corpse::Framebuffer));
+            corpses.extend(target.scene.into_corpses());
+            corpses.extend(target.depth.into_corpses());
+            for intermediate in target.intermediates {
+                corpses.extend(intermediate.into_corpses());
+            }
+        }
+        self.state.bury(corpses);
+    }
+}
+
+impl<A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Instance<Backend = B>> Drop for PostProcessor<A, B, D, I> {
+    fn drop(&mut self) {
+        use core::ptr::read;
+
+        info!("{}", "Dropping post process chain".red());
+
+        self.destroy_targets();
+        self.pipelines.clear();
+
+        unsafe {
+            self.state.bury(vec![
+                Corpse::Sampler(ManuallyDrop::into_inner(read(&self.sampler))),
+                Corpse::RenderPass(ManuallyDrop::into_inner(read(&self.scene_render_pass))),
+                Corpse::RenderPass(ManuallyDrop::into_inner(read(&self.pass_render_pass))),
+                Corpse::RenderPass(ManuallyDrop::into_inner(read(&self.present_render_pass))),
+            ]);
+        }
+    }
+}
+
+struct PostProcessPipeline<A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Instance<Backend = B>> {
+    state: Arc<GraphicsState<A, B, D, I>>,
+    descriptor_layout: ManuallyDrop<B::DescriptorSetLayout>,
+    layout: ManuallyDrop<B::PipelineLayout>,
+    pipeline: ManuallyDrop<B::GraphicsPipeline>,
+    descriptor_pool: Option<B::DescriptorPool>,
+    descriptor_sets: Vec<B::DescriptorSet>,
+    parameters: [f32; 4],
+}
+
+impl<A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Instance<Backend = B>> PostProcessPipeline<A, B, D, I> {
+    fn new(
+        state: Arc<GraphicsState<A, B, D, I>>,
+        render_pass: &B::RenderPass,
+        set: &ShaderSet,
+        parameters: [f32; 4],
+    ) -> Result<Self, Error> {
+        let fragment = set
+            .fragment
+            .as_ref()
+            .ok_or(PipelineError::MissingFragmentShader)?;
+
+        let bindings = [
+            (0, DescriptorType::SampledImage),
+            (1, DescriptorType::Sampler),
+            (2, DescriptorType::SampledImage),
+        ]
+        .iter()
+        .map(|(binding, ty)| DescriptorSetLayoutBinding {
+            binding: *binding,
+            ty: *ty,
+            count: 1,
+            stage_flags: ShaderStageFlags::FRAGMENT,
+            immutable_samplers: false,
+        })
+        .collect::<Vec<_>>();
+
+        let device = state.device();
+        unsafe {
+            let descriptor_layout =
+                device.create_descriptor_set_layout(bindings, Vec::<B::Sampler>::new())?;
+            let layout = device.create_pipeline_layout(
+                Some(&descriptor_layout),
+                &[(ShaderStageFlags::FRAGMENT, 0..PUSH_CONSTANT_FLOATS)],
+            )?;
+
+            let vertex_module = device.create_shader_module(&set.vertex.spirv)?;
+            let fragment_module = device.create_shader_module(&fragment.spirv)?;
+
+            let pipeline = {
+                let entry = |module| EntryPoint {
+                    entry: "main",
+                    module,
+                    specialization: Specialization {
+                        constants: &[],
+                        data: &[],
+                    },
+                };
+                let desc = GraphicsPipelineDesc {
+                    shaders: GraphicsShaderSet {
+                        vertex: entry(&vertex_module),
+                        hull: None,
+                        domain: None,
+                        geometry: None,
+                        fragment: Some(entry(&fragment_module)),
+                    },
+                    rasterizer: Rasterizer::FILL,
+                    vertex_buffers: vec![],
+                    attributes: vec![],
+                    input_assembler: InputAssemblerDesc::new(Primitive::TriangleList),
+                    blender: BlendDesc {
+                        logic_op: None,
+                        targets: vec![ColorBlendDesc(ColorMask::ALL, BlendState::Off)],
+                    },
+                    depth_stencil: DepthStencilDesc {
+                        depth: DepthTest::Off,
+                        depth_bounds: false,
+                        stencil: StencilTest::Off,
+                    },
+                    multisampling: None,
+                    baked_states: BakedStates {
+                        viewport: None,
+                        scissor: None,
+                        blend_color: None,
+                        depth_bounds: None,
+                    },
+                    layout: &layout,
+                    subpass: Subpass {
+                        index: 0,
+                        main_pass: render_pass,
+                    },
+                    flags: PipelineCreationFlags::empty(),
+                    parent: BasePipeline::None,
+                };
+                device.create_graphics_pipeline(&desc, None)
+            };
+
+            device.destroy_shader_module(vertex_module);
+            device.destroy_shader_module(fragment_module);
+
+            Ok(Self {
+                state,
+                descriptor_layout: ManuallyDrop::new(descriptor_layout),
+                layout: ManuallyDrop::new(layout),
+                pipeline: ManuallyDrop::new(pipeline?),
+                descriptor_pool: None,
+                descriptor_sets: vec![],
+                parameters,
+            })
+        }
+    }
+
+    /// Allocates one descriptor set per swapchain image, each sampling its own input
+    fn bind_inputs(
+        &mut self,
+        inputs: &[&B::ImageView],
+        sampler: &B::Sampler,
+        lut: Option<&B::ImageView>,
+    ) -> Result<(), Error> {
+        unsafe {
+            // The frames in flight might still use the old sets
+            self.state.bury(self.descriptor_pool.take().map(Corpse::DescriptorPool));
+            self.descriptor_sets.clear();
+
+            let device = self.state.device();
+            let mut pool = device.create_descriptor_pool(
+                inputs.len(),
+                &[
+                    DescriptorRangeDesc {
+                        ty: DescriptorType::SampledImage,
+                        count: inputs.len() * 2,
+                    },
+                    DescriptorRangeDesc {
+                        ty: DescriptorType::Sampler,
+                        count: inputs.len(),
+                    },
+                ],
+            )?;
+
+            for input in inputs {
+                let set = pool.allocate_set(&self.descriptor_layout)?;
+                let mut writes = vec![
+                    DescriptorSetWrite {
+                        set: &set,
+                        binding: 0,
+                        array_offset: 0,
+                        descriptors: Some(Descriptor::Image(*input, Layout::ShaderReadOnlyOptimal)),
+                    },
+                    DescriptorSetWrite {
+                        set: &set,
+                        binding: 1,
+                        array_offset: 0,
+                        descriptors: Some(Descriptor::Sampler(sampler)),
+                    },
+                ];
+                if let Some(lut) = lut {
+                    writes.push(DescriptorSetWrite {
+                        set: &set,
+                        binding: 2,
+                        array_offset: 0,
+                        descriptors: Some(Descriptor::Image(lut, Layout::ShaderReadOnlyOptimal)),
+                    });
+                }
+                device.write_descriptor_sets(writes);
+                self.descriptor_sets.push(set);
+            }
+
+            self.descriptor_pool = Some(pool);
+        }
+        Ok(())
+    }
+}
+
+impl<A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Instance<Backend = B>> Drop for PostProcessPipeline<A, B, D, I> {
+    fn drop(&mut self) {
+        use core::ptr::read;
+
+        unsafe {
+            let mut corpses = vec![
+                Corpse::GraphicsPipeline(ManuallyDrop::into_inner(read(&self.pipeline))),
+                Corpse::PipelineLayout(ManuallyDrop::into_inner(read(&self.layout))),
+                Corpse::DescriptorSetLayout(ManuallyDrop::into_inner(read(&self.descriptor_layout))),
+            ];
+            corpses.extend(self.descriptor_pool.take().map(Corpse::DescriptorPool));
+            self.state.bury(corpses);
+        }
+    }
+}
@@ -0,0 +1,135 @@
+use gfx_hal::QueueType;
+
+/// What we need to know about a queue family in order to pick it
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueueFamilyInfo {
+    pub id: usize,
+    pub queue_type: QueueType,
+    pub max_queues: usize,
+    pub supports_present: bool,
+}
+
+/// The queue families a device is opened with
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QueueSelection {
+    /// Records graphics and compute work and presents, so it has to support all three
+    pub main: usize,
+
+    /// A separate family used for uploads, if the device has one
+    pub transfer: Option<usize>,
+}
+
+/// Picks the main family and, when available, a separate family for transfers. Families that can
+/// only transfer are preferred, since they usually map to dedicated copy engines. Returns None if
+/// no family can be used as the main one
+pub fn select_queue_families(families: &[QueueFamilyInfo]) -> Option<QueueSelection> {
+    let main = families
+        .iter()
+        .find(|family| {
+            family.queue_type == QueueType::General && family.supports_present && family.max_queues > 0
+        })?
+        .id;
+
+    let transfer = families
+        .iter()
+        .filter(|family| family.id != main && family.max_queues > 0)
+        .filter_map(|family| transfer_rank(family.queue_type).map(|rank| (rank, family.id)))
+        .min_by_key(|&(rank, _)| rank)
+        .map(|(_, id)| id);
+
+    Some(QueueSelection { main, transfer })
+}
+
+fn transfer_rank(queue_type: QueueType) -> Option<u8> {
+    match queue_type {
+        QueueType::Transfer => Some(0),
+        QueueType::Compute => Some(1),
+        QueueType::General => Some(2),
+        QueueType::Graphics => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn family(id: usize, queue_type: QueueType, supports_present: bool) -> QueueFamilyInfo {
+        QueueFamilyInfo {
+            id,
+            queue_type,
+            max_queues: 1,
+            supports_present,
+        }
+    }
+
+    #[test]
+    fn it_should_use_the_main_queue_for_everything_on_single_family_devices() {
+        let families = vec![family(0, QueueType::General, true)];
+        assert_eq!(
+            select_queue_families(&families),
+            Some(QueueSelection {
+                main: 0,
+                transfer: None
+            })
+        );
+    }
+
+    #[test]
+    fn it_should_prefer_dedicated_transfer_families() {
+        let families = vec![
+            family(0, QueueType::General, true),
+            family(1, QueueType::Compute, false),
+            family(2, QueueType::Transfer, false),
+        ];
+        assert_eq!(
+            select_queue_families(&families),
+            Some(QueueSelection {
+                main: 0,
+                transfer: Some(2)
+            })
+        );
+    }
+
+    #[test]
+    fn it_should_fall_back_to_async_compute_families_for_transfers() {
+        let families = vec![
+            family(0, QueueType::Compute, false),
+            family(1, QueueType::General, true),
+        ];
+        assert_eq!(
+            select_queue_families(&families),
+            Some(QueueSelection {
+                main: 1,
+                transfer: Some(0)
+            })
+        );
+    }
+
+    #[test]
+    fn it_should_never_present_from_a_transfer_family() {
+        let families = vec![
+            family(0, QueueType::Transfer, true),
+            family(1, QueueType::General, false),
+        ];
+        assert_eq!(select_queue_families(&families), None);
+    }
+
+    #[test]
+    fn it_should_skip_families_without_queues() {
+        let families = vec![
+            family(0, QueueType::General, true),
+            QueueFamilyInfo {
+                max_queues: 0,
+                ..family(1, QueueType::Transfer, false)
+            },
+        ];
+        assert_eq!(
+            select_queue_families(&families),
+            Some(QueueSelection {
+                main: 0,
+                transfer: None
+            })
+        );
+    }
+}
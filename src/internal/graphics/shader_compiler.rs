@@ -0,0 +1,33 @@
+use failure::Error;
+use gfx_hal::pso::Stage;
+use starstruck_build::Preprocessor;
+use starstruck_build::ShaderKind;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// Compiles glsl into SPIR-V. `name` is only used to make the error messages readable
+pub fn compile_glsl(source: &str, stage: Stage, name: &str) -> Result<Vec<u8>, Error> {
+    starstruck_build::compile_glsl(source, shader_kind(stage), name)
+}
+
+/// Compiles a glsl file, expanding any `#include`s relative to it
+pub fn compile_glsl_file(path: &Path, stage: Stage) -> Result<Vec<u8>, Error> {
+    let source = Preprocessor::new().process_file(path)?;
+    starstruck_build::compile_preprocessed(&source, shader_kind(stage))
+}
+
+/// The files a glsl file is built from, the file itself first and then everything it includes
+pub fn glsl_dependencies(path: &Path) -> Result<Vec<PathBuf>, Error> {
+    Ok(Preprocessor::new().process_file(path)?.dependencies)
+}
+
+fn shader_kind(stage: Stage) -> ShaderKind {
+    match stage {
+        Stage::Vertex => ShaderKind::Vertex,
+        Stage::Hull => ShaderKind::TessellationControl,
+        Stage::Domain => ShaderKind::TessellationEvaluation,
+        Stage::Geometry => ShaderKind::Geometry,
+        Stage::Fragment => ShaderKind::Fragment,
+        Stage::Compute => ShaderKind::Compute,
+    }
+}
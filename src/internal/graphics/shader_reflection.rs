@@ -0,0 +1,656 @@
+use crate::errors::PipelineError;
+use failure::Error;
+use gfx_hal::format::Format;
+use gfx_hal::pso::AttributeDesc;
+use gfx_hal::pso::DescriptorArrayIndex;
+use gfx_hal::pso::DescriptorBinding;
+use gfx_hal::pso::DescriptorType;
+use std::collections::HashMap;
+
+const MAGIC: u32 = 0x0723_0203;
+
+const OP_NAME: u32 = 5;
+const OP_TYPE_BOOL: u32 = 20;
+const OP_TYPE_INT: u32 = 21;
+const OP_TYPE_FLOAT: u32 = 22;
+const OP_TYPE_VECTOR: u32 = 23;
+const OP_TYPE_MATRIX: u32 = 24;
+const OP_TYPE_IMAGE: u32 = 25;
+const OP_TYPE_SAMPLER: u32 = 26;
+const OP_TYPE_SAMPLED_IMAGE: u32 = 27;
+const OP_TYPE_ARRAY: u32 = 28;
+const OP_TYPE_RUNTIME_ARRAY: u32 = 29;
+const OP_TYPE_STRUCT: u32 = 30;
+const OP_TYPE_POINTER: u32 = 32;
+const OP_CONSTANT: u32 = 43;
+const OP_SPEC_CONSTANT: u32 = 50;
+const OP_VARIABLE: u32 = 59;
+const OP_DECORATE: u32 = 71;
+const OP_MEMBER_DECORATE: u32 = 72;
+
+const DECORATION_BUFFER_BLOCK: u32 = 3;
+const DECORATION_ARRAY_STRIDE: u32 = 6;
+const DECORATION_MATRIX_STRIDE: u32 = 7;
+const DECORATION_BUILT_IN: u32 = 11;
+const DECORATION_LOCATION: u32 = 30;
+const DECORATION_BINDING: u32 = 33;
+const DECORATION_DESCRIPTOR_SET: u32 = 34;
+const DECORATION_OFFSET: u32 = 35;
+
+const STORAGE_UNIFORM_CONSTANT: u32 = 0;
+const STORAGE_INPUT: u32 = 1;
+const STORAGE_UNIFORM: u32 = 2;
+const STORAGE_PUSH_CONSTANT: u32 = 9;
+const STORAGE_STORAGE_BUFFER: u32 = 12;
+
+const DIM_BUFFER: u32 = 5;
+const DIM_SUBPASS_DATA: u32 = 6;
+
+/// What a shader module declares, as far as the pipeline layout and vertex input are concerned
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShaderReflection {
+    pub push_constant_floats: u32,
+    pub bindings: Vec<(DescriptorBinding, DescriptorType, DescriptorArrayIndex)>,
+    pub inputs: Vec<ShaderInput>,
+}
+
+/// A single location read by the shader. Matrices take up one location per column
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShaderInput {
+    pub name: String,
+    pub location: u32,
+    pub scalar: ScalarType,
+    pub components: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScalarType {
+    Float,
+    Int,
+    Uint,
+    Bool,
+}
+
+#[derive(Debug, Clone)]
+enum Type {
+    Scalar(ScalarType, u32),
+    Vector(u32, u32),
+    Matrix(u32, u32),
+    Image { dim: u32, sampled: u32 },
+    Sampler,
+    SampledImage,
+    Array(u32, u32),
+    RuntimeArray(u32),
+    Struct(Vec<u32>),
+    Pointer(u32),
+}
+
+#[derive(Debug, Default)]
+struct Module {
+    names: HashMap<u32, String>,
+    decorations: HashMap<(u32, u32), u32>,
+    member_decorations: HashMap<(u32, u32, u32), u32>,
+    types: HashMap<u32, Type>,
+    constants: HashMap<u32, u32>,
+    variables: Vec<(u32, u32, u32)>,
+}
+
+/// Parses a SPIR-V module and returns its push constants, descriptor bindings and inputs
+pub fn reflect(spirv: &[u8]) -> Result<ShaderReflection, Error> {
+    let module = Module::parse(&read_words(spirv)?)?;
+
+    let mut push_constant_floats = 0;
+    let mut bindings = vec![];
+    let mut inputs = vec![];
+
+    for &(id, type_id, storage_class) in &module.variables {
+        let pointee = match module.types.get(&type_id) {
+            Some(Type::Pointer(pointee)) => *pointee,
+            _ => bail!("Variable `{}` is not a pointer", module.name(id)),
+        };
+
+        match storage_class {
+            STORAGE_PUSH_CONSTANT => {
+                // Push constant blocks only hold 32 bit types, so the size is a multiple of 4
+                let size = module.size_of(pointee)?;
+                push_constant_floats = push_constant_floats.max(size / 4);
+            }
+            STORAGE_UNIFORM_CONSTANT | STORAGE_UNIFORM | STORAGE_STORAGE_BUFFER => {
+                let set = module.decoration(id, DECORATION_DESCRIPTOR_SET).unwrap_or(0);
+                if set != 0 {
+                    bail!(
+                        "`{}` uses descriptor set {}, only set 0 is supported",
+                        module.name(id),
+                        set
+                    );
+                }
+                let binding = match module.decoration(id, DECORATION_BINDING) {
+                    Some(binding) => binding,
+                    None => continue,
+                };
+                let (ty, count) = module.descriptor_type(pointee, storage_class)?;
+                bindings.push((binding, ty, count));
+            }
+            STORAGE_INPUT => {
+                if module.decoration(id, DECORATION_BUILT_IN).is_some() || module.is_built_in_block(pointee) {
+                    continue;
+                }
+                if let Some(location) = module.decoration(id, DECORATION_LOCATION) {
+                    inputs.extend(module.inputs(id, pointee, location)?);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    bindings.sort_by_key(|(binding, _, _)| *binding);
+    inputs.sort_by_key(|input| input.location);
+
+    Ok(ShaderReflection {
+        push_constant_floats,
+        bindings,
+        inputs,
+    })
+}
+
+/// Checks that every input of a vertex shader is fed by a vertex attribute of a matching type
+pub fn validate_vertex_attributes(inputs: &[ShaderInput], attributes: &[AttributeDesc]) -> Result<(), Error> {
+    for input in inputs {
+        let attribute = match attributes.iter().find(|a| a.location == input.location) {
+            Some(attribute) => attribute,
+            None => Err(PipelineError::MissingVertexAttribute {
+                input: input.name.clone(),
+                location: input.location,
+            })?,
+        };
+
+        if let Some((scalar, components)) = format_components(attribute.element.format) {
+            if scalar != input.scalar || components != input.components {
+                Err(PipelineError::VertexAttributeMismatch {
+                    input: input.name.clone(),
+                    location: input.location,
+                    components: input.components,
+                    scalar: format!("{:?}", input.scalar),
+                    format: attribute.element.format,
+                })?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Checks that the push constants and bindings declared for a shader cover everything it uses.
+/// `bindings` should hold the bindings of every stage in the set, since they share one layout
+pub fn validate_layout(
+    reflection: &ShaderReflection,
+    push_constant_floats: u32,
+    bindings: &[(DescriptorBinding, DescriptorType, DescriptorArrayIndex)],
+) -> Result<(), Error> {
+    if reflection.push_constant_floats > push_constant_floats {
+        bail!(
+            "The shader uses {} floats of push constants, but only {} are declared",
+            reflection.push_constant_floats,
+            push_constant_floats
+        );
+    }
+
+    for (binding, ty, count) in &reflection.bindings {
+        match bindings.iter().find(|(declared, _, _)| declared == binding) {
+            Some((_, declared_ty, declared_count)) if declared_ty == ty && declared_count >= count => {}
+            Some((_, declared_ty, declared_count)) => bail!(
+                "The shader uses {} {:?} at binding {}, but {} {:?} is declared",
+                count,
+                ty,
+                binding,
+                declared_count,
+                declared_ty
+            ),
+            None => bail!(
+                "The shader uses {} {:?} at binding {}, but the binding isn't declared",
+                count,
+                ty,
+                binding
+            ),
+        }
+    }
+    Ok(())
+}
+
+/// The type the shader sees when reading a vertex attribute of this format. Returns `None` for
+/// formats that aren't checked
+fn format_components(format: Format) -> Option<(ScalarType, u32)> {
+    match format {
+        Format::R32Float | Format::R8Unorm => Some((ScalarType::Float, 1)),
+        Format::Rg32Float | Format::Rg8Unorm => Some((ScalarType::Float, 2)),
+        Format::Rgb32Float | Format::Rgb8Unorm => Some((ScalarType::Float, 3)),
+        Format::Rgba32Float | Format::Rgba8Unorm => Some((ScalarType::Float, 4)),
+        Format::R32Int => Some((ScalarType::Int, 1)),
+        Format::Rg32Int => Some((ScalarType::Int, 2)),
+        Format::Rgb32Int => Some((ScalarType::Int, 3)),
+        Format::Rgba32Int => Some((ScalarType::Int, 4)),
+        Format::R32Uint => Some((ScalarType::Uint, 1)),
+        Format::Rg32Uint => Some((ScalarType::Uint, 2)),
+        Format::Rgb32Uint => Some((ScalarType::Uint, 3)),
+        Format::Rgba32Uint => Some((ScalarType::Uint, 4)),
+        _ => None,
+    }
+}
+
+fn read_words(spirv: &[u8]) -> Result<Vec<u32>, Error> {
+    let chunks = spirv.chunks_exact(4);
+    if !chunks.remainder().is_empty() || spirv.len() < 20 {
+        bail!("Invalid SPIR-V, the module is {} bytes long", spirv.len());
+    }
+
+    let words: Vec<u32> = chunks
+        .map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        .collect();
+
+    if words[0] == MAGIC {
+        Ok(words)
+    } else if words[0].swap_bytes() == MAGIC {
+        Ok(words.into_iter().map(u32::swap_bytes).collect())
+    } else {
+        bail!("Invalid SPIR-V, the magic number is {:#x}", words[0])
+    }
+}
+
+impl Module {
+    fn parse(words: &[u32]) -> Result<Self, Error> {
+        let mut module = Module::default();
+
+        // The first five words are the header
+        let mut index = 5;
+        while index < words.len() {
+            let count = (words[index] >> 16) as usize;
+            let opcode = words[index] & 0xffff;
+            if count == 0 || index + count > words.len() {
+                bail!("Invalid SPIR-V, the instruction at word {} is truncated", index);
+            }
+            let operands = &words[index + 1..index + count];
+            module.add(opcode, operands)?;
+            index += count;
+        }
+
+        Ok(module)
+    }
+
+    fn add(&mut self, opcode: u32, operands: &[u32]) -> Result<(), Error> {
+        let operand = |index: usize| -> Result<u32, Error> {
+            operands
+                .get(index)
+                .cloned()
+                .ok_or_else(|| format_err!("Invalid SPIR-V, opcode {} is missing operands", opcode))
+        };
+
+        match opcode {
+            OP_NAME => {
+                self.names.insert(operand(0)?, read_string(&operands[1..]));
+            }
+            OP_DECORATE => {
+                self.decorations
+                    .insert((operand(0)?, operand(1)?), operands.get(2).cloned().unwrap_or(0));
+            }
+            OP_MEMBER_DECORATE => {
+                self.member_decorations.insert(
+                    (operand(0)?, operand(1)?, operand(2)?),
+                    operands.get(3).cloned().unwrap_or(0),
+                );
+            }
+            OP_TYPE_BOOL => {
+                self.types.insert(operand(0)?, Type::Scalar(ScalarType::Bool, 32));
+            }
+            OP_TYPE_INT => {
+                let scalar = if operand(2)? == 0 {
+                    ScalarType::Uint
+                } else {
+                    ScalarType::Int
+                };
+                self.types.insert(operand(0)?, Type::Scalar(scalar, operand(1)?));
+            }
+            OP_TYPE_FLOAT => {
+                self.types.insert(operand(0)?, Type::Scalar(ScalarType::Float, operand(1)?));
+            }
+            OP_TYPE_VECTOR => {
+                self.types.insert(operand(0)?, Type::Vector(operand(1)?, operand(2)?));
+            }
+            OP_TYPE_MATRIX => {
+                self.types.insert(operand(0)?, Type::Matrix(operand(1)?, operand(2)?));
+            }
+            OP_TYPE_IMAGE => {
+                self.types.insert(
+                    operand(0)?,
+                    Type::Image {
+                        dim: operand(2)?,
+                        sampled: operand(6)?,
+                    },
+                );
+            }
+            OP_TYPE_SAMPLER => {
+                self.types.insert(operand(0)?, Type::Sampler);
+            }
+            OP_TYPE_SAMPLED_IMAGE => {
+                self.types.insert(operand(0)?, Type::SampledImage);
+            }
+            OP_TYPE_ARRAY => {
+                self.types.insert(operand(0)?, Type::Array(operand(1)?, operand(2)?));
+            }
+            OP_TYPE_RUNTIME_ARRAY => {
+                self.types.insert(operand(0)?, Type::RuntimeArray(operand(1)?));
+            }
+            OP_TYPE_STRUCT => {
+                self.types.insert(operand(0)?, Type::Struct(operands[1..].to_vec()));
+            }
+            OP_TYPE_POINTER => {
+                self.types.insert(operand(0)?, Type::Pointer(operand(2)?));
+            }
+            OP_CONSTANT | OP_SPEC_CONSTANT => {
+                self.constants.insert(operand(1)?, operand(2)?);
+            }
+            OP_VARIABLE => {
+                self.variables.push((operand(1)?, operand(0)?, operand(2)?));
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn name(&self, id: u32) -> String {
+        self.names
+            .get(&id)
+            .filter(|name| !name.is_empty())
+            .cloned()
+            .unwrap_or_else(|| format!("%{}", id))
+    }
+
+    fn decoration(&self, id: u32, decoration: u32) -> Option<u32> {
+        self.decorations.get(&(id, decoration)).cloned()
+    }
+
+    fn member_decoration(&self, id: u32, member: u32, decoration: u32) -> Option<u32> {
+        self.member_decorations.get(&(id, member, decoration)).cloned()
+    }
+
+    fn get(&self, id: u32) -> Result<&Type, Error> {
+        self.types
+            .get(&id)
+            .ok_or_else(|| format_err!("Invalid SPIR-V, %{} is not a known type", id))
+    }
+
+    fn array_length(&self, id: u32) -> Result<u32, Error> {
+        self.constants
+            .get(&id)
+            .cloned()
+            .ok_or_else(|| format_err!("Invalid SPIR-V, array length %{} is not a constant", id))
+    }
+
+    fn is_built_in_block(&self, id: u32) -> bool {
+        match self.types.get(&id) {
+            Some(Type::Struct(members)) => (0..members.len() as u32)
+                .any(|member| self.member_decoration(id, member, DECORATION_BUILT_IN).is_some()),
+            Some(Type::Array(element, _)) => self.is_built_in_block(*element),
+            _ => false,
+        }
+    }
+
+    /// The size in bytes, using the offsets and strides decorated by the compiler
+    fn size_of(&self, id: u32) -> Result<u32, Error> {
+        Ok(match self.get(id)? {
+            Type::Scalar(_, width) => width / 8,
+            Type::Vector(component, count) => self.size_of(*component)? * count,
+            Type::Matrix(column, count) => self.size_of(*column)? * count,
+            Type::Array(element, length) => {
+                let stride = match self.decoration(id, DECORATION_ARRAY_STRIDE) {
+                    Some(stride) => stride,
+                    None => self.size_of(*element)?,
+                };
+                stride * self.array_length(*length)?
+            }
+            Type::Struct(members) => {
+                let mut size = 0;
+                for (member, &member_type) in members.iter().enumerate() {
+                    let member = member as u32;
+                    let offset = self
+                        .member_decoration(id, member, DECORATION_OFFSET)
+                        .unwrap_or(size);
+                    let member_size = match (
+                        self.get(member_type)?,
+                        self.member_decoration(id, member, DECORATION_MATRIX_STRIDE),
+                    ) {
+                        (Type::Matrix(_, columns), Some(stride)) => stride * columns,
+                        _ => self.size_of(member_type)?,
+                    };
+                    size = size.max(offset + member_size);
+                }
+                size
+            }
+            ty => bail!("A {:?} has no size", ty),
+        })
+    }
+
+    fn descriptor_type(&self, id: u32, storage_class: u32) -> Result<(DescriptorType, DescriptorArrayIndex), Error> {
+        let mut id = id;
+        let mut count = 1;
+        loop {
+            match self.get(id)? {
+                Type::Array(element, length) => {
+                    count *= self.array_length(*length)? as usize;
+                    id = *element;
+                }
+                Type::RuntimeArray(element) => id = *element,
+                _ => break,
+            }
+        }
+
+        let ty = match self.get(id)? {
+            Type::Sampler => DescriptorType::Sampler,
+            Type::SampledImage => DescriptorType::CombinedImageSampler,
+            Type::Image { dim, .. } if *dim == DIM_SUBPASS_DATA => DescriptorType::InputAttachment,
+            Type::Image { dim, sampled } if *dim == DIM_BUFFER => {
+                if *sampled == 2 {
+                    DescriptorType::StorageTexelBuffer
+                } else {
+                    DescriptorType::UniformTexelBuffer
+                }
+            }
+            Type::Image { sampled, .. } => {
+                if *sampled == 2 {
+                    DescriptorType::StorageImage
+                } else {
+                    DescriptorType::SampledImage
+                }
+            }
+            Type::Struct(_) => {
+                if storage_class == STORAGE_STORAGE_BUFFER || self.decoration(id, DECORATION_BUFFER_BLOCK).is_some() {
+                    DescriptorType::StorageBuffer
+                } else {
+                    DescriptorType::UniformBuffer
+                }
+            }
+            ty => bail!("A {:?} can't be bound as a descriptor", ty),
+        };
+        Ok((ty, count))
+    }
+
+    fn inputs(&self, id: u32, type_id: u32, location: u32) -> Result<Vec<ShaderInput>, Error> {
+        let name = self.name(id);
+        let input = |location: u32, type_id: u32| -> Result<ShaderInput, Error> {
+            let (scalar, components) = match self.get(type_id)? {
+                Type::Scalar(scalar, _) => (*scalar, 1),
+                Type::Vector(component, count) => match self.get(*component)? {
+                    Type::Scalar(scalar, _) => (*scalar, *count),
+                    ty => bail!("Input `{}` is a vector of {:?}", name, ty),
+                },
+                ty => bail!("Input `{}` has unsupported type {:?}", name, ty),
+            };
+            Ok(ShaderInput {
+                name: name.clone(),
+                location,
+                scalar,
+                components,
+            })
+        };
+
+        match self.get(type_id)? {
+            Type::Matrix(column, columns) => (0..*columns)
+                .map(|offset| input(location + offset, *column))
+                .collect(),
+            Type::Array(element, length) => (0..self.array_length(*length)?)
+                .map(|offset| input(location + offset, *element))
+                .collect(),
+            _ => Ok(vec![input(location, type_id)?]),
+        }
+    }
+}
+
+/// Reads a nul terminated string packed into words
+fn read_string(words: &[u32]) -> String {
+    let bytes: Vec<u8> = words
+        .iter()
+        .flat_map(|word| word.to_le_bytes().to_vec())
+        .take_while(|byte| *byte != 0)
+        .collect();
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gfx_hal::pso::Element;
+    use pretty_assertions::assert_eq;
+
+    fn op(opcode: u32, operands: &[u32]) -> Vec<u32> {
+        let mut words = vec![((operands.len() as u32 + 1) << 16) | opcode];
+        words.extend_from_slice(operands);
+        words
+    }
+
+    fn name(id: u32, name: &str) -> Vec<u32> {
+        let mut bytes = name.as_bytes().to_vec();
+        bytes.resize((bytes.len() / 4 + 1) * 4, 0);
+        let mut operands = vec![id];
+        operands.extend(
+            bytes
+                .chunks(4)
+                .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]])),
+        );
+        op(OP_NAME, &operands)
+    }
+
+    /// Roughly what glslang emits for a vertex shader with a `mat4` push constant, a `vec3` input,
+    /// a uniform buffer at binding 2 and an array of two textures at binding 0
+    fn module() -> Vec<u8> {
+        let instructions = vec![
+            name(10, "position"),
+            op(OP_DECORATE, &[10, DECORATION_LOCATION, 0]),
+            op(OP_DECORATE, &[11, DECORATION_BUILT_IN, 42]),
+            op(OP_MEMBER_DECORATE, &[6, 0, DECORATION_OFFSET, 0]),
+            op(OP_MEMBER_DECORATE, &[6, 0, DECORATION_MATRIX_STRIDE, 16]),
+            op(OP_DECORATE, &[20, DECORATION_BINDING, 2]),
+            op(OP_DECORATE, &[20, DECORATION_DESCRIPTOR_SET, 0]),
+            op(OP_DECORATE, &[21, DECORATION_BINDING, 0]),
+            op(OP_TYPE_FLOAT, &[1, 32]),
+            op(OP_TYPE_VECTOR, &[2, 1, 3]),
+            op(OP_TYPE_VECTOR, &[3, 1, 4]),
+            op(OP_TYPE_MATRIX, &[4, 3, 4]),
+            op(OP_TYPE_INT, &[5, 32, 0]),
+            op(OP_TYPE_STRUCT, &[6, 4]),
+            op(OP_TYPE_POINTER, &[7, STORAGE_PUSH_CONSTANT, 6]),
+            op(OP_VARIABLE, &[7, 8, STORAGE_PUSH_CONSTANT]),
+            op(OP_TYPE_POINTER, &[9, STORAGE_INPUT, 2]),
+            op(OP_VARIABLE, &[9, 10, STORAGE_INPUT]),
+            op(OP_TYPE_POINTER, &[12, STORAGE_INPUT, 5]),
+            op(OP_VARIABLE, &[12, 11, STORAGE_INPUT]),
+            op(OP_TYPE_STRUCT, &[13, 3]),
+            op(OP_TYPE_POINTER, &[14, STORAGE_UNIFORM, 13]),
+            op(OP_VARIABLE, &[14, 20, STORAGE_UNIFORM]),
+            op(OP_TYPE_IMAGE, &[15, 1, 1, 0, 0, 0, 1, 0]),
+            op(OP_CONSTANT, &[5, 16, 2]),
+            op(OP_TYPE_ARRAY, &[17, 15, 16]),
+            op(OP_TYPE_POINTER, &[18, STORAGE_UNIFORM_CONSTANT, 17]),
+            op(OP_VARIABLE, &[18, 21, STORAGE_UNIFORM_CONSTANT]),
+        ];
+
+        let mut words = vec![MAGIC, 0x0001_0000, 0, 30, 0];
+        words.extend(instructions.into_iter().flatten());
+        words.iter().flat_map(|word| word.to_le_bytes().to_vec()).collect()
+    }
+
+    #[test]
+    fn it_should_reflect_push_constants_bindings_and_inputs() {
+        let reflection = reflect(&module()).unwrap();
+
+        assert_eq!(
+            reflection,
+            ShaderReflection {
+                push_constant_floats: 16,
+                bindings: vec![
+                    (0, DescriptorType::SampledImage, 2),
+                    (2, DescriptorType::UniformBuffer, 1),
+                ],
+                inputs: vec![ShaderInput {
+                    name: "position".to_string(),
+                    location: 0,
+                    scalar: ScalarType::Float,
+                    components: 3,
+                }],
+            }
+        );
+    }
+
+    #[test]
+    fn it_should_reject_bytes_that_are_not_spirv() {
+        assert_eq!(reflect(&[0; 24]).is_err(), true);
+    }
+
+    #[test]
+    fn mismatching_attributes_should_be_an_error() {
+        let inputs = reflect(&module()).unwrap().inputs;
+        let attribute = |format| AttributeDesc {
+            location: 0,
+            binding: 0,
+            element: Element { format, offset: 0 },
+        };
+
+        assert_eq!(
+            validate_vertex_attributes(&inputs, &[attribute(Format::Rgb32Float)]).is_ok(),
+            true
+        );
+        let mismatch = validate_vertex_attributes(&inputs, &[attribute(Format::Rg32Float)]).unwrap_err();
+        assert_eq!(
+            Some(&PipelineError::VertexAttributeMismatch {
+                input: "position".to_string(),
+                location: 0,
+                components: 3,
+                scalar: "Float".to_string(),
+                format: Format::Rg32Float,
+            }),
+            mismatch.downcast_ref::<PipelineError>()
+        );
+        let missing = validate_vertex_attributes(&inputs, &[]).unwrap_err();
+        assert_eq!(
+            Some(&PipelineError::MissingVertexAttribute {
+                input: "position".to_string(),
+                location: 0,
+            }),
+            missing.downcast_ref::<PipelineError>()
+        );
+    }
+
+    #[test]
+    fn undeclared_bindings_should_be_an_error() {
+        let reflection = reflect(&module()).unwrap();
+        let bindings = vec![
+            (0, DescriptorType::SampledImage, 2),
+            (2, DescriptorType::UniformBuffer, 1),
+        ];
+
+        assert_eq!(validate_layout(&reflection, 16, &bindings).is_ok(), true);
+        assert_eq!(validate_layout(&reflection, 0, &bindings).is_err(), true);
+        assert_eq!(validate_layout(&reflection, 16, &bindings[..1]).is_err(), true);
+        assert_eq!(
+            validate_layout(&reflection, 16, &[(0, DescriptorType::Sampler, 2), bindings[1]]).is_err(),
+            true
+        );
+    }
+}
@@ -0,0 +1,195 @@
+use crate::allocator::GpuAllocator;
+use crate::graphics::ShaderDescription;
+use crate::graphics::ShaderSet;
+use crate::internal::graphics::compile_glsl_file;
+use crate::internal::graphics::glsl_dependencies;
+use crate::internal::graphics::GraphicsState;
+use crate::internal::FileWatcher;
+use colored::*;
+use failure::Error;
+use gfx_hal::pso::Stage;
+use gfx_hal::Backend;
+use gfx_hal::Device;
+use gfx_hal::Instance;
+use std::borrow::Cow;
+use std::fmt;
+use std::fmt::Debug;
+use std::fmt::Formatter;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Rebuilds a pipeline from a recompiled shader set. Returns false if the pipeline has been dropped
+pub type RebuildPipeline<A, B, D, I> =
+    Box<Fn(&Arc<GraphicsState<A, B, D, I>>, &ShaderSet) -> Result<bool, Error> + Send + Sync>;
+
+struct WatchedPipeline<A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Instance<Backend = B>> {
+    set: ShaderSet,
+    /// The files each stage is built from, its source file and everything it includes
+    dependencies: Vec<(Stage, Vec<PathBuf>)>,
+    rebuild: RebuildPipeline<A, B, D, I>,
+}
+
+/// Keeps track of pipelines created from glsl files, and recompiles them when the files change
+pub struct ShaderReloader<A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Instance<Backend = B>> {
+    watcher: Mutex<FileWatcher>,
+    pipelines: Mutex<Vec<WatchedPipeline<A, B, D, I>>>,
+}
+
+impl<A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Instance<Backend = B>> ShaderReloader<A, B, D, I> {
+    pub fn new() -> Self {
+        Self {
+            watcher: Mutex::new(FileWatcher::new(Duration::from_millis(500))),
+            pipelines: Mutex::new(vec![]),
+        }
+    }
+
+    /// Watches the source files of the set and the files they include, if it has any
+    pub fn watch(&self, set: ShaderSet, rebuild: RebuildPipeline<A, B, D, I>) {
+        let dependencies: Vec<_> = shader_sources(&set)
+            .into_iter()
+            .map(|(stage, path)| {
+                let files = glsl_dependencies(&path).unwrap_or_else(|_| vec![path]);
+                (stage, files)
+            })
+            .collect();
+        if dependencies.is_empty() {
+            return;
+        }
+
+        {
+            let mut watcher = self.watcher.lock().unwrap();
+            for path in dependencies.iter().flat_map(|(_, files)| files) {
+                watcher.watch(path);
+            }
+        }
+        self.pipelines.lock().unwrap().push(WatchedPipeline {
+            set,
+            dependencies,
+            rebuild,
+        });
+    }
+
+    /// Recompiles changed shaders and rebuilds the pipelines using them. Must be called between
+    /// frames, since the old pipelines are destroyed. If a shader fails to compile the error is
+    /// logged and the old pipeline is kept
+    pub fn reload(&self, state: &Arc<GraphicsState<A, B, D, I>>) {
+        let changed = self.watcher.lock().unwrap().poll();
+        if changed.is_empty() {
+            return;
+        }
+
+        let mut pipelines = self.pipelines.lock().unwrap();
+        let _ = state.device().wait_idle();
+
+        let mut index = 0;
+        while index < pipelines.len() {
+            let stages: Vec<_> = pipelines[index]
+                .dependencies
+                .iter()
+                .filter(|(_, files)| files.iter().any(|path| changed.contains(path)))
+                .map(|(stage, _)| *stage)
+                .collect();
+            if stages.is_empty() {
+                index += 1;
+                continue;
+            }
+
+            // Includes may have been added or removed. If a file can't be read right now the old
+            // list is kept, so the pipeline still reloads once it's fixed
+            for (stage, files) in &mut pipelines[index].dependencies {
+                if stages.contains(stage) {
+                    if let Ok(dependencies) = glsl_dependencies(&files[0]) {
+                        *files = dependencies;
+                    }
+                }
+            }
+            {
+                let mut watcher = self.watcher.lock().unwrap();
+                for path in pipelines[index].dependencies.iter().flat_map(|(_, files)| files) {
+                    watcher.watch(path);
+                }
+            }
+
+            let set = match recompile(&pipelines[index].set, &stages) {
+                Ok(set) => set,
+                Err(error) => {
+                    error!("{}\n{}", "Failed to compile shader, keeping the old pipeline".red(), error);
+                    index += 1;
+                    continue;
+                }
+            };
+
+            match (pipelines[index].rebuild)(state, &set) {
+                Ok(true) => {
+                    info!("{}", "Pipeline reloaded".green());
+                    pipelines[index].set = set;
+                }
+                Ok(false) => {
+                    pipelines.remove(index);
+                    continue;
+                }
+                Err(error) => {
+                    error!("Failed to rebuild pipeline, keeping the old one: {}", error);
+                }
+            }
+            index += 1;
+        }
+    }
+}
+
+impl<A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Instance<Backend = B>> Debug for ShaderReloader<A, B, D, I> {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(f, "{:?}", self.watcher)?;
+        Ok(())
+    }
+}
+
+fn shader_sources(set: &ShaderSet) -> Vec<(Stage, PathBuf)> {
+    let descriptions = [
+        (Stage::Vertex, Some(&set.vertex)),
+        (Stage::Hull, set.hull.as_ref()),
+        (Stage::Domain, set.domain.as_ref()),
+        (Stage::Geometry, set.geometry.as_ref()),
+        (Stage::Fragment, set.fragment.as_ref()),
+    ];
+    descriptions
+        .iter()
+        .filter_map(|(stage, description)| {
+            description
+                .and_then(|d| d.source_path.as_ref())
+                .map(|path| (*stage, path.clone()))
+        })
+        .collect()
+}
+
+/// Recompiles the given stages from their source files
+fn recompile(set: &ShaderSet, stages: &[Stage]) -> Result<ShaderSet, Error> {
+    let recompile_description = |description: &ShaderDescription, stage: Stage| -> Result<ShaderDescription, Error> {
+        match description.source_path.as_ref() {
+            Some(path) if stages.contains(&stage) => {
+                let spirv = compile_glsl_file(path, stage)?;
+                Ok(ShaderDescription {
+                    spirv: Cow::Owned(spirv),
+                    ..description.clone()
+                })
+            }
+            _ => Ok(description.clone()),
+        }
+    };
+    let recompile_optional = |description: &Option<ShaderDescription>, stage: Stage| {
+        description
+            .as_ref()
+            .map(|d| recompile_description(d, stage))
+            .map_or(Ok(None), |result| result.map(Some))
+    };
+
+    Ok(ShaderSet {
+        vertex: recompile_description(&set.vertex, Stage::Vertex)?,
+        hull: recompile_optional(&set.hull, Stage::Hull)?,
+        domain: recompile_optional(&set.domain, Stage::Domain)?,
+        geometry: recompile_optional(&set.geometry, Stage::Geometry)?,
+        fragment: recompile_optional(&set.fragment, Stage::Fragment)?,
+    })
+}
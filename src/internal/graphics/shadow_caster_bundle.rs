@@ -0,0 +1,205 @@
+use crate::allocator::GpuAllocator;
+use crate::graphics::ShaderDescription;
+use crate::internal::graphics::reflect;
+use crate::internal::graphics::Corpse;
+use crate::internal::graphics::validate_layout;
+use crate::internal::graphics::validate_vertex_attributes;
+use crate::internal::graphics::GraphicsState;
+use crate::internal::graphics::ShadowMap;
+use crate::primitive::Vertex;
+use colored::*;
+use crate::errors::PipelineError;
+use failure::Error;
+use gfx_hal::pass::Subpass;
+use gfx_hal::pso::BakedStates;
+use gfx_hal::pso::BasePipeline;
+use gfx_hal::pso::BlendDesc;
+use gfx_hal::pso::Comparison;
+use gfx_hal::pso::DepthStencilDesc;
+use gfx_hal::pso::DepthTest;
+use gfx_hal::pso::EntryPoint;
+use gfx_hal::pso::GraphicsPipelineDesc;
+use gfx_hal::pso::GraphicsShaderSet;
+use gfx_hal::pso::InputAssemblerDesc;
+use gfx_hal::pso::PipelineCreationFlags;
+use gfx_hal::pso::Rasterizer;
+use gfx_hal::pso::ShaderStageFlags;
+use gfx_hal::pso::Specialization;
+use gfx_hal::pso::StencilTest;
+use gfx_hal::pso::VertexBufferDesc;
+use gfx_hal::Backend;
+use gfx_hal::Device;
+use gfx_hal::Instance;
+use gfx_hal::Primitive;
+use std::fmt;
+use std::fmt::Debug;
+use std::fmt::Formatter;
+use std::marker::PhantomData;
+use std::mem::ManuallyDrop;
+use std::sync::Arc;
+
+/// A depth only pipeline that renders the position of a vertex into a [`ShadowMap`]. Only the
+/// attribute at `location = 0` is used, so any vertex with a position there can cast shadows
+pub struct ShadowCasterBundle<V: Vertex, A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Instance<Backend = B>> {
+    layout: ManuallyDrop<B::PipelineLayout>,
+    pipeline: ManuallyDrop<B::GraphicsPipeline>,
+    state: Arc<GraphicsState<A, B, D, I>>,
+    phantom: PhantomData<V>,
+}
+
+impl<V: Vertex, A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Instance<Backend = B>> ShadowCasterBundle<V, A, B, D, I> {
+    pub fn new(state: Arc<GraphicsState<A, B, D, I>>, vertex: &ShaderDescription) -> Result<Self, Error> {
+        info!("{}", "Creating new shadow caster pipeline".green());
+
+        let device = state.device();
+        let layout = unsafe {
+            device.create_pipeline_layout(
+                Vec::<B::DescriptorSetLayout>::new(),
+                &[(ShaderStageFlags::VERTEX, 0..vertex.push_constant_floats)],
+            )?
+        };
+
+        // The pipeline only needs a compatible render pass, so a temporary one will do
+        let render_pass = ShadowMap::<A, B, D, I>::create_render_pass(&device)?;
+        let pipeline = Self::create_pipeline(&device, &render_pass, &layout, vertex);
+        unsafe {
+            device.destroy_render_pass(render_pass);
+        }
+
+        let pipeline = match pipeline {
+            Ok(pipeline) => pipeline,
+            Err(error) => {
+                unsafe { device.destroy_pipeline_layout(layout) };
+                return Err(error);
+            }
+        };
+
+        Ok(Self {
+            layout: ManuallyDrop::new(layout),
+            pipeline: ManuallyDrop::new(pipeline),
+            state,
+            phantom: PhantomData,
+        })
+    }
+
+    pub fn layout(&self) -> &B::PipelineLayout {
+        &self.layout
+    }
+
+    pub fn pipeline(&self) -> &B::GraphicsPipeline {
+        &self.pipeline
+    }
+
+    fn create_pipeline(
+        device: &D,
+        render_pass: &B::RenderPass,
+        layout: &B::PipelineLayout,
+        vertex: &ShaderDescription,
+    ) -> Result<B::GraphicsPipeline, Error> {
+        let attributes: Vec<_> = V::attributes()
+            .into_iter()
+            .filter(|attribute| attribute.location == 0)
+            .collect();
+        let stage = format!("{:?}", ShaderStageFlags::VERTEX);
+        let reflection = reflect(&vertex.spirv).map_err(|error| PipelineError::InvalidShader {
+            stage: stage.clone(),
+            reason: error.to_string(),
+        })?;
+        validate_layout(&reflection, vertex.push_constant_floats, &[]).map_err(|error| {
+            PipelineError::InvalidShaderDescription {
+                stage,
+                reason: error.to_string(),
+            }
+        })?;
+        validate_vertex_attributes(&reflection.inputs, &attributes)?;
+
+        let module = unsafe { device.create_shader_module(&vertex.spirv)? };
+
+        let result = {
+            let shaders = GraphicsShaderSet {
+                vertex: EntryPoint {
+                    entry: "main",
+                    module: &module,
+                    specialization: Specialization {
+                        constants: &[],
+                        data: &[],
+                    },
+                },
+                hull: None,
+                domain: None,
+                geometry: None,
+                fragment: None,
+            };
+
+            let desc = GraphicsPipelineDesc {
+                shaders,
+                rasterizer: Rasterizer::FILL,
+                vertex_buffers: vec![VertexBufferDesc {
+                    binding: 0,
+                    stride: V::stride() as u32,
+                    rate: 0,
+                }],
+                attributes,
+                input_assembler: InputAssemblerDesc::new(Primitive::TriangleList),
+                blender: BlendDesc {
+                    logic_op: None,
+                    targets: vec![],
+                },
+                depth_stencil: DepthStencilDesc {
+                    depth: DepthTest::On {
+                        fun: Comparison::LessEqual,
+                        write: true,
+                    },
+                    depth_bounds: false,
+                    stencil: StencilTest::Off,
+                },
+                multisampling: None,
+                baked_states: BakedStates {
+                    viewport: None,
+                    scissor: None,
+                    blend_color: None,
+                    depth_bounds: None,
+                },
+                layout,
+                subpass: Subpass {
+                    index: 0,
+                    main_pass: render_pass,
+                },
+                flags: PipelineCreationFlags::empty(),
+                parent: BasePipeline::None,
+            };
+
+            unsafe { device.create_graphics_pipeline(&desc, None) }
+        };
+
+        unsafe { device.destroy_shader_module(module) };
+
+        Ok(result?)
+    }
+}
+
+impl<V: Vertex, A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Instance<Backend = B>> Drop
+    for ShadowCasterBundle<V, A, B, D, I>
+{
+    fn drop(&mut self) {
+        use core::ptr::read;
+
+        info!("{}", "Dropping shadow caster pipeline".red());
+
+        unsafe {
+            self.state.bury(vec![
+                Corpse::GraphicsPipeline(ManuallyDrop::into_inner(read(&self.pipeline))),
+                Corpse::PipelineLayout(ManuallyDrop::into_inner(read(&self.layout))),
+            ]);
+        }
+    }
+}
+
+impl<V: Vertex, A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Instance<Backend = B>> Debug
+    for ShadowCasterBundle<V, A, B, D, I>
+{
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(f, "Shadow caster pipeline")?;
+        Ok(())
+    }
+}
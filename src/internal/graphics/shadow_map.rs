@@ -0,0 +1,175 @@
+use crate::allocator::GpuAllocator;
+use crate::internal::graphics::depth_image::DepthImage;
+use crate::internal::graphics::Corpse;
+use crate::internal::graphics::GraphicsState;
+use colored::*;
+use failure::Error;
+use gfx_hal::format::Format;
+use gfx_hal::image::Access as ImageAccess;
+use gfx_hal::image::Anisotropic;
+use gfx_hal::image::Filter;
+use gfx_hal::image::Layout;
+use gfx_hal::image::Lod;
+use gfx_hal::image::PackedColor;
+use gfx_hal::image::SamplerInfo;
+use gfx_hal::image::Usage;
+use gfx_hal::image::WrapMode;
+use gfx_hal::pass::Attachment;
+use gfx_hal::pass::AttachmentLoadOp;
+use gfx_hal::pass::AttachmentOps;
+use gfx_hal::pass::AttachmentStoreOp;
+use gfx_hal::pass::SubpassDependency;
+use gfx_hal::pass::SubpassDesc;
+use gfx_hal::pass::SubpassRef;
+use gfx_hal::pso::Comparison;
+use gfx_hal::pso::PipelineStage;
+use gfx_hal::window::Extent2D;
+use gfx_hal::Backend;
+use gfx_hal::Device;
+use gfx_hal::Instance;
+use std::mem::ManuallyDrop;
+use std::sync::Arc;
+
+/// A depth only render target that is rendered from the point of view of a light, and then
+/// sampled with a comparison sampler when drawing the lit scene
+pub struct ShadowMap<A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Instance<Backend = B>> {
+    depth_image: ManuallyDrop<DepthImage<B, D>>,
+    sampler: ManuallyDrop<B::Sampler>,
+    render_pass: ManuallyDrop<B::RenderPass>,
+    framebuffer: ManuallyDrop<B::Framebuffer>,
+    extent: Extent2D,
+    state: Arc<GraphicsState<A, B, D, I>>,
+}
+
+impl<A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Instance<Backend = B>> ShadowMap<A, B, D, I> {
+    pub fn new(state: Arc<GraphicsState<A, B, D, I>>, resolution: u32) -> Result<Self, Error> {
+        info!("{}", "Creating new shadow map".green());
+
+        let device = state.device();
+        let adapter = state.adapter();
+
+        let extent = Extent2D {
+            width: resolution,
+            height: resolution,
+        };
+        let depth_image = DepthImage::with_usage(
+            Arc::clone(&device),
+            adapter,
+            extent,
+            Usage::DEPTH_STENCIL_ATTACHMENT | Usage::SAMPLED,
+        )?;
+        let render_pass = Self::create_render_pass(&device)?;
+        let framebuffer = unsafe {
+            device.create_framebuffer(
+                &render_pass,
+                Some(&*depth_image.image_view),
+                gfx_hal::image::Extent {
+                    width: extent.width,
+                    height: extent.height,
+                    depth: 1,
+                },
+            )?
+        };
+
+        // Everything outside of the shadow map is considered lit, hence the white border
+        let sampler = unsafe {
+            device.create_sampler(SamplerInfo {
+                min_filter: Filter::Linear,
+                mag_filter: Filter::Linear,
+                mip_filter: Filter::Nearest,
+                wrap_mode: (WrapMode::Border, WrapMode::Border, WrapMode::Border),
+                lod_bias: Lod::from(0.0),
+                lod_range: Lod::from(0.0)..Lod::from(1.0),
+                comparison: Some(Comparison::LessEqual),
+                border: PackedColor(0xFFFF_FFFF),
+                anisotropic: Anisotropic::Off,
+            })?
+        };
+
+        Ok(Self {
+            depth_image: ManuallyDrop::new(depth_image),
+            sampler: ManuallyDrop::new(sampler),
+            render_pass: ManuallyDrop::new(render_pass),
+            framebuffer: ManuallyDrop::new(framebuffer),
+            extent,
+            state,
+        })
+    }
+
+    pub fn render_pass(&self) -> &B::RenderPass {
+        &self.render_pass
+    }
+
+    pub fn framebuffer(&self) -> &B::Framebuffer {
+        &self.framebuffer
+    }
+
+    pub fn extent(&self) -> Extent2D {
+        self.extent
+    }
+
+    pub fn image_view(&self) -> &B::ImageView {
+        &self.depth_image.image_view
+    }
+
+    pub fn sampler(&self) -> &B::Sampler {
+        &self.sampler
+    }
+
+    /// The render pass used by all shadow maps. Pipelines created against it can be used with any
+    /// shadow map since the passes are compatible
+    pub(crate) fn create_render_pass(device: &D) -> Result<B::RenderPass, Error> {
+        let depth_attachment = Attachment {
+            format: Some(Format::D32Float),
+            samples: 1,
+            ops: AttachmentOps {
+                load: AttachmentLoadOp::Clear,
+                store: AttachmentStoreOp::Store,
+            },
+            stencil_ops: AttachmentOps::DONT_CARE,
+            layouts: Layout::Undefined..Layout::DepthStencilReadOnlyOptimal,
+        };
+        let subpass = SubpassDesc {
+            colors: &[],
+            depth_stencil: Some(&(0, Layout::DepthStencilAttachmentOptimal)),
+            inputs: &[],
+            resolves: &[],
+            preserves: &[],
+        };
+        let in_dependency = SubpassDependency {
+            passes: SubpassRef::External..SubpassRef::Pass(0),
+            stages: PipelineStage::FRAGMENT_SHADER..PipelineStage::EARLY_FRAGMENT_TESTS,
+            accesses: ImageAccess::SHADER_READ..ImageAccess::DEPTH_STENCIL_ATTACHMENT_WRITE,
+        };
+        let out_dependency = SubpassDependency {
+            passes: SubpassRef::Pass(0)..SubpassRef::External,
+            stages: PipelineStage::LATE_FRAGMENT_TESTS..PipelineStage::FRAGMENT_SHADER,
+            accesses: ImageAccess::DEPTH_STENCIL_ATTACHMENT_WRITE..ImageAccess::SHADER_READ,
+        };
+        Ok(unsafe {
+            device.create_render_pass(
+                &[depth_attachment],
+                &[subpass],
+                &[in_dependency, out_dependency],
+            )?
+        })
+    }
+}
+
+impl<A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Instance<Backend = B>> Drop for ShadowMap<A, B, D, I> {
+    fn drop(&mut self) {
+        use core::ptr::read;
+
+        info!("{}", "Dropping shadow map".red());
+
+        unsafe {
+            let mut corpses = vec![
+                Corpse::Framebuffer(ManuallyDrop::into_inner(read(&self.framebuffer))),
+                Corpse::RenderPass(ManuallyDrop::into_inner(read(&self.render_pass))),
+                Corpse::Sampler(ManuallyDrop::into_inner(read(&self.sampler))),
+            ];
+            corpses.extend(ManuallyDrop::into_inner(read(&self.depth_image)).into_corpses());
+            self.state.bury(corpses);
+        }
+    }
+}
@@ -0,0 +1,123 @@
+/// Hands out ranges of a fixed size staging buffer in FIFO order. Positions are virtual and only
+/// ever grow, the offset into the buffer is the position modulo the capacity. Everything before a
+/// position is freed at once with `release_to`, which matches how batches complete on a queue
+#[derive(Debug)]
+pub struct StagingRing {
+    capacity: u64,
+    head: u64,
+    tail: u64,
+}
+
+impl StagingRing {
+    pub fn new(capacity: u64) -> Self {
+        Self {
+            capacity,
+            head: 0,
+            tail: 0,
+        }
+    }
+
+    pub fn capacity(&self) -> u64 {
+        self.capacity
+    }
+
+    /// The position after the last allocation, pass this to `release_to` once everything allocated
+    /// so far is no longer in use
+    pub fn head(&self) -> u64 {
+        self.head
+    }
+
+    /// Returns the offset into the buffer, which is a multiple of `align`, or None if there isn't
+    /// enough free space. A range never wraps around the end of the buffer
+    pub fn allocate(&mut self, size: u64, align: u64) -> Option<u64> {
+        debug_assert!(align > 0);
+        if size == 0 || size > self.capacity {
+            return None;
+        }
+
+        // The offset is aligned rather than the position, since the alignment doesn't have to
+        // divide the capacity
+        let lap = self.head - self.head % self.capacity;
+        let offset = (self.head % self.capacity + align - 1) / align * align;
+        let start = if offset + size > self.capacity {
+            lap + self.capacity
+        } else {
+            lap + offset
+        };
+        if start + size - self.tail > self.capacity {
+            return None;
+        }
+
+        self.head = start + size;
+        Some(start % self.capacity)
+    }
+
+    pub fn release_to(&mut self, position: u64) {
+        debug_assert!(position >= self.tail && position <= self.head);
+        self.tail = position;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.head == self.tail
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn it_should_align_allocations() {
+        let mut ring = StagingRing::new(256);
+        assert_eq!(ring.allocate(3, 16), Some(0));
+        assert_eq!(ring.allocate(3, 16), Some(16));
+        assert_eq!(ring.head(), 19);
+    }
+
+    #[test]
+    fn it_should_align_offsets_to_sizes_that_are_not_powers_of_two() {
+        let mut ring = StagingRing::new(256);
+        assert_eq!(ring.allocate(5, 12), Some(0));
+        assert_eq!(ring.allocate(5, 12), Some(12));
+        assert_eq!(ring.allocate(220, 1), Some(17));
+        ring.release_to(ring.head());
+
+        // 237 rounds up to 240, which still fits, and the next lap starts at an offset of 0
+        assert_eq!(ring.allocate(12, 12), Some(240));
+        assert_eq!(ring.allocate(6, 12), Some(0));
+    }
+
+    #[test]
+    fn it_should_refuse_allocations_that_overlap_ranges_in_use() {
+        let mut ring = StagingRing::new(256);
+        assert_eq!(ring.allocate(200, 16), Some(0));
+        assert_eq!(ring.allocate(100, 16), None);
+
+        ring.release_to(ring.head());
+        assert!(ring.is_empty());
+        assert_eq!(ring.allocate(100, 16), Some(0));
+    }
+
+    #[test]
+    fn it_should_wrap_instead_of_splitting_a_range() {
+        let mut ring = StagingRing::new(256);
+        assert_eq!(ring.allocate(160, 16), Some(0));
+        let first_batch = ring.head();
+        assert_eq!(ring.allocate(64, 16), Some(160));
+
+        // 32 bytes are left at the end, so this has to start over at the beginning, which is still
+        // in use by the first batch
+        assert_eq!(ring.allocate(64, 16), None);
+        ring.release_to(first_batch);
+        assert_eq!(ring.allocate(64, 16), Some(0));
+        assert_eq!(ring.allocate(128, 16), None);
+    }
+
+    #[test]
+    fn it_should_refuse_allocations_larger_than_the_ring() {
+        let mut ring = StagingRing::new(256);
+        assert_eq!(ring.allocate(257, 16), None);
+        assert_eq!(ring.allocate(0, 16), None);
+    }
+}
@@ -1,5 +1,6 @@
 use crate::errors::CreateEncoderError;
 use crate::errors::CreateEncoderErrorKind;
+use crate::errors::SwapchainError;
 use crate::internal::graphics::depth_image::DepthImage;
 use arrayvec::ArrayVec;
 use colored::*;
@@ -39,7 +40,7 @@ use gfx_hal::Backbuffer;
 use gfx_hal::Backend;
 use gfx_hal::CommandPool;
 use gfx_hal::FrameSync;
-use gfx_hal::Graphics;
+use gfx_hal::General;
 use gfx_hal::PresentMode;
 use gfx_hal::QueueGroup;
 use gfx_hal::Submission;
@@ -48,10 +49,34 @@ use std::mem::ManuallyDrop;
 use std::sync::Arc;
 use winit::Window;
 
+/// The fences of the submitted frames, borrowed apart from the command buffers
+pub struct FrameFences<'a, B: Backend, D: Device<B>> {
+    device: &'a D,
+    fences: &'a [B::Fence],
+    frames: &'a [Option<u64>],
+}
+
+impl<'a, B: Backend, D: Device<B>> FrameFences<'a, B, D> {
+    /// Waits on the fences of the submitted frames, returns the number of the newest frame that
+    /// was waited on, if any
+    pub fn wait(&self) -> Result<Option<u64>, CreateEncoderError> {
+        let mut completed = None;
+        for (fence, frame) in self.fences.iter().zip(self.frames) {
+            if let Some(frame) = *frame {
+                if unsafe { self.device.wait_for_fence(fence, core::u64::MAX) }.is_err() {
+                    Err(CreateEncoderErrorKind::DeviceLost)?;
+                }
+                completed = completed.max(Some(frame));
+            }
+        }
+        Ok(completed)
+    }
+}
+
 pub struct SwapchainBundle<B: Backend, D: Device<B>> {
     device: Arc<D>,
     swapchain: ManuallyDrop<B::Swapchain>,
-    command_buffers: Vec<CommandBuffer<B, Graphics, MultiShot, Primary>>,
+    command_buffers: Vec<CommandBuffer<B, General, MultiShot, Primary>>,
     in_flight_fences: Vec<B::Fence>,
     render_finished_semaphores: Vec<B::Semaphore>,
     image_available_semaphores: Vec<B::Semaphore>,
@@ -60,10 +85,14 @@ pub struct SwapchainBundle<B: Backend, D: Device<B>> {
     framebuffers: Vec<B::Framebuffer>,
     depth_images: Vec<DepthImage<B, D>>,
     render_area: Extent2D,
+    format: Format,
     current_frame: usize,
+    frame: u64,
+    fence_frames: Vec<Option<u64>>,
     image_index: usize,
     frames_in_flight: usize,
-    dpi: f64
+    dpi: f64,
+    released: bool,
 }
 
 impl<B: Backend, D: Device<B>> SwapchainBundle<B, D> {
@@ -72,13 +101,13 @@ impl<B: Backend, D: Device<B>> SwapchainBundle<B, D> {
         device: Arc<D>,
         window: &Window,
         surface: &mut B::Surface,
-        command_pool: &mut CommandPool<B, Graphics>,
+        command_pool: &mut CommandPool<B, General>,
     ) -> Result<Self, Error> {
         info!("{}", "Creating new swapchain".green());
 
         let (swapchain, backbuffer, format, render_area, image_count, dpi) =
             Self::create_swapchain(adapter, &device, window, surface)?;
-        let render_pass = Self::create_render_pass(&device, format)?;
+        let render_pass = Self::create_render_pass(&device, format, Layout::Present)?;
 
         let (image_available_semaphores, render_finished_semaphores, in_flight_fences) = {
             let mut image_available_semaphores: Vec<B::Semaphore> = vec![];
@@ -154,10 +183,14 @@ impl<B: Backend, D: Device<B>> SwapchainBundle<B, D> {
             framebuffers,
             depth_images,
             render_area,
+            format,
             current_frame: 0,
+            frame: 0,
+            fence_frames: vec![None; image_count],
             image_index: 0,
             frames_in_flight: image_count,
-            dpi
+            dpi,
+            released: false,
         })
     }
 
@@ -178,10 +211,38 @@ impl<B: Backend, D: Device<B>> SwapchainBundle<B, D> {
         self.dpi
     }
 
-    pub fn next_encoder(&mut self) -> Result<RenderPassInlineEncoder<B>, CreateEncoderError> {
-        let encoder = unsafe {
+    pub fn format(&self) -> Format {
+        self.format
+    }
+
+    pub fn image_views(&self) -> &[B::ImageView] {
+        &self.image_views
+    }
+
+    /// Waits until every frame submitted so far is done on the gpu. The fence of the frame being
+    /// recorded has been reset, so only the fences that were submitted are waited on. Returns the
+    /// number of the newest frame that was waited on, if any
+    pub fn wait_for_frames_in_flight(&self) -> Result<Option<u64>, CreateEncoderError> {
+        self.fences().wait()
+    }
+
+    fn fences(&self) -> FrameFences<B, D> {
+        FrameFences {
+            device: &*self.device,
+            fences: &self.in_flight_fences,
+            frames: &self.fence_frames,
+        }
+    }
+
+    /// Waits for the next swapchain image and starts recording its command buffer. Returns the
+    /// number of the frame whose fence was waited on, if any
+    pub fn begin_frame(&mut self, frame: u64) -> Result<Option<u64>, CreateEncoderError> {
+        let completed;
+        unsafe {
             let flight_fence = &self.in_flight_fences[self.current_frame];
+            completed = self.fence_frames[self.current_frame].take();
             self.current_frame = (self.current_frame + 1) % self.frames_in_flight;
+            self.frame = frame;
 
             if self
                 .device
@@ -217,14 +278,42 @@ impl<B: Backend, D: Device<B>> SwapchainBundle<B, D> {
                     }
                 })? as _;
 
+            self.command_buffers[self.image_index].begin(false);
+        }
+        Ok(completed)
+    }
+
+    /// The command buffer of the current frame, commands recorded here before `next_encoder`
+    /// execute before the main render pass. The fences of the frames in flight are handed out
+    /// along with it, so that they can be waited on while recording
+    pub fn command_buffer_and_fences(&mut self) -> (&mut CommandBuffer<B, General, MultiShot, Primary>, FrameFences<B, D>) {
+        let fences = FrameFences {
+            device: &*self.device,
+            fences: &self.in_flight_fences,
+            frames: &self.fence_frames,
+        };
+        (&mut self.command_buffers[self.image_index], fences)
+    }
+
+    /// Begins the main render pass of the current frame. If a `target` is given the render pass
+    /// will draw into that framebuffer (indexed by the swapchain image) instead of directly into
+    /// the swapchain
+    pub fn next_encoder(
+        &mut self,
+        target: Option<(&B::RenderPass, &[B::Framebuffer])>,
+    ) -> RenderPassInlineEncoder<B> {
+        unsafe {
             let clear_values = [
                 ClearValue::Color(ClearColor::Float([0.0, 0.0, 0.0, 1.0])),
                 ClearValue::DepthStencil(ClearDepthStencil(1.0, 0)),
             ];
-            self.command_buffers[self.image_index].begin(false);
+            let (render_pass, framebuffer) = match target {
+                Some((render_pass, framebuffers)) => (render_pass, &framebuffers[self.image_index]),
+                None => (&**self.render_pass, &self.framebuffers[self.image_index]),
+            };
             self.command_buffers[self.image_index].begin_render_pass_inline(
-                &self.render_pass,
-                &self.framebuffers[self.image_index],
+                render_pass,
+                framebuffer,
                 Rect {
                     x: 0,
                     y: 0,
@@ -233,14 +322,55 @@ impl<B: Backend, D: Device<B>> SwapchainBundle<B, D> {
                 },
                 clear_values.iter(),
             )
-        };
-        Ok(encoder)
+        }
+    }
+
+    /// Destroys the swapchain and everything tied to it, so that the window can be given a new one
+    /// after the device was lost. The bundle must not be used afterwards
+    pub fn release(&mut self) {
+        if self.released {
+            return;
+        }
+        self.released = true;
+
+        info!("{}", "Dropping Swapchain".red());
+        let _ = self.device.wait_idle();
+        unsafe {
+            for depth_image in self.depth_images.drain(..) {
+                drop(depth_image)
+            }
+            for fence in self.in_flight_fences.drain(..) {
+                self.device.destroy_fence(fence)
+            }
+            for semaphore in self.render_finished_semaphores.drain(..) {
+                self.device.destroy_semaphore(semaphore)
+            }
+            for semaphore in self.image_available_semaphores.drain(..) {
+                self.device.destroy_semaphore(semaphore)
+            }
+            for framebuffer in self.framebuffers.drain(..) {
+                self.device.destroy_framebuffer(framebuffer);
+            }
+            for image_view in self.image_views.drain(..) {
+                self.device.destroy_image_view(image_view);
+            }
+            // LAST RESORT STYLE CODE, NOT TO BE IMITATED LIGHTLY
+            use core::ptr::read;
+            self.device
+                .destroy_render_pass(ManuallyDrop::into_inner(read(&*self.render_pass)));
+            self.device
+                .destroy_swapchain(ManuallyDrop::into_inner(read(&self.swapchain)));
+        }
     }
 
-    pub fn present_swapchain(
+    /// Submits the current frame. `before_submit` is given the chance to record additional
+    /// commands after the main render pass has ended
+    pub fn present_swapchain<F: FnOnce(&mut CommandBuffer<B, General, MultiShot, Primary>, usize)>(
         &mut self,
-        queue_group: &mut QueueGroup<B, Graphics>,
-    ) -> Result<(), Error> {
+        queue_group: &mut QueueGroup<B, General>,
+        before_submit: F,
+    ) -> Result<(), CreateEncoderError> {
+        before_submit(&mut self.command_buffers[self.image_index], self.image_index);
         unsafe {
             self.command_buffers[self.image_index].finish();
 
@@ -262,6 +392,7 @@ impl<B: Backend, D: Device<B>> SwapchainBundle<B, D> {
             let the_command_queue = &mut queue_group.queues[0];
 
             the_command_queue.submit(submission, Some(flight_fence));
+            self.fence_frames[self.current_frame] = Some(self.frame);
             if self
                 .swapchain
                 .present(
@@ -271,8 +402,13 @@ impl<B: Backend, D: Device<B>> SwapchainBundle<B, D> {
                 )
                 .is_err()
             {
-                //panic!() // No idea why this happens...
-                warn!("No frame presented");
+                // Presenting doesn't tell us why it failed, so check whether the device is still
+                // alive by looking at the fence we just submitted with
+                if self.device.get_fence_status(flight_fence).is_err() {
+                    Err(CreateEncoderErrorKind::DeviceLost)?;
+                }
+                warn!("No frame presented, the swapchain needs to be reconstructed");
+                Err(CreateEncoderErrorKind::RecreateSwapchain)?;
             };
         };
 
@@ -300,7 +436,7 @@ impl<B: Backend, D: Device<B>> SwapchainBundle<B, D> {
                 .iter()
                 .cloned()
                 .find(|pm| present_modes.contains(pm))
-                .ok_or_else(|| format_err!("No PresentMode values specified!"))?
+                .ok_or(SwapchainError::NoPresentMode)?
         };
 
         // Find window alpha
@@ -310,7 +446,7 @@ impl<B: Backend, D: Device<B>> SwapchainBundle<B, D> {
                 .iter()
                 .cloned()
                 .find(|ca| composite_alphas.contains(ca))
-                .ok_or_else(|| format_err!("No CompositeAlpha values specified!"))?
+                .ok_or(SwapchainError::NoCompositeAlpha)?
         };
 
         // Select format
@@ -325,7 +461,7 @@ impl<B: Backend, D: Device<B>> SwapchainBundle<B, D> {
                 None => formats
                     .get(0)
                     .cloned()
-                    .ok_or_else(|| format_err!("Preferred format list was empty!"))?,
+                    .ok_or(SwapchainError::EmptyFormatList)?,
             },
         };
 
@@ -335,7 +471,7 @@ impl<B: Backend, D: Device<B>> SwapchainBundle<B, D> {
         let extent = {
             let window_client_area = window
                 .get_inner_size()
-                .ok_or_else(|| format_err!("Window doesn't exist!"))?;
+                .ok_or(SwapchainError::WindowMissing)?;
             Extent2D {
                 width: caps
                     .extents
@@ -358,9 +494,7 @@ impl<B: Backend, D: Device<B>> SwapchainBundle<B, D> {
         let image_usage = if caps.usage.contains(Usage::COLOR_ATTACHMENT) {
             Usage::COLOR_ATTACHMENT
         } else {
-            Err(format_err!(
-                "The Surface isn't capable of supporting color!"
-            ))?
+            Err(SwapchainError::ColorUnsupported)?
         };
         let swapchain_config = SwapchainConfig {
             present_mode,
@@ -379,7 +513,11 @@ impl<B: Backend, D: Device<B>> SwapchainBundle<B, D> {
         Ok((swapchain, backbuffer, format, extent, image_count as _, dpi_factor))
     }
 
-    fn create_render_pass(device: &D, format: Format) -> Result<B::RenderPass, Error> {
+    pub(crate) fn create_render_pass(
+        device: &D,
+        format: Format,
+        final_layout: Layout,
+    ) -> Result<B::RenderPass, Error> {
         let color_attachment = Attachment {
             format: Some(format),
             samples: 4,
@@ -388,7 +526,7 @@ impl<B: Backend, D: Device<B>> SwapchainBundle<B, D> {
                 store: AttachmentStoreOp::Store,
             },
             stencil_ops: AttachmentOps::DONT_CARE,
-            layouts: Layout::Undefined..Layout::Present,
+            layouts: Layout::Undefined..final_layout,
         };
         let depth_attachment = Attachment {
             format: Some(Format::D32Float),
@@ -419,13 +557,14 @@ impl<B: Backend, D: Device<B>> SwapchainBundle<B, D> {
         };
         let out_dependency = SubpassDependency {
             passes: SubpassRef::Pass(0)..SubpassRef::External,
+            // Offscreen targets are sampled by the post process passes afterwards
             stages: PipelineStage::COLOR_ATTACHMENT_OUTPUT | PipelineStage::EARLY_FRAGMENT_TESTS
-                ..PipelineStage::COLOR_ATTACHMENT_OUTPUT,
+                ..PipelineStage::COLOR_ATTACHMENT_OUTPUT | PipelineStage::FRAGMENT_SHADER,
             accesses: (ImageAccess::COLOR_ATTACHMENT_READ
                 | ImageAccess::COLOR_ATTACHMENT_WRITE
                 | ImageAccess::DEPTH_STENCIL_ATTACHMENT_READ
                 | ImageAccess::DEPTH_STENCIL_ATTACHMENT_WRITE)
-                ..ImageAccess::empty(),
+                ..ImageAccess::SHADER_READ,
         };
         Ok(unsafe {
             device.create_render_pass(
@@ -439,33 +578,6 @@ impl<B: Backend, D: Device<B>> SwapchainBundle<B, D> {
 
 impl<B: Backend, D: Device<B>> Drop for SwapchainBundle<B, D> {
     fn drop(&mut self) {
-        info!("{}", "Dropping Swapchain".red());
-        let _ = self.device.wait_idle();
-        unsafe {
-            for depth_image in self.depth_images.drain(..) {
-                drop(depth_image)
-            }
-            for fence in self.in_flight_fences.drain(..) {
-                self.device.destroy_fence(fence)
-            }
-            for semaphore in self.render_finished_semaphores.drain(..) {
-                self.device.destroy_semaphore(semaphore)
-            }
-            for semaphore in self.image_available_semaphores.drain(..) {
-                self.device.destroy_semaphore(semaphore)
-            }
-            for framebuffer in self.framebuffers.drain(..) {
-                self.device.destroy_framebuffer(framebuffer);
-            }
-            for image_view in self.image_views.drain(..) {
-                self.device.destroy_image_view(image_view);
-            }
-            // LAST RESORT STYLE CODE, NOT TO BE IMITATED LIGHTLY
-            use core::ptr::read;
-            self.device
-                .destroy_render_pass(ManuallyDrop::into_inner(read(&*self.render_pass)));
-            self.device
-                .destroy_swapchain(ManuallyDrop::into_inner(read(&self.swapchain)));
-        }
+        self.release();
     }
 }
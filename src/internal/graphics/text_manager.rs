@@ -18,6 +18,7 @@ use crate::allocator::GpuAllocator;
 use crate::context::Context;
 use crate::graphics::Pipeline;
 use crate::primitive::Vertex3dColorUv;
+use crate::primitive::shapes;
 use crate::graphics::ShaderSet;
 use crate::graphics::ShaderDescription;
 use futures::Future;
@@ -27,6 +28,7 @@ use std::sync::Mutex;
 use crate::graphics::Texture;
 use crate::internal::graphics::Single;
 use crate::graphics::R8Unorm;
+use std::borrow::Cow;
 
 #[allow(clippy::type_complexity)]
 pub struct TextManager<'a, A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Instance<Backend = B>> {
@@ -41,14 +43,6 @@ pub struct TextManager<'a, A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: I
 }
 
 impl<'a, A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Instance<Backend = B>> TextManager<'a, A, B, D, I> {
-    const INDEXES: [u16; 6] = [0, 1, 2, 3, 0, 1];
-    const VERTEXES: [Vertex3dColorUv; 4] = [
-        Vertex3dColorUv { x: -0.5, y: 0.5, z: 0.0, r: 1.0, g: 1.0, b: 0.0, a: 1.0, u: 0.0, v: 1.0 },
-        Vertex3dColorUv { x: 0.5, y: -0.5, z: 0.0, r: 1.0, g: 1.0, b: 0.0, a: 1.0, u: 1.0, v: 0.0 },
-        Vertex3dColorUv { x: 0.5, y: 0.5, z: 0.0, r: 1.0, g: 1.0, b: 0.0, a: 1.0, u: 1.0, v: 1.0 },
-        Vertex3dColorUv { x: -0.5, y: -0.5, z: 0.0, r: 1.0, g: 1.0, b: 0.0, a: 1.0, u: 0.0, v: 0.0 }
-    ];
-
     pub fn new(setup: Arc<SetupContext<A, B, D, I>>) -> Result<Self, Error> {
 
         let hack_font: &[u8] = include_bytes!("hack.ttf");
@@ -57,20 +51,22 @@ impl<'a, A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Instance<Backend =
 
         let pipeline = setup.create_pipeline(ShaderSet {
             vertex: ShaderDescription {
-                spirv: include_bytes!(concat!(env!("OUT_DIR"), "/text.vert.spv")),
+                spirv: Cow::Borrowed(include_bytes!(concat!(env!("OUT_DIR"), "/text.vert.spv"))),
                 push_constant_floats: 16,
-                bindings: vec![]
+                bindings: vec![],
+                source_path: None,
             },
             hull: None,
             domain: None,
             geometry: None,
             fragment: Some(ShaderDescription {
-                spirv: include_bytes!(concat!(env!("OUT_DIR"), "/text.frag.spv")),
+                spirv: Cow::Borrowed(include_bytes!(concat!(env!("OUT_DIR"), "/text.frag.spv"))),
                 push_constant_floats: 0,
                 bindings: vec![
                     (0, DescriptorType::SampledImage, 1),
                     (1, DescriptorType::Sampler, 1),
-                ]
+                ],
+                source_path: None,
             })
         }).wait()?;
 
@@ -79,7 +75,15 @@ impl<'a, A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Instance<Backend =
 
         pipeline.bind_texture(&texture);
 
-        let debug_bundle = setup.create_bundle(&Self::INDEXES, &Self::VERTEXES).wait()?;
+        let debug_quad = shapes::quad(1.0, 1.0);
+        let debug_vertexes: Vec<Vertex3dColorUv> = debug_quad
+            .to_vertices::<Vertex3dColorUv>()
+            .into_iter()
+            .map(|vertex| Vertex3dColorUv { b: 0.0, ..vertex })
+            .collect();
+        let debug_bundle = setup
+            .create_bundle_owned(Arc::new(debug_quad.to_u16_indices()), Arc::new(debug_vertexes))
+            .wait()?;
 
         Ok(Self {
             glyph_brush,
@@ -1,12 +1,19 @@
+use crate::errors::AllocationError;
+use crate::errors::TextureError;
 use crate::internal::graphics::buffer_bundle::CPU;
 use crate::internal::graphics::BufferBundle;
+use crate::internal::graphics::Corpse;
 use crate::internal::graphics::GraphicsState;
+use crate::internal::graphics::UploadDestination;
+use crate::internal::graphics::UploadFuture;
 use colored::*;
 use failure::Error;
+use futures::lazy;
 use futures::Future;
 use gfx_hal::adapter::PhysicalDevice;
 use gfx_hal::buffer::Usage as BufferUsage;
 use gfx_hal::format::Aspects;
+use gfx_hal::format::ImageFeature;
 use gfx_hal::image::Anisotropic;
 use gfx_hal::image::Filter;
 use gfx_hal::image::Layout;
@@ -16,7 +23,6 @@ use gfx_hal::image::SubresourceRange;
 use gfx_hal::image::WrapMode;
 use gfx_hal::memory::Properties;
 use gfx_hal::memory::Requirements;
-use gfx_hal::pool::CommandPoolCreateFlags;
 use gfx_hal::pso::PipelineStage;
 use gfx_hal::Backend;
 use gfx_hal::Device;
@@ -30,14 +36,13 @@ use std::mem::ManuallyDrop;
 use std::sync::Arc;
 use crate::allocator::GpuAllocator;
 use crate::allocator::Memory;
+use crate::allocator::MemoryUsage;
 use gfx_hal::format::AsFormat;
 use gfx_hal::image::ViewCapabilities;
 use std::marker::PhantomData;
 use futures::future::IntoFuture;
-use image::ImageBuffer;
-use image::Pixel;
-use std::ops::Deref;
 use gfx_hal::format::Rgba8Srgb;
+use gfx_hal::format::Rgba8Unorm;
 
 
 pub trait TextureType {
@@ -72,7 +77,7 @@ impl TextureType for Cube {
 pub struct TextureBundle<F: AsFormat + Send, TA: TextureType, A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Instance<Backend = B>> {
     image: ManuallyDrop<B::Image>,
     requirements: Requirements,
-    memory: Memory<B>,
+    memory: ManuallyDrop<Memory<B>>,
     image_view: ManuallyDrop<B::ImageView>,
     sampler: ManuallyDrop<B::Sampler>,
     state: Arc<GraphicsState<A, B, D, I>>,
@@ -80,8 +85,8 @@ pub struct TextureBundle<F: AsFormat + Send, TA: TextureType, A: GpuAllocator<B,
     height: u32,
     row_pitch: u32,
     pixel_size: u32,
-    phantom_format: PhantomData<F>,
-    phantom_type: PhantomData<TA>,
+    phantom_format: PhantomData<fn() -> F>,
+    phantom_type: PhantomData<fn() -> TA>,
 }
 
 impl<F: AsFormat + Send, A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Instance<Backend = B>> TextureBundle<F, Single, A, B, D, I> {
@@ -108,6 +113,12 @@ impl<A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Instance<Backend = B>>
     }
 }
 
+impl<A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Instance<Backend = B>> TextureBundle<Rgba8Unorm, Single, A, B, D, I> {
+    pub fn write_data_from_image(self, image: RgbaImage) -> impl Future<Item = Self, Error = Error> {
+        self.do_write_data_from_image(image)
+    }
+}
+
 
 impl<F: AsFormat + Send, TA: TextureType, A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Instance<Backend = B>> TextureBundle<F, TA, A, B, D, I> {
     fn create_image(state: Arc<GraphicsState<A, B, D, I>>, mip_map_levels: u8, layers: u16, width: u32, height: u32) -> Result<Self, Error> {
@@ -130,12 +141,19 @@ impl<F: AsFormat + Send, TA: TextureType, A: GpuAllocator<B, D>, B: Backend, D:
                 format!("{:?}x{:?}", width, height).yellow()
             );
 
+            let features = state.adapter().physical_device.format_properties(Some(F::SELF)).optimal_tiling;
+            if !features.contains(ImageFeature::SAMPLED) {
+                Err(TextureError::UnsupportedFormat(F::SELF))?;
+            }
+
             let mut image = state.device().create_image(
                 gfx_hal::image::Kind::D2(width, height, layers, 1),
                 mip_map_levels,
                 F::SELF,
                 gfx_hal::image::Tiling::Optimal,
-                gfx_hal::image::Usage::TRANSFER_DST | gfx_hal::image::Usage::SAMPLED,
+                gfx_hal::image::Usage::TRANSFER_SRC
+                    | gfx_hal::image::Usage::TRANSFER_DST
+                    | gfx_hal::image::Usage::SAMPLED,
                 TA::view_capabilities(),
             )?;
 
@@ -153,10 +171,11 @@ impl<F: AsFormat + Send, TA: TextureType, A: GpuAllocator<B, D>, B: Backend, D:
                         && memory_type.properties.contains(Properties::DEVICE_LOCAL)
                 })
                 .map(|(id, _)| MemoryTypeId(id))
-                .ok_or_else(|| format_err!("No queue group found"))?;
+                .ok_or(AllocationError::NoSuitableMemoryType("texture"))?;
 
-            let mut memory = state.allocator().allocate_memory(memory_type_id, requirements.size)?;
-            memory.bind_image_memory(&state.device(), 0, &mut image)?;
+            let mut memory = state.allocator().allocate_memory_for(MemoryUsage::Image, memory_type_id, requirements.size, requirements.alignment)?;
+            memory.set_label(format!("{}x{} {:?} texture", width, height, F::SELF));
+            memory.bind_image_memory(&state.device(), &mut image)?;
 
             // TODO: Map to better errors
             let image_view = state.device().create_image_view(
@@ -186,7 +205,7 @@ impl<F: AsFormat + Send, TA: TextureType, A: GpuAllocator<B, D>, B: Backend, D:
             Ok(Self {
                 image: ManuallyDrop::new(image),
                 requirements,
-                memory,
+                memory: ManuallyDrop::new(memory),
                 image_view: ManuallyDrop::new(image_view),
                 sampler: ManuallyDrop::new(sampler),
                 state,
@@ -201,59 +220,53 @@ impl<F: AsFormat + Send, TA: TextureType, A: GpuAllocator<B, D>, B: Backend, D:
     }
 
     fn do_write_data_from_bytes<'a>(&'a self, subset: (u32, u32, u32, u32), data: &[u8]) -> impl Future<Item = &'a Self, Error = Error> + 'a {
-        let arc_data = Arc::new(Vec::from(data));
-        debug_assert!((subset.2 * subset.3) * (u32::from(F::SELF.surface_desc().bits) / 8) == arc_data.len() as u32, "Data must contain enough bytes for the subset");
+        debug_assert!((subset.2 * subset.3) * (u32::from(F::SELF.surface_desc().bits) / 8) == data.len() as u32, "Data must contain enough bytes for the subset");
 
-        BufferBundle::<A, B, D, I, CPU, u8>::new(
-            Arc::clone(&self.state),
-            data.len() as _,
-            BufferUsage::TRANSFER_SRC,
-        ).and_then(| bundle| {
-            bundle.write_data(arc_data)
-        }).and_then(move |bundle| {
-            self.write_data_into_texture(subset, bundle)?;
-            Ok(self)
+        self.upload(subset, subset.2, data.len() as u64, |staging| {
+            staging.copy_from_slice(data);
         })
-
+        .into_future()
+        .flatten()
+        .map(move |_| self)
     }
 
-    fn do_write_data_from_image<P, C>(self, image: ImageBuffer<P, C>) -> impl Future<Item = Self, Error = Error> where
-        P: Pixel + 'static + Send + Sync,
-        P::Subpixel: 'static,
-        C: Deref<Target = [P::Subpixel]> + Send {
-
+    fn do_write_data_from_image(self, image: RgbaImage) -> impl Future<Item = Self, Error = Error> {
         debug_assert!(image.width() == self.width, "Image must be of same width as bundle");
         debug_assert!(image.height() == self.height, "Image must be of same height as bundle");
 
-
         let required_bytes = u64::from(self.row_pitch * self.height);
-        let row_size = self.pixel_size * self.width;
-        let row_pitch = self.row_pitch;
+        let row_size = (self.pixel_size * self.width) as usize;
+        let row_pitch = self.row_pitch as usize;
 
-        BufferBundle::<A, B, D, I, CPU, P>::new(
-            Arc::clone(&self.state),
+        self.upload(
+            (0, 0, self.width, self.height),
+            self.row_pitch / self.pixel_size,
             required_bytes,
-            BufferUsage::TRANSFER_SRC,
+            |staging| {
+                for (y, row) in image.chunks(row_size).enumerate() {
+                    let dest_base = y * row_pitch;
+                    staging[dest_base..dest_base + row.len()].copy_from_slice(row);
+                }
+            },
         )
-        .and_then(move |bundle: BufferBundle<A, B, D, I, CPU, P>| {
-            bundle.write_image_data(image, row_size, row_pitch)
-        })
-        .and_then(move |bundle| {
-            self.write_data_into_texture((0, 0, self.width, self.height), bundle)?;
-            Ok(self)
-        })
+        .into_future()
+        .flatten()
+        .map(move |_| self)
     }
 
-    fn write_data_into_texture<T: Copy + Send + Sync>(&self, subset: (u32, u32, u32, u32), mut bundle: BufferBundle<A, B, D, I, CPU, T>) -> Result<(), Error> {
-        unsafe {
-            let mut pool = self.state.device().create_command_pool_typed(
-                &bundle.queue_group,
-                CommandPoolCreateFlags::TRANSIENT,
-            )?;
-
-            let mut cmd_buffer = pool.acquire_command_buffer::<gfx_hal::command::OneShot>();
-            cmd_buffer.begin();
-
+    /// Stages `len` bytes through the upload manager and records the copy into the subset, the
+    /// upload manager moves the image to `ShaderReadOnlyOptimal` afterwards. `buffer_width` is the
+    /// length of a staged row in pixels
+    fn upload<W: FnOnce(&mut [u8])>(&self, subset: (u32, u32, u32, u32), buffer_width: u32, len: u64, write: W) -> Result<UploadFuture<B, D>, Error> {
+        trace!("Staging texture upload of {} bytes", len);
+        let range = SubresourceRange {
+            aspects: Aspects::COLOR,
+            levels: 0..1,
+            layers: 0..1,
+        };
+        let destination = UploadDestination::Image(&*self.image, range.clone());
+        let align = u64::from(self.pixel_size);
+        self.state.uploads().upload(len, align, destination, write, |cmd_buffer, staging, offset| unsafe {
             let image_barrier = gfx_hal::memory::Barrier::Image {
                 states: (gfx_hal::image::Access::empty(), Layout::Undefined)
                     ..(
@@ -262,27 +275,21 @@ impl<F: AsFormat + Send, TA: TextureType, A: GpuAllocator<B, D>, B: Backend, D:
                 ),
                 target: &*self.image,
                 families: None,
-                range: SubresourceRange {
-                    aspects: Aspects::COLOR,
-                    levels: 0..1,
-                    layers: 0..1,
-                },
+                range,
             };
-
             cmd_buffer.pipeline_barrier(
                 PipelineStage::TOP_OF_PIPE..PipelineStage::TRANSFER,
                 gfx_hal::memory::Dependencies::empty(),
                 &[image_barrier],
             );
 
-            trace!("Copying bundle to texture");
             cmd_buffer.copy_buffer_to_image(
-                &bundle.buffer,
+                staging,
                 &*self.image,
                 Layout::TransferDstOptimal,
                 &[gfx_hal::command::BufferImageCopy {
-                    buffer_offset: 0,
-                    buffer_width: subset.2,
+                    buffer_offset: offset,
+                    buffer_width,
                     buffer_height: subset.3,
                     image_layers: gfx_hal::image::SubresourceLayers {
                         aspects: Aspects::COLOR,
@@ -297,44 +304,98 @@ impl<F: AsFormat + Send, TA: TextureType, A: GpuAllocator<B, D>, B: Backend, D:
                     },
                 }],
             );
+        })
+    }
+
+    /// Copies the first layer into a host visible buffer and resolves with its pixels, tightly
+    /// packed row by row. The texture must have been written to
+    pub fn read_back<'a>(&'a self) -> impl Future<Item = Vec<u8>, Error = Error> + Send + 'a {
+        lazy(move || self.submit_read_back()).flatten()
+    }
 
+    /// Like `read_back`, but the copy is submitted right away, so the future doesn't borrow the
+    /// texture. Dropping the texture before the copy is done is fine, the graveyard waits for it
+    pub fn submit_read_back(&self) -> Result<impl Future<Item = Vec<u8>, Error = Error> + Send, Error> {
+        let staging = BufferBundle::<A, B, D, I, CPU, u8>::allocate_for(
+            Arc::clone(&self.state),
+            u64::from(self.row_pitch * self.height),
+            BufferUsage::TRANSFER_DST,
+            Properties::CPU_VISIBLE,
+            MemoryUsage::Transient,
+        )?;
+        let range = SubresourceRange {
+            aspects: Aspects::COLOR,
+            levels: 0..1,
+            layers: 0..1,
+        };
+
+        trace!("Copying texture back to the host");
+        let done = self.state.submit_once(|cmd_buffer| unsafe {
             let image_barrier = gfx_hal::memory::Barrier::Image {
-                states: (
-                    gfx_hal::image::Access::TRANSFER_WRITE,
-                    Layout::TransferDstOptimal,
-                )
-                    ..(
-                    gfx_hal::image::Access::SHADER_READ,
-                    Layout::ShaderReadOnlyOptimal,
-                ),
+                states: (gfx_hal::image::Access::SHADER_READ, Layout::ShaderReadOnlyOptimal)
+                    ..(gfx_hal::image::Access::TRANSFER_READ, Layout::TransferSrcOptimal),
                 target: &*self.image,
                 families: None,
-                range: SubresourceRange {
-                    aspects: Aspects::COLOR,
-                    levels: 0..1,
-                    layers: 0..1,
-                },
+                range: range.clone(),
             };
             cmd_buffer.pipeline_barrier(
-                PipelineStage::TRANSFER..PipelineStage::FRAGMENT_SHADER,
+                PipelineStage::FRAGMENT_SHADER..PipelineStage::TRANSFER,
                 gfx_hal::memory::Dependencies::empty(),
                 &[image_barrier],
             );
 
-            cmd_buffer.finish();
-            let upload_fence = self.state.device().create_fence(false)?;
-            let queue = &mut bundle.queue_group.queues[0];
-            queue.submit_nosemaphores(Some(&cmd_buffer), Some(&upload_fence));
-            self.state
-                .device()
-                .wait_for_fence(&upload_fence, core::u64::MAX)?;
-            self.state.device().destroy_fence(upload_fence);
-
-            // 11. Destroy the staging bundle and one shot buffer now that we're done
-            pool.free(Some(cmd_buffer));
+            cmd_buffer.copy_image_to_buffer(
+                &*self.image,
+                Layout::TransferSrcOptimal,
+                &staging.buffer,
+                &[gfx_hal::command::BufferImageCopy {
+                    buffer_offset: 0,
+                    buffer_width: self.row_pitch / self.pixel_size,
+                    buffer_height: self.height,
+                    image_layers: gfx_hal::image::SubresourceLayers {
+                        aspects: Aspects::COLOR,
+                        level: 0,
+                        layers: 0..1,
+                    },
+                    image_offset: gfx_hal::image::Offset { x: 0, y: 0, z: 0 },
+                    image_extent: gfx_hal::image::Extent {
+                        width: self.width,
+                        height: self.height,
+                        depth: 1,
+                    },
+                }],
+            );
 
-            Ok(())
-        }
+            let image_barrier = gfx_hal::memory::Barrier::Image {
+                states: (gfx_hal::image::Access::TRANSFER_READ, Layout::TransferSrcOptimal)
+                    ..(gfx_hal::image::Access::SHADER_READ, Layout::ShaderReadOnlyOptimal),
+                target: &*self.image,
+                families: None,
+                range,
+            };
+            cmd_buffer.pipeline_barrier(
+                PipelineStage::TRANSFER..PipelineStage::FRAGMENT_SHADER,
+                gfx_hal::memory::Dependencies::empty(),
+                &[image_barrier],
+            );
+            cmd_buffer.pipeline_barrier(
+                PipelineStage::TRANSFER..PipelineStage::HOST,
+                gfx_hal::memory::Dependencies::empty(),
+                &[gfx_hal::memory::Barrier::AllBuffers(
+                    gfx_hal::buffer::Access::TRANSFER_WRITE..gfx_hal::buffer::Access::HOST_READ,
+                )],
+            );
+        })?;
+
+        let row_size = (self.pixel_size * self.width) as usize;
+        let row_pitch = self.row_pitch as usize;
+        Ok(done.and_then(move |_| {
+            let data = staging.read_data()?;
+            Ok(data
+                .chunks(row_pitch)
+                .flat_map(|row| row[..row_size].iter().cloned())
+                .collect())
+        }))
     }
 
     pub fn sampler(&self) -> &B::Sampler {
@@ -344,6 +405,14 @@ impl<F: AsFormat + Send, TA: TextureType, A: GpuAllocator<B, D>, B: Backend, D:
     pub fn image_view(&self) -> &B::ImageView {
         &self.image_view
     }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
 }
 
 impl<F: AsFormat + Send, TA: TextureType, A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Instance<Backend = B>> Drop for TextureBundle<F, TA, A, B, D, I> {
@@ -352,14 +421,14 @@ impl<F: AsFormat + Send, TA: TextureType, A: GpuAllocator<B, D>, B: Backend, D:
 
         info!("{}", "Dropping texture".red());
 
-        let device = self.state.device();
-
-        self.state.allocator().free_memory(&mut self.memory);
-
+        // The texture might still be sampled by a frame in flight
         unsafe {
-            device.destroy_sampler(ManuallyDrop::into_inner(read(&self.sampler)));
-            device.destroy_image_view(ManuallyDrop::into_inner(read(&self.image_view)));
-            device.destroy_image(ManuallyDrop::into_inner(read(&self.image)));
+            self.state.bury(vec![
+                Corpse::Sampler(ManuallyDrop::into_inner(read(&self.sampler))),
+                Corpse::ImageView(ManuallyDrop::into_inner(read(&self.image_view))),
+                Corpse::Image(ManuallyDrop::into_inner(read(&self.image))),
+                Corpse::Memory(ManuallyDrop::into_inner(read(&self.memory))),
+            ]);
         }
     }
 }
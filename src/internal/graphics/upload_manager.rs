@@ -0,0 +1,684 @@
+use crate::errors::AllocationError;
+use crate::errors::AssetError;
+use crate::internal::graphics::Graveyard;
+use crate::internal::graphics::StagingRing;
+use crate::internal::FenceWaiter;
+use crate::internal::PendingFence;
+use arrayvec::ArrayVec;
+use colored::*;
+use failure::Error;
+use futures::sync::oneshot;
+use futures::task::current;
+use futures::Async;
+use futures::Future;
+use futures::Poll;
+use gfx_hal::adapter::MemoryType;
+use gfx_hal::buffer::Access;
+use gfx_hal::buffer::Usage as BufferUsage;
+use gfx_hal::command::CommandBuffer;
+use gfx_hal::command::OneShot;
+use gfx_hal::command::Primary;
+use gfx_hal::image::Access as ImageAccess;
+use gfx_hal::image::Layout;
+use gfx_hal::image::SubresourceRange;
+use gfx_hal::memory::Barrier;
+use gfx_hal::memory::Dependencies;
+use gfx_hal::memory::Properties;
+use gfx_hal::pool::CommandPool;
+use gfx_hal::pool::CommandPoolCreateFlags;
+use gfx_hal::pso::PipelineStage;
+use gfx_hal::queue::family::QueueGroup;
+use gfx_hal::queue::QueueFamilyId;
+use gfx_hal::Backend;
+use gfx_hal::Device;
+use gfx_hal::General;
+use gfx_hal::Limits;
+use gfx_hal::MemoryTypeId;
+use gfx_hal::Submission;
+use gfx_hal::Transfer;
+use std::collections::VecDeque;
+use std::fmt;
+use std::fmt::Debug;
+use std::fmt::Formatter;
+use std::ops::Range;
+use std::mem::ManuallyDrop;
+use std::slice;
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::RwLock;
+
+/// The size of the staging memory shared by all uploads. Larger uploads get a staging buffer of
+/// their own
+const STAGING_RING_SIZE: u64 = 32 * 1024 * 1024;
+
+/// The staging memory stays mapped for as long as the manager lives
+struct MappedPtr(*mut u8);
+
+// The pointer is only accessed while holding the lock around `Inner`
+unsafe impl Send for MappedPtr {}
+
+/// The resource an upload writes to. Once the copy is recorded it is made available to the main
+/// queue, which also hands it over from the transfer queue if there is one
+pub enum UploadDestination<'a, B: Backend> {
+    Buffer(&'a B::Buffer),
+
+    /// The image must be in `TransferDstOptimal` after the copy, it's left in `ShaderReadOnlyOptimal`
+    Image(&'a B::Image, SubresourceRange),
+}
+
+struct Batch<B: Backend> {
+    id: u64,
+    pool: CommandPool<B, General>,
+
+    /// Records the uploads when there is no transfer queue, otherwise it only acquires them
+    command_buffer: CommandBuffer<B, General, OneShot, Primary>,
+    transfer: Option<(CommandPool<B, Transfer>, CommandBuffer<B, Transfer, OneShot, Primary>)>,
+    signals: Vec<oneshot::Sender<()>>,
+    dedicated: Vec<(B::Buffer, B::Memory)>,
+}
+
+struct SubmittedBatch<B: Backend, D: Device<B>> {
+    pool: CommandPool<B, General>,
+    transfer_pool: Option<CommandPool<B, Transfer>>,
+    semaphore: Option<B::Semaphore>,
+    signal: Arc<BatchSignal<B, D>>,
+    ring_end: u64,
+    dedicated: Vec<(B::Buffer, B::Memory)>,
+}
+
+/// Signals every upload of a batch once its fence is signaled
+struct BatchSignal<B: Backend, D: Device<B>> {
+    fence: ManuallyDrop<B::Fence>,
+    device: Arc<D>,
+    signals: Mutex<Vec<oneshot::Sender<()>>>,
+}
+
+impl<B: Backend, D: Device<B>> PendingFence for BatchSignal<B, D> {
+    fn wait(&self, timeout_ns: u64) -> bool {
+        unsafe { self.device.wait_for_fence(&self.fence, timeout_ns) }.unwrap_or(true)
+    }
+
+    fn notify(&self) {
+        for signal in self.signals.lock().unwrap().drain(..) {
+            // The upload might have been dropped already, that's fine
+            let _ = signal.send(());
+        }
+    }
+}
+
+impl<B: Backend, D: Device<B>> Drop for BatchSignal<B, D> {
+    fn drop(&mut self) {
+        use core::ptr::read;
+        unsafe {
+            self.device
+                .destroy_fence(ManuallyDrop::into_inner(read(&self.fence)));
+        }
+    }
+}
+
+struct Inner<B: Backend, D: Device<B>> {
+    staging_buffer: ManuallyDrop<B::Buffer>,
+    staging_memory: ManuallyDrop<B::Memory>,
+    mapped: MappedPtr,
+    ring: StagingRing,
+    transfer_queue: Option<QueueGroup<B, Transfer>>,
+    open: Option<Batch<B>>,
+    next_batch_id: u64,
+    in_flight: VecDeque<SubmittedBatch<B, D>>,
+}
+
+struct Shared<B: Backend, D: Device<B>> {
+    device: Arc<D>,
+    queue_group: Arc<RwLock<QueueGroup<B, General>>>,
+    waiter: Mutex<Sender<Arc<dyn PendingFence>>>,
+    /// Keeps the resources dropped while a batch is in flight alive until it has completed
+    graveyard: Arc<Graveyard<B>>,
+    memory_types: Vec<MemoryType>,
+    coherent: bool,
+    align: u64,
+    inner: Mutex<Inner<B, D>>,
+}
+
+/// Collects buffer and texture uploads into batches. Every batch is recorded into one command
+/// buffer and submitted with a single fence, the data is staged in a ring of mapped memory that is
+/// reused once the batches using it have completed.
+///
+/// A batch is submitted when one of its uploads is polled a second time, which gives everything
+/// started in the same pass of the executor the chance to end up in it. It is also submitted once
+/// per frame, and whenever the ring runs out of space.
+///
+/// If the device has a separate transfer queue the copies are submitted there, and the main queue
+/// waits for them and takes over ownership of the uploaded resources before using them
+pub struct UploadManager<B: Backend, D: Device<B>> {
+    shared: Arc<Shared<B, D>>,
+}
+
+impl<B: Backend, D: Device<B>> UploadManager<B, D> {
+    pub fn new(
+        device: Arc<D>,
+        queue_group: Arc<RwLock<QueueGroup<B, General>>>,
+        transfer_queue: Option<QueueGroup<B, Transfer>>,
+        waiter: &FenceWaiter,
+        graveyard: Arc<Graveyard<B>>,
+        memory_types: &[MemoryType],
+        limits: &Limits,
+    ) -> Result<Self, Error> {
+        let (staging_buffer, staging_memory, _, coherent) =
+            unsafe { Self::create_staging(&device, memory_types, STAGING_RING_SIZE)? };
+        let mapped = unsafe { device.map_memory(&staging_memory, 0..STAGING_RING_SIZE)? };
+
+        // Copies into images need offsets that are a multiple of 4, on top of what the device
+        // prefers. Each upload adds the size of its texels
+        let align = limits.optimal_buffer_copy_offset_alignment.max(4);
+
+        info!(
+            "{} {} {}",
+            "Created upload manager with a staging ring of".green(),
+            format!("{} bytes", STAGING_RING_SIZE).yellow(),
+            if transfer_queue.is_some() {
+                "using a dedicated transfer queue".green()
+            } else {
+                "using the main queue".green()
+            }
+        );
+
+        Ok(Self {
+            shared: Arc::new(Shared {
+                device,
+                queue_group,
+                waiter: Mutex::new(waiter.sender()),
+                graveyard,
+                memory_types: memory_types.to_vec(),
+                coherent,
+                align,
+                inner: Mutex::new(Inner {
+                    staging_buffer: ManuallyDrop::new(staging_buffer),
+                    staging_memory: ManuallyDrop::new(staging_memory),
+                    mapped: MappedPtr(mapped),
+                    ring: StagingRing::new(STAGING_RING_SIZE),
+                    transfer_queue,
+                    open: None,
+                    next_batch_id: 0,
+                    in_flight: VecDeque::new(),
+                }),
+            }),
+        })
+    }
+
+    /// Creates a host visible staging buffer, in coherent memory if there is any. Returns the size
+    /// of the allocation and whether it's coherent, otherwise writes have to be flushed
+    unsafe fn create_staging(
+        device: &Arc<D>,
+        memory_types: &[MemoryType],
+        size: u64,
+    ) -> Result<(B::Buffer, B::Memory, u64, bool), Error> {
+        let mut buffer = device.create_buffer(size, BufferUsage::TRANSFER_SRC)?;
+        let requirements = device.get_buffer_requirements(&buffer);
+        let find = |properties: Properties| {
+            memory_types
+                .iter()
+                .enumerate()
+                .find(|&(id, memory_type)| {
+                    requirements.type_mask & (1 << id) != 0 && memory_type.properties.contains(properties)
+                })
+                .map(|(id, memory_type)| (MemoryTypeId(id), memory_type.properties.contains(Properties::COHERENT)))
+        };
+        let (memory_type_id, coherent) =
+            match find(Properties::CPU_VISIBLE | Properties::COHERENT).or_else(|| find(Properties::CPU_VISIBLE)) {
+                Some(found) => found,
+                None => {
+                    device.destroy_buffer(buffer);
+                    return Err(AllocationError::NoSuitableMemoryType("staging buffer").into());
+                }
+            };
+        let memory = device.allocate_memory(memory_type_id, requirements.size)?;
+        device.bind_buffer_memory(&memory, 0, &mut buffer)?;
+        Ok((buffer, memory, requirements.size, coherent))
+    }
+
+    /// Stages `len` bytes and records a copy out of them into the current batch. `write` fills
+    /// the staging memory, `record` records the copy into `destination` given the staging buffer
+    /// and the offset of the data in it. The offset is a multiple of `align`, which is the texel
+    /// size for images. The returned future resolves once the copy has completed, right away if
+    /// there is nothing to copy
+    pub fn upload<W, R>(
+        &self,
+        len: u64,
+        align: u64,
+        destination: UploadDestination<B>,
+        write: W,
+        record: R,
+    ) -> Result<UploadFuture<B, D>, Error>
+    where
+        W: FnOnce(&mut [u8]),
+        R: FnOnce(&mut CommandBuffer<B, Transfer, OneShot, Primary>, &B::Buffer, u64),
+    {
+        if len == 0 {
+            let (sender, receiver) = oneshot::channel();
+            let _ = sender.send(());
+            return Ok(UploadFuture {
+                shared: Arc::clone(&self.shared),
+                batch_id: u64::max_value(),
+                receiver,
+                yielded: false,
+            });
+        }
+
+        let shared = &self.shared;
+        let align = lcm(shared.align, align);
+        let mut inner = shared.inner.lock().unwrap();
+        shared.reclaim(&mut inner);
+
+        let mut offset = inner.ring.allocate(len, align);
+        if offset.is_none() && inner.open.is_some() {
+            // Submit what we have so far, that might free up some of the ring
+            shared.submit(&mut inner)?;
+            shared.reclaim(&mut inner);
+            offset = inner.ring.allocate(len, align);
+        }
+
+        let dedicated = match offset {
+            Some(offset) => unsafe {
+                let data = slice::from_raw_parts_mut(inner.mapped.0.add(offset as usize), len as usize);
+                write(data);
+                None
+            },
+            None => unsafe {
+                trace!("Upload of {} bytes doesn't fit in the staging ring", len);
+                let device = &shared.device;
+                let (buffer, memory, size, coherent) = Self::create_staging(device, &shared.memory_types, len)?;
+                // The whole allocation is mapped and flushed, which needs no aligning to the atom size
+                let mapped = device.map_memory(&memory, 0..size)?;
+                write(slice::from_raw_parts_mut(mapped, len as usize));
+                if !coherent {
+                    device.flush_mapped_memory_ranges(Some((&memory, 0..size)))?;
+                }
+                device.unmap_memory(&memory);
+                Some((buffer, memory))
+            },
+        };
+
+        if inner.open.is_none() {
+            let batch = shared.open_batch(inner.next_batch_id, inner.transfer_queue.as_ref())?;
+            inner.next_batch_id += 1;
+            inner.open = Some(batch);
+        }
+
+        let (sender, receiver) = oneshot::channel();
+        let Inner {
+            open,
+            staging_buffer,
+            transfer_queue,
+            ..
+        } = &mut *inner;
+        let batch = open.as_mut().unwrap();
+        {
+            let command_buffer = match &mut batch.transfer {
+                Some((_, command_buffer)) => command_buffer,
+                None => batch.command_buffer.downgrade(),
+            };
+            match dedicated {
+                Some((buffer, memory)) => {
+                    record(command_buffer, &buffer, 0);
+                    batch.dedicated.push((buffer, memory));
+                }
+                None => record(command_buffer, staging_buffer, offset.unwrap()),
+            }
+        }
+        let families = transfer_queue
+            .as_ref()
+            .map(|transfer_queue| transfer_queue.family()..shared.queue_group.read().unwrap().family());
+        unsafe { Self::hand_over(batch, destination, families) };
+        batch.signals.push(sender);
+
+        Ok(UploadFuture {
+            shared: Arc::clone(&self.shared),
+            batch_id: batch.id,
+            receiver,
+            yielded: false,
+        })
+    }
+
+    /// Makes the destination available to the main queue. With a transfer queue this releases it
+    /// there and acquires it on the main queue, buffers are otherwise covered by the barrier at the
+    /// end of the batch
+    unsafe fn hand_over(
+        batch: &mut Batch<B>,
+        destination: UploadDestination<B>,
+        families: Option<Range<QueueFamilyId>>,
+    ) {
+        let consumers = PipelineStage::VERTEX_INPUT
+            | PipelineStage::VERTEX_SHADER
+            | PipelineStage::FRAGMENT_SHADER
+            | PipelineStage::COMPUTE_SHADER;
+        match destination {
+            UploadDestination::Buffer(target) => {
+                if let Some((_, transfer)) = &mut batch.transfer {
+                    transfer.pipeline_barrier(
+                        PipelineStage::TRANSFER..PipelineStage::BOTTOM_OF_PIPE,
+                        Dependencies::empty(),
+                        &[Barrier::Buffer {
+                            states: Access::TRANSFER_WRITE..Access::empty(),
+                            target,
+                            families: families.clone(),
+                        }],
+                    );
+                    batch.command_buffer.pipeline_barrier(
+                        PipelineStage::TRANSFER..consumers,
+                        Dependencies::empty(),
+                        &[Barrier::Buffer {
+                            states: Access::empty()..buffer_reads(),
+                            target,
+                            families,
+                        }],
+                    );
+                }
+            }
+            UploadDestination::Image(target, range) => {
+                let states = (ImageAccess::TRANSFER_WRITE, Layout::TransferDstOptimal)
+                    ..(ImageAccess::SHADER_READ, Layout::ShaderReadOnlyOptimal);
+                if let Some((_, transfer)) = &mut batch.transfer {
+                    transfer.pipeline_barrier(
+                        PipelineStage::TRANSFER..PipelineStage::BOTTOM_OF_PIPE,
+                        Dependencies::empty(),
+                        &[Barrier::Image {
+                            states: states.clone(),
+                            target,
+                            families: families.clone(),
+                            range: range.clone(),
+                        }],
+                    );
+                }
+                batch.command_buffer.pipeline_barrier(
+                    PipelineStage::TRANSFER..consumers,
+                    Dependencies::empty(),
+                    &[Barrier::Image {
+                        states,
+                        target,
+                        families,
+                        range,
+                    }],
+                );
+            }
+        }
+    }
+
+    /// Submits the current batch, if there is one
+    pub fn flush(&self) -> Result<(), Error> {
+        self.shared.flush(None)
+    }
+}
+
+impl<B: Backend, D: Device<B>> Shared<B, D> {
+    fn open_batch(&self, id: u64, transfer_queue: Option<&QueueGroup<B, Transfer>>) -> Result<Batch<B>, Error> {
+        let queue_group = self.queue_group.read().unwrap();
+        unsafe {
+            let mut pool = self
+                .device
+                .create_command_pool_typed(&queue_group, CommandPoolCreateFlags::TRANSIENT)?;
+            let mut command_buffer = pool.acquire_command_buffer::<OneShot>();
+            command_buffer.begin();
+
+            let transfer = match transfer_queue {
+                Some(transfer_queue) => {
+                    let mut pool = self
+                        .device
+                        .create_command_pool_typed(transfer_queue, CommandPoolCreateFlags::TRANSIENT)?;
+                    let mut command_buffer = pool.acquire_command_buffer::<OneShot>();
+                    command_buffer.begin();
+                    Some((pool, command_buffer))
+                }
+                None => None,
+            };
+
+            Ok(Batch {
+                id,
+                pool,
+                command_buffer,
+                transfer,
+                signals: Vec::new(),
+                dedicated: Vec::new(),
+            })
+        }
+    }
+
+    /// Submits the open batch. If `batch_id` is given it's only submitted if it's that batch
+    fn flush(&self, batch_id: Option<u64>) -> Result<(), Error> {
+        let mut inner = self.inner.lock().unwrap();
+        let is_open = match (&inner.open, batch_id) {
+            (Some(batch), Some(id)) => batch.id == id,
+            (Some(_), None) => true,
+            (None, _) => false,
+        };
+        if is_open {
+            self.submit(&mut inner)?;
+        }
+        self.reclaim(&mut inner);
+        Ok(())
+    }
+
+    fn submit(&self, inner: &mut Inner<B, D>) -> Result<(), Error> {
+        let mut batch = match inner.open.take() {
+            Some(batch) => batch,
+            None => return Ok(()),
+        };
+
+        trace!("Submitting upload batch with {} uploads", batch.signals.len());
+        unsafe {
+            if batch.transfer.is_none() {
+                // Make the uploaded data visible to everything that comes after
+                batch.command_buffer.pipeline_barrier(
+                    PipelineStage::TRANSFER
+                        ..PipelineStage::TRANSFER
+                            | PipelineStage::VERTEX_INPUT
+                            | PipelineStage::VERTEX_SHADER
+                            | PipelineStage::FRAGMENT_SHADER
+                            | PipelineStage::COMPUTE_SHADER,
+                    Dependencies::empty(),
+                    &[Barrier::AllBuffers(Access::TRANSFER_WRITE..buffer_reads())],
+                );
+            }
+            batch.command_buffer.finish();
+
+            if !self.coherent {
+                self.device
+                    .flush_mapped_memory_ranges(Some((&*inner.staging_memory, 0..STAGING_RING_SIZE)))?;
+            }
+
+            // The copies go to the transfer queue first, the main queue waits for them before it
+            // acquires the uploaded resources
+            let (transfer_pool, semaphore) = match batch.transfer.take() {
+                Some((pool, mut command_buffer)) => {
+                    command_buffer.finish();
+                    let semaphore = self.device.create_semaphore()?;
+                    let signal_semaphores: ArrayVec<[_; 1]> = [&semaphore].into();
+                    inner.transfer_queue.as_mut().unwrap().queues[0].submit(
+                        Submission {
+                            command_buffers: Some(&command_buffer),
+                            wait_semaphores: None,
+                            signal_semaphores,
+                        },
+                        None,
+                    );
+                    (Some(pool), Some(semaphore))
+                }
+                None => (None, None),
+            };
+
+            let fence = self.device.create_fence(false)?;
+            {
+                let wait_semaphores: ArrayVec<[_; 1]> = semaphore
+                    .iter()
+                    .map(|semaphore| (semaphore, PipelineStage::TRANSFER))
+                    .collect();
+                let mut queue_group = self.queue_group.write().unwrap();
+                queue_group.queues[0].submit(
+                    Submission {
+                        command_buffers: Some(&batch.command_buffer),
+                        wait_semaphores,
+                        signal_semaphores: None,
+                    },
+                    Some(&fence),
+                );
+            }
+
+            let signal = Arc::new(BatchSignal {
+                fence: ManuallyDrop::new(fence),
+                device: Arc::clone(&self.device),
+                signals: Mutex::new(batch.signals),
+            });
+            // If the waiter is gone the uploads are signaled when the batch is reclaimed instead
+            let waiting: Arc<dyn PendingFence> = signal.clone();
+            let _ = self.waiter.lock().unwrap().send(waiting);
+            self.graveyard.submitted(signal.clone());
+
+            inner.in_flight.push_back(SubmittedBatch {
+                pool: batch.pool,
+                transfer_pool,
+                semaphore,
+                signal,
+                ring_end: inner.ring.head(),
+                dedicated: batch.dedicated,
+            });
+        }
+        Ok(())
+    }
+
+    /// Frees the resources of the batches that have completed, in the order they were submitted
+    fn reclaim(&self, inner: &mut Inner<B, D>) {
+        while inner
+            .in_flight
+            .front()
+            .map_or(false, |batch| batch.signal.wait(0))
+        {
+            let batch = inner.in_flight.pop_front().unwrap();
+            batch.signal.notify();
+            inner.ring.release_to(batch.ring_end);
+            unsafe { self.destroy_submitted(batch) };
+        }
+    }
+
+    unsafe fn destroy_submitted(&self, batch: SubmittedBatch<B, D>) {
+        if let Some(pool) = batch.transfer_pool {
+            self.device.destroy_command_pool(pool.into_raw());
+        }
+        if let Some(semaphore) = batch.semaphore {
+            self.device.destroy_semaphore(semaphore);
+        }
+        self.destroy_batch(batch.pool, batch.dedicated);
+    }
+
+    unsafe fn destroy_batch(&self, pool: CommandPool<B, General>, dedicated: Vec<(B::Buffer, B::Memory)>) {
+        self.device.destroy_command_pool(pool.into_raw());
+        for (buffer, memory) in dedicated {
+            self.device.destroy_buffer(buffer);
+            self.device.free_memory(memory);
+        }
+    }
+}
+
+/// The least common multiple, texels of 3, 6 or 12 bytes don't divide a power of two alignment
+fn lcm(a: u64, b: u64) -> u64 {
+    let (mut x, mut y) = (a, b);
+    while y != 0 {
+        let rest = x % y;
+        x = y;
+        y = rest;
+    }
+    a / x * b
+}
+
+/// Every way an uploaded buffer might be read afterwards
+fn buffer_reads() -> Access {
+    Access::TRANSFER_READ
+        | Access::INDEX_BUFFER_READ
+        | Access::VERTEX_BUFFER_READ
+        | Access::CONSTANT_BUFFER_READ
+        | Access::SHADER_READ
+}
+
+impl<B: Backend, D: Device<B>> Drop for Shared<B, D> {
+    fn drop(&mut self) {
+        use core::ptr::read;
+
+        info!("{}", "Dropping upload manager".red());
+
+        let (open, in_flight) = {
+            let inner = self.inner.get_mut().unwrap();
+            (inner.open.take(), inner.in_flight.drain(..).collect::<Vec<_>>())
+        };
+        unsafe {
+            if let Some(batch) = open {
+                if let Some((pool, _)) = batch.transfer {
+                    self.device.destroy_command_pool(pool.into_raw());
+                }
+                self.destroy_batch(batch.pool, batch.dedicated);
+            }
+            for batch in in_flight {
+                batch.signal.wait(core::u64::MAX);
+                batch.signal.notify();
+                self.destroy_submitted(batch);
+            }
+
+            let inner = self.inner.get_mut().unwrap();
+            self.device.unmap_memory(&inner.staging_memory);
+            self.device
+                .destroy_buffer(ManuallyDrop::into_inner(read(&inner.staging_buffer)));
+            self.device
+                .free_memory(ManuallyDrop::into_inner(read(&inner.staging_memory)));
+        }
+    }
+}
+
+impl<B: Backend, D: Device<B>> Debug for UploadManager<B, D> {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        let inner = self.shared.inner.lock().unwrap();
+        write!(f, "UploadManager {{ ring: {:?}, in_flight: {} }}", inner.ring, inner.in_flight.len())?;
+        Ok(())
+    }
+}
+
+/// Resolves once the batch containing the upload has completed
+pub struct UploadFuture<B: Backend, D: Device<B>> {
+    shared: Arc<Shared<B, D>>,
+    batch_id: u64,
+    receiver: oneshot::Receiver<()>,
+    yielded: bool,
+}
+
+impl<B: Backend, D: Device<B>> Future for UploadFuture<B, D> {
+    type Item = ();
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<(), Error> {
+        match self.receiver.poll() {
+            Ok(Async::Ready(())) => Ok(Async::Ready(())),
+            Ok(Async::NotReady) => {
+                if self.yielded {
+                    self.shared.flush(Some(self.batch_id))?;
+                } else {
+                    // Give the uploads started by the rest of this task a chance to join the batch
+                    self.yielded = true;
+                    current().notify();
+                }
+                Ok(Async::NotReady)
+            }
+            Err(_) => Err(AssetError::UploadCancelled)?,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::lcm;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn it_should_align_to_both_the_device_and_the_texel_size() {
+        assert_eq!(16, lcm(16, 4));
+        assert_eq!(48, lcm(16, 12));
+        assert_eq!(12, lcm(4, 3));
+        assert_eq!(256, lcm(256, 1));
+    }
+}
@@ -1,9 +1,18 @@
+mod fence_waiter;
+mod file_watcher;
 mod future_fence;
 mod mat4_ext;
+#[cfg(test)]
+mod test_directory;
 
 pub mod graphics;
 pub mod menu;
 
+pub use self::fence_waiter::FenceWaiter;
+pub use self::fence_waiter::PendingFence;
+pub use self::file_watcher::FileWatcher;
 pub use self::future_fence::FenceExt;
 pub use self::future_fence::FutureFence;
 pub use self::mat4_ext::Mat4Ext;
+#[cfg(test)]
+pub use self::test_directory::TestDirectory;
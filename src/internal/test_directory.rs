@@ -0,0 +1,41 @@
+use std::env::temp_dir;
+use std::fs::create_dir_all;
+use std::fs::remove_dir_all;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+
+static NEXT_DIRECTORY: AtomicUsize = AtomicUsize::new(0);
+
+/// An empty directory in the temp dir that only one test uses, it's removed again when dropped.
+/// The name has the process id and a counter in it, so tests running in parallel never share files
+#[derive(Debug)]
+pub struct TestDirectory {
+    path: PathBuf,
+}
+
+impl TestDirectory {
+    pub fn new(name: &str) -> Self {
+        let number = NEXT_DIRECTORY.fetch_add(1, Ordering::SeqCst);
+        let path = temp_dir().join(format!("starstruck_{}_{}_{}", name, process::id(), number));
+        let _ = remove_dir_all(&path);
+        create_dir_all(&path).unwrap();
+        Self { path }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn join<P: AsRef<Path>>(&self, path: P) -> PathBuf {
+        self.path.join(path)
+    }
+}
+
+impl Drop for TestDirectory {
+    fn drop(&mut self) {
+        let _ = remove_dir_all(&self.path);
+    }
+}
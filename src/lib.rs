@@ -20,19 +20,23 @@ extern crate log;
 
 mod context;
 mod internal;
+mod prepare_context;
 mod setup_context;
 mod starstruck;
 mod starstruck_builder;
 
+pub mod asset;
 pub mod camera;
 pub mod errors;
 pub mod graphics;
 pub mod input;
 pub mod menu;
+pub mod mesh;
 pub mod primitive;
 pub mod allocator;
 
 pub use self::context::*;
+pub use self::prepare_context::*;
 pub use self::setup_context::*;
 pub use self::starstruck::Starstruck;
 pub use self::starstruck_builder::StarstruckBuilder;
@@ -6,6 +6,7 @@ use crate::graphics::Texture;
 use std::sync::Arc;
 use crate::setup_context::SetupContext;
 use crate::primitive::Vertex2DUV;
+use crate::primitive::shapes;
 use crate::allocator::GpuAllocator;
 use crate::allocator::DefaultGpuAllocator;
 use gfx_hal::Backend;
@@ -36,20 +37,10 @@ impl<
     D: Device<B>,
     I: Instance<Backend = B>,
 > Image<A, B, D, I> {
-    const INDEXES: [u16; 6] = [0, 1, 2, 3, 0, 1];
-
-
     pub fn new(setup: Arc<SetupContext<A, B, D, I>>, image_data: &'static [u8]) -> impl Future<Item=Self, Error=Error> {
         let (_window_width, _window_height) = setup.logical_window_size();
 
-        const VERTICES: [Vertex2DUV; 4] = [
-            Vertex2DUV { x: -1.0, y: 1.0, r: 0.0, g: 1.0 },
-            Vertex2DUV { x: 1.0, y: -1.0, r: 1.0, g: 0.0 },
-            Vertex2DUV { x: 1.0, y: 1.0, r: 1.0, g: 1.0 },
-            Vertex2DUV { x: -1.0, y: -1.0, r: 0.0, g: 0.0 },
-        ];
-
-        let bundle_future = setup.create_bundle(&Self::INDEXES, &VERTICES);
+        let bundle_future = setup.create_bundle_from_shape::<Vertex2DUV>(&shapes::quad(2.0, 2.0));
         let texture_future = setup.create_texture_from_bytes(image_data);
         let pipeline_future = setup.create_textured_pipeline();
 
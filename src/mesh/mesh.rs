@@ -0,0 +1,253 @@
+use crate::errors::MeshError;
+use crate::mesh::normals;
+use crate::mesh::optimize;
+use crate::mesh::simplify;
+use crate::mesh::tangents;
+use crate::mesh::weld;
+use crate::primitive::shapes::Shape;
+use crate::primitive::shapes::ShapeVertex;
+use failure::Error;
+use obj::Obj;
+use obj::SimplePolygon;
+use std::io::BufReader;
+use vek::Vec2;
+use vek::Vec3;
+use vek::Vec4;
+
+/// A vertex of a `Mesh`. The tangent is in xyz, and w holds the sign of the bitangent, see
+/// `Mesh::with_tangents`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MeshVertex {
+    pub position: Vec3<f32>,
+    pub normal: Vec3<f32>,
+    pub uv: Vec2<f32>,
+    pub tangent: Vec4<f32>,
+}
+
+impl MeshVertex {
+    pub fn new(position: Vec3<f32>, normal: Vec3<f32>, uv: Vec2<f32>) -> Self {
+        Self {
+            position,
+            normal,
+            uv,
+            tangent: Vec4::zero(),
+        }
+    }
+}
+
+impl From<ShapeVertex> for MeshVertex {
+    fn from(vertex: ShapeVertex) -> Self {
+        Self::new(vertex.position, vertex.normal, vertex.uv)
+    }
+}
+
+impl From<MeshVertex> for ShapeVertex {
+    fn from(vertex: MeshVertex) -> Self {
+        ShapeVertex::new(vertex.position, vertex.normal, vertex.uv)
+    }
+}
+
+/// An indexed triangle list that is processed on the cpu, typically a model that was just loaded.
+/// Convert it into vertices and indices for `SetupContext::create_bundle_from_mesh` once it's
+/// ready.
+///
+/// ```
+/// use starstruck::mesh::Mesh;
+/// use starstruck::primitive::shapes;
+///
+/// let mesh = Mesh::from(shapes::icosphere(1.0, 3))
+///     .with_smooth_normals()
+///     .with_tangents()
+///     .with_optimized_vertex_cache()
+///     .with_optimized_vertex_fetch();
+/// let lod = mesh.simplified(mesh.triangle_count() / 4, 0.2);
+/// assert!(lod.triangle_count() <= mesh.triangle_count() / 4);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mesh {
+    vertices: Vec<MeshVertex>,
+    indices: Vec<u32>,
+}
+
+impl Mesh {
+    /// Fails unless the indices make whole triangles and only point at existing vertices
+    pub fn new(vertices: Vec<MeshVertex>, indices: Vec<u32>) -> Result<Self, Error> {
+        if indices.len() % 3 != 0 {
+            Err(MeshError::NotTriangles(indices.len()))?;
+        }
+        if let Some(index) = indices.iter().find(|index| **index as usize >= vertices.len()) {
+            Err(MeshError::IndexOutOfRange {
+                index: *index,
+                vertex_count: vertices.len(),
+            })?;
+        }
+        Ok(Self { vertices, indices })
+    }
+
+    /// Reads every object and group of an obj file, polygons are split into triangles. Every
+    /// corner gets a vertex of its own, so you usually want to weld the mesh afterwards. Normals
+    /// and uvs the file doesn't have are left as zero
+    pub fn from_obj(data: &[u8]) -> Result<Self, Error> {
+        let obj: Obj<SimplePolygon> = Obj::load_buf(&mut BufReader::new(data))?;
+        let mut vertices = vec![];
+        for polygon in obj.objects.iter().flat_map(|object| &object.groups).flat_map(|group| &group.polys) {
+            for corner in 1..polygon.len().saturating_sub(1) {
+                for tuple in &[&polygon[0], &polygon[corner], &polygon[corner + 1]] {
+                    let position = obj.position.get(tuple.0).ok_or(MeshError::MissingObjData("position"))?;
+                    let uv = match tuple.1 {
+                        Some(uv) => *obj.texture.get(uv).ok_or(MeshError::MissingObjData("uv"))?,
+                        None => [0.0; 2],
+                    };
+                    let normal = match tuple.2 {
+                        Some(normal) => *obj.normal.get(normal).ok_or(MeshError::MissingObjData("normal"))?,
+                        None => [0.0; 3],
+                    };
+                    vertices.push(MeshVertex::new(Vec3::from(*position), Vec3::from(normal), Vec2::from(uv)));
+                }
+            }
+        }
+        let indices = (0..vertices.len() as u32).collect();
+        Ok(Self { vertices, indices })
+    }
+
+    pub fn vertices(&self) -> &[MeshVertex] {
+        &self.vertices
+    }
+
+    pub fn indices(&self) -> &[u32] {
+        &self.indices
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        self.indices.len() / 3
+    }
+
+    /// Converts the vertices into one of the vertex types, the attributes the type doesn't have are
+    /// left out
+    pub fn to_vertices<V: From<ShapeVertex>>(&self) -> Vec<V> {
+        self.vertices.iter().map(|vertex| V::from(ShapeVertex::from(*vertex))).collect()
+    }
+
+    /// The indices as `u16`, which is what bundles are usually created with
+    ///
+    /// # Panics
+    ///
+    /// Panics if the mesh has more vertices than a `u16` can index
+    pub fn to_u16_indices(&self) -> Vec<u16> {
+        assert!(
+            self.vertices.len() <= usize::from(u16::max_value()) + 1,
+            "The mesh has {} vertices, which is too many for u16 indices",
+            self.vertices.len()
+        );
+        self.indices.iter().map(|index| *index as u16).collect()
+    }
+
+    /// Gives every triangle vertices of its own, with the normal of the triangle
+    pub fn with_flat_normals(self) -> Self {
+        let vertices = normals::flat_normals(&self.vertices, &self.indices);
+        let indices = (0..vertices.len() as u32).collect();
+        Self { vertices, indices }
+    }
+
+    /// Averages the normals of the triangles around every position, weighted by the angle of the
+    /// triangle at the vertex. Vertices at the same position get the same normal, even when they
+    /// are split at a uv seam
+    pub fn with_smooth_normals(mut self) -> Self {
+        normals::smooth_normals(&mut self.vertices, &self.indices);
+        self
+    }
+
+    /// Generates tangents with MikkTSpace, so normal maps baked by other tools line up. Vertices
+    /// whose triangles have different tangent spaces, such as along a mirrored uv seam, are split.
+    /// The bitangent is `tangent.w * normal.cross(tangent.xyz())`. Needs normals and uvs
+    pub fn with_tangents(mut self) -> Self {
+        tangents::tangents(&mut self.vertices, &mut self.indices);
+        self
+    }
+
+    /// Merges vertices whose attributes are all within `tolerance` of each other, the first of them
+    /// is kept. Vertices no triangle uses are dropped, and the rest are ordered by first use.
+    /// Values are compared on a grid of `tolerance`, so two values close to each other can still
+    /// end up on either side of a line. A tolerance of zero only merges exact copies
+    pub fn welded(self, tolerance: f32) -> Self {
+        let (vertices, indices) = weld::weld(&self.vertices, &self.indices, tolerance);
+        Self { vertices, indices }
+    }
+
+    /// Reorders the triangles so the vertices they use are more likely to be in the post transform
+    /// cache of the gpu, using Tom Forsyth's linear speed vertex cache optimisation
+    pub fn with_optimized_vertex_cache(mut self) -> Self {
+        self.indices = optimize::optimize_vertex_cache(&self.indices, self.vertices.len());
+        self
+    }
+
+    /// Reorders the vertices by the order the triangles use them, so they are fetched from memory
+    /// in order. Vertices no triangle uses are dropped. Do this after optimising the vertex cache
+    pub fn with_optimized_vertex_fetch(self) -> Self {
+        let (vertices, indices) = optimize::optimize_vertex_fetch(&self.vertices, &self.indices);
+        Self { vertices, indices }
+    }
+
+    /// The number of vertices transformed per triangle with a first in first out cache of the given
+    /// size, lower is better. It's between 0.5 for the best possible grid and 3
+    pub fn average_cache_miss_ratio(&self, cache_size: usize) -> f32 {
+        optimize::average_cache_miss_ratio(&self.indices, cache_size)
+    }
+
+    /// A simplified copy for a lower level of detail, made by collapsing edges with quadric error
+    /// metrics. Collapsing stops at `target_triangle_count` triangles, or before the surface would
+    /// move further than about `max_error`. The vertices that are kept don't move, and the borders
+    /// of the mesh, hard edges and uv seams are left as they are
+    pub fn simplified(&self, target_triangle_count: usize, max_error: f32) -> Self {
+        let indices = simplify::simplify(&self.vertices, &self.indices, target_triangle_count, max_error);
+        let (vertices, indices) = optimize::optimize_vertex_fetch(&self.vertices, &indices);
+        Self { vertices, indices }
+    }
+}
+
+impl From<Shape> for Mesh {
+    fn from(shape: Shape) -> Self {
+        Self::from(&shape)
+    }
+}
+
+impl<'a> From<&'a Shape> for Mesh {
+    fn from(shape: &'a Shape) -> Self {
+        Self {
+            vertices: shape.vertices().iter().map(|vertex| MeshVertex::from(*vertex)).collect(),
+            indices: shape.indices().to_vec(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn it_should_refuse_broken_index_lists() {
+        let vertices = vec![MeshVertex::new(Vec3::zero(), Vec3::unit_y(), Vec2::zero()); 3];
+        assert_eq!(
+            "The mesh has 4 indices, which isn't a whole number of triangles",
+            format!("{}", Mesh::new(vertices.clone(), vec![0, 1, 2, 0]).unwrap_err())
+        );
+        assert_eq!(
+            "The mesh has 3 vertices, but an index points at vertex 3",
+            format!("{}", Mesh::new(vertices, vec![0, 1, 3]).unwrap_err())
+        );
+    }
+
+    #[test]
+    fn it_should_read_obj_files_as_triangles() {
+        let obj = b"v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nvt 0 0\nvt 1 1\nf 1/1 2/2 3/2 4/1\n";
+        let mesh = Mesh::from_obj(obj).unwrap();
+        assert_eq!(2, mesh.triangle_count());
+        assert_eq!(Vec3::new(1.0, 1.0, 0.0), mesh.vertices()[2].position);
+        assert_eq!(Vec2::new(1.0, 1.0), mesh.vertices()[2].uv);
+        assert_eq!(Vec3::zero(), mesh.vertices()[2].normal);
+
+        let welded = mesh.welded(0.0);
+        assert_eq!((4, vec![0, 1, 2, 0, 2, 3]), (welded.vertices().len(), welded.indices().to_vec()));
+    }
+}
@@ -0,0 +1,17 @@
+//! Fixing up indexed meshes on the cpu before they are uploaded, see [`Mesh`]. Normals, tangents,
+//! welding, vertex cache and fetch ordering, and simplification for lod levels. Everything here is
+//! deterministic, the same mesh always gives the same result
+
+#[allow(clippy::module_inception)]
+mod mesh;
+mod normals;
+mod optimize;
+mod simplify;
+mod tangents;
+mod weld;
+
+#[doc(inline)]
+pub use self::mesh::Mesh;
+
+#[doc(inline)]
+pub use self::mesh::MeshVertex;
@@ -0,0 +1,113 @@
+use crate::mesh::MeshVertex;
+use std::collections::HashMap;
+use vek::Vec3;
+
+pub(crate) fn flat_normals(vertices: &[MeshVertex], indices: &[u32]) -> Vec<MeshVertex> {
+    indices
+        .chunks(3)
+        .flat_map(|triangle| {
+            let corners = [
+                vertices[triangle[0] as usize],
+                vertices[triangle[1] as usize],
+                vertices[triangle[2] as usize],
+            ];
+            let normal = normalized_or_zero(face_normal(corners[0].position, corners[1].position, corners[2].position));
+            corners.iter().map(|corner| MeshVertex { normal, ..*corner }).collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+pub(crate) fn smooth_normals(vertices: &mut [MeshVertex], indices: &[u32]) {
+    let (groups, group_count) = position_groups(vertices);
+    let mut normals = vec![Vec3::zero(); group_count];
+    for triangle in indices.chunks(3) {
+        let positions = [
+            vertices[triangle[0] as usize].position,
+            vertices[triangle[1] as usize].position,
+            vertices[triangle[2] as usize].position,
+        ];
+        let normal = normalized_or_zero(face_normal(positions[0], positions[1], positions[2]));
+        for corner in 0..3 {
+            let angle = corner_angle(positions[corner], positions[(corner + 1) % 3], positions[(corner + 2) % 3]);
+            normals[groups[triangle[corner] as usize]] += normal * angle;
+        }
+    }
+    for (vertex, group) in vertices.iter_mut().zip(groups) {
+        vertex.normal = normalized_or_zero(normals[group]);
+    }
+}
+
+/// Points the way the triangle faces when wound counter clockwise, its length is twice the area
+pub(crate) fn face_normal(a: Vec3<f32>, b: Vec3<f32>, c: Vec3<f32>) -> Vec3<f32> {
+    (b - a).cross(c - a)
+}
+
+/// The angle between the edges going out from `corner`, zero for degenerate triangles
+pub(crate) fn corner_angle(corner: Vec3<f32>, b: Vec3<f32>, c: Vec3<f32>) -> f32 {
+    let (ab, ac) = (normalized_or_zero(b - corner), normalized_or_zero(c - corner));
+    if ab == Vec3::zero() || ac == Vec3::zero() {
+        return 0.0;
+    }
+    ab.dot(ac).max(-1.0).min(1.0).acos()
+}
+
+pub(crate) fn normalized_or_zero(vector: Vec3<f32>) -> Vec3<f32> {
+    let length = vector.magnitude();
+    if length > 0.0 {
+        vector / length
+    } else {
+        Vec3::zero()
+    }
+}
+
+/// Numbers the distinct positions in order of appearance, returns the number of every vertex and
+/// how many there are
+pub(crate) fn position_groups(vertices: &[MeshVertex]) -> (Vec<usize>, usize) {
+    let mut numbers = HashMap::new();
+    let groups = vertices
+        .iter()
+        .map(|vertex| {
+            // Adding zero turns -0.0 into 0.0, so they are the same position
+            let position = vertex.position + Vec3::zero();
+            let key = [position.x.to_bits(), position.y.to_bits(), position.z.to_bits()];
+            let next = numbers.len();
+            *numbers.entry(key).or_insert(next)
+        })
+        .collect();
+    (groups, numbers.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mesh::Mesh;
+    use crate::primitive::shapes;
+    use pretty_assertions::assert_eq;
+    use vek::Vec3;
+
+    #[test]
+    fn it_should_give_every_triangle_its_own_flat_normal() {
+        let mesh = Mesh::from(shapes::icosphere(1.0, 1)).with_flat_normals();
+        assert_eq!((240, 80), (mesh.vertices().len(), mesh.triangle_count()));
+        for triangle in mesh.vertices().chunks(3) {
+            let centre = (triangle[0].position + triangle[1].position + triangle[2].position) / 3.0;
+            assert_eq!(triangle[0].normal, triangle[2].normal);
+            assert!(triangle[0].normal.dot(centre.normalized()) > 0.9);
+        }
+    }
+
+    #[test]
+    fn it_should_smooth_normals_across_uv_seams() {
+        let shape = shapes::uv_sphere(2.0, 16, 8);
+        let mesh = Mesh::from(&shape).with_flat_normals().with_smooth_normals();
+        for vertex in mesh.vertices() {
+            let expected = vertex.position.normalized();
+            assert!(vertex.normal.dot(expected) > 0.99, "{:?} should point along {:?}", vertex.normal, expected);
+        }
+
+        // The last column of every row is at the same position as the first one
+        let mesh = Mesh::from(&shape).with_smooth_normals();
+        let row = 4 * 17;
+        assert_eq!(mesh.vertices()[row].normal, mesh.vertices()[row + 16].normal);
+        assert!(mesh.vertices()[0].normal.dot(Vec3::new(0.0, -1.0, 0.0)) > 0.9999);
+    }
+}
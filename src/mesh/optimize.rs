@@ -0,0 +1,189 @@
+use crate::mesh::MeshVertex;
+use std::collections::VecDeque;
+
+/// The size of the cache the scores are tuned for
+const CACHE_SIZE: usize = 32;
+
+/// Picks the next triangle by the scores of its vertices, which go up the more recently the vertex
+/// was used and the fewer triangles it has left. Only triangles around the vertices in the cache
+/// are looked at, so it runs in linear time. Ties go to the first triangle
+pub(crate) fn optimize_vertex_cache(indices: &[u32], vertex_count: usize) -> Vec<u32> {
+    let triangle_count = indices.len() / 3;
+
+    // The triangles of every vertex, in one list
+    let mut offsets = vec![0; vertex_count + 1];
+    for index in indices {
+        offsets[*index as usize + 1] += 1;
+    }
+    for vertex in 0..vertex_count {
+        offsets[vertex + 1] += offsets[vertex];
+    }
+    let mut remaining: Vec<usize> = (0..vertex_count).map(|vertex| offsets[vertex + 1] - offsets[vertex]).collect();
+    let mut filled = offsets.clone();
+    let mut adjacency = vec![0; indices.len()];
+    for (position, index) in indices.iter().enumerate() {
+        adjacency[filled[*index as usize]] = position / 3;
+        filled[*index as usize] += 1;
+    }
+    let triangles_of = |vertex: u32| &adjacency[offsets[vertex as usize]..offsets[vertex as usize + 1]];
+
+    let mut cache_positions: Vec<Option<usize>> = vec![None; vertex_count];
+    let mut vertex_scores: Vec<f32> = (0..vertex_count).map(|vertex| vertex_score(None, remaining[vertex])).collect();
+    let triangle_score = |vertex_scores: &[f32], triangle: usize| {
+        indices[triangle * 3..triangle * 3 + 3].iter().map(|index| vertex_scores[*index as usize]).sum::<f32>()
+    };
+    let mut triangle_scores: Vec<f32> = (0..triangle_count).map(|triangle| triangle_score(&vertex_scores, triangle)).collect();
+
+    let mut emitted = vec![false; triangle_count];
+    let mut cache: Vec<u32> = vec![];
+    let mut output = Vec::with_capacity(indices.len());
+    let mut next_unemitted = 0;
+    let mut best = best_triangle(0..triangle_count, &emitted, &triangle_scores);
+    while output.len() < triangle_count * 3 {
+        let triangle = match best {
+            Some(triangle) => triangle,
+            None => {
+                while emitted[next_unemitted] {
+                    next_unemitted += 1;
+                }
+                next_unemitted
+            }
+        };
+        emitted[triangle] = true;
+        let corners = &indices[triangle * 3..triangle * 3 + 3];
+        output.extend_from_slice(corners);
+        for index in corners {
+            remaining[*index as usize] -= 1;
+        }
+
+        let mut new_cache = corners.to_vec();
+        new_cache.extend(cache.iter().filter(|index| !corners.contains(index)));
+        let evicted = if new_cache.len() > CACHE_SIZE { new_cache.split_off(CACHE_SIZE) } else { vec![] };
+        for index in &evicted {
+            cache_positions[*index as usize] = None;
+        }
+        for (position, index) in new_cache.iter().enumerate() {
+            cache_positions[*index as usize] = Some(position);
+        }
+        cache = new_cache;
+
+        for index in cache.iter().chain(&evicted) {
+            let vertex = *index as usize;
+            vertex_scores[vertex] = vertex_score(cache_positions[vertex], remaining[vertex]);
+        }
+        for index in cache.iter().chain(&evicted) {
+            for triangle in triangles_of(*index) {
+                if !emitted[*triangle] {
+                    triangle_scores[*triangle] = triangle_score(&vertex_scores, *triangle);
+                }
+            }
+        }
+        best = best_triangle(cache.iter().flat_map(|index| triangles_of(*index).iter().cloned()), &emitted, &triangle_scores);
+    }
+    output
+}
+
+fn vertex_score(cache_position: Option<usize>, remaining: usize) -> f32 {
+    if remaining == 0 {
+        return -1.0;
+    }
+    let cache_score = match cache_position {
+        None => 0.0,
+        // The last triangle is scored the same no matter the order of its vertices
+        Some(position) if position < 3 => 0.75,
+        Some(position) => (1.0 - (position - 3) as f32 / (CACHE_SIZE - 3) as f32).powf(1.5),
+    };
+    cache_score + 2.0 / (remaining as f32).sqrt()
+}
+
+fn best_triangle<T: Iterator<Item = usize>>(triangles: T, emitted: &[bool], scores: &[f32]) -> Option<usize> {
+    let mut best: Option<usize> = None;
+    for triangle in triangles.filter(|triangle| !emitted[*triangle]) {
+        if best.map_or(true, |best| scores[triangle] > scores[best]) {
+            best = Some(triangle);
+        }
+    }
+    best
+}
+
+pub(crate) fn optimize_vertex_fetch(vertices: &[MeshVertex], indices: &[u32]) -> (Vec<MeshVertex>, Vec<u32>) {
+    let mut numbers = vec![None; vertices.len()];
+    let mut fetched = vec![];
+    let indices = indices
+        .iter()
+        .map(|index| {
+            *numbers[*index as usize].get_or_insert_with(|| {
+                fetched.push(vertices[*index as usize]);
+                fetched.len() as u32 - 1
+            })
+        })
+        .collect();
+    (fetched, indices)
+}
+
+pub(crate) fn average_cache_miss_ratio(indices: &[u32], cache_size: usize) -> f32 {
+    if indices.is_empty() {
+        return 0.0;
+    }
+    let mut cache = VecDeque::with_capacity(cache_size + 1);
+    let mut misses = 0;
+    for index in indices {
+        if !cache.contains(index) {
+            misses += 1;
+            cache.push_back(*index);
+            if cache.len() > cache_size {
+                cache.pop_front();
+            }
+        }
+    }
+    misses as f32 / (indices.len() / 3) as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mesh::Mesh;
+    use crate::primitive::shapes;
+    use pretty_assertions::assert_eq;
+
+    /// A grid with its triangles in a scattered order
+    fn scattered_grid() -> Mesh {
+        let grid = Mesh::from(shapes::plane(1.0, 1.0, 40, 40));
+        let triangles = grid.triangle_count();
+        let indices = (0..triangles)
+            .map(|triangle| triangle * 7919 % triangles)
+            .flat_map(|triangle| grid.indices()[triangle * 3..triangle * 3 + 3].to_vec())
+            .collect();
+        Mesh::new(grid.vertices().to_vec(), indices).unwrap()
+    }
+
+    #[test]
+    fn it_should_lower_the_cache_miss_ratio() {
+        let grid = scattered_grid();
+        let optimized = grid.clone().with_optimized_vertex_cache();
+        assert!(grid.average_cache_miss_ratio(16) > 2.0);
+        assert!(optimized.average_cache_miss_ratio(16) < 0.9);
+
+        let mut triangles: Vec<_> = grid.indices().chunks(3).map(|triangle| triangle.to_vec()).collect();
+        let mut optimized_triangles: Vec<_> = optimized.indices().chunks(3).map(|triangle| triangle.to_vec()).collect();
+        triangles.sort();
+        optimized_triangles.sort();
+        assert_eq!(triangles, optimized_triangles);
+    }
+
+    #[test]
+    fn it_should_be_deterministic() {
+        let first = scattered_grid().with_optimized_vertex_cache().with_optimized_vertex_fetch();
+        let second = scattered_grid().with_optimized_vertex_cache().with_optimized_vertex_fetch();
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn it_should_order_vertices_by_first_use() {
+        let mesh = Mesh::from(shapes::quad(1.0, 1.0));
+        let reversed: Vec<u32> = mesh.indices().iter().rev().cloned().collect();
+        let fetched = Mesh::new(mesh.vertices().to_vec(), reversed).unwrap().with_optimized_vertex_fetch();
+        assert_eq!(vec![0, 1, 2, 2, 3, 0], fetched.indices().to_vec());
+        assert_eq!(mesh.vertices()[0], fetched.vertices()[0]);
+        assert_eq!(mesh.vertices()[3], fetched.vertices()[1]);
+    }
+}
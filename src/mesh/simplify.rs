@@ -0,0 +1,320 @@
+use crate::mesh::normals::position_groups;
+use crate::mesh::MeshVertex;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::collections::HashMap;
+use std::ops::AddAssign;
+use vek::Vec3;
+
+/// How much a triangle may turn during a collapse, as the cosine of the angle
+const MAX_ROTATION: f64 = 0.25;
+
+/// The cost as bits, the vertex that moves, the vertex it moves to, and the versions of their groups
+/// when the collapse was queued. Reversed so the cheapest collapse comes first, ties go to the
+/// lowest vertices
+type Collapse = Reverse<(u64, u32, u32, u32, u32)>;
+
+/// The sum of the squared distances to a set of planes, as the upper half of a symmetric 4x4 matrix
+#[derive(Clone, Copy, Debug, Default)]
+struct Quadric([f64; 10]);
+
+impl Quadric {
+    /// The plane through `point` facing `normal`, which is unit length
+    fn plane(normal: Vec3<f64>, point: Vec3<f64>) -> Self {
+        let (a, b, c) = (normal.x, normal.y, normal.z);
+        let d = -normal.dot(point);
+        Quadric([a * a, a * b, a * c, a * d, b * b, b * c, b * d, c * c, c * d, d * d])
+    }
+
+    fn error(&self, point: Vec3<f64>) -> f64 {
+        let q = &self.0;
+        let (x, y, z) = (point.x, point.y, point.z);
+        q[0] * x * x
+            + 2.0 * q[1] * x * y
+            + 2.0 * q[2] * x * z
+            + 2.0 * q[3] * x
+            + q[4] * y * y
+            + 2.0 * q[5] * y * z
+            + 2.0 * q[6] * y
+            + q[7] * z * z
+            + 2.0 * q[8] * z
+            + q[9]
+    }
+}
+
+impl AddAssign for Quadric {
+    fn add_assign(&mut self, other: Quadric) {
+        for (value, other) in self.0.iter_mut().zip(other.0.iter()) {
+            *value += other;
+        }
+    }
+}
+
+/// Collapses one vertex of an edge into the other, cheapest first. Vertices that share their
+/// position with another vertex, like the ones along uv seams and hard edges, and vertices on the
+/// border of the mesh never move, but others can collapse into them. Collapses that would flip or
+/// sharply turn a triangle are skipped. Returns the triangles that are left, in their old order
+pub(crate) fn simplify(vertices: &[MeshVertex], indices: &[u32], target_triangle_count: usize, max_error: f32) -> Vec<u32> {
+    let mut state = Simplifier::new(vertices, indices);
+    let max_error = f64::from(max_error) * f64::from(max_error);
+    while state.live > target_triangle_count {
+        let Reverse((cost, from, to, from_version, to_version)) = match state.queue.pop() {
+            Some(collapse) => collapse,
+            None => break,
+        };
+        let (from, to) = (from as usize, to as usize);
+        if state.collapsed[from] || state.version(from) != from_version || state.version(to) != to_version {
+            continue;
+        }
+        if f64::from_bits(cost) > max_error {
+            break;
+        }
+        if state.connected(from, to) && !state.turns_triangles(from, to) {
+            state.collapse(from, to);
+        }
+    }
+    state.indices()
+}
+
+struct Simplifier {
+    positions: Vec<Vec3<f64>>,
+    indices: Vec<u32>,
+    groups: Vec<usize>,
+    members: Vec<Vec<usize>>,
+    quadrics: Vec<Quadric>,
+    versions: Vec<u32>,
+    locked: Vec<bool>,
+    collapsed: Vec<bool>,
+    triangles: Vec<Vec<usize>>,
+    removed: Vec<bool>,
+    live: usize,
+    queue: BinaryHeap<Collapse>,
+}
+
+impl Simplifier {
+    fn new(vertices: &[MeshVertex], indices: &[u32]) -> Self {
+        let (groups, group_count) = position_groups(vertices);
+        let positions = vertices.iter().map(|vertex| vertex.position.map(f64::from)).collect();
+        let mut state = Self {
+            positions,
+            indices: indices.to_vec(),
+            groups,
+            members: vec![vec![]; group_count],
+            quadrics: vec![Quadric::default(); group_count],
+            versions: vec![0; group_count],
+            locked: vec![false; group_count],
+            collapsed: vec![false; vertices.len()],
+            triangles: vec![vec![]; vertices.len()],
+            removed: vec![false; indices.len() / 3],
+            live: indices.len() / 3,
+            queue: BinaryHeap::new(),
+        };
+
+        let mut edges = HashMap::new();
+        for triangle in 0..state.live {
+            let corners = state.corners(triangle);
+            for corner in 0..3 {
+                let (a, b) = (state.groups[corners[corner]], state.groups[corners[(corner + 1) % 3]]);
+                *edges.entry((a.min(b), a.max(b))).or_insert(0) += 1;
+                if !state.triangles[corners[corner]].contains(&triangle) {
+                    state.triangles[corners[corner]].push(triangle);
+                }
+            }
+            let normal = state.normal(corners);
+            let length = normal.magnitude();
+            if length > 0.0 {
+                let plane = Quadric::plane(normal / length, state.positions[corners[0]]);
+                for corner in &corners {
+                    state.quadrics[state.groups[*corner]] += plane;
+                }
+            }
+        }
+
+        for vertex in 0..vertices.len() {
+            if !state.triangles[vertex].is_empty() {
+                state.members[state.groups[vertex]].push(vertex);
+            }
+        }
+        for members in &state.members {
+            if members.len() > 1 {
+                state.locked[state.groups[members[0]]] = true;
+            }
+        }
+        // Edges that aren't shared by exactly two triangles are on a border, or where the mesh
+        // isn't a surface
+        for triangle in 0..state.live {
+            let corners = state.corners(triangle);
+            for corner in 0..3 {
+                let (a, b) = (state.groups[corners[corner]], state.groups[corners[(corner + 1) % 3]]);
+                if edges[&(a.min(b), a.max(b))] != 2 {
+                    state.locked[a] = true;
+                    state.locked[b] = true;
+                }
+            }
+        }
+
+        for triangle in 0..state.live {
+            let corners = state.corners(triangle);
+            for corner in 0..3 {
+                state.push(corners[corner], corners[(corner + 1) % 3]);
+                state.push(corners[(corner + 1) % 3], corners[corner]);
+            }
+        }
+        state
+    }
+
+    fn corners(&self, triangle: usize) -> [usize; 3] {
+        let corners = &self.indices[triangle * 3..triangle * 3 + 3];
+        [corners[0] as usize, corners[1] as usize, corners[2] as usize]
+    }
+
+    fn normal(&self, corners: [usize; 3]) -> Vec3<f64> {
+        let [a, b, c] = [self.positions[corners[0]], self.positions[corners[1]], self.positions[corners[2]]];
+        (b - a).cross(c - a)
+    }
+
+    fn version(&self, vertex: usize) -> u32 {
+        self.versions[self.groups[vertex]]
+    }
+
+    fn live_triangles(&self, vertex: usize) -> Vec<usize> {
+        self.triangles[vertex].iter().cloned().filter(|triangle| !self.removed[*triangle]).collect()
+    }
+
+    /// Queues the collapse of `from` into `to`, unless `from` can't move
+    fn push(&mut self, from: usize, to: usize) {
+        let (from_group, to_group) = (self.groups[from], self.groups[to]);
+        if self.locked[from_group] || self.collapsed[from] || from_group == to_group {
+            return;
+        }
+        let mut quadric = self.quadrics[from_group];
+        quadric += self.quadrics[to_group];
+        let cost = quadric.error(self.positions[to]).max(0.0);
+        self.queue.push(Reverse((
+            cost.to_bits(),
+            from as u32,
+            to as u32,
+            self.versions[from_group],
+            self.versions[to_group],
+        )));
+    }
+
+    fn connected(&self, from: usize, to: usize) -> bool {
+        self.live_triangles(from).iter().any(|triangle| self.corners(*triangle).contains(&to))
+    }
+
+    /// Whether a triangle that stays would flip, or turn further than `MAX_ROTATION`
+    fn turns_triangles(&self, from: usize, to: usize) -> bool {
+        self.live_triangles(from).into_iter().any(|triangle| {
+            let corners = self.corners(triangle);
+            if corners.contains(&to) {
+                return false;
+            }
+            let before = self.normal(corners);
+            let after = self.normal([
+                if corners[0] == from { to } else { corners[0] },
+                if corners[1] == from { to } else { corners[1] },
+                if corners[2] == from { to } else { corners[2] },
+            ]);
+            after.dot(before) <= MAX_ROTATION * after.magnitude() * before.magnitude()
+        })
+    }
+
+    fn collapse(&mut self, from: usize, to: usize) {
+        for triangle in self.live_triangles(from) {
+            if self.corners(triangle).contains(&to) {
+                self.removed[triangle] = true;
+                self.live -= 1;
+            } else {
+                for index in &mut self.indices[triangle * 3..triangle * 3 + 3] {
+                    if *index as usize == from {
+                        *index = to as u32;
+                    }
+                }
+                self.triangles[to].push(triangle);
+            }
+        }
+        self.collapsed[from] = true;
+
+        let (from_group, to_group) = (self.groups[from], self.groups[to]);
+        let quadric = self.quadrics[from_group];
+        self.quadrics[to_group] += quadric;
+        self.versions[from_group] += 1;
+        self.versions[to_group] += 1;
+
+        // The cost of every collapse into or out of the group changed
+        for member in self.members[to_group].clone() {
+            for triangle in self.live_triangles(member) {
+                for corner in self.corners(triangle).iter() {
+                    if *corner != member {
+                        self.push(member, *corner);
+                        self.push(*corner, member);
+                    }
+                }
+            }
+        }
+    }
+
+    fn indices(&self) -> Vec<u32> {
+        (0..self.removed.len())
+            .filter(|triangle| !self.removed[*triangle])
+            .flat_map(|triangle| self.indices[triangle * 3..triangle * 3 + 3].to_vec())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mesh::Mesh;
+    use crate::primitive::shapes;
+    use pretty_assertions::assert_eq;
+
+    fn area(mesh: &Mesh) -> f32 {
+        mesh.indices()
+            .chunks(3)
+            .map(|triangle| {
+                let [a, b, c] = [
+                    mesh.vertices()[triangle[0] as usize].position,
+                    mesh.vertices()[triangle[1] as usize].position,
+                    mesh.vertices()[triangle[2] as usize].position,
+                ];
+                (b - a).cross(c - a).magnitude() / 2.0
+            })
+            .sum()
+    }
+
+    #[test]
+    fn it_should_simplify_to_the_target_count() {
+        let sphere = Mesh::from(shapes::icosphere(1.0, 3));
+        let simplified = sphere.simplified(320, 1.0);
+        assert!(simplified.triangle_count() <= 320 && simplified.triangle_count() > 200);
+        assert!(simplified.vertices().len() < sphere.vertices().len() / 3);
+        for triangle in simplified.indices().chunks(3) {
+            let [a, b, c] = [
+                simplified.vertices()[triangle[0] as usize].position,
+                simplified.vertices()[triangle[1] as usize].position,
+                simplified.vertices()[triangle[2] as usize].position,
+            ];
+            assert!((b - a).cross(c - a).dot(a + b + c) > 0.0, "Triangle {:?} flipped", triangle);
+        }
+        assert_eq!(simplified, sphere.simplified(320, 1.0));
+    }
+
+    #[test]
+    fn it_should_stop_at_the_max_error() {
+        let sphere = Mesh::from(shapes::icosphere(1.0, 2));
+        assert_eq!(sphere.triangle_count(), sphere.simplified(0, 0.0).triangle_count());
+        assert!(sphere.simplified(0, 0.05).triangle_count() > sphere.simplified(0, 0.5).triangle_count());
+    }
+
+    #[test]
+    fn it_should_keep_borders_and_seams() {
+        let plane = Mesh::from(shapes::plane(1.0, 1.0, 10, 10));
+        let simplified = plane.simplified(0, 0.0);
+        assert_eq!(40, simplified.vertices().len());
+        assert!((area(&simplified) - 1.0).abs() < 1e-4);
+
+        let cube = Mesh::from(shapes::cube(1.0));
+        assert_eq!(cube, cube.simplified(0, 1.0));
+    }
+}
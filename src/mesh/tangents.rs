@@ -0,0 +1,431 @@
+use crate::mesh::normals::normalized_or_zero;
+use crate::mesh::MeshVertex;
+use std::collections::HashMap;
+use vek::Vec3;
+use vek::Vec4;
+
+/// Corners whose tangents point further apart than this are split into separate tangent spaces,
+/// the cosine of the 180 degrees MikkTSpace uses by default
+const SPLIT_COS: f32 = -1.0;
+
+struct Triangle {
+    /// The directions of u and v on the triangle, flipped when the uvs are mirrored
+    tangent: Vec3<f32>,
+    bitangent: Vec3<f32>,
+    preserves_orientation: bool,
+    /// The uvs have no area, so the triangle joins the group of whichever neighbour reaches it
+    group_with_any: bool,
+    /// Two of the corners are at the same position
+    degenerate: bool,
+    /// The triangle across the edge from each corner to the next one
+    neighbours: [Option<usize>; 3],
+    /// The group each corner belongs to
+    groups: [Option<usize>; 3],
+}
+
+/// The triangles around a vertex that are connected through edges and have the same orientation
+struct Group {
+    vertex: u32,
+    preserves_orientation: bool,
+    triangles: Vec<usize>,
+}
+
+/// A port of MikkTSpace. Corners around a vertex are grouped by the orientation of their uvs, and
+/// each group averages the tangents of its triangles projected onto the plane of the normal,
+/// weighted by their angles at the vertex. Vertices used by more than one group, such as along a
+/// mirrored uv seam, are split so every group gets its own. Corners without any uv direction get
+/// any tangent at a right angle to the normal
+pub(crate) fn tangents(vertices: &mut Vec<MeshVertex>, indices: &mut [u32]) {
+    let shared = shared_vertices(vertices, indices);
+    let mut triangles = triangles(vertices, indices, &shared);
+    let groups = groups(&mut triangles, &shared);
+    let spaces = tangent_spaces(vertices, indices, &shared, &triangles, &groups);
+
+    let mut assigned = vec![false; vertices.len()];
+    let mut splits = HashMap::new();
+    for (corner, space) in spaces.into_iter().enumerate() {
+        let vertex = indices[corner] as usize;
+        let tangent = match space {
+            Some((tangent, preserves_orientation)) if tangent != Vec3::zero() => Vec4::new(
+                tangent.x,
+                tangent.y,
+                tangent.z,
+                if preserves_orientation { 1.0 } else { -1.0 },
+            ),
+            _ => fallback(vertices[vertex].normal),
+        };
+        if !assigned[vertex] {
+            assigned[vertex] = true;
+            vertices[vertex].tangent = tangent;
+        } else if vertices[vertex].tangent != tangent {
+            let key = (
+                vertex,
+                [
+                    tangent.x.to_bits(),
+                    tangent.y.to_bits(),
+                    tangent.z.to_bits(),
+                    tangent.w.to_bits(),
+                ],
+            );
+            indices[corner] = *splits.entry(key).or_insert_with(|| {
+                vertices.push(MeshVertex {
+                    tangent,
+                    ..vertices[vertex]
+                });
+                (vertices.len() - 1) as u32
+            });
+        }
+    }
+    for (vertex, assigned) in vertices.iter_mut().zip(assigned) {
+        if !assigned {
+            vertex.tangent = fallback(vertex.normal);
+        }
+    }
+}
+
+/// Numbers every corner by its position, normal and uv, so copies of a vertex count as one
+fn shared_vertices(vertices: &[MeshVertex], indices: &[u32]) -> Vec<u32> {
+    let mut numbers = HashMap::new();
+    indices
+        .iter()
+        .map(|&index| {
+            let vertex = vertices[index as usize];
+            // Adding zero turns -0.0 into 0.0, so they are the same value
+            let (position, normal, uv) = (
+                vertex.position + Vec3::zero(),
+                vertex.normal + Vec3::zero(),
+                vertex.uv,
+            );
+            let key = [
+                position.x.to_bits(),
+                position.y.to_bits(),
+                position.z.to_bits(),
+                normal.x.to_bits(),
+                normal.y.to_bits(),
+                normal.z.to_bits(),
+                (uv.x + 0.0).to_bits(),
+                (uv.y + 0.0).to_bits(),
+            ];
+            *numbers.entry(key).or_insert(index)
+        })
+        .collect()
+}
+
+fn triangles(vertices: &[MeshVertex], indices: &[u32], shared: &[u32]) -> Vec<Triangle> {
+    let mut triangles: Vec<Triangle> = indices
+        .chunks(3)
+        .map(|triangle| {
+            let corners = [
+                vertices[triangle[0] as usize],
+                vertices[triangle[1] as usize],
+                vertices[triangle[2] as usize],
+            ];
+            let (edge_1, edge_2) = (
+                corners[1].position - corners[0].position,
+                corners[2].position - corners[0].position,
+            );
+            let (uv_1, uv_2) = (corners[1].uv - corners[0].uv, corners[2].uv - corners[0].uv);
+            let area = uv_1.x * uv_2.y - uv_1.y * uv_2.x;
+            let tangent = edge_1 * uv_2.y - edge_2 * uv_1.y;
+            let bitangent = edge_2 * uv_1.x - edge_1 * uv_2.x;
+            let sign = if area > 0.0 { 1.0 } else { -1.0 };
+            let (tangent_length, bitangent_length) = (tangent.magnitude(), bitangent.magnitude());
+            Triangle {
+                tangent: if area != 0.0 && tangent_length > 0.0 {
+                    tangent * (sign / tangent_length)
+                } else {
+                    tangent
+                },
+                bitangent: if area != 0.0 && bitangent_length > 0.0 {
+                    bitangent * (sign / bitangent_length)
+                } else {
+                    bitangent
+                },
+                preserves_orientation: area > 0.0,
+                group_with_any: area == 0.0 || tangent_length == 0.0 || bitangent_length == 0.0,
+                degenerate: corners[0].position == corners[1].position
+                    || corners[0].position == corners[2].position
+                    || corners[1].position == corners[2].position,
+                neighbours: [None; 3],
+                groups: [None; 3],
+            }
+        })
+        .collect();
+
+    // Edges are only shared by triangles that are wound the same way, so the edge is reversed
+    let mut open: HashMap<(u32, u32), Vec<(usize, usize)>> = HashMap::new();
+    for triangle in 0..triangles.len() {
+        if triangles[triangle].degenerate {
+            continue;
+        }
+        for corner in 0..3 {
+            let (from, to) = (
+                shared[triangle * 3 + corner],
+                shared[triangle * 3 + (corner + 1) % 3],
+            );
+            let neighbour = open.get_mut(&(to, from)).and_then(|edges| {
+                if edges.is_empty() {
+                    None
+                } else {
+                    Some(edges.remove(0))
+                }
+            });
+            match neighbour {
+                Some((other, other_corner)) => {
+                    triangles[triangle].neighbours[corner] = Some(other);
+                    triangles[other].neighbours[other_corner] = Some(triangle);
+                },
+                None => open.entry((from, to)).or_default().push((triangle, corner)),
+            }
+        }
+    }
+    triangles
+}
+
+fn groups(triangles: &mut [Triangle], shared: &[u32]) -> Vec<Group> {
+    let mut groups = vec![];
+    for triangle in 0..triangles.len() {
+        for corner in 0..3 {
+            let first = &triangles[triangle];
+            if first.degenerate || first.group_with_any || first.groups[corner].is_some() {
+                continue;
+            }
+            let number = groups.len();
+            let mut group = Group {
+                vertex: shared[triangle * 3 + corner],
+                preserves_orientation: first.preserves_orientation,
+                triangles: vec![triangle],
+            };
+            triangles[triangle].groups[corner] = Some(number);
+
+            let mut pending = vec![
+                triangles[triangle].neighbours[corner],
+                triangles[triangle].neighbours[(corner + 2) % 3],
+            ];
+            while let Some(next) = pending.pop() {
+                let next = match next {
+                    Some(next) => next,
+                    None => continue,
+                };
+                let corner = match (0..3).find(|&corner| shared[next * 3 + corner] == group.vertex)
+                {
+                    Some(corner) => corner,
+                    None => continue,
+                };
+                let other = &mut triangles[next];
+                if other.groups[corner].is_some() {
+                    continue;
+                }
+                if other.group_with_any && other.groups.iter().all(Option::is_none) {
+                    other.preserves_orientation = group.preserves_orientation;
+                }
+                if other.preserves_orientation != group.preserves_orientation {
+                    continue;
+                }
+                other.groups[corner] = Some(number);
+                group.triangles.push(next);
+                pending.push(other.neighbours[corner]);
+                pending.push(other.neighbours[(corner + 2) % 3]);
+            }
+            groups.push(group);
+        }
+    }
+    groups
+}
+
+/// The tangent and the orientation of every corner, corners no group reached get `None`
+fn tangent_spaces(
+    vertices: &[MeshVertex],
+    indices: &[u32],
+    shared: &[u32],
+    triangles: &[Triangle],
+    groups: &[Group],
+) -> Vec<Option<(Vec3<f32>, bool)>> {
+    let mut spaces = vec![None; indices.len()];
+    for (number, group) in groups.iter().enumerate() {
+        let mut subgroups: Vec<(Vec<usize>, Vec3<f32>)> = vec![];
+        for &triangle in &group.triangles {
+            let corner = (0..3)
+                .find(|&corner| triangles[triangle].groups[corner] == Some(number))
+                .expect("Every triangle of a group has a corner in it");
+            let normal = vertices[indices[triangle * 3 + corner] as usize].normal;
+            let (tangent, bitangent) = (
+                project(triangles[triangle].tangent, normal),
+                project(triangles[triangle].bitangent, normal),
+            );
+            let mut members: Vec<usize> = group
+                .triangles
+                .iter()
+                .cloned()
+                .filter(|&other| {
+                    triangles[triangle].group_with_any
+                        || triangles[other].group_with_any
+                        || (tangent.dot(project(triangles[other].tangent, normal)) > SPLIT_COS
+                            && bitangent.dot(project(triangles[other].bitangent, normal))
+                                > SPLIT_COS)
+                })
+                .collect();
+            members.sort();
+
+            let space = match subgroups.iter().find(|(existing, _)| *existing == members) {
+                Some((_, space)) => *space,
+                None => {
+                    let space = average_tangent(
+                        vertices,
+                        indices,
+                        shared,
+                        triangles,
+                        &members,
+                        group.vertex,
+                    );
+                    subgroups.push((members, space));
+                    space
+                },
+            };
+            spaces[triangle * 3 + corner] = Some((space, group.preserves_orientation));
+        }
+    }
+
+    // Degenerate triangles take the tangent space of another corner of the same vertex
+    let mut found = HashMap::new();
+    for (corner, space) in spaces.iter().enumerate() {
+        if let Some(space) = space {
+            found.entry(shared[corner]).or_insert(*space);
+        }
+    }
+    for (corner, space) in spaces.iter_mut().enumerate() {
+        if triangles[corner / 3].degenerate {
+            *space = found.get(&shared[corner]).cloned();
+        }
+    }
+    spaces
+}
+
+/// The tangents of the triangles at `vertex` projected onto the plane of its normal, weighted by
+/// the angles of the triangles at the vertex
+fn average_tangent(
+    vertices: &[MeshVertex],
+    indices: &[u32],
+    shared: &[u32],
+    triangles: &[Triangle],
+    members: &[usize],
+    vertex: u32,
+) -> Vec3<f32> {
+    let mut sum = Vec3::zero();
+    for &triangle in members {
+        if triangles[triangle].group_with_any {
+            continue;
+        }
+        let corner = match (0..3).find(|&corner| shared[triangle * 3 + corner] == vertex) {
+            Some(corner) => corner,
+            None => continue,
+        };
+        let position = |corner: usize| vertices[indices[triangle * 3 + corner] as usize].position;
+        let normal = vertices[indices[triangle * 3 + corner] as usize].normal;
+        let previous = project(position((corner + 2) % 3) - position(corner), normal);
+        let next = project(position((corner + 1) % 3) - position(corner), normal);
+        let angle = previous.dot(next).max(-1.0).min(1.0).acos();
+        sum += project(triangles[triangle].tangent, normal) * angle;
+    }
+    normalized_or_zero(sum)
+}
+
+/// The direction of `vector` on the plane of `normal`
+fn project(vector: Vec3<f32>, normal: Vec3<f32>) -> Vec3<f32> {
+    normalized_or_zero(vector - normal * normal.dot(vector))
+}
+
+fn fallback(normal: Vec3<f32>) -> Vec4<f32> {
+    let tangent = perpendicular(normal);
+    Vec4::new(tangent.x, tangent.y, tangent.z, 1.0)
+}
+
+fn perpendicular(normal: Vec3<f32>) -> Vec3<f32> {
+    let axis = if normal.x.abs() < 0.9 {
+        Vec3::unit_x()
+    } else {
+        Vec3::unit_y()
+    };
+    let tangent = normalized_or_zero(axis - normal * normal.dot(axis));
+    if tangent == Vec3::zero() {
+        Vec3::unit_x()
+    } else {
+        tangent
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mesh::Mesh;
+    use crate::mesh::MeshVertex;
+    use crate::primitive::shapes;
+    use pretty_assertions::assert_eq;
+    use vek::Vec2;
+    use vek::Vec3;
+    use vek::Vec4;
+
+    #[test]
+    fn it_should_point_tangents_along_u() {
+        let mesh = Mesh::from(shapes::quad(2.0, 2.0)).with_tangents();
+        for vertex in mesh.vertices() {
+            assert_eq!(Vec4::new(1.0, 0.0, 0.0, 1.0), vertex.tangent);
+        }
+    }
+
+    #[test]
+    fn it_should_flip_the_sign_of_mirrored_uvs() {
+        let quad = Mesh::from(shapes::quad(2.0, 2.0));
+        let mirrored = quad
+            .vertices()
+            .iter()
+            .map(|vertex| MeshVertex {
+                uv: Vec2::new(1.0 - vertex.uv.x, vertex.uv.y),
+                ..*vertex
+            })
+            .collect();
+        let mesh = Mesh::new(mirrored, quad.indices().to_vec())
+            .unwrap()
+            .with_tangents();
+        for vertex in mesh.vertices() {
+            assert_eq!(Vec4::new(-1.0, 0.0, 0.0, -1.0), vertex.tangent);
+        }
+    }
+
+    #[test]
+    fn it_should_keep_tangents_at_right_angles_to_the_normals() {
+        let mesh = Mesh::from(shapes::torus(2.0, 0.5, 24, 12)).with_tangents();
+        for vertex in mesh.vertices() {
+            assert!(vertex.tangent.xyz().dot(vertex.normal).abs() < 1e-4);
+            assert!((vertex.tangent.xyz().magnitude() - 1.0).abs() < 1e-4);
+            assert_eq!(1.0, vertex.tangent.w.abs());
+        }
+    }
+
+    #[test]
+    fn it_should_split_vertices_shared_by_mirrored_triangles() {
+        let vertex = |x: f32, y: f32, u: f32, v: f32| MeshVertex {
+            position: Vec3::new(x, y, 0.0),
+            normal: Vec3::unit_z(),
+            uv: Vec2::new(u, v),
+            tangent: Vec4::zero(),
+        };
+        let vertices = vec![
+            vertex(0.0, 0.0, 0.0, 0.0),
+            vertex(1.0, 0.0, 1.0, 0.0),
+            vertex(1.0, 1.0, 1.0, 1.0),
+            vertex(0.0, 1.0, 2.0, 0.0),
+        ];
+        let mesh = Mesh::new(vertices, vec![0, 1, 2, 0, 2, 3])
+            .unwrap()
+            .with_tangents();
+
+        assert_eq!(6, mesh.vertices().len());
+        assert_eq!(&[0, 1, 2, 4, 5, 3], mesh.indices());
+        for &index in &mesh.indices()[..3] {
+            assert_eq!(1.0, mesh.vertices()[index as usize].tangent.w);
+        }
+        for &index in &mesh.indices()[3..] {
+            assert_eq!(-1.0, mesh.vertices()[index as usize].tangent.w);
+        }
+    }
+}
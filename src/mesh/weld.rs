@@ -0,0 +1,71 @@
+use crate::mesh::MeshVertex;
+use std::collections::HashMap;
+
+pub(crate) fn weld(vertices: &[MeshVertex], indices: &[u32], tolerance: f32) -> (Vec<MeshVertex>, Vec<u32>) {
+    let mut welded = vec![];
+    let mut numbers = HashMap::new();
+    let indices = indices
+        .iter()
+        .map(|index| {
+            let vertex = vertices[*index as usize];
+            *numbers.entry(key(&vertex, tolerance)).or_insert_with(|| {
+                welded.push(vertex);
+                welded.len() as u32 - 1
+            })
+        })
+        .collect();
+    (welded, indices)
+}
+
+/// Every attribute rounded to the grid of the tolerance, or its bits when there's no tolerance
+fn key(vertex: &MeshVertex, tolerance: f32) -> [i64; 12] {
+    let values = [
+        vertex.position.x,
+        vertex.position.y,
+        vertex.position.z,
+        vertex.normal.x,
+        vertex.normal.y,
+        vertex.normal.z,
+        vertex.uv.x,
+        vertex.uv.y,
+        vertex.tangent.x,
+        vertex.tangent.y,
+        vertex.tangent.z,
+        vertex.tangent.w,
+    ];
+    let mut key = [0; 12];
+    for (key, value) in key.iter_mut().zip(values.iter()) {
+        *key = if tolerance > 0.0 {
+            (value / tolerance).round() as i64
+        } else {
+            // Adding zero turns -0.0 into 0.0
+            i64::from((value + 0.0).to_bits())
+        };
+    }
+    key
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mesh::Mesh;
+    use crate::primitive::shapes;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn it_should_weld_flat_triangles_back_together() {
+        let sphere = Mesh::from(shapes::icosphere(1.0, 2));
+        let welded = sphere.clone().with_flat_normals().with_smooth_normals().welded(1e-4);
+        assert_eq!(sphere.vertices().len(), welded.vertices().len());
+        assert_eq!(sphere.triangle_count(), welded.triangle_count());
+    }
+
+    #[test]
+    fn it_should_keep_vertices_with_different_attributes() {
+        let cube = Mesh::from(shapes::cube(1.0));
+        assert_eq!(24, cube.clone().welded(1e-4).vertices().len());
+
+
+        let flat = Mesh::from(shapes::icosphere(1.0, 1)).with_flat_normals();
+        assert_eq!(240, flat.welded(1e-4).vertices().len());
+    }
+}
@@ -0,0 +1,155 @@
+use crate::allocator::DefaultChunk;
+use crate::allocator::DefaultGpuAllocator;
+use crate::allocator::GpuAllocator;
+use crate::graphics::Bundle;
+use crate::graphics::BundleEncoderExt;
+use crate::graphics::ComputePipeline;
+use crate::graphics::DirectionalLight;
+use crate::graphics::ShadowCasterPipeline;
+use crate::internal::Mat4Ext;
+use crate::primitive::Index;
+use crate::primitive::Vertex;
+use crate::setup_context::SetupContext;
+use failure::Error;
+use gfx_hal::command::ClearDepthStencil;
+use gfx_hal::command::ClearValue;
+use gfx_hal::command::CommandBuffer;
+use gfx_hal::command::MultiShot;
+use gfx_hal::command::Primary;
+use gfx_hal::command::RenderPassInlineEncoder;
+use gfx_hal::pso::Rect;
+use gfx_hal::pso::ShaderStageFlags;
+use gfx_hal::pso::Viewport;
+use gfx_hal::Backend;
+use gfx_hal::Device;
+use gfx_hal::General;
+use gfx_hal::Instance;
+use std::marker::PhantomData;
+use std::sync::Arc;
+use vek::Mat4;
+
+/// Passed to the prepare callback each frame, before the main render pass begins. This is where
+/// offscreen passes such as shadow maps are rendered
+pub struct PrepareContext<
+    'a,
+    A: GpuAllocator<B, D> = DefaultGpuAllocator<DefaultChunk<backend::Backend, backend::Device>, backend::Backend, backend::Device>,
+    B: Backend = backend::Backend,
+    D: Device<B> = backend::Device,
+    I: Instance<Backend = B> = backend::Instance,
+> {
+    setup_context: Arc<SetupContext<A, B, D, I>>,
+    command_buffer: &'a mut CommandBuffer<B, General, MultiShot, Primary>,
+    wait_for_frames_in_flight: &'a Fn() -> Result<(), Error>,
+}
+
+impl<'a, A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Instance<Backend = B>> PrepareContext<'a, A, B, D, I> {
+    pub(crate) fn new(
+        setup_context: Arc<SetupContext<A, B, D, I>>,
+        command_buffer: &'a mut CommandBuffer<B, General, MultiShot, Primary>,
+        wait_for_frames_in_flight: &'a Fn() -> Result<(), Error>,
+    ) -> Self {
+        Self {
+            setup_context,
+            command_buffer,
+            wait_for_frames_in_flight,
+        }
+    }
+
+    pub fn setup_context(&self) -> &SetupContext<A, B, D, I> {
+        &*self.setup_context
+    }
+
+    /// Records a dispatch of the compute pipeline into this frame. Everything drawn later in the
+    /// frame sees the results
+    pub fn dispatch(&mut self, pipeline: &ComputePipeline<A, B, D, I>, workgroups: [u32; 3], push_constants: &[u32]) {
+        pipeline.bundle().record(self.command_buffer, workgroups, push_constants);
+    }
+
+    /// Renders the shadow map of the light. Everything drawn with the encoder passed to the
+    /// callback casts shadows. This also uploads the current light data used by the lit pipelines
+    pub fn render_shadow_map<F: FnOnce(&mut ShadowEncoder<A, B, D, I>) -> Result<(), Error>>(
+        &mut self,
+        light: &mut DirectionalLight<A, B, D, I>,
+        callback: F,
+    ) -> Result<(), Error> {
+        // The frames in flight read the uniform, so they're only waited on when the light changed
+        if light.uniform_changed() {
+            (self.wait_for_frames_in_flight)()?;
+            light.write_uniform()?;
+        }
+
+        let light_space = light.light_space_matrix();
+        let shadow_map = light.shadow_map();
+        let extent = shadow_map.extent();
+        let rect = Rect {
+            x: 0,
+            y: 0,
+            w: extent.width as _,
+            h: extent.height as _,
+        };
+        let clear_values = [ClearValue::DepthStencil(ClearDepthStencil(1.0, 0))];
+
+        let mut encoder = unsafe {
+            self.command_buffer.begin_render_pass_inline(
+                shadow_map.render_pass(),
+                shadow_map.framebuffer(),
+                rect,
+                clear_values.iter(),
+            )
+        };
+        unsafe {
+            encoder.set_viewports(
+                0,
+                &[Viewport {
+                    rect,
+                    depth: 0.0..1.0,
+                }],
+            );
+            encoder.set_scissors(0, &[rect]);
+        }
+
+        let mut shadow_encoder = ShadowEncoder {
+            encoder,
+            light_space,
+            phantom: PhantomData,
+        };
+        callback(&mut shadow_encoder)
+    }
+}
+
+/// Draws shadow casters into the shadow map of a light
+pub struct ShadowEncoder<
+    'a,
+    A: GpuAllocator<B, D> = DefaultGpuAllocator<DefaultChunk<backend::Backend, backend::Device>, backend::Backend, backend::Device>,
+    B: Backend = backend::Backend,
+    D: Device<B> = backend::Device,
+    I: Instance<Backend = B> = backend::Instance,
+> {
+    encoder: RenderPassInlineEncoder<'a, B>,
+    light_space: Mat4<f32>,
+    phantom: PhantomData<fn() -> (A, D, I)>,
+}
+
+impl<'a, A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Instance<Backend = B>> ShadowEncoder<'a, A, B, D, I> {
+    pub fn draw<In: Index, V: Vertex>(
+        &mut self,
+        pipeline: &ShadowCasterPipeline<V, A, B, D, I>,
+        bundle: &Bundle<In, V, A, B, D, I>,
+    ) where
+        RenderPassInlineEncoder<'a, B>: BundleEncoderExt<In, V, A, B, D, I>,
+    {
+        let caster = pipeline.bundle();
+        unsafe {
+            self.encoder.bind_graphics_pipeline(caster.pipeline());
+        }
+        self.encoder.bind_bundle(bundle);
+
+        unsafe {
+            let mut mat = self.light_space;
+            let mat_data = mat.as_push_constant_data();
+            self.encoder
+                .push_graphics_constants(caster.layout(), ShaderStageFlags::VERTEX, 0, mat_data);
+            self.encoder.draw_indexed(0..bundle.index_count(), 0, 0..1)
+        }
+    }
+}
@@ -1,3 +1,5 @@
+pub mod shapes;
+
 mod index;
 mod vertex;
 mod vertex_xy;
@@ -6,6 +8,9 @@ mod vertex_xyz;
 mod vertex_xyz_rg;
 mod vertex_xyz_rgba_uv;
 
+#[doc(inline)]
+pub use self::vertex::ObjVertex;
+
 #[doc(inline)]
 pub use self::vertex::Vertex;
 
@@ -0,0 +1,550 @@
+//! Indexed geometry for common shapes. Every shape is centred on the origin and built around the y
+//! axis, and its triangles wind counter clockwise when seen from the side the normals point to.
+//! The uvs grow along x and y, matching the screen space of the renderer, where y points down.
+//!
+//! ```
+//! use starstruck::primitive::shapes;
+//! use starstruck::primitive::Vertex3DUV;
+//!
+//! let sphere = shapes::uv_sphere(1.0, 32, 16);
+//! let vertices: Vec<Vertex3DUV> = sphere.to_vertices();
+//! assert_eq!(vertices.len(), sphere.vertices().len());
+//! ```
+
+use crate::primitive::VertexXY;
+use crate::primitive::VertexXYRG;
+use crate::primitive::VertexXYZ;
+use crate::primitive::VertexXYZRG;
+use crate::primitive::VertexXYZRGBAUV;
+use std::collections::HashMap;
+use std::f32::consts::PI;
+use vek::Vec2;
+use vek::Vec3;
+
+/// A point on a generated shape
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ShapeVertex {
+    pub position: Vec3<f32>,
+    pub normal: Vec3<f32>,
+    pub uv: Vec2<f32>,
+}
+
+impl ShapeVertex {
+    pub fn new(position: Vec3<f32>, normal: Vec3<f32>, uv: Vec2<f32>) -> Self {
+        Self { position, normal, uv }
+    }
+}
+
+/// The vertices and triangle list indices of a generated shape
+#[derive(Debug, Clone, PartialEq)]
+pub struct Shape {
+    vertices: Vec<ShapeVertex>,
+    indices: Vec<u32>,
+}
+
+impl Shape {
+    pub fn vertices(&self) -> &[ShapeVertex] {
+        &self.vertices
+    }
+
+    pub fn indices(&self) -> &[u32] {
+        &self.indices
+    }
+
+    /// Converts the vertices into one of the vertex types, the attributes the type doesn't have are
+    /// left out
+    pub fn to_vertices<V: From<ShapeVertex>>(&self) -> Vec<V> {
+        self.vertices.iter().map(|vertex| V::from(*vertex)).collect()
+    }
+
+    /// The indices as `u16`, which is what bundles are usually created with
+    ///
+    /// # Panics
+    ///
+    /// Panics if the shape has more vertices than a `u16` can index
+    pub fn to_u16_indices(&self) -> Vec<u16> {
+        assert!(
+            self.vertices.len() <= usize::from(u16::max_value()) + 1,
+            "The shape has {} vertices, which is too many for u16 indices",
+            self.vertices.len()
+        );
+        self.indices.iter().map(|index| *index as u16).collect()
+    }
+
+    /// The number of triangles
+    pub fn triangle_count(&self) -> usize {
+        self.indices.len() / 3
+    }
+
+    fn new() -> Self {
+        Self {
+            vertices: vec![],
+            indices: vec![],
+        }
+    }
+
+    fn push(&mut self, position: Vec3<f32>, normal: Vec3<f32>, uv: Vec2<f32>) {
+        self.vertices.push(ShapeVertex::new(position, normal, uv));
+    }
+
+    /// Adds the triangles between rows of `columns` vertices, starting at vertex `first`. The
+    /// triangles touching the first or last row can be left out, where the row is a single point
+    fn push_grid(&mut self, first: u32, rows: u32, columns: u32, skip_first: bool, skip_last: bool) {
+        for row in 0..rows - 1 {
+            for column in 0..columns - 1 {
+                let a = first + row * columns + column;
+                let b = a + 1;
+                let c = a + columns;
+                let d = c + 1;
+                if !(skip_first && row == 0) {
+                    self.indices.extend_from_slice(&[a, c, b]);
+                }
+                if !(skip_last && row == rows - 2) {
+                    self.indices.extend_from_slice(&[b, c, d]);
+                }
+            }
+        }
+    }
+
+    /// Adds a flat disc facing up or down, made of a centre vertex and a fan around it
+    fn push_cap(&mut self, radius: f32, y: f32, segments: u32, up: bool) {
+        let normal = Vec3::new(0.0, if up { 1.0 } else { -1.0 }, 0.0);
+        let centre = self.vertices.len() as u32;
+        self.push(Vec3::new(0.0, y, 0.0), normal, Vec2::new(0.5, 0.5));
+        for (cos, sin) in circle(segments) {
+            self.push(
+                Vec3::new(radius * cos, y, radius * sin),
+                normal,
+                Vec2::new(cos * 0.5 + 0.5, sin * 0.5 + 0.5),
+            );
+        }
+        for segment in 0..segments {
+            let a = centre + 1 + segment;
+            let b = a + 1;
+            if up {
+                self.indices.extend_from_slice(&[centre, b, a]);
+            } else {
+                self.indices.extend_from_slice(&[centre, a, b]);
+            }
+        }
+    }
+}
+
+/// A rectangle in the xy plane facing +z
+pub fn quad(width: f32, height: f32) -> Shape {
+    let mut shape = Shape::new();
+    for &(x, y) in &[(-0.5f32, -0.5f32), (0.5, -0.5), (0.5, 0.5), (-0.5, 0.5)] {
+        shape.push(Vec3::new(x * width, y * height, 0.0), Vec3::unit_z(), Vec2::new(x + 0.5, y + 0.5));
+    }
+    shape.indices = vec![0, 1, 2, 2, 3, 0];
+    shape
+}
+
+/// A cube with flat faces, every face has uvs covering the whole texture
+pub fn cube(size: f32) -> Shape {
+    let half = size / 2.0;
+    // The normal, and the directions the u and v coordinates grow in
+    let faces = [
+        (Vec3::unit_x(), -Vec3::unit_z(), Vec3::unit_y()),
+        (-Vec3::unit_x(), Vec3::unit_z(), Vec3::unit_y()),
+        (Vec3::unit_y(), Vec3::unit_x(), -Vec3::unit_z()),
+        (-Vec3::unit_y(), Vec3::unit_x(), Vec3::unit_z()),
+        (Vec3::unit_z(), Vec3::unit_x(), Vec3::unit_y()),
+        (-Vec3::unit_z(), -Vec3::unit_x(), Vec3::unit_y()),
+    ];
+
+    let mut shape = Shape::new();
+    for &(normal, u, v) in &faces {
+        let first = shape.vertices.len() as u32;
+        for &(a, b) in &[(-1.0f32, -1.0f32), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)] {
+            let position = (normal + u * a + v * b) * half;
+            shape.push(position, normal, Vec2::new((a + 1.0) / 2.0, (b + 1.0) / 2.0));
+        }
+        shape.indices.extend_from_slice(&[first, first + 1, first + 2, first + 2, first + 3, first]);
+    }
+    shape
+}
+
+/// A sphere made of `rings` rows of `segments` quads, running from the pole at -y to the pole at
+/// +y. There are at least 3 segments and 2 rings
+pub fn uv_sphere(radius: f32, segments: u32, rings: u32) -> Shape {
+    let (segments, rings) = (segments.max(3), rings.max(2));
+    let mut shape = Shape::new();
+    for ring in 0..=rings {
+        let v = ring as f32 / rings as f32;
+        push_sphere_row(&mut shape, radius, v * PI, 0.0, segments, v);
+    }
+    shape.push_grid(0, rings + 1, segments + 1, true, true);
+    shape
+}
+
+/// A sphere made by subdividing the triangles of an icosahedron, which spreads the vertices more
+/// evenly than `uv_sphere`. Every subdivision splits each triangle into 4. The vertices are
+/// shared, so the uvs wrap around at the seam
+pub fn icosphere(radius: f32, subdivisions: u32) -> Shape {
+    let t = (1.0 + 5f32.sqrt()) / 2.0;
+    let mut points: Vec<Vec3<f32>> = [
+        (-1.0, t, 0.0),
+        (1.0, t, 0.0),
+        (-1.0, -t, 0.0),
+        (1.0, -t, 0.0),
+        (0.0, -1.0, t),
+        (0.0, 1.0, t),
+        (0.0, -1.0, -t),
+        (0.0, 1.0, -t),
+        (t, 0.0, -1.0),
+        (t, 0.0, 1.0),
+        (-t, 0.0, -1.0),
+        (-t, 0.0, 1.0),
+    ]
+    .iter()
+    .map(|(x, y, z)| Vec3::new(*x, *y, *z).normalized())
+    .collect();
+    #[rustfmt::skip]
+    let mut triangles: Vec<[u32; 3]> = vec![
+        [0, 11, 5], [0, 5, 1], [0, 1, 7], [0, 7, 10], [0, 10, 11],
+        [1, 5, 9], [5, 11, 4], [11, 10, 2], [10, 7, 6], [7, 1, 8],
+        [3, 9, 4], [3, 4, 2], [3, 2, 6], [3, 6, 8], [3, 8, 9],
+        [4, 9, 5], [2, 4, 11], [6, 2, 10], [8, 6, 7], [9, 8, 1],
+    ];
+
+    for _ in 0..subdivisions {
+        let mut midpoints = HashMap::new();
+        let mut midpoint = |a: u32, b: u32| {
+            *midpoints.entry((a.min(b), a.max(b))).or_insert_with(|| {
+                let point = (points[a as usize] + points[b as usize]).normalized();
+                points.push(point);
+                points.len() as u32 - 1
+            })
+        };
+        triangles = triangles
+            .iter()
+            .flat_map(|&[a, b, c]| {
+                let (ab, bc, ca) = (midpoint(a, b), midpoint(b, c), midpoint(c, a));
+                vec![[a, ab, ca], [b, bc, ab], [c, ca, bc], [ab, bc, ca]]
+            })
+            .collect();
+    }
+
+    let mut shape = Shape::new();
+    for point in points {
+        let u = point.z.atan2(point.x) / (2.0 * PI) + 0.5;
+        let v = point.y.max(-1.0).min(1.0).asin() / PI + 0.5;
+        shape.push(point * radius, point, Vec2::new(u, v));
+    }
+    shape.indices = triangles.iter().flat_map(|triangle| triangle.iter().cloned()).collect();
+    shape
+}
+
+/// A cylinder along the y axis with flat caps. There are at least 3 segments
+pub fn cylinder(radius: f32, height: f32, segments: u32) -> Shape {
+    let segments = segments.max(3);
+    let mut shape = Shape::new();
+    for (row, y) in [-height / 2.0, height / 2.0].iter().enumerate() {
+        for (segment, (cos, sin)) in circle(segments).enumerate() {
+            shape.push(
+                Vec3::new(radius * cos, *y, radius * sin),
+                Vec3::new(cos, 0.0, sin),
+                Vec2::new(segment as f32 / segments as f32, row as f32),
+            );
+        }
+    }
+    shape.push_grid(0, 2, segments + 1, false, false);
+    shape.push_cap(radius, height / 2.0, segments, true);
+    shape.push_cap(radius, -height / 2.0, segments, false);
+    shape
+}
+
+/// A cone along the y axis with its tip at +y and a flat base. The tip has a vertex for every
+/// segment, so that the side is smoothly shaded. There are at least 3 segments
+pub fn cone(radius: f32, height: f32, segments: u32) -> Shape {
+    let segments = segments.max(3);
+    let slope = radius / height;
+    let length = (1.0 + slope * slope).sqrt();
+    let mut shape = Shape::new();
+    for row in 0..2 {
+        for segment in 0..=segments {
+            // The tip uses the angle halfway through the segment below it
+            let turn = (segment as f32 + row as f32 * 0.5) / segments as f32;
+            let (sin, cos) = sin_cos(turn * 2.0 * PI);
+            let (ring_radius, y) = if row == 0 { (radius, -height / 2.0) } else { (0.0, height / 2.0) };
+            shape.push(
+                Vec3::new(ring_radius * cos, y, ring_radius * sin),
+                Vec3::new(cos / length, slope / length, sin / length),
+                Vec2::new(segment as f32 / segments as f32, row as f32),
+            );
+        }
+    }
+    let columns = segments + 1;
+    for segment in 0..segments {
+        shape.indices.extend_from_slice(&[segment, segment + columns, segment + 1]);
+    }
+    shape.push_cap(radius, -height / 2.0, segments, false);
+    shape
+}
+
+/// A torus lying in the xz plane. `radius` is the distance from the centre to the middle of the
+/// tube, `segments` go around the centre and `sides` around the tube. There are at least 3 of
+/// each
+pub fn torus(radius: f32, tube_radius: f32, segments: u32, sides: u32) -> Shape {
+    let (segments, sides) = (segments.max(3), sides.max(3));
+    let mut shape = Shape::new();
+    for segment in 0..=segments {
+        let u = segment as f32 / segments as f32;
+        let (sin_around, cos_around) = sin_cos(u * 2.0 * PI);
+        for side in 0..=sides {
+            let v = side as f32 / sides as f32;
+            let (sin_tube, cos_tube) = sin_cos(v * 2.0 * PI);
+            let normal = Vec3::new(cos_tube * cos_around, -sin_tube, cos_tube * sin_around);
+            let centre = Vec3::new(radius * cos_around, 0.0, radius * sin_around);
+            shape.push(centre + normal * tube_radius, normal, Vec2::new(u, v));
+        }
+    }
+    shape.push_grid(0, segments + 1, sides + 1, false, false);
+    shape
+}
+
+/// A flat grid in the xz plane facing +y, split into `subdivisions_x` by `subdivisions_z` quads.
+/// There is at least one quad along each axis
+pub fn plane(width: f32, depth: f32, subdivisions_x: u32, subdivisions_z: u32) -> Shape {
+    let (subdivisions_x, subdivisions_z) = (subdivisions_x.max(1), subdivisions_z.max(1));
+    let mut shape = Shape::new();
+    for row in 0..=subdivisions_z {
+        let v = row as f32 / subdivisions_z as f32;
+        for column in 0..=subdivisions_x {
+            let u = column as f32 / subdivisions_x as f32;
+            shape.push(Vec3::new((u - 0.5) * width, 0.0, (v - 0.5) * depth), Vec3::unit_y(), Vec2::new(u, v));
+        }
+    }
+    shape.push_grid(0, subdivisions_z + 1, subdivisions_x + 1, false, false);
+    shape
+}
+
+/// A cylinder along the y axis with half spheres for caps, `height` is the length of the
+/// cylinder between them. Each half sphere has `rings` rows. There are at least 3 segments and 1
+/// ring
+pub fn capsule(radius: f32, height: f32, segments: u32, rings: u32) -> Shape {
+    let (segments, rings) = (segments.max(3), rings.max(1));
+    let total = height + radius * 2.0;
+    let mut shape = Shape::new();
+    for &(first, offset) in &[(0.0, -height / 2.0), (PI / 2.0, height / 2.0)] {
+        for ring in 0..=rings {
+            let polar = first + PI / 2.0 * ring as f32 / rings as f32;
+            let y = offset - polar.cos() * radius;
+            push_sphere_row(&mut shape, radius, polar, offset, segments, (y + total / 2.0) / total);
+        }
+    }
+    shape.push_grid(0, rings * 2 + 2, segments + 1, true, true);
+    shape
+}
+
+/// Adds a row of a sphere at the polar angle, 0 being the pole at -y
+fn push_sphere_row(shape: &mut Shape, radius: f32, polar: f32, offset: f32, segments: u32, v: f32) {
+    let (sin_polar, cos_polar) = sin_cos(polar);
+    for (segment, (cos, sin)) in circle(segments).enumerate() {
+        let normal = Vec3::new(sin_polar * cos, -cos_polar, sin_polar * sin);
+        shape.push(
+            normal * radius + Vec3::new(0.0, offset, 0.0),
+            normal,
+            Vec2::new(segment as f32 / segments as f32, v),
+        );
+    }
+}
+
+/// The cosine and sine of `segments + 1` angles going once around the circle, the last one being
+/// the same as the first so that the uvs can wrap around
+fn circle(segments: u32) -> impl Iterator<Item = (f32, f32)> {
+    (0..=segments).map(move |segment| {
+        let (sin, cos) = sin_cos((segment % segments) as f32 / segments as f32 * 2.0 * PI);
+        (cos, sin)
+    })
+}
+
+/// `f32::sin_cos` without the rounding error around zero, so that vertices that should be at the
+/// same position, like the ones along a seam or at a pole, are at exactly the same position
+fn sin_cos(angle: f32) -> (f32, f32) {
+    let snap = |value: f32| if value.abs() < 1e-6 { 0.0 } else { value };
+    let (sin, cos) = angle.sin_cos();
+    (snap(sin), snap(cos))
+}
+
+impl From<ShapeVertex> for VertexXY {
+    fn from(vertex: ShapeVertex) -> Self {
+        Self {
+            x: vertex.position.x,
+            y: vertex.position.y,
+        }
+    }
+}
+
+impl From<ShapeVertex> for VertexXYRG {
+    fn from(vertex: ShapeVertex) -> Self {
+        Self {
+            x: vertex.position.x,
+            y: vertex.position.y,
+            r: vertex.uv.x,
+            g: vertex.uv.y,
+        }
+    }
+}
+
+impl From<ShapeVertex> for VertexXYZ {
+    fn from(vertex: ShapeVertex) -> Self {
+        Self {
+            x: vertex.position.x,
+            y: vertex.position.y,
+            z: vertex.position.z,
+        }
+    }
+}
+
+impl From<ShapeVertex> for VertexXYZRG {
+    fn from(vertex: ShapeVertex) -> Self {
+        Self {
+            x: vertex.position.x,
+            y: vertex.position.y,
+            z: vertex.position.z,
+            r: vertex.uv.x,
+            g: vertex.uv.y,
+        }
+    }
+}
+
+/// The color is white
+impl From<ShapeVertex> for VertexXYZRGBAUV {
+    fn from(vertex: ShapeVertex) -> Self {
+        Self {
+            x: vertex.position.x,
+            y: vertex.position.y,
+            z: vertex.position.z,
+            r: 1.0,
+            g: 1.0,
+            b: 1.0,
+            a: 1.0,
+            u: vertex.uv.x,
+            v: vertex.uv.y,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    /// Every triangle should have an area and face the way its vertex normals point
+    fn assert_wound_outwards(shape: &Shape) {
+        for triangle in shape.indices().chunks(3) {
+            let [a, b, c] = [
+                shape.vertices()[triangle[0] as usize],
+                shape.vertices()[triangle[1] as usize],
+                shape.vertices()[triangle[2] as usize],
+            ];
+            let face = (b.position - a.position).cross(c.position - a.position);
+            assert!(face.magnitude_squared() > 1e-12, "Triangle {:?} is degenerate", triangle);
+            let normal = a.normal + b.normal + c.normal;
+            assert!(face.dot(normal) > 0.0, "Triangle {:?} is wound the wrong way", triangle);
+        }
+    }
+
+    fn assert_unit_normals(shape: &Shape) {
+        for vertex in shape.vertices() {
+            assert!((vertex.normal.magnitude() - 1.0).abs() < 1e-4, "Normal {:?} isn't unit length", vertex.normal);
+        }
+    }
+
+    fn counts(shape: &Shape) -> (usize, usize) {
+        (shape.vertices().len(), shape.indices().len())
+    }
+
+    #[test]
+    fn it_should_generate_quads() {
+        let shape = quad(2.0, 1.0);
+        assert_eq!((4, 6), counts(&shape));
+        assert_eq!(Vec2::new(0.0, 0.0), shape.vertices()[0].uv);
+        assert_eq!(Vec3::new(-1.0, -0.5, 0.0), shape.vertices()[0].position);
+        assert_wound_outwards(&shape);
+    }
+
+    #[test]
+    fn it_should_generate_cubes() {
+        let shape = cube(2.0);
+        assert_eq!((24, 36), counts(&shape));
+        assert!(shape.vertices().iter().all(|vertex| vertex.position.map(f32::abs) == Vec3::broadcast(1.0)));
+        assert_wound_outwards(&shape);
+        assert_unit_normals(&shape);
+    }
+
+    #[test]
+    fn it_should_generate_uv_spheres() {
+        let shape = uv_sphere(2.0, 16, 8);
+        assert_eq!((9 * 17, 6 * 16 * 7), counts(&shape));
+        assert!(shape.vertices().iter().all(|vertex| (vertex.position.magnitude() - 2.0).abs() < 1e-4));
+        assert_eq!(shape.vertices()[17 * 4].position, shape.vertices()[17 * 4 + 16].position);
+        assert_wound_outwards(&shape);
+        assert_unit_normals(&shape);
+    }
+
+    #[test]
+    fn it_should_generate_icospheres() {
+        assert_eq!((12, 60), counts(&icosphere(1.0, 0)));
+
+        let shape = icosphere(1.0, 2);
+        assert_eq!((162, 960), counts(&shape));
+        assert_wound_outwards(&shape);
+        assert_unit_normals(&shape);
+    }
+
+    #[test]
+    fn it_should_generate_cylinders() {
+        let shape = cylinder(1.0, 2.0, 8);
+        assert_eq!((2 * 9 + 2 * 10, 12 * 8), counts(&shape));
+        assert_wound_outwards(&shape);
+        assert_unit_normals(&shape);
+    }
+
+    #[test]
+    fn it_should_generate_cones() {
+        let shape = cone(1.0, 2.0, 8);
+        assert_eq!((2 * 9 + 10, 6 * 8), counts(&shape));
+        assert_wound_outwards(&shape);
+        assert_unit_normals(&shape);
+    }
+
+    #[test]
+    fn it_should_generate_tori() {
+        let shape = torus(2.0, 0.5, 8, 6);
+        assert_eq!((9 * 7, 6 * 8 * 6), counts(&shape));
+        assert_wound_outwards(&shape);
+        assert_unit_normals(&shape);
+    }
+
+    #[test]
+    fn it_should_generate_subdivided_planes() {
+        let shape = plane(4.0, 2.0, 3, 2);
+        assert_eq!((4 * 3, 6 * 3 * 2), counts(&shape));
+        assert_eq!(Vec3::new(2.0, 0.0, 1.0), shape.vertices()[11].position);
+        assert_wound_outwards(&shape);
+    }
+
+    #[test]
+    fn it_should_generate_capsules() {
+        let shape = capsule(0.5, 1.0, 8, 4);
+        assert_eq!((2 * 5 * 9, 12 * 8 * 4), counts(&shape));
+        let (bottom, top) = shape
+            .vertices()
+            .iter()
+            .fold((0.0f32, 0.0f32), |(bottom, top), vertex| (bottom.min(vertex.position.y), top.max(vertex.position.y)));
+        assert!((bottom + 1.0).abs() < 1e-4 && (top - 1.0).abs() < 1e-4);
+        assert_wound_outwards(&shape);
+        assert_unit_normals(&shape);
+    }
+
+    #[test]
+    fn it_should_convert_into_vertex_types() {
+        let shape = quad(2.0, 2.0);
+        let vertices: Vec<VertexXYZRG> = shape.to_vertices();
+        assert_eq!((1.0, 1.0, 1.0, 1.0), (vertices[2].x, vertices[2].y, vertices[2].r, vertices[2].g));
+        assert_eq!(vec![0, 1, 2, 2, 3, 0], shape.to_u16_indices());
+    }
+}
@@ -1,3 +1,4 @@
+use failure::Error;
 use gfx_hal::pso::AttributeDesc;
 use std::fmt::Debug;
 
@@ -12,3 +13,10 @@ pub trait Vertex: Debug + Copy + Send + Sync {
     /// Attributes contains some additional info sent to the GPU.
     fn attributes() -> Vec<AttributeDesc>;
 }
+
+/// A vertex that can be read from the first group of an obj file. Used both when loading meshes
+/// at runtime and when packing them ahead of time, see `asset::PackedMesh`
+pub trait ObjVertex: Vertex {
+    /// Reads the indices and vertices of the obj file
+    fn from_obj(data: &[u8]) -> Result<(Vec<u16>, Vec<Self>), Error>;
+}
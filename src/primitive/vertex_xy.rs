@@ -14,6 +14,7 @@ use gfx_hal::Device;
 use gfx_hal::Instance;
 use std::mem::size_of;
 use crate::allocator::GpuAllocator;
+use std::borrow::Cow;
 
 /// A vertex with two floats. This is often used to represent a 2D position
 ///
@@ -63,17 +64,19 @@ impl<A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Instance<Backend = B>>
     ) -> Box<Future<Item = Pipeline<VertexXY, A, B, D, I>, Error = Error> + Send> {
         let set = ShaderSet {
             vertex: ShaderDescription {
-                spirv: include_bytes!(concat!(env!("OUT_DIR"), "/vertex_xy_default.vert.spv")),
+                spirv: Cow::Borrowed(include_bytes!(concat!(env!("OUT_DIR"), "/vertex_xy_default.vert.spv"))),
                 push_constant_floats: 16,
                 bindings: vec![],
+                source_path: None,
             },
             hull: None,
             domain: None,
             geometry: None,
             fragment: Some(ShaderDescription {
-                spirv: include_bytes!(concat!(env!("OUT_DIR"), "/vertex_xy_default.frag.spv")),
+                spirv: Cow::Borrowed(include_bytes!(concat!(env!("OUT_DIR"), "/vertex_xy_default.frag.spv"))),
                 push_constant_floats: 0,
                 bindings: vec![],
+                source_path: None,
             }),
         };
 
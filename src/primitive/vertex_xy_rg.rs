@@ -15,6 +15,7 @@ use gfx_hal::Device;
 use gfx_hal::Instance;
 use std::mem::size_of;
 use crate::allocator::GpuAllocator;
+use std::borrow::Cow;
 
 #[derive(Debug, Clone, Copy, Default)]
 pub struct VertexXYRG {
@@ -63,20 +64,22 @@ CreateTexturedPipeline<VertexXYRG, A, B, D, I> for SetupContext<A, B, D, I>
     ) -> Box<Future<Item = Pipeline<VertexXYRG, A, B, D, I>, Error = Error> + Send> {
         let set = ShaderSet {
             vertex: ShaderDescription {
-                spirv: include_bytes!(concat!(env!("OUT_DIR"), "/vertex_xy_rg_textured.vert.spv")),
+                spirv: Cow::Borrowed(include_bytes!(concat!(env!("OUT_DIR"), "/vertex_xy_rg_textured.vert.spv"))),
                 push_constant_floats: 16,
                 bindings: vec![],
+                source_path: None,
             },
             hull: None,
             domain: None,
             geometry: None,
             fragment: Some(ShaderDescription {
-                spirv: include_bytes!(concat!(env!("OUT_DIR"), "/vertex_xy_rg_textured.frag.spv")),
+                spirv: Cow::Borrowed(include_bytes!(concat!(env!("OUT_DIR"), "/vertex_xy_rg_textured.frag.spv"))),
                 push_constant_floats: 0,
                 bindings: vec![
                     (0, DescriptorType::SampledImage, 1),
                     (1, DescriptorType::Sampler, 1),
                 ],
+                source_path: None,
             }),
         };
 
@@ -2,14 +2,17 @@ use crate::graphics::Bundle;
 use crate::graphics::Pipeline;
 use crate::graphics::ShaderDescription;
 use crate::graphics::ShaderSet;
+use crate::primitive::ObjVertex;
 use crate::primitive::Vertex;
 use crate::setup_context::CreateBundleFromObj;
 use crate::setup_context::CreateDefaultPipeline;
+use crate::setup_context::CreateLitPipeline;
 use crate::setup_context::SetupContext;
 use failure::Error;
 use futures::Future;
 use gfx_hal::format::Format;
 use gfx_hal::pso::AttributeDesc;
+use gfx_hal::pso::DescriptorType;
 use gfx_hal::pso::Element;
 use gfx_hal::Backend;
 use gfx_hal::Device;
@@ -21,6 +24,9 @@ use std::mem::size_of;
 use std::mem::transmute;
 use std::sync::Arc;
 use crate::allocator::GpuAllocator;
+use crate::errors::AssetError;
+use crate::errors::MeshError;
+use std::borrow::Cow;
 
 #[derive(Debug, Default, Clone, Copy)]
 pub struct VertexXYZ {
@@ -60,17 +66,19 @@ impl<A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Instance<Backend = B>>
     ) -> Box<Future<Item = Pipeline<VertexXYZ, A, B, D, I>, Error = Error> + Send> {
         let set = ShaderSet {
             vertex: ShaderDescription {
-                spirv: include_bytes!(concat!(env!("OUT_DIR"), "/vertex_xyz_default.vert.spv")),
+                spirv: Cow::Borrowed(include_bytes!(concat!(env!("OUT_DIR"), "/vertex_xyz_default.vert.spv"))),
                 push_constant_floats: 16,
                 bindings: vec![],
+                source_path: None,
             },
             hull: None,
             domain: None,
             geometry: None,
             fragment: Some(ShaderDescription {
-                spirv: include_bytes!(concat!(env!("OUT_DIR"), "/vertex_xyz_default.frag.spv")),
+                spirv: Cow::Borrowed(include_bytes!(concat!(env!("OUT_DIR"), "/vertex_xyz_default.frag.spv"))),
                 push_constant_floats: 0,
                 bindings: vec![],
+                source_path: None,
             }),
         };
 
@@ -78,6 +86,71 @@ impl<A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Instance<Backend = B>>
     }
 }
 
+impl<A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Instance<Backend = B>> CreateLitPipeline<VertexXYZ, A, B, D, I>
+    for SetupContext<A, B, D, I>
+{
+    #[allow(clippy::type_complexity)]
+    fn create_lit_pipeline(
+        &self,
+    ) -> Box<Future<Item = Pipeline<VertexXYZ, A, B, D, I>, Error = Error> + Send> {
+        let set = ShaderSet {
+            vertex: ShaderDescription {
+                spirv: Cow::Borrowed(include_bytes!(concat!(env!("OUT_DIR"), "/vertex_xyz_lit.vert.spv"))),
+                push_constant_floats: 16,
+                bindings: vec![(2, DescriptorType::UniformBuffer, 1)],
+                source_path: None,
+            },
+            hull: None,
+            domain: None,
+            geometry: None,
+            fragment: Some(ShaderDescription {
+                spirv: Cow::Borrowed(include_bytes!(concat!(env!("OUT_DIR"), "/vertex_xyz_lit.frag.spv"))),
+                push_constant_floats: 0,
+                bindings: vec![
+                    (0, DescriptorType::SampledImage, 1),
+                    (1, DescriptorType::Sampler, 1),
+                ],
+                source_path: None,
+            }),
+        };
+
+        Box::new(self.create_pipeline(set))
+    }
+}
+
+impl ObjVertex for VertexXYZ {
+    fn from_obj(data: &[u8]) -> Result<(Vec<u16>, Vec<Self>), Error> {
+        let mut reader = BufReader::new(data);
+        let obj_data: Obj<SimplePolygon> = Obj::load_buf(&mut reader)?;
+        let vertex_count = obj_data.position.len();
+        if vertex_count > u16::max_value() as usize + 1 {
+            Err(MeshError::TooManyVertices {
+                count: vertex_count,
+                max: u16::max_value() as usize + 1,
+            })?;
+        }
+
+        let mut indexes: Vec<u16> = vec![];
+        let polys = obj_data.objects.iter().flat_map(|object| &object.groups).flat_map(|group| &group.polys);
+        for poly in polys {
+            for corner in 1..poly.len().saturating_sub(1) {
+                for tuple in &[&poly[0], &poly[corner], &poly[corner + 1]] {
+                    if tuple.0 >= vertex_count {
+                        Err(MeshError::MissingObjData("position"))?;
+                    }
+                    indexes.push(tuple.0 as u16);
+                }
+            }
+        }
+        if indexes.is_empty() {
+            Err(AssetError::Empty("mesh"))?;
+        }
+
+        let vertices: Vec<VertexXYZ> = unsafe { transmute(obj_data.position) };
+        Ok((indexes, vertices))
+    }
+}
+
 impl<A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Instance<Backend = B>>
     CreateBundleFromObj<u16, VertexXYZ, A, B, D, I> for SetupContext<A, B, D, I>
 {
@@ -87,23 +160,9 @@ impl<A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Instance<Backend = B>>
         &self,
         data: &[u8],
     ) -> Box<Future<Item = Bundle<u16, VertexXYZ, A, B, D, I>, Error = Error> + Send> {
-        let mut reader = BufReader::new(data);
-        match Obj::load_buf(&mut reader) {
-            Ok(obj_data) => {
-                let vertices: Vec<VertexXYZ> = unsafe { transmute(obj_data.position) };
-                let indexes: Vec<u16> = obj_data.objects[0].groups[0]
-                    .polys
-                    .clone()
-                    .into_iter()
-                    .flat_map(|poly: SimplePolygon| {
-                        let array: Vec<u16> = poly.into_iter().map(|p| p.0 as _).collect();
-                        array
-                    })
-                    .collect();
-
-                Box::new(self.create_bundle_owned(Arc::new(indexes), Arc::new(vertices)))
-            }
-            Err(err) => Box::new(futures::done(Err(err.into()))),
+        match VertexXYZ::from_obj(data) {
+            Ok((indexes, vertices)) => Box::new(self.create_bundle_owned(Arc::new(indexes), Arc::new(vertices))),
+            Err(err) => Box::new(futures::done(Err(err))),
         }
     }
 }
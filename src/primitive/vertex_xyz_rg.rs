@@ -2,6 +2,7 @@ use crate::graphics::Bundle;
 use crate::graphics::Pipeline;
 use crate::graphics::ShaderDescription;
 use crate::graphics::ShaderSet;
+use crate::primitive::ObjVertex;
 use crate::primitive::Vertex;
 use crate::setup_context::CreateBundleFromObj;
 use crate::setup_context::CreateTexturedPipeline;
@@ -21,6 +22,9 @@ use std::io::BufReader;
 use std::mem::size_of;
 use std::sync::Arc;
 use crate::allocator::GpuAllocator;
+use crate::errors::AssetError;
+use crate::errors::MeshError;
+use std::borrow::Cow;
 
 #[derive(Debug, Clone, Copy, Default)]
 pub struct VertexXYZRG {
@@ -70,20 +74,22 @@ impl<A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Instance<Backend = B>>
     ) -> Box<Future<Item = Pipeline<VertexXYZRG, A, B, D, I>, Error = Error> + Send> {
         let set = ShaderSet {
             vertex: ShaderDescription {
-                spirv: include_bytes!(concat!(env!("OUT_DIR"), "/vertex_xyz_rg_textured.vert.spv")),
+                spirv: Cow::Borrowed(include_bytes!(concat!(env!("OUT_DIR"), "/vertex_xyz_rg_textured.vert.spv"))),
                 push_constant_floats: 16,
                 bindings: vec![],
+                source_path: None,
             },
             hull: None,
             domain: None,
             geometry: None,
             fragment: Some(ShaderDescription {
-                spirv: include_bytes!(concat!(env!("OUT_DIR"), "/vertex_xyz_rg_textured.frag.spv")),
+                spirv: Cow::Borrowed(include_bytes!(concat!(env!("OUT_DIR"), "/vertex_xyz_rg_textured.frag.spv"))),
                 push_constant_floats: 0,
                 bindings: vec![
                     (0, DescriptorType::SampledImage, 1),
                     (1, DescriptorType::Sampler, 1),
                 ],
+                source_path: None,
             }),
         };
 
@@ -91,6 +97,47 @@ impl<A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Instance<Backend = B>>
     }
 }
 
+impl ObjVertex for VertexXYZRG {
+    fn from_obj(data: &[u8]) -> Result<(Vec<u16>, Vec<Self>), Error> {
+        let mut reader = BufReader::new(data);
+        let obj_data: Obj<SimplePolygon> = Obj::load_buf(&mut reader)?;
+        let mut vertices: Vec<VertexXYZRG> = Vec::with_capacity(obj_data.position.len() * 2);
+
+        let polys = obj_data.objects.iter().flat_map(|object| &object.groups).flat_map(|group| &group.polys);
+        for poly in polys {
+            for corner in 1..poly.len().saturating_sub(1) {
+                for tuple in &[&poly[0], &poly[corner], &poly[corner + 1]] {
+                    let position = obj_data.position.get(tuple.0).ok_or(MeshError::MissingObjData("position"))?;
+                    let uv = tuple
+                        .1
+                        .and_then(|uv| obj_data.texture.get(uv))
+                        .ok_or(MeshError::MissingObjData("uv"))?;
+                    vertices.push(Vertex3DUV {
+                        x: position[0],
+                        y: position[1],
+                        z: position[2],
+                        r: uv[0],
+                        g: uv[1],
+                    });
+                }
+            }
+        }
+
+        if vertices.is_empty() {
+            Err(AssetError::Empty("mesh"))?;
+        }
+        if vertices.len() > u16::max_value() as usize + 1 {
+            Err(MeshError::TooManyVertices {
+                count: vertices.len(),
+                max: u16::max_value() as usize + 1,
+            })?;
+        }
+        let indexes = (0..vertices.len()).map(|index| index as u16).collect();
+
+        Ok((indexes, vertices))
+    }
+}
+
 impl<A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Instance<Backend = B>>
     CreateBundleFromObj<u16, VertexXYZRG, A, B, D, I> for SetupContext<A, B, D, I>
 {
@@ -99,49 +146,51 @@ impl<A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Instance<Backend = B>>
         &self,
         data: &[u8],
     ) -> Box<Future<Item = Bundle<u16, VertexXYZRG, A, B, D, I>, Error = Error> + Send> {
-        let mut reader = BufReader::new(data);
-        match Obj::load_buf(&mut reader) {
-            Ok(obj_data) => {
-                let mut vertices: Vec<VertexXYZRG> =
-                    Vec::with_capacity(obj_data.position.len() * 2);
-                let mut indexes: Vec<u16> =
-                    Vec::with_capacity(obj_data.objects[0].groups[0].polys.len());
-
-                let polys: &[SimplePolygon] = &obj_data.objects[0].groups[0].polys;
-                let mut i = 0;
-                for poly in polys {
-                    indexes.push(i);
-                    i += 1;
-                    vertices.push(Vertex3DUV {
-                        x: obj_data.position[poly[0].0][0],
-                        y: obj_data.position[poly[0].0][1],
-                        z: obj_data.position[poly[0].0][2],
-                        r: obj_data.texture[poly[0].1.unwrap()][0],
-                        g: obj_data.texture[poly[0].1.unwrap()][1],
-                    });
-                    indexes.push(i);
-                    i += 1;
-                    vertices.push(Vertex3DUV {
-                        x: obj_data.position[poly[1].0][0],
-                        y: obj_data.position[poly[1].0][1],
-                        z: obj_data.position[poly[1].0][2],
-                        r: obj_data.texture[poly[1].1.unwrap()][0],
-                        g: obj_data.texture[poly[1].1.unwrap()][1],
-                    });
-                    indexes.push(i);
-                    i += 1;
-                    vertices.push(Vertex3DUV {
-                        x: obj_data.position[poly[2].0][0],
-                        y: obj_data.position[poly[2].0][1],
-                        z: obj_data.position[poly[2].0][2],
-                        r: obj_data.texture[poly[2].1.unwrap()][0],
-                        g: obj_data.texture[poly[2].1.unwrap()][1],
-                    })
-                }
+        match VertexXYZRG::from_obj(data) {
+            Ok((indexes, vertices)) => Box::new(self.create_bundle_owned(Arc::new(indexes), Arc::new(vertices))),
+            Err(err) => Box::new(futures::done(Err(err))),
+        }
+    }
+}
 
-                Box::new(self.create_bundle_owned(Arc::new(indexes), Arc::new(vertices)))
-            }
-            Err(err) => Box::new(futures::done(Err(err.into()))),
+#[cfg(test)]
+mod tests {
+    use crate::errors::AssetError;
+    use crate::errors::MeshError;
+    use crate::primitive::ObjVertex;
+    use crate::primitive::VertexXYZRG;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn it_should_triangulate_obj_files() {
+        let obj = b"v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nvt 0 0\nvt 1 1\nf 1/1 2/2 3/2 4/1\n";
+        let (indexes, vertices) = VertexXYZRG::from_obj(obj).unwrap();
+        assert_eq!(vec![0, 1, 2, 3, 4, 5], indexes);
+        assert_eq!((1.0, 1.0, 1.0), (vertices[2].x, vertices[2].y, vertices[2].r));
+    }
+
+    #[test]
+    fn it_should_refuse_obj_files_it_cant_read_without_panicking() {
+        let no_uvs = VertexXYZRG::from_obj(b"v 0 0 0\nv 1 0 0\nv 1 1 0\nf 1 2 3\n").unwrap_err();
+        assert_eq!(Some(&MeshError::MissingObjData("uv")), no_uvs.downcast_ref::<MeshError>());
+
+        let no_faces = VertexXYZRG::from_obj(b"v 0 0 0\n").unwrap_err();
+        assert_eq!(
+            "A mesh needs at least one element",
+            format!("{}", no_faces.downcast_ref::<AssetError>().unwrap())
+        );
+    }
+
+    #[test]
+    fn it_should_refuse_obj_files_with_more_vertices_than_u16_indices_can_point_at() {
+        let mut obj = b"v 0 0 0\nvt 0 0\n".to_vec();
+        for _ in 0..21846 {
+            obj.extend_from_slice(b"f 1/1 1/1 1/1\n");
         }
+        let error = VertexXYZRG::from_obj(&obj).unwrap_err();
+        assert_eq!(
+            Some(&MeshError::TooManyVertices { count: 65538, max: 65536 }),
+            error.downcast_ref::<MeshError>()
+        );
     }
 }
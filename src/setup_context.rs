@@ -1,15 +1,31 @@
+use crate::asset::AssetServer;
+use crate::asset::AssetSource;
+use crate::asset::PackedMesh;
 use crate::graphics::Bundle;
+use crate::graphics::ComputePipeline;
+use crate::graphics::DirectionalLight;
 use crate::graphics::Pipeline;
+use crate::errors::AssetError;
+use crate::allocator::HeapStats;
+use crate::allocator::LiveAllocation;
+use crate::graphics::ShaderDescription;
 use crate::graphics::ShaderSet;
+use crate::graphics::ShadowCasterPipeline;
+use crate::graphics::ShadowConfig;
+use crate::graphics::StorageBuffer;
 use crate::graphics::Texture;
 use crate::internal::graphics::GraphicsState;
+use crate::mesh::Mesh;
 use crate::primitive::Index;
+use crate::primitive::shapes::Shape;
+use crate::primitive::shapes::ShapeVertex;
 use crate::primitive::Vertex;
 use failure::Error;
 use futures::Future;
 use gfx_hal::Backend;
 use gfx_hal::Device;
 use gfx_hal::Instance;
+use std::path::Path;
 use std::sync::Arc;
 use crate::allocator::GpuAllocator;
 use crate::allocator::DefaultGpuAllocator;
@@ -20,6 +36,8 @@ use image::load_from_memory;
 use crate::graphics::Single;
 use crate::graphics::Rgba8Srgb;
 use futures::lazy;
+use vek::Rgb;
+use vek::Vec3;
 
 #[allow(clippy::type_complexity)]
 pub struct SetupContext<
@@ -28,16 +46,23 @@ pub struct SetupContext<
     D: Device<B> = backend::Device,
     I: Instance<Backend = B> = backend::Instance,
 > {
-    state: Arc<GraphicsState<A, B, D, I>>
+    state: Arc<GraphicsState<A, B, D, I>>,
+    assets: AssetServer<A, B, D, I>,
 }
 
 impl<A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Instance<Backend = B>> SetupContext<A, B, D, I> {
-    pub(crate) fn new(state: Arc<GraphicsState<A, B, D, I>>) -> Self {
+    pub(crate) fn new(state: Arc<GraphicsState<A, B, D, I>>, assets: AssetSource) -> Self {
         Self {
-            state
+            assets: AssetServer::new(Arc::clone(&state), assets),
+            state,
         }
     }
 
+    /// Loads textures, meshes, shaders and fonts from the asset root in the background
+    pub fn assets(&self) -> &AssetServer<A, B, D, I> {
+        &self.assets
+    }
+
     pub fn create_bundle<In: Index, V: Vertex>(
         &self,
         indexes: &'static [In],
@@ -47,15 +72,60 @@ impl<A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Instance<Backend = B>>
             Arc::clone(&self.state),
             Arc::new(Vec::from(indexes)),
             Arc::new(Vec::from(vertexes)),
+            false,
         )
     }
 
+    /// Like `create_bundle`, but keeps a copy of the data so the bundle can be recreated after the
+    /// device was lost, see `Recreatable`
+    pub fn create_recreatable_bundle<In: Index, V: Vertex>(
+        &self,
+        indexes: Vec<In>,
+        vertexes: Vec<V>,
+    ) -> impl Future<Item = Bundle<In, V, A, B, D, I>, Error = Error> + Send {
+        Bundle::new(Arc::clone(&self.state), Arc::new(indexes), Arc::new(vertexes), true)
+    }
+
     pub(crate) fn create_bundle_owned<In: Index, V: Vertex>(
         &self,
         indexes: Arc<Vec<In>>,
         vertexes: Arc<Vec<V>>,
     ) -> impl Future<Item = Bundle<In, V, A, B, D, I>, Error = Error> + Send {
-        Bundle::new(Arc::clone(&self.state), indexes, vertexes)
+        Bundle::new(Arc::clone(&self.state), indexes, vertexes, false)
+    }
+
+    /// Uploads a mesh once it has been processed, see `Mesh`
+    pub fn create_bundle_from_mesh<V: Vertex + From<ShapeVertex>>(
+        &self,
+        mesh: &Mesh,
+    ) -> impl Future<Item = Bundle<u16, V, A, B, D, I>, Error = Error> + Send {
+        self.create_bundle_owned(Arc::new(mesh.to_u16_indices()), Arc::new(mesh.to_vertices()))
+    }
+
+    /// Uploads a generated shape, see `primitive::shapes`
+    pub fn create_bundle_from_shape<V: Vertex + From<ShapeVertex>>(
+        &self,
+        shape: &Shape,
+    ) -> impl Future<Item = Bundle<u16, V, A, B, D, I>, Error = Error> + Send {
+        self.create_bundle_owned(Arc::new(shape.to_u16_indices()), Arc::new(shape.to_vertices()))
+    }
+
+    /// Memory maps a mesh saved in the binary mesh format and uploads it straight from the mapping,
+    /// see `PackedMesh` and `Bundle::pack`
+    pub fn create_bundle_from_mesh_file<In: Index, V: Vertex, P: AsRef<Path>>(
+        &self,
+        path: P,
+    ) -> impl Future<Item = Bundle<In, V, A, B, D, I>, Error = Error> + Send {
+        let state = Arc::clone(&self.state);
+        let path = path.as_ref().to_path_buf();
+        lazy(move || PackedMesh::open(path)).and_then(move |mesh| Bundle::from_packed(state, mesh))
+    }
+
+    pub(crate) fn create_bundle_from_packed<In: Index, V: Vertex, M: AsRef<[u8]> + Send + Sync + 'static>(
+        &self,
+        mesh: PackedMesh<M>,
+    ) -> impl Future<Item = Bundle<In, V, A, B, D, I>, Error = Error> + Send {
+        Bundle::from_packed(Arc::clone(&self.state), mesh)
     }
 
     pub fn create_pipeline<V: 'static + Vertex>(
@@ -65,26 +135,99 @@ impl<A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Instance<Backend = B>>
         Pipeline::new(Arc::clone(&self.state), shader_set)
     }
 
+    pub fn create_compute_pipeline(
+        &self,
+        description: ShaderDescription,
+    ) -> impl Future<Item = ComputePipeline<A, B, D, I>, Error = Error> + Send {
+        ComputePipeline::new(Arc::clone(&self.state), description)
+    }
+
+    pub fn create_storage_buffer<T: 'static + Copy + Send + Sync>(
+        &self,
+        data: Vec<T>,
+    ) -> impl Future<Item = StorageBuffer<T, A, B, D, I>, Error = Error> + Send {
+        StorageBuffer::new(Arc::clone(&self.state), Arc::new(data), false)
+    }
+
+    /// Like `create_storage_buffer`, but keeps the initial data so the buffer can be recreated
+    /// after the device was lost, see `Recreatable`
+    pub fn create_recreatable_storage_buffer<T: 'static + Copy + Send + Sync>(
+        &self,
+        data: Vec<T>,
+    ) -> impl Future<Item = StorageBuffer<T, A, B, D, I>, Error = Error> + Send {
+        StorageBuffer::new(Arc::clone(&self.state), Arc::new(data), true)
+    }
+
+    /// Runs the compute pipeline once, outside of the frame loop. The future resolves once the
+    /// GPU is done, after which the results can be used by any pipeline
+    pub fn dispatch_compute(
+        &self,
+        pipeline: &ComputePipeline<A, B, D, I>,
+        workgroups: [u32; 3],
+        push_constants: &[u32],
+    ) -> impl Future<Item = (), Error = Error> + Send {
+        let state = Arc::clone(&self.state);
+        let pipeline = pipeline.clone();
+        let push_constants = push_constants.to_vec();
+        lazy(move || {
+            let done = state.submit_once(|command_buffer| {
+                pipeline.bundle().record(command_buffer, workgroups, &push_constants)
+            })?;
+            Ok((done, pipeline))
+        })
+        .and_then(|(done, pipeline)| done.map(move |_| drop(pipeline)))
+    }
+
+    /// Creates a shadow casting directional light, see [`ShadowConfig`] for the available options
+    pub fn create_directional_light(
+        &self,
+        direction: Vec3<f32>,
+        color: Rgb<f32>,
+        config: ShadowConfig,
+    ) -> impl Future<Item = DirectionalLight<A, B, D, I>, Error = Error> + Send {
+        DirectionalLight::new(Arc::clone(&self.state), direction, color, config)
+    }
+
+    /// Creates a pipeline that draws bundles into shadow maps using the built in vertex shader
+    pub fn create_shadow_caster_pipeline<V: 'static + Vertex>(
+        &self,
+    ) -> impl Future<Item = ShadowCasterPipeline<V, A, B, D, I>, Error = Error> + Send {
+        ShadowCasterPipeline::new(
+            Arc::clone(&self.state),
+            ShadowCasterPipeline::<V, A, B, D, I>::default_vertex_shader(),
+        )
+    }
+
     pub fn create_texture_from_bytes(
         &self,
         image_data: &'static [u8],
     ) -> impl Future<Item = Texture<Rgba8Srgb, Single, A, B, D, I>, Error = Error> + Send {
         let cloned_state = Arc::clone(&self.state);
         lazy(move || {
-            Ok(load_from_memory(image_data)?)
+            Ok(load_from_memory(image_data).map_err(AssetError::from)?)
         }).and_then(move |image| {
-            Texture::<Rgba8Srgb, Single, A, B, D, I>::new(cloned_state, image)
+            Texture::<Rgba8Srgb, Single, A, B, D, I>::new(cloned_state, image, false)
         })
     }
 
     pub fn create_texture_from_image(&self, image: DynamicImage) -> impl Future<Item = Texture<Rgba8Srgb, Single, A, B, D, I>, Error = Error> + Send {
-        Texture::<Rgba8Srgb, Single, A, B, D, I>::new(Arc::clone(&self.state), image)
+        Texture::<Rgba8Srgb, Single, A, B, D, I>::new(Arc::clone(&self.state), image, false)
+    }
+
+    /// Like `create_texture_from_image`, but keeps the pixels so the texture can be recreated after
+    /// the device was lost, see `Recreatable`
+    pub fn create_recreatable_texture_from_image(&self, image: DynamicImage) -> impl Future<Item = Texture<Rgba8Srgb, Single, A, B, D, I>, Error = Error> + Send {
+        Texture::<Rgba8Srgb, Single, A, B, D, I>::new(Arc::clone(&self.state), image, true)
     }
 
     pub fn create_texture_sized<F: AsFormat + Send>(&self, width: u32, height: u32) -> impl Future<Item = Texture<F, Single, A, B, D, I>, Error = Error> + Send {
         Texture::<F, Single, A, B, D, I>::sized(Arc::clone(&self.state), 1, width, height)
     }
 
+    pub(crate) fn state(&self) -> Arc<GraphicsState<A, B, D, I>> {
+        Arc::clone(&self.state)
+    }
+
     pub fn logical_window_size(&self) -> (u32, u32) {
         self.state.logical_window_size()
     }
@@ -92,6 +235,16 @@ impl<A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Instance<Backend = B>>
     pub fn dpi(&self) -> f64 {
         self.state.dpi()
     }
+
+    /// The usage of each device memory heap, as reported by the allocator
+    pub fn memory_stats(&self) -> Vec<HeapStats> {
+        self.state.allocator().stats()
+    }
+
+    /// The memory that hasn't been freed yet, see `TrackingAllocator`
+    pub fn live_allocations(&self) -> Vec<LiveAllocation> {
+        self.state.allocator().live_allocations()
+    }
 }
 
 pub trait CreateDefaultPipeline<V: Vertex, A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Instance<Backend = B>> {
@@ -108,6 +261,15 @@ pub trait CreateTexturedPipeline<V: Vertex, A: GpuAllocator<B, D>, B: Backend, D
     ) -> Box<Future<Item = Pipeline<V, A, B, D, I>, Error = Error> + Send>;
 }
 
+/// Creates a pipeline that is lit by a [`DirectionalLight`] and receives shadows. The light must be
+/// bound with `Pipeline::bind_directional_light` before drawing
+pub trait CreateLitPipeline<V: Vertex, A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Instance<Backend = B>> {
+    #[allow(clippy::type_complexity)]
+    fn create_lit_pipeline(
+        &self,
+    ) -> Box<Future<Item = Pipeline<V, A, B, D, I>, Error = Error> + Send>;
+}
+
 pub trait CreateBundleFromObj<
     In: Index,
     V: Vertex,
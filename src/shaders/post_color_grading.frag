@@ -0,0 +1,44 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(set = 0, binding = 0) uniform texture2D source;
+layout(set = 0, binding = 1) uniform sampler samp;
+layout(set = 0, binding = 2) uniform texture2D lut;
+
+layout (push_constant) uniform PostProcessConstants {
+  vec2 resolution;
+  float time;
+  float padding;
+  vec4 parameters;
+} constants;
+
+layout (location = 0) in vec2 frag_uv;
+
+layout(location = 0) out vec4 target;
+
+// The lut is stored as a horizontal strip of `size` slices, each `size` x `size` pixels,
+// where red grows along x, green along y and blue selects the slice
+vec3 sample_lut(vec3 color, float size) {
+  float blue = color.b * (size - 1.0);
+  float slice_low = floor(blue);
+  float slice_high = min(slice_low + 1.0, size - 1.0);
+
+  vec2 texel = vec2(1.0 / (size * size), 1.0 / size);
+  vec2 inside = vec2(
+    0.5 * texel.x + color.r * (size - 1.0) * texel.x,
+    0.5 * texel.y + color.g * (size - 1.0) * texel.y
+  );
+
+  vec3 low = texture(sampler2D(lut, samp), inside + vec2(slice_low / size, 0.0)).rgb;
+  vec3 high = texture(sampler2D(lut, samp), inside + vec2(slice_high / size, 0.0)).rgb;
+  return mix(low, high, blue - slice_low);
+}
+
+void main() {
+  vec4 color = texture(sampler2D(source, samp), frag_uv);
+  float size = constants.parameters.x;
+  float intensity = constants.parameters.y;
+
+  vec3 graded = sample_lut(clamp(color.rgb, 0.0, 1.0), size);
+  target = vec4(mix(color.rgb, graded, intensity), color.a);
+}
@@ -0,0 +1,14 @@
+#version 450
+
+layout (location = 0) out vec2 frag_uv;
+
+out gl_PerVertex {
+  vec4 gl_Position;
+};
+
+// Emits a single triangle covering the whole screen, no vertex buffers needed
+void main()
+{
+  frag_uv = vec2((gl_VertexIndex << 1) & 2, gl_VertexIndex & 2);
+  gl_Position = vec4(frag_uv * 2.0 - 1.0, 0.0, 1.0);
+}
@@ -0,0 +1,80 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(set = 0, binding = 0) uniform texture2D source;
+layout(set = 0, binding = 1) uniform sampler samp;
+
+layout (push_constant) uniform PostProcessConstants {
+  vec2 resolution;
+  float time;
+  float padding;
+  vec4 parameters;
+} constants;
+
+layout (location = 0) in vec2 frag_uv;
+
+layout(location = 0) out vec4 target;
+
+const float FXAA_REDUCE_MIN = 1.0 / 128.0;
+const float FXAA_REDUCE_MUL = 1.0 / 8.0;
+const float FXAA_SPAN_MAX = 8.0;
+
+vec4 fetch(vec2 uv) {
+  return texture(sampler2D(source, samp), uv);
+}
+
+float luma(vec3 color) {
+  return dot(color, vec3(0.299, 0.587, 0.114));
+}
+
+void main() {
+  vec2 texel = 1.0 / constants.resolution;
+
+  vec3 rgb_nw = fetch(frag_uv + vec2(-1.0, -1.0) * texel).rgb;
+  vec3 rgb_ne = fetch(frag_uv + vec2(1.0, -1.0) * texel).rgb;
+  vec3 rgb_sw = fetch(frag_uv + vec2(-1.0, 1.0) * texel).rgb;
+  vec3 rgb_se = fetch(frag_uv + vec2(1.0, 1.0) * texel).rgb;
+  vec4 rgba_m = fetch(frag_uv);
+
+  float luma_nw = luma(rgb_nw);
+  float luma_ne = luma(rgb_ne);
+  float luma_sw = luma(rgb_sw);
+  float luma_se = luma(rgb_se);
+  float luma_m = luma(rgba_m.rgb);
+
+  float luma_min = min(luma_m, min(min(luma_nw, luma_ne), min(luma_sw, luma_se)));
+  float luma_max = max(luma_m, max(max(luma_nw, luma_ne), max(luma_sw, luma_se)));
+
+  vec2 direction = vec2(
+    -((luma_nw + luma_ne) - (luma_sw + luma_se)),
+    (luma_nw + luma_sw) - (luma_ne + luma_se)
+  );
+
+  float direction_reduce = max(
+    (luma_nw + luma_ne + luma_sw + luma_se) * (0.25 * FXAA_REDUCE_MUL),
+    FXAA_REDUCE_MIN
+  );
+  float inverse_direction_adjustment = 1.0 / (min(abs(direction.x), abs(direction.y)) + direction_reduce);
+
+  direction = clamp(
+    direction * inverse_direction_adjustment,
+    vec2(-FXAA_SPAN_MAX),
+    vec2(FXAA_SPAN_MAX)
+  ) * texel;
+
+  vec3 result_a = 0.5 * (
+    fetch(frag_uv + direction * (1.0 / 3.0 - 0.5)).rgb +
+    fetch(frag_uv + direction * (2.0 / 3.0 - 0.5)).rgb
+  );
+  vec3 result_b = result_a * 0.5 + 0.25 * (
+    fetch(frag_uv + direction * -0.5).rgb +
+    fetch(frag_uv + direction * 0.5).rgb
+  );
+
+  float luma_b = luma(result_b);
+  if (luma_b < luma_min || luma_b > luma_max) {
+    target = vec4(result_a, rgba_m.a);
+  } else {
+    target = vec4(result_b, rgba_m.a);
+  }
+}
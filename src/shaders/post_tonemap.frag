@@ -0,0 +1,37 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(set = 0, binding = 0) uniform texture2D source;
+layout(set = 0, binding = 1) uniform sampler samp;
+
+layout (push_constant) uniform PostProcessConstants {
+  vec2 resolution;
+  float time;
+  float padding;
+  vec4 parameters;
+} constants;
+
+layout (location = 0) in vec2 frag_uv;
+
+layout(location = 0) out vec4 target;
+
+// ACES filmic curve fitted by Krzysztof Narkowicz
+vec3 aces(vec3 color) {
+  const float a = 2.51;
+  const float b = 0.03;
+  const float c = 2.43;
+  const float d = 0.59;
+  const float e = 0.14;
+  return clamp((color * (a * color + b)) / (color * (c * color + d) + e), 0.0, 1.0);
+}
+
+void main() {
+  vec4 color = texture(sampler2D(source, samp), frag_uv);
+  float exposure = constants.parameters.x;
+  float gamma = constants.parameters.y;
+
+  vec3 mapped = aces(color.rgb * exposure);
+  mapped = pow(mapped, vec3(1.0 / gamma));
+
+  target = vec4(mapped, color.a);
+}
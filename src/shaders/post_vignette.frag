@@ -0,0 +1,30 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(set = 0, binding = 0) uniform texture2D source;
+layout(set = 0, binding = 1) uniform sampler samp;
+
+layout (push_constant) uniform PostProcessConstants {
+  vec2 resolution;
+  float time;
+  float padding;
+  vec4 parameters;
+} constants;
+
+layout (location = 0) in vec2 frag_uv;
+
+layout(location = 0) out vec4 target;
+
+void main() {
+  vec4 color = texture(sampler2D(source, samp), frag_uv);
+  float intensity = constants.parameters.x;
+  float radius = constants.parameters.y;
+  float softness = constants.parameters.z;
+
+  // Keep the vignette round on non square screens
+  vec2 position = frag_uv - vec2(0.5);
+  position.x *= constants.resolution.x / constants.resolution.y;
+
+  float vignette = smoothstep(radius, radius - softness, length(position));
+  target = vec4(mix(color.rgb, color.rgb * vignette, intensity), color.a);
+}
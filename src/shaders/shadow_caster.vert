@@ -0,0 +1,16 @@
+#version 450
+
+layout (push_constant) uniform PushConsts {
+  mat4 light_space;
+} push;
+
+layout (location = 0) in vec3 position;
+
+out gl_PerVertex {
+  vec4 gl_Position;
+};
+
+void main()
+{
+  gl_Position = push.light_space * vec4(position, 1.0);
+}
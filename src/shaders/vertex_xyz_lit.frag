@@ -0,0 +1,48 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(set = 0, binding = 0) uniform texture2D shadow_map;
+layout(set = 0, binding = 1) uniform samplerShadow shadow_sampler;
+
+layout (location = 0) in vec3 frag_position;
+layout (location = 1) in vec4 frag_light_space;
+layout (location = 2) flat in vec3 frag_light_direction;
+layout (location = 3) flat in vec3 frag_light_color;
+layout (location = 4) flat in vec4 frag_shadow;
+
+layout(location = 0) out vec4 target;
+
+// Percentage closer filtering over a (2 * radius + 1)^2 kernel
+float shadow_factor() {
+  vec3 coords = frag_light_space.xyz / frag_light_space.w;
+  if (coords.z > 1.0) {
+    return 1.0;
+  }
+
+  vec2 uv = coords.xy * 0.5 + 0.5;
+  float bias = frag_shadow.x;
+  float texel = frag_shadow.y;
+  int radius = int(frag_shadow.z);
+
+  float lit = 0.0;
+  float samples = 0.0;
+  for (int x = -radius; x <= radius; x++) {
+    for (int y = -radius; y <= radius; y++) {
+      vec3 position = vec3(uv + vec2(x, y) * texel, coords.z - bias);
+      lit += texture(sampler2DShadow(shadow_map, shadow_sampler), position);
+      samples += 1.0;
+    }
+  }
+  return lit / samples;
+}
+
+void main() {
+  // Flat normals from the screen space derivatives, the order accounts for the y down screen
+  vec3 normal = normalize(cross(dFdy(frag_position), dFdx(frag_position)));
+  float diffuse = max(dot(normal, -frag_light_direction), 0.0);
+  float ambient = frag_shadow.w;
+
+  vec3 base = vec3(1.0, 0.2, 0.2);
+  vec3 color = base * (ambient + diffuse * shadow_factor() * frag_light_color);
+  target = vec4(color, 1.0);
+}
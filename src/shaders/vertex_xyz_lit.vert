@@ -0,0 +1,35 @@
+#version 450
+
+layout (push_constant) uniform PushConsts {
+  mat4 mvp;
+} push;
+
+layout (set = 0, binding = 2) uniform DirectionalLight {
+  mat4 light_space;
+  vec4 direction;
+  vec4 color;
+  // x: bias, y: texel size, z: pcf radius, w: ambient
+  vec4 shadow;
+} light;
+
+layout (location = 0) in vec3 position;
+
+layout (location = 0) out vec3 frag_position;
+layout (location = 1) out vec4 frag_light_space;
+layout (location = 2) flat out vec3 frag_light_direction;
+layout (location = 3) flat out vec3 frag_light_color;
+layout (location = 4) flat out vec4 frag_shadow;
+
+out gl_PerVertex {
+  vec4 gl_Position;
+};
+
+void main()
+{
+  frag_position = position;
+  frag_light_space = light.light_space * vec4(position, 1.0);
+  frag_light_direction = normalize(light.direction.xyz);
+  frag_light_color = light.color.rgb;
+  frag_shadow = light.shadow;
+  gl_Position = push.mvp * vec4(position, 1.0);
+}
@@ -1,9 +1,15 @@
+use crate::asset::AssetSource;
 use crate::context::Context;
+use crate::errors::CreateEncoderError;
 use crate::errors::CreateEncoderErrorKind;
 use crate::input::UserInput;
+use crate::graphics::AdapterPreference;
+use crate::graphics::PostProcessChain;
 use crate::internal::graphics::GraphicsState;
+use crate::internal::graphics::PostProcessor;
 use crate::internal::menu::InitView;
 use crate::internal::menu::MenuManager;
+use crate::prepare_context::PrepareContext;
 use crate::setup_context::SetupContext;
 use colored::*;
 use failure::Error;
@@ -16,6 +22,8 @@ use gfx_hal::Instance;
 use std::fmt;
 use std::fmt::Debug;
 use std::fmt::Formatter;
+use std::mem;
+use std::path::PathBuf;
 use std::sync::Arc;
 use std::thread;
 use std::time::Instant;
@@ -23,6 +31,8 @@ use winit::EventsLoop;
 use winit::Window;
 use winit::WindowBuilder;
 use std::sync::mpsc::channel;
+use std::sync::mpsc::Receiver;
+use std::sync::Mutex;
 use crate::allocator::GpuAllocator;
 use glyph_brush::Layout;
 
@@ -39,6 +49,15 @@ $$\\   $$ |  $$ |$$\\ $$  __$$ |$$ |       \\____$$\\   $$ |$$\\ $$ |      $$ |
 
  ";
 
+/// Where the state, or the error of a failed setup, arrives once the setup is done
+type SetupReceiver<S> = Receiver<Result<S, Error>>;
+
+/// Why the render loop ended
+enum LoopExit {
+    Stopped,
+    DeviceLost,
+}
+
 pub trait State: Send + Sync + 'static {}
 
 impl<T: Send + Sync + 'static> State for T {}
@@ -52,7 +71,23 @@ pub struct Starstruck<S: State, A: GpuAllocator<B, D>, B: Backend = backend::Bac
     events_loop: EventsLoop,
     graphics_state: Arc<GraphicsState<A, B, D, I>>,
     setup_context: Arc<SetupContext<A, B, D, I>>,
-    setup_callback: Option<Box<Future<Item = S, Error = Error> + Send>>,
+    post_process_chain: PostProcessChain,
+    adapter_preference: AdapterPreference,
+    assets: AssetSource,
+    allocator: Box<Fn() -> A>,
+    setup_callback: Box<Fn(Arc<SetupContext<A, B, D, I>>) -> Box<Future<Item = S, Error = Error> + Send>>,
+    device_lost_callback: Option<Box<FnMut(
+        (
+            &mut S,
+            Arc<SetupContext<A, B, D, I>>,
+        ),
+    ) -> Result<(), Error>>>,
+    prepare_callback: Box<FnMut(
+        (
+            &mut S,
+            &mut PrepareContext<A, B, D, I>,
+        ),
+    ) -> Result<(), Error>>,
     render_callback: Box<FnMut(
         (
             &mut S,
@@ -70,22 +105,41 @@ impl<'a, S: State, A: GpuAllocator>
     ///
     /// * `title` - The name used for this app. This is also displayed in the window on operating systems that supports it
     /// * `setup_callback` - Callback used to setup all needed dependencies
+    /// * `prepare_callback` - Called on each render loop before the main render pass. Used to render shadow maps
     /// * `render_callback` - Called on each render loop. Used to draw the app
+    /// * `post_process_chain` - Full screen passes applied after the scene has been drawn
+    /// * `asset_root` - The directory or asset archive assets are loaded from
+    /// * `device_lost_callback` - Called with a new setup context after the device was lost, used to recreate resources
     ///
     /// # Errors
     ///
     /// Result might contain an error if something went wrong during setup
-    #[allow(clippy::type_complexity)]
+    #[allow(clippy::type_complexity, clippy::too_many_arguments)]
     pub(crate) fn init<R: Future<Item = S, Error = Error> + Send + 'static, I: IntoFuture<Future = R, Item = S, Error = Error> + 'static>(
         title: &str,
-        mut setup_callback: Box<FnMut(Arc<SetupContext<A>>) -> I + Send>,
+        setup_callback: Box<FnMut(Arc<SetupContext<A>>) -> I + Send>,
+        prepare_callback: Box<FnMut(
+            (
+                &mut S,
+                &mut PrepareContext<A, backend::Backend, backend::Device, backend::Instance>,
+            ),
+        ) -> Result<(), Error>>,
         render_callback: Box<FnMut(
             (
                 &mut S,
                 &mut Context<A, backend::Backend, backend::Device, backend::Instance>,
             ),
         ) -> Result<(), Error>>,
-        allocator: A
+        allocator: Box<Fn() -> A>,
+        post_process_chain: PostProcessChain,
+        adapter_preference: AdapterPreference,
+        asset_root: PathBuf,
+        device_lost_callback: Option<Box<FnMut(
+            (
+                &mut S,
+                Arc<SetupContext<A>>,
+            ),
+        ) -> Result<(), Error>>>,
     ) -> Result<Self, Error>
     {
         Self::print_banner();
@@ -95,15 +149,17 @@ impl<'a, S: State, A: GpuAllocator>
         let events_loop = EventsLoop::new();
         let window = WindowBuilder::new().with_title(title).build(&events_loop)?;
 
-        let graphics_state = Arc::new(GraphicsState::new(title, &window, allocator)?);
-        let context = Arc::new(SetupContext::new(Arc::clone(&graphics_state)));
+        let assets = AssetSource::mount(asset_root)?;
+        let graphics_state = Arc::new(GraphicsState::new(title, &window, allocator(), &adapter_preference)?);
+        let context = Arc::new(SetupContext::new(Arc::clone(&graphics_state), assets.clone()));
 
-        let s_callback = {
-            let cloned_context = Arc::clone(&context);
-            let future = Box::new(lazy(move || setup_callback(cloned_context)))
-                as Box<Future<Item = S, Error = Error> + Send>;
-            Some(future)
-        };
+        // The setup callback runs again if the device is lost without a device lost callback
+        let setup_callback = Arc::new(Mutex::new(setup_callback));
+        let s_callback = Box::new(move |context: Arc<SetupContext<A>>| {
+            let callback = Arc::clone(&setup_callback);
+            Box::new(lazy(move || (&mut *callback.lock().unwrap())(context)))
+                as Box<Future<Item = S, Error = Error> + Send>
+        });
 
         Ok(Self {
             title: title.to_string(),
@@ -111,7 +167,13 @@ impl<'a, S: State, A: GpuAllocator>
             events_loop,
             graphics_state,
             setup_context: context,
+            post_process_chain,
+            adapter_preference,
+            assets,
+            allocator,
             setup_callback: s_callback,
+            device_lost_callback,
+            prepare_callback,
             render_callback,
         })
     }
@@ -139,45 +201,95 @@ impl<'a, S: State, A: GpuAllocator>
     /// # }
     /// ```
     pub fn run(mut self) -> Result<(), Error> {
+        let mut state = None;
+        let mut setup = self.start_setup();
+        loop {
+            match self.render_loop(&mut state, &setup)? {
+                LoopExit::Stopped => return Ok(()),
+                LoopExit::DeviceLost => self.recover(&mut state, &mut setup)?,
+            }
+        }
+    }
+
+    fn start_setup(&self) -> SetupReceiver<S> {
+        spawn_setup((self.setup_callback)(Arc::clone(&self.setup_context)))
+    }
+
+    /// Creates a new graphics state after the device was lost, and gives the app the chance to
+    /// recreate its resources. Without a device lost callback the state is dropped and the setup
+    /// callback runs again
+    fn recover(&mut self, state: &mut Option<S>, setup: &mut SetupReceiver<S>) -> Result<(), Error> {
+        warn!("{}", "The device was lost, recreating the graphics state".red());
+
+        self.graphics_state.release_swapchain();
+        let graphics_state = Arc::new(GraphicsState::new(
+            &self.title,
+            &self.window,
+            (self.allocator)(),
+            &self.adapter_preference,
+        )?);
+        // Cached assets belong to the old device, so the new context starts with an empty cache
+        self.setup_context = Arc::new(SetupContext::new(Arc::clone(&graphics_state), self.assets.clone()));
+        self.graphics_state = graphics_state;
+
+        let setup_callback = &self.setup_callback;
+        let setup_context = &self.setup_context;
+        restore_state(
+            state,
+            setup,
+            self.device_lost_callback.as_mut().map(|callback| &mut **callback),
+            Arc::clone(setup_context),
+            || spawn_setup(setup_callback(Arc::clone(setup_context))),
+        )
+    }
+
+    fn render_loop(&mut self, state: &mut Option<S>, setup: &SetupReceiver<S>) -> Result<LoopExit, Error> {
         let events_loop = &mut self.events_loop;
         let graphics_state = &mut self.graphics_state;
-        let setup = self.setup_callback.take().unwrap();
         let s_context = &self.setup_context;
+        let prepare_callback = &mut self.prepare_callback;
         let render_callback = &mut self.render_callback;
         let initial_view = Arc::new(InitView::new(Arc::clone(s_context)).wait()?);
         let mut menu_manager =
             MenuManager::new(Arc::clone(&s_context), initial_view).wait()?;
+        if state.is_some() {
+            menu_manager.hide_loading_view();
+        }
+        let mut post_processor = if self.post_process_chain.is_empty() {
+            None
+        } else {
+            let chain = self.post_process_chain.clone();
+            Some(PostProcessor::new(Arc::clone(graphics_state), chain).wait()?)
+        };
 
         let mut user_input = UserInput::new();
 
-        let (sender, receiver) = channel();
-
-        thread::spawn(move || {
-            let now = Instant::now();
-            let r: S = setup.wait().unwrap();
-            info!(
-                "{}",
-                format!("Setup took {:?} to complete", now.elapsed()).magenta()
-            );
-            sender.send(r).unwrap()
-        });
-
         let mut recreate_swapchain = false;
         let mut end_requested = false;
-        let mut state = None;
         let mut old_render_area = graphics_state.render_area();
 
         let mut last_time = Instant::now();
         let mut fps = 0.0;
         let mut fps_show_counter = 0;
 
+        // Frames that are skipped don't build a context, so their reloaded paths wait for the next
+        let mut reloaded_assets = vec![];
+
         info!("Entering render loop");
         loop {
             let render_area = graphics_state.render_area();
             if state.is_none() {
-                state = receiver.try_recv().ok();
-                if state.is_some() {
-                    menu_manager.hide_loading_view();
+                match setup.try_recv() {
+                    Ok(Ok(ready)) => {
+                        *state = Some(ready);
+                        menu_manager.hide_loading_view();
+                    }
+                    Ok(Err(ref error)) if is_device_lost(error) => {
+                        error!("{}", error);
+                        return Ok(LoopExit::DeviceLost);
+                    }
+                    Ok(Err(error)) => return Err(error),
+                    Err(_) => {}
                 }
             }
 
@@ -189,17 +301,84 @@ impl<'a, S: State, A: GpuAllocator>
             if recreate_swapchain {
                 //graphics_state.device().wait_idle()?;
                 graphics_state.recreate_swapchain(&self.window)?;
+                if let Some(processor) = post_processor.as_mut() {
+                    processor.resize()?;
+                }
                 recreate_swapchain = false;
             }
             user_input.reset_and_poll_events(events_loop);
 
             menu_manager.draw_text(format!("Fps: {}", fps).as_str(), 16.0, (0.0, 0.0), Layout::default());
 
+            graphics_state.shader_reloader().reload(graphics_state);
+            match s_context.assets().reload() {
+                Ok(reloaded) => {
+                    for path in reloaded {
+                        if !reloaded_assets.contains(&path) {
+                            reloaded_assets.push(path);
+                        }
+                    }
+                }
+                Err(ref error) if is_device_lost(error) => {
+                    error!("{}", error);
+                    return Ok(LoopExit::DeviceLost);
+                }
+                Err(error) => return Err(error),
+            }
+
+            // Submit whatever was staged since the last frame so it can be drawn this frame
+            if let Err(error) = graphics_state.uploads().flush() {
+                if is_device_lost(&error) {
+                    error!("{}", error);
+                    return Ok(LoopExit::DeviceLost);
+                }
+                return Err(error);
+            }
+
+            if let Err(error) = graphics_state.begin_frame() {
+                match error.kind() {
+                    CreateEncoderErrorKind::RecreateSwapchain => {
+                        recreate_swapchain = true;
+                        user_input.flush();
+                        continue;
+                    }
+                    CreateEncoderErrorKind::Timeout => continue,
+                    CreateEncoderErrorKind::DeviceLost | CreateEncoderErrorKind::OutOfMemory => {
+                        error!("{}", error);
+                        return Ok(LoopExit::DeviceLost);
+                    }
+                }
+            };
+
+            let prepared = graphics_state.prepare_frame(|command_buffer, wait_for_frames_in_flight| {
+                if menu_manager.should_draw_content() {
+                    if let Some(d) = state.as_mut() {
+                        let mut context =
+                            PrepareContext::new(Arc::clone(&s_context), command_buffer, wait_for_frames_in_flight);
+                        prepare_callback((d, &mut context))?;
+                    }
+                }
+                Ok(())
+            });
+            if let Err(error) = prepared {
+                if is_device_lost(&error) {
+                    error!("{}", error);
+                    return Ok(LoopExit::DeviceLost);
+                }
+                return Err(error);
+            }
+
             let user_input_clone = user_input.clone();
             {
-                if let Err(error) = graphics_state.next_encoder(|encoder| {
-                    let mut context =
-                        Context::new(user_input_clone, Arc::clone(&s_context), encoder, render_area);
+                let target = post_processor.as_ref().map(|processor| processor.scene_target());
+                graphics_state.next_encoder(target, |encoder| {
+                    let mut context = Context::new(
+                        user_input_clone,
+                        Arc::clone(&s_context),
+                        encoder,
+                        render_area,
+                        mem::replace(&mut reloaded_assets, vec![]),
+                    );
 
                     let draw = menu_manager.should_draw_content();
                     if draw {
@@ -213,20 +392,23 @@ impl<'a, S: State, A: GpuAllocator>
                         end_requested = true;
                     }
                     Ok(())
-                }) {
-                    match error.kind() {
-                        CreateEncoderErrorKind::RecreateSwapchain => {
-                            recreate_swapchain = true;
-                            user_input.flush();
-                            continue;
-                        }
-                        CreateEncoderErrorKind::Timeout => continue,
-                        _ => bail!(error),
-                    }
-                };
+                });
             };
 
-            graphics_state.present_swapchain()?;
+            if let Err(error) = graphics_state.present_swapchain(|command_buffer, image_index| {
+                if let Some(processor) = post_processor.as_ref() {
+                    processor.record(command_buffer, image_index);
+                }
+            }) {
+                match error.kind() {
+                    CreateEncoderErrorKind::RecreateSwapchain => recreate_swapchain = true,
+                    CreateEncoderErrorKind::Timeout => {}
+                    CreateEncoderErrorKind::DeviceLost | CreateEncoderErrorKind::OutOfMemory => {
+                        error!("{}", error);
+                        return Ok(LoopExit::DeviceLost);
+                    }
+                }
+            }
 
             if user_input.resized {
                 recreate_swapchain = true;
@@ -234,7 +416,7 @@ impl<'a, S: State, A: GpuAllocator>
 
             if user_input.end_requested || end_requested {
                 info!("Stopping starstruck");
-                break;
+                return Ok(LoopExit::Stopped);
             }
 
             user_input.flush();
@@ -251,8 +433,6 @@ impl<'a, S: State, A: GpuAllocator>
                 fps_show_counter += 1;
             }
         }
-
-        Ok(())
     }
 
     fn print_banner() {
@@ -260,6 +440,56 @@ impl<'a, S: State, A: GpuAllocator>
     }
 }
 
+/// Runs the setup on a separate thread, the state or the error is sent back once it's done
+fn spawn_setup<S: State>(setup: Box<Future<Item = S, Error = Error> + Send>) -> SetupReceiver<S> {
+    let (sender, receiver) = channel();
+
+    thread::spawn(move || {
+        let now = Instant::now();
+        let result = setup.wait();
+        info!(
+            "{}",
+            format!("Setup took {:?} to complete", now.elapsed()).magenta()
+        );
+        // Nobody is listening anymore if the device was lost in the meantime
+        let _ = sender.send(result);
+    });
+    receiver
+}
+
+/// Hands the state to the device lost callback together with the new context. Without a callback
+/// the state is dropped and the setup starts over. A setup that hadn't finished yet also starts
+/// over, since it was creating its resources on the old device
+fn restore_state<S, C, F, T>(
+    state: &mut Option<S>,
+    setup: &mut SetupReceiver<S>,
+    device_lost_callback: Option<&mut F>,
+    context: C,
+    start_setup: T,
+) -> Result<(), Error>
+where
+    F: FnMut((&mut S, C)) -> Result<(), Error> + ?Sized,
+    T: FnOnce() -> SetupReceiver<S>,
+{
+    match (state.as_mut(), device_lost_callback) {
+        (Some(state), Some(callback)) => callback((state, context)),
+        _ => {
+            *state = None;
+            *setup = start_setup();
+            Ok(())
+        }
+    }
+}
+
+/// Whether the error means the device was lost, or ran out of memory, and the render loop should
+/// recover instead of giving up
+fn is_device_lost(error: &Error) -> bool {
+    match error.downcast_ref::<CreateEncoderError>().map(CreateEncoderError::kind) {
+        Some(CreateEncoderErrorKind::DeviceLost) | Some(CreateEncoderErrorKind::OutOfMemory) => true,
+        _ => false,
+    }
+}
+
 impl<S: State, A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Instance<Backend = B>> Debug
     for Starstruck<S, A, B, D, I>
 {
@@ -269,3 +499,97 @@ impl<S: State, A: GpuAllocator<B, D>, B: Backend, D: Device<B>, I: Instance<Back
         write!(f, "EventsLoop: {:?}", self.events_loop)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::is_device_lost;
+    use super::restore_state;
+    use crate::errors::CreateEncoderError;
+    use crate::errors::CreateEncoderErrorKind;
+    use failure::Error;
+    use pretty_assertions::assert_eq;
+    use std::sync::mpsc::channel;
+    use std::sync::mpsc::Receiver;
+
+    type Callback = FnMut((&mut Vec<&'static str>, &'static str)) -> Result<(), Error>;
+
+    fn finished_setup(state: Vec<&'static str>) -> Receiver<Result<Vec<&'static str>, Error>> {
+        let (sender, receiver) = channel();
+        sender.send(Ok(state)).unwrap();
+        receiver
+    }
+
+    #[test]
+    fn it_should_hand_the_state_to_the_device_lost_callback() {
+        let mut state = Some(vec!["old"]);
+        let (_sender, mut setup) = channel();
+        let mut callback = |(state, context): (&mut Vec<&'static str>, &'static str)| -> Result<(), Error> {
+            state.push(context);
+            Ok(())
+        };
+
+        restore_state(&mut state, &mut setup, Some(&mut callback), "recovered", || {
+            panic!("The setup shouldn't run again")
+        })
+        .unwrap();
+
+        assert_eq!(Some(vec!["old", "recovered"]), state);
+        assert!(setup.try_recv().is_err());
+    }
+
+    #[test]
+    fn it_should_start_the_setup_over_without_a_device_lost_callback() {
+        let mut state = Some(vec!["old"]);
+        let (_sender, mut setup) = channel();
+
+        restore_state::<_, _, Callback, _>(&mut state, &mut setup, None, "recovered", || {
+            finished_setup(vec!["new"])
+        })
+        .unwrap();
+
+        assert_eq!(None, state);
+        assert_eq!(vec!["new"], setup.try_recv().unwrap().unwrap());
+    }
+
+    #[test]
+    fn it_should_start_an_unfinished_setup_over() {
+        let mut state = None;
+        let (_sender, mut setup) = channel();
+        let mut callback = |_: (&mut Vec<&'static str>, &'static str)| -> Result<(), Error> {
+            panic!("There is no state to hand to the callback")
+        };
+
+        restore_state(&mut state, &mut setup, Some(&mut callback), "recovered", || {
+            finished_setup(vec!["new"])
+        })
+        .unwrap();
+
+        assert_eq!(None, state);
+        assert_eq!(vec!["new"], setup.try_recv().unwrap().unwrap());
+    }
+
+    #[test]
+    fn it_should_pass_on_errors_of_the_device_lost_callback() {
+        let mut state = Some(vec!["old"]);
+        let (_sender, mut setup) = channel();
+        let mut callback = |_: (&mut Vec<&'static str>, &'static str)| -> Result<(), Error> {
+            Err(format_err!("Could not recreate the textures"))
+        };
+
+        let result = restore_state(&mut state, &mut setup, Some(&mut callback), "recovered", || {
+            panic!("The setup shouldn't run again")
+        });
+
+        assert_eq!("Could not recreate the textures", result.unwrap_err().to_string());
+        assert_eq!(Some(vec!["old"]), state);
+    }
+
+    #[test]
+    fn it_should_only_recover_from_device_loss_and_running_out_of_memory() {
+        let error = |kind: CreateEncoderErrorKind| Error::from(CreateEncoderError::from(kind));
+        assert_eq!(true, is_device_lost(&error(CreateEncoderErrorKind::DeviceLost)));
+        assert_eq!(true, is_device_lost(&error(CreateEncoderErrorKind::OutOfMemory)));
+        assert_eq!(false, is_device_lost(&error(CreateEncoderErrorKind::Timeout)));
+        assert_eq!(false, is_device_lost(&format_err!("The prepare callback failed")));
+    }
+}
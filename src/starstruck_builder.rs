@@ -3,12 +3,18 @@ use failure::Error;
 use futures::Future;
 use futures::IntoFuture;
 use crate::context::Context;
+use std::path::PathBuf;
 use std::sync::Arc;
 use crate::setup_context::SetupContext;
 use crate::allocator::GpuAllocator;
 use crate::allocator::DefaultGpuAllocator;
 use crate::starstruck::State;
 use crate::allocator::DefaultChunk;
+use crate::graphics::AdapterDescription;
+use crate::graphics::AdapterPreference;
+use crate::graphics::PostProcessChain;
+use crate::internal::graphics::GraphicsState;
+use crate::prepare_context::PrepareContext;
 
 /// The main way to construct a starstruck instance
 ///
@@ -32,13 +38,28 @@ pub struct StarstruckBuilder<
 > {
     title: String,
     setup_callback: Box<(FnMut(Arc<SetupContext<A>>) -> R + Send)>,
+    prepare_callback: Box<FnMut(
+        (
+            &mut S,
+            &mut PrepareContext<A, backend::Backend, backend::Device, backend::Instance>,
+        ),
+    ) -> Result<(), Error>>,
     render_callback: Box<FnMut(
         (
             &mut S,
             &mut Context<A, backend::Backend, backend::Device, backend::Instance>,
         ),
     ) -> Result<(), Error>>,
-    allocator: A
+    allocator: Box<Fn() -> A>,
+    post_process_chain: PostProcessChain,
+    adapter_preference: AdapterPreference,
+    asset_root: PathBuf,
+    device_lost_callback: Option<Box<FnMut(
+        (
+            &mut S,
+            Arc<SetupContext<A>>,
+        ),
+    ) -> Result<(), Error>>>,
 }
 
 
@@ -47,10 +68,20 @@ impl StarstruckBuilder<(), Result<(), Error>, DefaultGpuAllocator<DefaultChunk<b
         Self {
             title: "Starstruck".to_string(),
             setup_callback: Box::new(|_| Ok(())),
+            prepare_callback: Box::new(|_| Ok(())),
             render_callback: Box::new(|_| Ok(())),
-            allocator: DefaultGpuAllocator::new()
+            allocator: Box::new(DefaultGpuAllocator::new),
+            post_process_chain: PostProcessChain::new(),
+            adapter_preference: AdapterPreference::HighPerformance,
+            asset_root: PathBuf::from("assets"),
+            device_lost_callback: None,
         }
     }
+
+    /// Lists the adapters on this machine along with their limits and memory heaps
+    pub fn available_adapters() -> Vec<AdapterDescription> {
+        <GraphicsState>::available_adapters()
+    }
 }
 
 impl<
@@ -63,8 +94,13 @@ impl<
         Self {
             title: "Starstruck".to_string(),
             setup_callback: Box::new(setup_callback),
+            prepare_callback: Box::new(|_| Ok(())),
             render_callback: Box::new(|_| Ok(())),
-            allocator: DefaultGpuAllocator::new()
+            allocator: Box::new(DefaultGpuAllocator::new),
+            post_process_chain: PostProcessChain::new(),
+            adapter_preference: AdapterPreference::HighPerformance,
+            asset_root: PathBuf::from("assets"),
+            device_lost_callback: None,
         }
     }
 
@@ -91,8 +127,71 @@ impl<
         self
     }
 
+    /// Called each frame before the main render pass, used to render offscreen passes such as
+    /// shadow maps
+    pub fn with_prepare_callback<T: 'static + FnMut(
+        (
+            &mut S,
+            &mut PrepareContext<A, backend::Backend, backend::Device, backend::Instance>,
+        ),
+    ) -> Result<(), Error>>(mut self, callback: T) -> Self {
+        self.prepare_callback = Box::new(callback);
+        self
+    }
+
+    /// Renders the scene offscreen and applies the passes of the chain before presenting
+    pub fn with_post_process_chain(mut self, chain: PostProcessChain) -> Self {
+        self.post_process_chain = chain;
+        self
+    }
+
+    /// Creates the allocator used for device memory, for example a `DefaultGpuAllocator` with a
+    /// budget. It's called again for the new device when the device is lost
+    pub fn with_allocator<T: 'static + Fn() -> A>(mut self, allocator: T) -> Self {
+        self.allocator = Box::new(allocator);
+        self
+    }
+
+    /// Decides which adapter to render with when there are several. Defaults to
+    /// `AdapterPreference::HighPerformance`, the `STARSTRUCK_ADAPTER` environment variable overrides it
+    pub fn with_adapter_preference(mut self, preference: AdapterPreference) -> Self {
+        self.adapter_preference = preference;
+        self
+    }
+
+    /// The directory the `AssetServer` loads assets from, defaults to `assets` in the working
+    /// directory. Pointing it at an archive built by `starstruck-pack` mounts the archive instead
+    pub fn with_asset_root<P: Into<PathBuf>>(mut self, root: P) -> Self {
+        self.asset_root = root.into();
+        self
+    }
+
+    /// Called after the device was lost and a new one has been created. The callback is given the
+    /// state and a setup context for the new device, resources created with the old one can no
+    /// longer be used and need to be replaced, see `Recreatable`. Without this callback the state is
+    /// dropped and the setup callback runs again
+    pub fn with_device_lost_callback<T: 'static + FnMut(
+        (
+            &mut S,
+            Arc<SetupContext<A>>,
+        ),
+    ) -> Result<(), Error>>(mut self, callback: T) -> Self {
+        self.device_lost_callback = Some(Box::new(callback));
+        self
+    }
+
     pub fn init(self) -> Result<Starstruck<S, A>, Error> {
-        Starstruck::init(&self.title, self.setup_callback, self.render_callback, self.allocator)
+        Starstruck::init(
+            &self.title,
+            self.setup_callback,
+            self.prepare_callback,
+            self.render_callback,
+            self.allocator,
+            self.post_process_chain,
+            self.adapter_preference,
+            self.asset_root,
+            self.device_lost_callback,
+        )
     }
 }
 
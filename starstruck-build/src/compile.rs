@@ -0,0 +1,149 @@
+use crate::Preprocessed;
+use crate::ShaderKind;
+use failure::Error;
+use glsl_to_spirv::ShaderType;
+use std::io::Read;
+
+/// Compiles glsl into SPIR-V. `name` is only used to make the error messages readable
+pub fn compile_glsl(source: &str, kind: ShaderKind, name: &str) -> Result<Vec<u8>, Error> {
+    compile_preprocessed(&Preprocessed::from_source(source, name), kind)
+}
+
+/// Compiles expanded glsl into SPIR-V. Errors point into the file the failing line was included
+/// from
+pub fn compile_preprocessed(source: &Preprocessed, kind: ShaderKind) -> Result<Vec<u8>, Error> {
+    let shader_type = match kind {
+        ShaderKind::Vertex => ShaderType::Vertex,
+        ShaderKind::TessellationControl => ShaderType::TessellationControl,
+        ShaderKind::TessellationEvaluation => ShaderType::TessellationEvaluation,
+        ShaderKind::Geometry => ShaderType::Geometry,
+        ShaderKind::Fragment => ShaderType::Fragment,
+        ShaderKind::Compute => ShaderType::Compute,
+    };
+
+    let mut compiled_file = glsl_to_spirv::compile(&source.source, shader_type)
+        .map_err(|message| format_err!("{}", format_compile_errors(source, &message)))?;
+
+    let mut compiled_bytes = Vec::new();
+    compiled_file.read_to_end(&mut compiled_bytes)?;
+    Ok(compiled_bytes)
+}
+
+/// glslang reports errors against the temporary file it was given, this rewrites every error to
+/// `file:line: message` followed by the offending line of the source
+fn format_compile_errors(source: &Preprocessed, message: &str) -> String {
+    let name = source
+        .locate(1)
+        .map(|(path, _)| path.display().to_string())
+        .unwrap_or_default();
+
+    message
+        .lines()
+        .filter_map(|line| {
+            let (severity, rest) = if let Some(rest) = line.strip_prefix("ERROR: ") {
+                ("error", rest)
+            } else if let Some(rest) = line.strip_prefix("WARNING: ") {
+                ("warning", rest)
+            } else {
+                return None;
+            };
+
+            match split_line_number(rest) {
+                Some((line_number, text)) => {
+                    let code = source
+                        .line(line_number)
+                        .map(|code| code.trim())
+                        .unwrap_or("");
+                    let (file, line_number) = source
+                        .locate(line_number)
+                        .map(|(path, line)| (path.display().to_string(), line))
+                        .unwrap_or_else(|| (name.clone(), line_number));
+                    Some(format!(
+                        "{}:{}: {}: {}\n    {}",
+                        file, line_number, severity, text, code
+                    ))
+                }
+                None => Some(format!("{}: {}: {}", name, severity, rest)),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Finds the first `:<line>:` in the error, the file name before it may itself contain colons
+fn split_line_number(error: &str) -> Option<(usize, &str)> {
+    let mut start = 0;
+    while let Some(offset) = error[start..].find(':') {
+        let colon = start + offset;
+        let after = &error[colon + 1..];
+        if let Some(end) = after.find(':') {
+            if let Ok(line_number) = after[..end].parse::<usize>() {
+                return Some((line_number, after[end + 1..].trim()));
+            }
+        }
+        start = colon + 1;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::format_compile_errors;
+    use crate::Preprocessed;
+    use crate::Preprocessor;
+    use pretty_assertions::assert_eq;
+    use std::io;
+    use std::path::Path;
+
+    #[test]
+    fn errors_should_point_to_the_source_line() {
+        let source = Preprocessed::from_source(
+            "#version 450\nvoid main() {\n  foo = 1.0;\n}\n",
+            "shader.frag",
+        );
+        let message = "/tmp/.tmpAbc/0.frag\n\
+                       ERROR: /tmp/.tmpAbc/0.frag:3: 'foo' : undeclared identifier \n\
+                       ERROR: 1 compilation errors.  No code generated.\n";
+
+        let formatted = format_compile_errors(&source, message);
+
+        assert_eq!(
+            formatted,
+            "shader.frag:3: error: 'foo' : undeclared identifier\n    foo = 1.0;\n\
+             shader.frag: error: 1 compilation errors.  No code generated."
+        );
+    }
+
+    #[test]
+    fn windows_paths_should_not_be_mistaken_for_line_numbers() {
+        let source = Preprocessed::from_source("#version 450\nvoid main(", "shader.vert");
+        let message = "ERROR: C:\\temp\\0.vert:2: '' : syntax error";
+
+        let formatted = format_compile_errors(&source, message);
+
+        assert_eq!(
+            formatted,
+            "shader.vert:2: error: '' : syntax error\n    void main("
+        );
+    }
+
+    #[test]
+    fn errors_in_includes_should_point_to_the_included_file() {
+        let read = |path: &Path| match path.to_str() {
+            Some("main.frag") => Ok("#version 450\n#include \"light.glsl\"\n".to_string()),
+            Some("light.glsl") => Ok("// light\nfloat light = foo;\n".to_string()),
+            _ => Err(io::Error::from(io::ErrorKind::NotFound)),
+        };
+        let source = Preprocessor::new()
+            .process_with(Path::new("main.frag"), &read)
+            .unwrap();
+        let message = "ERROR: /tmp/0.frag:3: 'foo' : undeclared identifier";
+
+        let formatted = format_compile_errors(&source, message);
+
+        assert_eq!(
+            formatted,
+            "light.glsl:2: error: 'foo' : undeclared identifier\n    float light = foo;"
+        );
+    }
+}
@@ -0,0 +1,23 @@
+//! Compiles the glsl shaders of a starstruck application from a build script
+//!
+//! Supports every shader stage, `#include` directives and defines, and generates a module with a
+//! `ShaderDescription` constant for every shader. See [`ShaderCompiler`] for an example
+
+#[macro_use]
+extern crate failure;
+
+mod compile;
+mod preprocessor;
+mod shader_compiler;
+mod shader_kind;
+
+pub use failure::Error;
+
+#[doc(inline)]
+pub use self::compile::*;
+#[doc(inline)]
+pub use self::preprocessor::*;
+#[doc(inline)]
+pub use self::shader_compiler::*;
+#[doc(inline)]
+pub use self::shader_kind::*;
@@ -0,0 +1,304 @@
+use failure::Error;
+use std::fs::read_to_string;
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// Expands `#include "file"` and `#include <file>` directives and injects defines after the
+/// `#version` line
+///
+/// Quoted includes are resolved relative to the including file first and then against the include
+/// directories, angled includes only against the include directories
+#[derive(Debug, Clone, Default)]
+pub struct Preprocessor {
+    include_dirs: Vec<PathBuf>,
+    defines: Vec<(String, String)>,
+}
+
+/// The expanded source together with every file it was built from
+#[derive(Debug, Clone)]
+pub struct Preprocessed {
+    pub source: String,
+    /// All files read while expanding, the root file included
+    pub dependencies: Vec<PathBuf>,
+    /// The file and line each line of `source` came from
+    lines: Vec<(PathBuf, usize)>,
+}
+
+impl Preprocessed {
+    /// Wraps source that needs no expanding
+    pub fn from_source<P: AsRef<Path>>(source: &str, name: P) -> Self {
+        let name = name.as_ref();
+        Self {
+            source: source.to_string(),
+            dependencies: vec![],
+            lines: (1..=source.lines().count())
+                .map(|line| (name.to_path_buf(), line))
+                .collect(),
+        }
+    }
+
+    /// Maps a line of the expanded source back to the file and line it came from
+    pub fn locate(&self, line: usize) -> Option<(&Path, usize)> {
+        line.checked_sub(1)
+            .and_then(|index| self.lines.get(index))
+            .map(|(path, line)| (path.as_path(), *line))
+    }
+
+    /// The expanded source line, 1 based
+    pub fn line(&self, line: usize) -> Option<&str> {
+        line.checked_sub(1)
+            .and_then(|index| self.source.lines().nth(index))
+    }
+}
+
+impl Preprocessor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_include_dir<P: AsRef<Path>>(mut self, dir: P) -> Self {
+        self.include_dirs.push(dir.as_ref().to_path_buf());
+        self
+    }
+
+    pub fn with_define(mut self, name: &str, value: &str) -> Self {
+        self.defines.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn process_file<P: AsRef<Path>>(&self, path: P) -> Result<Preprocessed, Error> {
+        self.process_with(path.as_ref(), &|path: &Path| read_to_string(path))
+    }
+
+    /// Same as `process_file`, but reads files through `read`
+    pub fn process_with<R: Fn(&Path) -> io::Result<String>>(
+        &self,
+        path: &Path,
+        read: &R,
+    ) -> Result<Preprocessed, Error> {
+        let source = read(path).map_err(|error| format_err!("{}: {}", path.display(), error))?;
+
+        let mut output = Preprocessed {
+            source: String::new(),
+            dependencies: vec![path.to_path_buf()],
+            lines: vec![],
+        };
+        let mut stack = vec![path.to_path_buf()];
+        self.expand(&source, path, true, &mut stack, &mut output, read)?;
+        Ok(output)
+    }
+
+    fn expand<R: Fn(&Path) -> io::Result<String>>(
+        &self,
+        source: &str,
+        path: &Path,
+        is_root: bool,
+        stack: &mut Vec<PathBuf>,
+        output: &mut Preprocessed,
+        read: &R,
+    ) -> Result<(), Error> {
+        for (index, line) in source.lines().enumerate() {
+            let line_number = index + 1;
+
+            if let Some((name, quoted)) = parse_include(line) {
+                let (include_path, include_source) =
+                    self.resolve(name, quoted, path, read).ok_or_else(|| {
+                        format_err!(
+                            "{}:{}: could not find include \"{}\"",
+                            path.display(),
+                            line_number,
+                            name
+                        )
+                    })?;
+                if stack.contains(&include_path) {
+                    bail!(
+                        "{}:{}: \"{}\" includes itself",
+                        path.display(),
+                        line_number,
+                        include_path.display()
+                    );
+                }
+                if !output.dependencies.contains(&include_path) {
+                    output.dependencies.push(include_path.clone());
+                }
+
+                stack.push(include_path.clone());
+                self.expand(&include_source, &include_path, false, stack, output, read)?;
+                stack.pop();
+                continue;
+            }
+
+            output.source.push_str(line);
+            output.source.push('\n');
+            output.lines.push((path.to_path_buf(), line_number));
+
+            if is_root && line.trim_start().starts_with("#version") {
+                for (name, value) in &self.defines {
+                    output
+                        .source
+                        .push_str(&format!("#define {} {}\n", name, value));
+                    output.lines.push((path.to_path_buf(), line_number));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn resolve<R: Fn(&Path) -> io::Result<String>>(
+        &self,
+        name: &str,
+        quoted: bool,
+        including: &Path,
+        read: &R,
+    ) -> Option<(PathBuf, String)> {
+        let relative = if quoted {
+            including.parent().map(|dir| dir.join(name))
+        } else {
+            None
+        };
+
+        relative
+            .into_iter()
+            .chain(self.include_dirs.iter().map(|dir| dir.join(name)))
+            .filter_map(|candidate| read(&candidate).ok().map(|source| (candidate, source)))
+            .next()
+    }
+}
+
+/// Returns the included name and whether it was quoted
+fn parse_include(line: &str) -> Option<(&str, bool)> {
+    let line = line.trim();
+    if !line.starts_with('#') {
+        return None;
+    }
+    let directive = line[1..].trim_start();
+    if !directive.starts_with("include") {
+        return None;
+    }
+    let argument = directive["include".len()..].trim();
+
+    if argument.starts_with('"') && argument.len() > 1 {
+        argument[1..]
+            .find('"')
+            .map(|end| (&argument[1..=end], true))
+    } else if argument.starts_with('<') {
+        argument.find('>').map(|end| (&argument[1..end], false))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Preprocessor;
+    use pretty_assertions::assert_eq;
+    use std::collections::HashMap;
+    use std::io;
+    use std::path::Path;
+    use std::path::PathBuf;
+
+    fn reader(files: &[(&str, &str)]) -> impl Fn(&Path) -> io::Result<String> {
+        let files: HashMap<PathBuf, String> = files
+            .iter()
+            .map(|(path, source)| (PathBuf::from(path), source.to_string()))
+            .collect();
+        move |path: &Path| {
+            files
+                .get(path)
+                .cloned()
+                .ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))
+        }
+    }
+
+    #[test]
+    fn it_should_expand_includes_relative_to_the_including_file() {
+        let read = reader(&[
+            (
+                "shaders/main.frag",
+                "#version 450\n#include \"common/light.glsl\"\nvoid main() {}\n",
+            ),
+            (
+                "shaders/common/light.glsl",
+                "float light() { return 1.0; }\n",
+            ),
+        ]);
+
+        let result = Preprocessor::new()
+            .process_with(Path::new("shaders/main.frag"), &read)
+            .unwrap();
+
+        assert_eq!(
+            result.source,
+            "#version 450\nfloat light() { return 1.0; }\nvoid main() {}\n"
+        );
+        assert_eq!(
+            result.dependencies,
+            vec![
+                PathBuf::from("shaders/main.frag"),
+                PathBuf::from("shaders/common/light.glsl")
+            ]
+        );
+    }
+
+    #[test]
+    fn angled_includes_should_use_the_include_dirs() {
+        let read = reader(&[
+            ("main.vert", "#version 450\n#include <math.glsl>\n"),
+            ("lib/math.glsl", "const float PI = 3.14;\n"),
+        ]);
+
+        let result = Preprocessor::new()
+            .with_include_dir("lib")
+            .process_with(Path::new("main.vert"), &read)
+            .unwrap();
+
+        assert_eq!(result.source, "#version 450\nconst float PI = 3.14;\n");
+    }
+
+    #[test]
+    fn defines_should_be_placed_after_the_version() {
+        let read = reader(&[("main.vert", "#version 450\nvoid main() {}\n")]);
+
+        let result = Preprocessor::new()
+            .with_define("SHADOWS", "1")
+            .process_with(Path::new("main.vert"), &read)
+            .unwrap();
+
+        assert_eq!(
+            result.source,
+            "#version 450\n#define SHADOWS 1\nvoid main() {}\n"
+        );
+    }
+
+    #[test]
+    fn lines_should_map_back_to_the_file_they_came_from() {
+        let read = reader(&[
+            (
+                "main.frag",
+                "#version 450\n#include \"a.glsl\"\nvoid main() {}\n",
+            ),
+            ("a.glsl", "// a\nfloat a;\n"),
+        ]);
+
+        let result = Preprocessor::new()
+            .with_define("A", "1")
+            .process_with(Path::new("main.frag"), &read)
+            .unwrap();
+
+        assert_eq!(result.locate(4), Some((Path::new("a.glsl"), 2)));
+        assert_eq!(result.locate(5), Some((Path::new("main.frag"), 3)));
+    }
+
+    #[test]
+    fn include_cycles_should_be_an_error() {
+        let read = reader(&[
+            ("a.glsl", "#include \"b.glsl\"\n"),
+            ("b.glsl", "#include \"a.glsl\"\n"),
+        ]);
+
+        let result = Preprocessor::new().process_with(Path::new("a.glsl"), &read);
+
+        assert_eq!(result.is_err(), true);
+    }
+}
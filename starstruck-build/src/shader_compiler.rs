@@ -0,0 +1,238 @@
+use crate::compile_preprocessed;
+use crate::Preprocessor;
+use crate::ShaderKind;
+use failure::Error;
+use std::env;
+use std::fs::read_dir;
+use std::fs::write;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// A shader written by [`ShaderCompiler::compile`]
+#[derive(Debug, Clone)]
+pub struct CompiledShader {
+    /// The name generated for this shader, `shadow.frag` becomes `SHADOW_FRAG`. The module has the
+    /// SPIR-V as `SHADOW_FRAG_SPIRV` and the description as `shadow_frag()`
+    pub name: String,
+    pub kind: ShaderKind,
+    pub source_path: PathBuf,
+    pub spirv_path: PathBuf,
+    /// The source file and every file it includes
+    pub dependencies: Vec<PathBuf>,
+}
+
+/// Compiles every shader in a directory from a build script
+///
+/// Each `.vert`, `.tesc`, `.tese`, `.geom`, `.frag` and `.comp` file is compiled to
+/// `$OUT_DIR/<file name>.spv`, and a module with a function returning the `ShaderDescription` of
+/// every shader is written to `$OUT_DIR/shaders.rs`. The push constants and bindings of the
+/// descriptions are reflected from the SPIR-V. Other files, such as `.glsl` includes, are left
+/// alone
+///
+/// # Examples
+///
+/// ```no_run
+/// // build.rs
+/// fn main() -> Result<(), starstruck_build::Error> {
+///     starstruck_build::ShaderCompiler::new("shaders")
+///         .with_define("MAX_LIGHTS", "4")
+///         .compile()?;
+///     Ok(())
+/// }
+///
+/// // main.rs
+/// // mod shaders { include!(concat!(env!("OUT_DIR"), "/shaders.rs")); }
+/// // let vertex = shaders::lit_vert();
+/// ```
+#[derive(Debug, Clone)]
+pub struct ShaderCompiler {
+    dir: PathBuf,
+    out_dir: Option<PathBuf>,
+    module: String,
+    description_path: String,
+    preprocessor: Preprocessor,
+}
+
+impl ShaderCompiler {
+    pub fn new<P: AsRef<Path>>(dir: P) -> Self {
+        let dir = dir.as_ref().to_path_buf();
+        Self {
+            preprocessor: Preprocessor::new().with_include_dir(&dir),
+            dir,
+            out_dir: None,
+            module: "shaders.rs".to_string(),
+            description_path: "::starstruck::graphics::ShaderDescription".to_string(),
+        }
+    }
+
+    pub fn with_define(mut self, name: &str, value: &str) -> Self {
+        self.preprocessor = self.preprocessor.with_define(name, value);
+        self
+    }
+
+    /// Adds a directory to search for `#include`s, the shader directory is always searched
+    pub fn with_include_dir<P: AsRef<Path>>(mut self, dir: P) -> Self {
+        self.preprocessor = self.preprocessor.with_include_dir(dir);
+        self
+    }
+
+    /// Writes the output here instead of `$OUT_DIR`
+    pub fn with_out_dir<P: AsRef<Path>>(mut self, dir: P) -> Self {
+        self.out_dir = Some(dir.as_ref().to_path_buf());
+        self
+    }
+
+    /// The file name of the generated module, `shaders.rs` by default
+    pub fn with_module(mut self, file_name: &str) -> Self {
+        self.module = file_name.to_string();
+        self
+    }
+
+    /// The path the generated module uses for `ShaderDescription`. Only needed when compiling the
+    /// shaders of starstruck itself, or when it's renamed in `Cargo.toml`
+    pub fn with_description_path(mut self, path: &str) -> Self {
+        self.description_path = path.to_string();
+        self
+    }
+
+    /// Compiles all shaders and prints `cargo:rerun-if-changed` for every file that was read
+    pub fn compile(&self) -> Result<Vec<CompiledShader>, Error> {
+        let out_dir = match &self.out_dir {
+            Some(dir) => dir.clone(),
+            None => PathBuf::from(env::var("OUT_DIR")?),
+        };
+
+        // New shaders only show up as a change of the directory
+        println!("cargo:rerun-if-changed={}", self.dir.display());
+
+        let mut paths = vec![];
+        for entry in read_dir(&self.dir)? {
+            let entry = entry?;
+            if entry.file_type()?.is_file() {
+                paths.push(entry.path());
+            }
+        }
+        paths.sort();
+
+        let mut compiled = vec![];
+        for path in paths {
+            let kind = match path
+                .extension()
+                .and_then(|ext| ShaderKind::from_extension(&ext.to_string_lossy()))
+            {
+                Some(kind) => kind,
+                None => continue,
+            };
+
+            let source = self.preprocessor.process_file(&path)?;
+            for dependency in &source.dependencies {
+                println!("cargo:rerun-if-changed={}", dependency.display());
+            }
+
+            let file_name = path.file_name().unwrap().to_string_lossy().to_string();
+            let spirv_path = out_dir.join(format!("{}.spv", file_name));
+            write(&spirv_path, compile_preprocessed(&source, kind)?)?;
+
+            compiled.push(CompiledShader {
+                name: constant_name(&file_name),
+                kind,
+                source_path: path,
+                spirv_path,
+                dependencies: source.dependencies,
+            });
+        }
+
+        write(
+            out_dir.join(&self.module),
+            generate_module(&compiled, &self.description_path),
+        )?;
+
+        Ok(compiled)
+    }
+}
+
+/// Compiles every shader in `dir` with the default settings
+pub fn compile_shaders<P: AsRef<Path>>(dir: P) -> Result<Vec<CompiledShader>, Error> {
+    ShaderCompiler::new(dir).compile()
+}
+
+/// The descriptions can't be constants since the bindings are a `Vec`, so every shader gets a
+/// function that reflects its push constants and bindings the same way `ShaderDescription::reflect`
+/// does at runtime
+fn generate_module(shaders: &[CompiledShader], description_path: &str) -> String {
+    let mut module = String::from("// Generated by starstruck-build, do not edit\n");
+    for shader in shaders {
+        module.push_str(&format!(
+            "\n/// Compiled from `{path}`\n\
+             pub const {name}_SPIRV: &[u8] = include_bytes!({spirv:?});\n\
+             \n\
+             /// The description of `{path}`, with the push constants and bindings it uses\n\
+             ///\n\
+             /// # Panics\n\
+             ///\n\
+             /// Panics if the shader uses a descriptor set other than 0\n\
+             pub fn {function}() -> {description} {{\n    \
+             {description}::reflect({name}_SPIRV).expect({message:?})\n\
+             }}\n",
+            path = shader.source_path.display(),
+            name = shader.name,
+            spirv = shader.spirv_path.to_string_lossy(),
+            function = shader.name.to_ascii_lowercase(),
+            description = description_path,
+            message = format!("{} has an unsupported layout", shader.source_path.display()),
+        ));
+    }
+    module
+}
+
+fn constant_name(file_name: &str) -> String {
+    let name: String = file_name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect();
+
+    if name.starts_with(|c: char| c.is_ascii_digit()) {
+        format!("_{}", name)
+    } else {
+        name
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::constant_name;
+    use super::generate_module;
+    use super::CompiledShader;
+    use crate::ShaderKind;
+    use pretty_assertions::assert_eq;
+    use std::path::PathBuf;
+
+    #[test]
+    fn constant_names_should_be_valid_identifiers() {
+        assert_eq!(constant_name("shadow_caster.vert"), "SHADOW_CASTER_VERT");
+        assert_eq!(constant_name("my-blur.comp"), "MY_BLUR_COMP");
+        assert_eq!(constant_name("2d.frag"), "_2D_FRAG");
+    }
+
+    #[test]
+    fn the_module_should_reflect_every_shader() {
+        let shader = CompiledShader {
+            name: "LIT_VERT".to_string(),
+            kind: ShaderKind::Vertex,
+            source_path: PathBuf::from("shaders/lit.vert"),
+            spirv_path: PathBuf::from("out/lit.vert.spv"),
+            dependencies: vec![PathBuf::from("shaders/lit.vert")],
+        };
+        let module = generate_module(&[shader], "crate::ShaderDescription");
+
+        assert!(module.contains("pub const LIT_VERT_SPIRV: &[u8] = include_bytes!(\"out/lit.vert.spv\");"));
+        assert!(module.contains("pub fn lit_vert() -> crate::ShaderDescription {"));
+        assert!(module.contains("crate::ShaderDescription::reflect(LIT_VERT_SPIRV)"));
+    }
+}
@@ -0,0 +1,62 @@
+/// The pipeline stage a shader is written for, decided by the file extension
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ShaderKind {
+    Vertex,
+    TessellationControl,
+    TessellationEvaluation,
+    Geometry,
+    Fragment,
+    Compute,
+}
+
+impl ShaderKind {
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension {
+            "vert" => Some(ShaderKind::Vertex),
+            "tesc" => Some(ShaderKind::TessellationControl),
+            "tese" => Some(ShaderKind::TessellationEvaluation),
+            "geom" => Some(ShaderKind::Geometry),
+            "frag" => Some(ShaderKind::Fragment),
+            "comp" => Some(ShaderKind::Compute),
+            _ => None,
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            ShaderKind::Vertex => "vert",
+            ShaderKind::TessellationControl => "tesc",
+            ShaderKind::TessellationEvaluation => "tese",
+            ShaderKind::Geometry => "geom",
+            ShaderKind::Fragment => "frag",
+            ShaderKind::Compute => "comp",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ShaderKind;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn every_stage_should_round_trip_through_its_extension() {
+        let kinds = [
+            ShaderKind::Vertex,
+            ShaderKind::TessellationControl,
+            ShaderKind::TessellationEvaluation,
+            ShaderKind::Geometry,
+            ShaderKind::Fragment,
+            ShaderKind::Compute,
+        ];
+
+        for kind in kinds.iter() {
+            assert_eq!(ShaderKind::from_extension(kind.extension()), Some(*kind));
+        }
+    }
+
+    #[test]
+    fn include_files_should_not_be_compiled() {
+        assert_eq!(ShaderKind::from_extension("glsl"), None);
+    }
+}